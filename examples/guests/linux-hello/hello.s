@@ -0,0 +1,92 @@
+# Issues the Linux syscalls a newlib program makes on its way through
+# `main`: stdio probes stdout with fstat, malloc grows the heap with brk, and
+# exit flushes, closes and calls exit_group.
+#
+# Reads a name from fd 0 and prints "Hello, <name>" to fd 1. Exits with 1 if
+# a syscall does not behave like Linux.
+
+    .section .text.init
+    .globl _start
+_start:
+    # fstat(1, &statbuf) must report a character device
+    li a0, 1
+    la a1, statbuf
+    li a7, 80
+    ecall
+    bnez a0, fail
+    la t0, statbuf
+    lw t1, 16(t0)
+    li t2, 0170000
+    and t1, t1, t2
+    li t2, 0020000
+    bne t1, t2, fail
+
+    # fstat on an unknown descriptor fails with -EBADF
+    li a0, 7
+    la a1, statbuf
+    li a7, 80
+    ecall
+    li t0, -9
+    bne a0, t0, fail
+
+    # sbrk(64): query the break, then move it
+    li a0, 0
+    li a7, 214
+    ecall
+    mv s0, a0
+    addi a0, s0, 64
+    li a7, 214
+    ecall
+    addi t0, s0, 64
+    bne a0, t0, fail
+
+    # read(0, heap, 64)
+    li a0, 0
+    mv a1, s0
+    li a2, 64
+    li a7, 63
+    ecall
+    bltz a0, fail
+    mv s1, a0
+
+    # write(1, "Hello, ", 7); write(1, heap, n)
+    li a0, 1
+    la a1, greeting
+    li a2, 7
+    li a7, 64
+    ecall
+    li a0, 1
+    mv a1, s0
+    mv a2, s1
+    li a7, 64
+    ecall
+
+    # close(1); exit_group(0)
+    li a0, 1
+    li a7, 57
+    ecall
+    bnez a0, fail
+    li a0, 0
+    li a7, 94
+    ecall
+
+fail:
+    li a0, 2
+    la a1, failure
+    li a2, 15
+    li a7, 64
+    ecall
+    li a0, 1
+    li a7, 93
+    ecall
+
+    .section .rodata
+greeting:
+    .ascii "Hello, "
+failure:
+    .ascii "syscall failed\n"
+
+    .section .bss
+    .balign 8
+statbuf:
+    .space 128
@@ -0,0 +1,27 @@
+//! Commits the wrapping sum of a `Vec<u32>` input to the journal, printing
+//! it labelled with the first argument if given. Panics on an empty input.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use zkvm_guest::{env, println};
+
+zkvm_guest::entry!(main);
+
+fn main() {
+    let values: Vec<u32> = env::read();
+    if values.is_empty() {
+        panic!("no values to sum");
+    }
+    println!("summing {} values", values.len());
+    let sum = values
+        .iter()
+        .fold(0u32, |acc, value| acc.wrapping_add(*value));
+    env::commit(&sum);
+    if let Some(label) = env::args().nth(1) {
+        println!("{label}: {sum}");
+    }
+}
@@ -0,0 +1,10 @@
+use std::{env, fs, path::PathBuf};
+
+// Puts `link.x` on the linker search path so guest binaries only need
+// `-Tlink.x` in their rustflags.
+fn main() {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::copy("link.x", out_dir.join("link.x")).unwrap();
+    println!("cargo:rustc-link-search={}", out_dir.display());
+    println!("cargo:rerun-if-changed=link.x");
+}
@@ -0,0 +1,47 @@
+/* Memory layout of a zkvm guest. Keep in sync with `zkvm_guest::memory`. */
+
+OUTPUT_ARCH(riscv)
+ENTRY(_start)
+
+MEMORY
+{
+  RAM : ORIGIN = 0x00010000, LENGTH = 0x00FF0000
+}
+
+/* The stack grows down from the top of memory; the heap grows up from the
+   end of .bss and must stay below the stack. */
+__stack_top = 0x01000000;
+__heap_end = 0x00F00000;
+
+SECTIONS
+{
+  .text : {
+    KEEP(*(.text.init))
+    *(.text .text.*)
+  } > RAM
+
+  .rodata : ALIGN(4) {
+    *(.srodata .srodata.*)
+    *(.rodata .rodata.*)
+  } > RAM
+
+  .data : ALIGN(4) {
+    __global_pointer$ = . + 0x800;
+    *(.sdata .sdata.*)
+    *(.data .data.*)
+  } > RAM
+
+  /* VM memory starts zeroed, so .bss needs no clearing at startup */
+  .bss (NOLOAD) : ALIGN(4) {
+    *(.sbss .sbss.*)
+    *(.bss .bss.*)
+    *(COMMON)
+  } > RAM
+
+  . = ALIGN(16);
+  __heap_start = .;
+
+  /DISCARD/ : {
+    *(.eh_frame .eh_frame_hdr)
+  }
+}
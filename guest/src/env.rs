@@ -0,0 +1,180 @@
+//! Safe wrappers around the guest syscalls.
+
+use core::ffi::{c_char, CStr};
+use core::fmt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::codec::{self, Deserializer, Reader};
+use crate::syscall::{fd, nr, syscall};
+
+/// Reads the input stream for the [`codec`].
+struct InputReader;
+
+impl Reader for InputReader {
+    fn read_exact(&mut self, buf: &mut [u8]) -> codec::Result<()> {
+        if read_slice(buf) != buf.len() {
+            return Err(codec::Error::UnexpectedEnd);
+        }
+        Ok(())
+    }
+}
+
+/// Reads up to `buf.len()` bytes from the input stream. Returns the number of
+/// bytes read, which is less than requested only at the end of the input.
+pub fn read_slice(buf: &mut [u8]) -> usize {
+    unsafe { syscall(nr::READ_INPUT, buf.as_mut_ptr() as u32, buf.len() as u32, 0) as usize }
+}
+
+/// Fills `buf` from the input stream, panicking if the input runs out.
+pub fn read_exact(buf: &mut [u8]) {
+    if read_slice(buf) != buf.len() {
+        panic!("unexpected end of input");
+    }
+}
+
+/// Reads a little-endian `u32` from the input stream.
+pub fn read_u32() -> u32 {
+    let mut buf = [0; 4];
+    read_exact(&mut buf);
+    u32::from_le_bytes(buf)
+}
+
+/// Decodes the next value from the input stream, panicking if it is
+/// malformed.
+pub fn read<T: DeserializeOwned>() -> T {
+    match T::deserialize(&mut Deserializer::new(InputReader)) {
+        Ok(value) => value,
+        Err(err) => panic!("failed to decode input: {err}"),
+    }
+}
+
+/// Encodes `value` and appends it to the public journal.
+pub fn commit<T: Serialize + ?Sized>(value: &T) {
+    match codec::to_vec(value) {
+        Ok(bytes) => commit_slice(&bytes),
+        Err(err) => panic!("failed to encode journal value: {err}"),
+    }
+}
+
+/// Appends `bytes` to the public journal.
+pub fn commit_slice(bytes: &[u8]) {
+    unsafe {
+        syscall(
+            nr::WRITE_JOURNAL,
+            bytes.as_ptr() as u32,
+            bytes.len() as u32,
+            0,
+        );
+    }
+}
+
+/// Writes `bytes` to the host's file descriptor `fd`.
+pub fn write(fd: u32, bytes: &[u8]) {
+    unsafe {
+        syscall(nr::WRITE, fd, bytes.as_ptr() as u32, bytes.len() as u32);
+    }
+}
+
+/// A [`fmt::Write`] sink for one of the host's file descriptors.
+pub struct FdWriter(u32);
+
+impl fmt::Write for FdWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write(self.0, s.as_bytes());
+        Ok(())
+    }
+}
+
+pub fn stdout() -> FdWriter {
+    FdWriter(fd::STDOUT)
+}
+
+pub fn stderr() -> FdWriter {
+    FdWriter(fd::STDERR)
+}
+
+#[doc(hidden)]
+pub fn _print(fd: u32, args: fmt::Arguments) {
+    let _ = fmt::Write::write_fmt(&mut FdWriter(fd), args);
+}
+
+/// Prints to the host's standard output.
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => {
+        $crate::env::_print($crate::syscall::fd::STDOUT, format_args!($($arg)*))
+    };
+}
+
+/// Prints to the host's standard output, with a newline.
+#[macro_export]
+macro_rules! println {
+    () => {
+        $crate::print!("\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::print!("{}\n", format_args!($($arg)*))
+    };
+}
+
+/// Prints to the host's standard error.
+#[macro_export]
+macro_rules! eprint {
+    ($($arg:tt)*) => {
+        $crate::env::_print($crate::syscall::fd::STDERR, format_args!($($arg)*))
+    };
+}
+
+/// Prints to the host's standard error, with a newline.
+#[macro_export]
+macro_rules! eprintln {
+    () => {
+        $crate::eprint!("\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::eprint!("{}\n", format_args!($($arg)*))
+    };
+}
+
+/// The strings of a null-terminated pointer array on the initial stack.
+fn strings(mut pointer: *const usize) -> impl Iterator<Item = &'static str> {
+    core::iter::from_fn(move || unsafe {
+        let string = *pointer;
+        if string == 0 {
+            return None;
+        }
+        pointer = pointer.add(1);
+        Some(
+            CStr::from_ptr(string as *const c_char)
+                .to_str()
+                .unwrap_or(""),
+        )
+    })
+}
+
+/// The program arguments the host started the guest with. The first is the
+/// program name, if the host passed one.
+pub fn args() -> impl Iterator<Item = &'static str> {
+    strings(unsafe { crate::rt::initial_stack().add(1) })
+}
+
+/// The environment variables the host set, as `(name, value)` pairs.
+pub fn vars() -> impl Iterator<Item = (&'static str, &'static str)> {
+    let stack = crate::rt::initial_stack();
+    let envp = unsafe { stack.add(1 + *stack + 1) };
+    strings(envp).map(|var| var.split_once('=').unwrap_or((var, "")))
+}
+
+/// The value of the environment variable `name`.
+pub fn var(name: &str) -> Option<&'static str> {
+    vars().find(|(var, _)| *var == name).map(|(_, value)| value)
+}
+
+/// Stops the guest with `code`.
+pub fn exit(code: u32) -> ! {
+    unsafe {
+        syscall(nr::HALT, code, 0, 0);
+    }
+    unreachable!()
+}
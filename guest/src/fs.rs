@@ -0,0 +1,83 @@
+//! Read-only access to the files the host provides.
+
+use alloc::vec::Vec;
+
+use crate::syscall::{nr, syscall, ERRNO_START};
+
+/// A negated Linux errno value returned by the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Error(pub i32);
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+fn check(result: u32) -> Result<u32> {
+    if result >= ERRNO_START {
+        return Err(Error(-(result as i32)));
+    }
+    Ok(result)
+}
+
+/// Where [`File::seek`] measures from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(u32),
+    Current(i32),
+    End(i32),
+}
+
+/// An open host file. It is closed when dropped.
+#[derive(Debug)]
+pub struct File {
+    fd: u32,
+}
+
+impl File {
+    pub fn open(path: &str) -> Result<File> {
+        let fd = check(unsafe { syscall(nr::OPEN, path.as_ptr() as u32, path.len() as u32, 0) })?;
+        Ok(File { fd })
+    }
+
+    /// Reads up to `buf.len()` bytes; returns 0 at the end of the file.
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let count =
+            unsafe { syscall(nr::READ, self.fd, buf.as_mut_ptr() as u32, buf.len() as u32) };
+        check(count).map(|count| count as usize)
+    }
+
+    /// Appends the rest of the file to `buf`; returns the count read.
+    pub fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
+        let start = buf.len();
+        let mut chunk = [0; 256];
+        loop {
+            match self.read(&mut chunk)? {
+                0 => return Ok(buf.len() - start),
+                count => buf.extend_from_slice(&chunk[..count]),
+            }
+        }
+    }
+
+    /// Moves the file offset; returns the new offset from the start.
+    pub fn seek(&mut self, pos: SeekFrom) -> Result<u32> {
+        let (offset, whence) = match pos {
+            SeekFrom::Start(offset) => (offset, 0),
+            SeekFrom::Current(offset) => (offset as u32, 1),
+            SeekFrom::End(offset) => (offset as u32, 2),
+        };
+        check(unsafe { syscall(nr::SEEK, self.fd, offset, whence) })
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        unsafe {
+            syscall(nr::CLOSE, self.fd, 0, 0);
+        }
+    }
+}
+
+/// Reads the whole file at `path`.
+pub fn read(path: &str) -> Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    File::open(path)?.read_to_end(&mut contents)?;
+    Ok(contents)
+}
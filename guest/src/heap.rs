@@ -0,0 +1,58 @@
+//! A bump allocator over the heap the host hands out through `BRK`. Memory
+//! is never freed; guests are short-lived.
+
+use crate::syscall::{nr, syscall};
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+
+/// The break is grown in steps of this many bytes where the host allows it.
+const BRK_STEP: usize = 4096;
+
+struct BumpAllocator {
+    next: UnsafeCell<usize>,
+    brk: UnsafeCell<usize>,
+}
+
+// Guests are single-threaded
+unsafe impl Sync for BumpAllocator {}
+
+fn brk(addr: usize) -> usize {
+    unsafe { syscall(nr::BRK, addr as u32, 0, 0) as usize }
+}
+
+unsafe impl GlobalAlloc for BumpAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let next = &mut *self.next.get();
+        let current_brk = &mut *self.brk.get();
+        if *current_brk == 0 {
+            *current_brk = brk(0);
+            *next = *current_brk;
+        }
+        let start = (*next + layout.align() - 1) & !(layout.align() - 1);
+        let end = match start.checked_add(layout.size()) {
+            Some(end) => end,
+            None => return core::ptr::null_mut(),
+        };
+        if end > *current_brk {
+            // Fall back to the exact size when the host refuses a full step
+            let mut granted = brk(end.next_multiple_of(BRK_STEP));
+            if granted < end {
+                granted = brk(end);
+            }
+            if granted < end {
+                return core::ptr::null_mut();
+            }
+            *current_brk = granted;
+        }
+        *next = end;
+        start as *mut u8
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
+}
+
+#[global_allocator]
+static HEAP: BumpAllocator = BumpAllocator {
+    next: UnsafeCell::new(0),
+    brk: UnsafeCell::new(0),
+};
@@ -0,0 +1,23 @@
+//! The host's Merkle-committed key-value store. Every access is checked
+//! against the state root by the VM.
+
+use crate::syscall::{nr, syscall, KV_VALUE_SIZE};
+
+pub type Value = [u8; KV_VALUE_SIZE];
+
+/// The value stored under `key`, zeroed if it was never set. Panics if `key`
+/// is outside the store.
+pub fn get(key: u32) -> Value {
+    let mut value = [0; KV_VALUE_SIZE];
+    if unsafe { syscall(nr::KV_GET, key, value.as_mut_ptr() as u32, 0) } != 0 {
+        panic!("key {key} is outside the store");
+    }
+    value
+}
+
+/// Stores `value` under `key`. Panics if `key` is outside the store.
+pub fn set(key: u32, value: &Value) {
+    if unsafe { syscall(nr::KV_SET, key, value.as_ptr() as u32, 0) } != 0 {
+        panic!("key {key} is outside the store");
+    }
+}
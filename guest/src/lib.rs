@@ -0,0 +1,55 @@
+//! # zkvm-guest
+//!
+//! Runtime for programs executed inside the zkvm: the entry point, syscall
+//! wrappers, a bump allocator and a panic handler. The RISC-V specific parts
+//! are only compiled for `riscv32` targets; the syscall numbers, memory map
+//! and the [`codec`] for typed input and journal values are shared with the
+//! host.
+//!
+//! A guest binary looks like:
+//!
+//! ```ignore
+//! #![no_std]
+//! #![no_main]
+//!
+//! zkvm_guest::entry!(main);
+//!
+//! fn main() {
+//!     let values: Vec<u32> = zkvm_guest::env::read();
+//!     zkvm_guest::env::commit(&values.iter().sum::<u32>());
+//! }
+//! ```
+//!
+//! and is linked with `-Tlink.x`.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod codec;
+pub mod memory;
+pub mod syscall;
+
+#[cfg(target_arch = "riscv32")]
+pub mod env;
+#[cfg(target_arch = "riscv32")]
+pub mod fs;
+#[cfg(target_arch = "riscv32")]
+mod heap;
+#[cfg(target_arch = "riscv32")]
+pub mod kv;
+#[cfg(target_arch = "riscv32")]
+mod rt;
+
+/// Declares the guest's main function. It runs after the stack is set up and
+/// the guest halts with exit code 0 when it returns.
+#[macro_export]
+macro_rules! entry {
+    ($main:path) => {
+        #[no_mangle]
+        extern "Rust" fn __zkvm_main() {
+            let main: fn() = $main;
+            main()
+        }
+    };
+}
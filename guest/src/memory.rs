@@ -0,0 +1,14 @@
+//! The guest memory map. `link.x` encodes the same layout.
+
+/// Where code and data are linked.
+pub const TEXT_START: u32 = 0x0001_0000;
+
+/// Upper bound of the heap, which grows up from the end of `.bss`.
+pub const HEAP_END: u32 = 0x00f0_0000;
+
+/// Top of the stack, which grows down towards `HEAP_END`. The host lays out
+/// the program arguments just below it.
+pub const STACK_TOP: u32 = 0x0100_0000;
+
+/// Memory size a VM needs to run guests linked with `link.x`.
+pub const MEMORY_SIZE: usize = STACK_TOP as usize;
@@ -0,0 +1,87 @@
+//! Startup code and panic handling.
+
+use crate::syscall::{nr, syscall, PanicLocation};
+use core::cell::UnsafeCell;
+use core::fmt::Write;
+
+core::arch::global_asm!(
+    ".section .text.init",
+    ".global _start",
+    "_start:",
+    ".option push",
+    ".option norelax",
+    "la gp, __global_pointer$",
+    ".option pop",
+    // The host has set up sp with argc, argv and envp
+    "mv a0, sp",
+    "call {start}",
+    start = sym __zkvm_start,
+);
+
+extern "Rust" {
+    fn __zkvm_main();
+}
+
+/// The initial stack pointer, for `env::args` and `env::vars`.
+struct InitialStack(UnsafeCell<*const usize>);
+
+// Guests are single-threaded
+unsafe impl Sync for InitialStack {}
+
+static INITIAL_STACK: InitialStack = InitialStack(UnsafeCell::new(core::ptr::null()));
+
+/// `argc`, followed by the null-terminated `argv` and `envp` arrays.
+pub(crate) fn initial_stack() -> *const usize {
+    unsafe { *INITIAL_STACK.0.get() }
+}
+
+extern "C" fn __zkvm_start(stack: *const usize) -> ! {
+    unsafe {
+        *INITIAL_STACK.0.get() = stack;
+        __zkvm_main()
+    };
+    crate::env::exit(0)
+}
+
+/// Formats into a fixed buffer, truncating what does not fit.
+struct PanicBuffer {
+    bytes: [u8; 256],
+    len: usize,
+}
+
+impl Write for PanicBuffer {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let n = s.len().min(self.bytes.len() - self.len);
+        self.bytes[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    let mut buffer = PanicBuffer {
+        bytes: [0; 256],
+        len: 0,
+    };
+    let _ = write!(buffer, "{}", info.message());
+    let location = info.location().map(|location| PanicLocation {
+        file_ptr: location.file().as_ptr() as u32,
+        file_len: location.file().len() as u32,
+        line: location.line(),
+        column: location.column(),
+    });
+    let location_ptr = match &location {
+        Some(location) => location as *const PanicLocation as u32,
+        None => 0,
+    };
+    unsafe {
+        syscall(
+            nr::PANIC,
+            buffer.bytes.as_ptr() as u32,
+            buffer.len as u32,
+            location_ptr,
+        );
+    }
+    loop {}
+}
@@ -0,0 +1,79 @@
+//! Syscall numbers and the raw `ecall` interface.
+//!
+//! The syscall number goes in `a7`, arguments in `a0`-`a2` and the result is
+//! returned in `a0`. Under RV32E, which has no `a7`, the number goes in `t0`.
+
+pub mod nr {
+    /// Stops the VM. `a0` is the exit code.
+    pub const HALT: u32 = 0;
+    /// Copies up to `a1` bytes of input to `a0`; returns the count copied.
+    pub const READ_INPUT: u32 = 1;
+    /// Appends `a1` bytes at `a0` to the journal.
+    pub const WRITE_JOURNAL: u32 = 2;
+    /// Aborts the guest with the UTF-8 message of `a1` bytes at `a0`. `a2`
+    /// points to a [`PanicLocation`](super::PanicLocation), or is 0.
+    pub const PANIC: u32 = 3;
+    /// Writes `a2` bytes at `a1` to file descriptor `a0`; returns the count
+    /// written.
+    pub const WRITE: u32 = 4;
+    /// Moves the program break to `a0` if the host allows it; returns the
+    /// resulting break. `a0 = 0` queries the current break.
+    pub const BRK: u32 = 5;
+    /// Opens the host file whose UTF-8 path of `a1` bytes is at `a0`;
+    /// returns a file descriptor.
+    pub const OPEN: u32 = 6;
+    /// Reads up to `a2` bytes from file descriptor `a0` to `a1`; returns the
+    /// count read, 0 at the end of the file.
+    pub const READ: u32 = 7;
+    /// Moves the offset of file descriptor `a0` by the signed `a1` from the
+    /// start, current offset or end as `a2` is 0, 1 or 2; returns the new
+    /// offset.
+    pub const SEEK: u32 = 8;
+    /// Closes file descriptor `a0`.
+    pub const CLOSE: u32 = 9;
+    /// Copies the [`KV_VALUE_SIZE`](super::KV_VALUE_SIZE) byte value stored
+    /// under key `a0` in the host's key-value store to `a1`.
+    pub const KV_GET: u32 = 10;
+    /// Stores the value at `a1` under key `a0`.
+    pub const KV_SET: u32 = 11;
+}
+
+/// Size of the values in the key-value store.
+pub const KV_VALUE_SIZE: usize = 32;
+
+/// Results of `OPEN`, `READ`, `SEEK` and `CLOSE` at or above this value are
+/// negated Linux errno values.
+pub const ERRNO_START: u32 = -4095i32 as u32;
+
+/// File descriptors accepted by `WRITE`.
+pub mod fd {
+    pub const STDOUT: u32 = 1;
+    pub const STDERR: u32 = 2;
+}
+
+/// Where a guest panic happened, as passed to `PANIC`.
+#[repr(C)]
+pub struct PanicLocation {
+    pub file_ptr: u32,
+    pub file_len: u32,
+    pub line: u32,
+    pub column: u32,
+}
+
+/// Issues a syscall.
+///
+/// # Safety
+///
+/// Pointer arguments must be valid for the access the syscall performs.
+#[cfg(target_arch = "riscv32")]
+pub unsafe fn syscall(nr: u32, a0: u32, a1: u32, a2: u32) -> u32 {
+    let ret;
+    core::arch::asm!(
+        "ecall",
+        inlateout("a0") a0 => ret,
+        in("a1") a1,
+        in("a2") a2,
+        in("a7") nr,
+    );
+    ret
+}
@@ -1,5 +1,10 @@
 use std::time::Instant;
-use zkvm_from_scratch::{encode_hex, Prover, StarkProver, StarkVerifier, Verifier, VmState};
+use zkvm_from_scratch::{
+    encode_hex, ExecutorEnv, Prover, StarkProver, StarkVerifier, Verifier, VmState,
+};
+
+/// Built from `examples/guests/sum` with `just build-guests`.
+const SUM_ELF: &[u8] = include_bytes!("../../examples/guests/sum.elf");
 
 fn main() {
     println!("🚀 ZKVM from Scratch - Zero-Knowledge Virtual Machine Demo");
@@ -66,23 +71,8 @@ fn demo_zero_knowledge_proof() {
     println!("🔒 Zero-Knowledge Proof Demo");
     println!("----------------------------");
 
-    // Create VM and load the same program
-    let mut vm = VmState::new(1024 * 1024);
-    let program = vec![
-        0x33, 0x80, 0x00, 0x00, // ADD r1, r0, r0
-        0x33, 0x01, 0x10, 0x00, // ADD r2, r1, r1
-        0x33, 0x81, 0x21, 0x00, // ADD r3, r2, r2
-    ];
-    vm.memory
-        .load_program(&program, 0)
-        .expect("Failed to load program");
-
-    // Set private inputs (these won't be revealed in the proof)
-    vm.registers[1] = 42; // Secret value 1
-    vm.registers[2] = 17; // Secret value 2
-    println!("✓ Set private inputs (hidden): r1 = 42, r2 = 17");
-
-    // Create prover and verifier
+    // Run the example guest on private inputs; only their sum is committed
+    let secrets = vec![42u32, 17];
     let stark_prover = StarkProver::default();
     let prover = Prover::new(stark_prover);
 
@@ -94,11 +84,25 @@ fn demo_zero_knowledge_proof() {
     // Phase 1: Generate execution trace
     println!("\n🔍 Phase 1: Execution Trace Generation");
     let trace_start = Instant::now();
-    let trace = prover
-        .generate_execution_trace(&mut vm, 3)
-        .expect("Failed to generate trace");
+    // Guest output is streamed to the terminal as the guest runs
+    let session = ExecutorEnv::builder()
+        .with_elf(SUM_ELF)
+        .with_input(&secrets)
+        .with_stdout(std::io::stdout())
+        .with_stderr(std::io::stderr())
+        .with_trace()
+        .build()
+        .and_then(|env| env.execute())
+        .expect("Failed to execute guest");
     let trace_time = trace_start.elapsed();
+    if let Some(panic) = &session.panic {
+        eprintln!("❌ {panic}");
+        return;
+    }
+    let sum: u32 = session.decode_journal().expect("Guest committed its sum");
+    let trace = session.trace.expect("Trace was requested");
 
+    println!("✓ Set inputs: {secrets:?}");
     println!("✓ Generated execution trace in {trace_time:?}");
     println!("  - {} execution steps recorded", trace.steps.len());
     println!(
@@ -109,9 +113,15 @@ fn demo_zero_knowledge_proof() {
             .map(|s| s.intermediate_values.len())
             .sum::<usize>()
     );
+    println!("  - Public journal: sum = {sum}");
+    println!(
+        "  - Memory: {} bytes of heap, {} bytes of stack, {} pages touched",
+        session.memory.peak_heap, session.memory.peak_stack, session.memory.touched_pages
+    );
 
     // Phase 2: Proof generation
     println!("\n⚡ Phase 2: Zero-Knowledge Proof Generation");
+
     let proof_start = Instant::now();
     let proof = prover
         .prove_execution(&trace)
@@ -128,9 +138,8 @@ fn demo_zero_knowledge_proof() {
     // Phase 3: Proof verification
     println!("\n🔐 Phase 3: Zero-Knowledge Proof Verification");
     let verify_start = Instant::now();
-    let public_inputs = vec![]; // No public inputs in this demo
     let is_valid = verifier
-        .verify(&proof, &public_inputs)
+        .verify(&proof, &trace.public_values())
         .expect("Failed to verify proof");
     let verify_time = verify_start.elapsed();
 
@@ -143,8 +152,8 @@ fn demo_zero_knowledge_proof() {
 
     // Demonstrate zero-knowledge property
     println!("\n🎭 Zero-Knowledge Properties:");
-    println!("  ✓ Private inputs (42, 17) are NOT revealed in proof");
-    println!("  ✓ Verifier confirms execution correctness WITHOUT seeing secrets");
+    println!("  ✓ The proof binds the input {secrets:?} and the journal as public values");
+    println!("  ✓ Verifier confirms execution correctness WITHOUT re-executing");
     println!("  ✓ Proof size is independent of private input size");
     println!("  ✓ Verification is faster than re-execution");
 
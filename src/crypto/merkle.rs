@@ -1,25 +1,53 @@
-use super::{hash_bytes, hash_pair, HashValue};
+use super::{hash_bytes, hash_pair, HashValue, SimpleHash};
+
+/// How a [`MerkleTree`] hashes its leaves and joins two nodes.
+pub trait MerkleHasher {
+    fn hash_leaf(&self, data: &[u8]) -> HashValue;
+    fn hash_node(&self, left: &HashValue, right: &HashValue) -> HashValue;
+}
+
+impl MerkleHasher for SimpleHash {
+    fn hash_leaf(&self, data: &[u8]) -> HashValue {
+        hash_bytes(data)
+    }
+
+    fn hash_node(&self, left: &HashValue, right: &HashValue) -> HashValue {
+        hash_pair(left, right)
+    }
+}
 
 #[derive(Debug, Clone)]
-pub struct MerkleTree {
+pub struct MerkleTree<H = SimpleHash> {
     leaves: Vec<HashValue>,
     nodes: Vec<Vec<HashValue>>,
     root: HashValue,
+    /// Set when the last leaf is a copy padding an odd number of leaves.
+    padded: bool,
+    hasher: H,
 }
 
 impl MerkleTree {
     pub fn new(data: Vec<&[u8]>) -> Self {
+        Self::with_hasher(data, SimpleHash)
+    }
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    pub fn with_hasher(data: Vec<&[u8]>, hasher: H) -> Self {
         if data.is_empty() {
             return Self {
                 leaves: vec![],
                 nodes: vec![],
                 root: [0u8; 32],
+                padded: false,
+                hasher,
             };
         }
 
-        let mut leaves: Vec<HashValue> = data.iter().map(|d| hash_bytes(d)).collect();
+        let mut leaves: Vec<HashValue> = data.iter().map(|d| hasher.hash_leaf(d)).collect();
 
-        if !leaves.len().is_multiple_of(2) {
+        let padded = !leaves.len().is_multiple_of(2);
+        if padded {
             leaves.push(*leaves.last().unwrap());
         }
 
@@ -36,7 +64,7 @@ impl MerkleTree {
                 } else {
                     &chunk[0]
                 };
-                next_level.push(hash_pair(left, right));
+                next_level.push(hasher.hash_node(left, right));
             }
 
             nodes.push(next_level.clone());
@@ -49,6 +77,8 @@ impl MerkleTree {
             leaves,
             nodes,
             root,
+            padded,
+            hasher,
         }
     }
 
@@ -85,20 +115,37 @@ impl MerkleTree {
         })
     }
 
-    pub fn verify_proof(&self, proof: &MerkleProof) -> bool {
-        let mut current_hash = proof.leaf;
-        let mut current_index = proof.index;
+    /// Replaces leaf `index` with the hash of `data` and rehashes only the
+    /// nodes on its path to the root. Returns `false` if there is no such
+    /// leaf.
+    pub fn update_leaf(&mut self, index: usize, data: &[u8]) -> bool {
+        if index + (self.padded as usize) >= self.leaves.len() {
+            return false;
+        }
+        let leaf = self.hasher.hash_leaf(data);
+        // The copy padding an odd number of leaves follows its original
+        let last = self.leaves.len() - 1;
+        self.leaves[index] = leaf;
+        self.nodes[0][index] = leaf;
+        if self.padded && index + 1 == last {
+            self.leaves[last] = leaf;
+            self.nodes[0][last] = leaf;
+        }
 
-        for sibling in &proof.proof {
-            if current_index.is_multiple_of(2) {
-                current_hash = hash_pair(&current_hash, sibling);
-            } else {
-                current_hash = hash_pair(sibling, &current_hash);
-            }
+        let mut current_index = index;
+        for level in 1..self.nodes.len() {
             current_index /= 2;
+            let below = &self.nodes[level - 1];
+            let left = below[2 * current_index];
+            let right = below.get(2 * current_index + 1).unwrap_or(&left);
+            self.nodes[level][current_index] = self.hasher.hash_node(&left, right);
         }
+        self.root = self.nodes[self.nodes.len() - 1][0];
+        true
+    }
 
-        current_hash == self.root
+    pub fn verify_proof(&self, proof: &MerkleProof) -> bool {
+        proof.root_with(&self.hasher, proof.leaf) == self.root
     }
 }
 
@@ -108,3 +155,32 @@ pub struct MerkleProof {
     pub proof: Vec<HashValue>,
     pub index: usize,
 }
+
+impl MerkleProof {
+    /// The root the proof leads to.
+    pub fn root(&self) -> HashValue {
+        self.root_with_leaf(self.leaf)
+    }
+
+    /// The root the tree would have with `leaf` in place of the proven one.
+    pub fn root_with_leaf(&self, leaf: HashValue) -> HashValue {
+        self.root_with(&SimpleHash, leaf)
+    }
+
+    /// [`Self::root_with_leaf`] for a tree joining its nodes with `hasher`.
+    pub fn root_with(&self, hasher: &impl MerkleHasher, leaf: HashValue) -> HashValue {
+        let mut current_hash = leaf;
+        let mut current_index = self.index;
+
+        for sibling in &self.proof {
+            if current_index.is_multiple_of(2) {
+                current_hash = hasher.hash_node(&current_hash, sibling);
+            } else {
+                current_hash = hasher.hash_node(sibling, &current_hash);
+            }
+            current_index /= 2;
+        }
+
+        current_hash
+    }
+}
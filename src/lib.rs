@@ -43,10 +43,14 @@ pub mod utils;
 // Re-export key items from modules
 pub use crypto::{HashValue, MerkleTree};
 pub use utils::{decode_hex, encode_hex};
-pub use vm::{ExecutionStep, Instruction, Opcode, VmState};
+pub use vm::{
+    ExecutionStep, ExecutorEnv, ExitStatus, GasMeter, Instruction, IsaProfile, Opcode, Session,
+    VmState,
+};
 pub use zkp::{
     ConstraintSystem, ExecutionTrace, Proof, Prover, StarkProver, StarkVerifier, Verifier,
 };
+pub use zkvm_guest::codec;
 
 // WebAssembly bindings (optional). Enable via `wasm-bindings` feature if needed.
 #[cfg(all(target_arch = "wasm32", feature = "wasm-bindings"))]
@@ -76,6 +80,1999 @@ mod tests {
         assert_eq!(vm.registers[0], 0);
     }
 
+    #[test]
+    fn test_out_of_gas() {
+        let program = vec![
+            0x33, 0x80, 0x00, 0x00, // ADD r1, r0, r0
+            0x33, 0x01, 0x10, 0x00, // ADD r2, r1, r1
+            0x33, 0x81, 0x21, 0x00, // ADD r3, r2, r2
+        ];
+        let gas = GasMeter::new(5).with_opcode_price(Opcode::Add, 2);
+        let mut vm = VmState::new(1024).with_gas_meter(gas);
+        vm.memory.load_program(&program, 0).unwrap();
+
+        assert_eq!(vm.run(3), Ok(ExitStatus::OutOfGas));
+        assert_eq!(vm.pc, 8);
+        assert_eq!(vm.gas.used, 4);
+
+        // The trap is sticky
+        assert_eq!(vm.run(3), Ok(ExitStatus::OutOfGas));
+        assert_eq!(vm.pc, 8);
+    }
+
+    #[test]
+    fn test_proof_reports_gas_used() {
+        let program = vec![0x33, 0x01, 0x10, 0x00]; // ADD r2, r1, r1
+        let mut vm = VmState::new(1024).with_gas_meter(GasMeter::new(100));
+        vm.memory.load_program(&program, 0).unwrap();
+
+        let prover = Prover::new(StarkProver::default());
+        let trace = prover.generate_execution_trace(&mut vm, 1).unwrap();
+        assert_eq!(trace.exit_status, ExitStatus::StepLimit);
+
+        let proof = prover.prove_execution(&trace).unwrap();
+        assert_eq!(proof.gas_used, 1);
+
+        let verifier = Verifier::new(StarkVerifier::default());
+        let public_values = trace.public_values();
+        assert!(verifier.verify(&proof, &public_values).unwrap());
+
+        let mut forged = proof.clone();
+        forged.gas_used = 0;
+        assert!(!verifier.verify(&forged, &public_values).unwrap());
+
+        // The AIR adds up the gas, so a prover cannot report less than the
+        // instructions cost
+        let mut bad = trace.clone();
+        bad.gas_used = 0;
+        let proof = prover.prove_execution(&bad).unwrap();
+        assert_eq!(proof.gas_used, 0);
+        assert!(!verifier.verify(&proof, &public_values).unwrap());
+
+        // Nor price them below the public schedule
+        bad.initial_state.gas = GasMeter::new(100).with_opcode_price(Opcode::Add, 0);
+        assert!(ConstraintSystem::new().check_trace(&bad).is_ok());
+        let proof = prover.prove_execution(&bad).unwrap();
+        assert!(verifier.verify(&proof, &bad.public_values()).unwrap());
+        assert!(!verifier.verify(&proof, &public_values).unwrap());
+    }
+
+    #[test]
+    fn test_rv32e_profile() {
+        // ADD x3, x2, x1 is fine, ADD x16, x1, x1 is not
+        let ok = 0x001101b3;
+        let bad = 0x00108833;
+        assert!(vm::decode_instruction(ok, IsaProfile::RV32E).is_ok());
+        assert!(vm::decode_instruction(bad, IsaProfile::RV32E).is_err());
+        assert!(vm::decode_instruction(bad, IsaProfile::RV32I).is_ok());
+
+        let mut vm = VmState::new(1024).with_profile(IsaProfile::RV32E);
+        vm.memory.load_program(&ok.to_le_bytes(), 0).unwrap();
+        let prover = Prover::new(StarkProver::default());
+        let trace = prover.generate_execution_trace(&mut vm, 1).unwrap();
+
+        let constraint_system = ConstraintSystem::new().with_profile(IsaProfile::RV32E);
+        assert_eq!(constraint_system.layout.registers.len(), 16);
+        // Dropping 16 registers drops their limbs and their rs1, rs2 and rd
+        // selector columns
+        let rv32i = ConstraintSystem::new().with_profile(IsaProfile::RV32I);
+        let per_register = rv32i.layout.registers[16].len() + 3;
+        assert_eq!(
+            constraint_system.air.width(),
+            rv32i.air.width() - 16 * per_register
+        );
+        assert!(constraint_system.check_trace(&trace).is_ok());
+
+        // The syscall number is in t0: li t0, 2; li a1, 0; ecall (empty
+        // journal write), then li t0, 0; li a0, 7; ecall
+        let program: Vec<u8> = [
+            0x00200293u32,
+            0x00000593,
+            0x00000073,
+            0x00000293,
+            0x00700513,
+            0x00000073,
+        ]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+        let mut vm = VmState::new(1 << 16).with_profile(IsaProfile::RV32E);
+        vm.memory.load_program(&program, 0).unwrap();
+        assert_eq!(vm.run(10), Ok(ExitStatus::Halted(7)));
+    }
+
+    #[test]
+    fn test_isa_profiles() {
+        use vm::{decode_instruction, Extension};
+
+        let mul = 0x02c58533; // mul a0, a1, a2
+        let amoadd = 0x00c5a52f; // amoadd.w a0, a2, (a1)
+        let c_addi = 0x1581; // c.addi a1, -32
+        let andn = 0x40c5f533; // andn a0, a1, a2
+
+        assert!(decode_instruction(mul, IsaProfile::RV32I).is_err());
+        assert!(decode_instruction(mul, IsaProfile::RV32IM).is_ok());
+        assert!(decode_instruction(amoadd, IsaProfile::RV32IMC).is_err());
+        assert!(decode_instruction(amoadd, IsaProfile::RV32IMAC).is_ok());
+        assert!(decode_instruction(c_addi, IsaProfile::RV32IM).is_err());
+        let expanded = decode_instruction(c_addi, IsaProfile::RV32IMC).unwrap();
+        assert_eq!(
+            expanded,
+            Instruction::new(Opcode::Addi, 11, 11, 0, -32).compressed()
+        );
+        assert!(decode_instruction(andn, IsaProfile::RV32IMAC).is_err());
+        assert!(decode_instruction(andn, IsaProfile::RV32IMAC.with_b()).is_ok());
+
+        let profile = IsaProfile::RV32IMAC.with_b().with(Extension::Zbc);
+        assert_eq!(profile.to_string(), "RV32IMAC_Zba_Zbb_Zbc_Zbs");
+        assert_eq!(IsaProfile::from_id(profile.id()), Some(profile));
+    }
+
+    #[test]
+    fn test_rv32imc_execution() {
+        // Sums 5 + 4 + 3 + 2 + 1 into a0 using a mix of compressed and full
+        // instructions:
+        //   c.li a1, 5
+        //   c.li a0, 0
+        // loop:
+        //   add a0, a0, a1
+        //   c.addi a1, -1
+        //   bne a1, zero, loop
+        let program: Vec<u8> = [
+            &0x4595u16.to_le_bytes()[..],
+            &0x4501u16.to_le_bytes(),
+            &0x00b50533u32.to_le_bytes(),
+            &0x15fdu16.to_le_bytes(),
+            &0xfe059de3u32.to_le_bytes(),
+        ]
+        .concat();
+
+        let mut vm = VmState::new(1024).with_profile(IsaProfile::RV32IMC);
+        vm.memory.load_program(&program, 0).unwrap();
+        vm.run(2 + 3 * 5).unwrap();
+        assert_eq!(vm.registers[10], 15);
+        assert_eq!(vm.pc, 14);
+
+        let mut vm = VmState::new(1024).with_profile(IsaProfile::RV32IM);
+        vm.memory.load_program(&program, 0).unwrap();
+        assert!(vm.run(1).is_err());
+    }
+
+    #[test]
+    fn test_rv64im_execution() {
+        let program: [u32; 24] = [
+            0xfff00513, // addi a0, zero, -1
+            0x0055059b, // addiw a1, a0, 5
+            0x02851613, // slli a2, a0, 40
+            0x03c65693, // srli a3, a2, 60
+            0x80000737, // lui a4, 0x80000
+            0x00e707bb, // addw a5, a4, a4
+            0x10c03023, // sd a2, 256(zero)
+            0x10003803, // ld a6, 256(zero)
+            0x10406883, // lwu a7, 260(zero)
+            0x10402903, // lw s2, 260(zero)
+            0x02a539b3, // mulhu s3, a0, a0
+            0x02a74a3b, // divw s4, a4, a0
+            0x0206fabb, // remuw s5, a3, zero
+            0x40475b1b, // sraiw s6, a4, 4
+            0x43f65b93, // srai s7, a2, 63
+            0x40a00c3b, // subw s8, zero, a0
+            0x00d51cbb, // sllw s9, a0, a3
+            0x00d55d3b, // srlw s10, a0, a3
+            0x40d75dbb, // sraw s11, a4, a3
+            0x02a702bb, // mulw t0, a4, a0
+            0x02d5533b, // divuw t1, a0, a3
+            0x02d763bb, // remw t2, a4, a3
+            0x01f51e1b, // slliw t3, a0, 31
+            0x00155e9b, // srliw t4, a0, 1
+        ];
+        let bytes: Vec<u8> = program.iter().flat_map(|w| w.to_le_bytes()).collect();
+
+        let mut vm = VmState::new(1024).with_profile(IsaProfile::RV64IM);
+        vm.memory.load_program(&bytes, 0).unwrap();
+        let prover = Prover::new(StarkProver::default());
+        let trace = prover.generate_execution_trace(&mut vm, 24).unwrap();
+
+        let expected: [(usize, u64); 24] = [
+            (10, 0xffff_ffff_ffff_ffff),
+            (11, 4),
+            (12, 0xffff_ff00_0000_0000),
+            (13, 0xf),
+            (14, 0xffff_ffff_8000_0000),
+            (15, 0),
+            (16, 0xffff_ff00_0000_0000),
+            (17, 0xffff_ff00),
+            (18, 0xffff_ffff_ffff_ff00),
+            (19, 0xffff_ffff_ffff_fffe),
+            (20, 0xffff_ffff_8000_0000),
+            (21, 0xf),
+            (22, 0xffff_ffff_f800_0000),
+            (23, 0xffff_ffff_ffff_ffff),
+            (24, 1),
+            (25, 0xffff_ffff_ffff_8000),
+            (26, 0x1ffff),
+            (27, 0xffff_ffff_ffff_0000),
+            (5, 0xffff_ffff_8000_0000),
+            (6, 0x1111_1111),
+            (7, 0xffff_ffff_ffff_fff8),
+            (28, 0xffff_ffff_8000_0000),
+            (29, 0x7fff_ffff),
+            (0, 0),
+        ];
+        for (reg, value) in expected {
+            assert_eq!(vm.registers[reg], value, "x{reg}");
+        }
+
+        // The trace carries 64-bit registers as 32-bit limbs
+        let ld = &trace.steps[7];
+        assert_eq!(ld.registers_after[16], 0);
+        assert_eq!(ld.registers_after_hi[16], 0xffff_ff00);
+        assert_eq!(ld.memory_accesses.len(), 2);
+
+        // The word and doubleword instructions are constrained like the rest
+        let constraint_system = ConstraintSystem::new().with_profile(IsaProfile::RV64IM);
+        assert!(constraint_system.check_trace(&trace).is_ok());
+        let proof = prover.prove_execution(&trace).unwrap();
+        let verifier = Verifier::new(StarkVerifier::default());
+        assert!(verifier.verify(&proof, &trace.public_values()).unwrap());
+
+        // A word instruction whose rd is not the low word of the result,
+        // sign-extended: ADDW, SUBW, MULW, DIVUW and SRLIW
+        for step in [5, 15, 19, 20, 23] {
+            let mut bad = trace.clone();
+            let rd = bad.steps[step].instruction.rd as usize;
+            bad.steps[step].registers_after_hi[rd] ^= 1;
+            for later in &mut bad.steps[step + 1..] {
+                later.registers_before_hi[rd] ^= 1;
+                later.registers_after_hi[rd] ^= 1;
+            }
+            bad.final_state.registers[rd] ^= 1 << 32;
+            assert_eq!(
+                constraint_system.check_trace(&bad),
+                Err("Transition constraint violated"),
+                "step {step}"
+            );
+        }
+
+        // LD loading another high word than the one SD stored
+        let mut bad = trace.clone();
+        bad.steps[7].memory_accesses[1].value_before ^= 1;
+        bad.steps[7].memory_accesses[1].value_after ^= 1;
+        bad.steps[7].registers_after_hi[16] ^= 1;
+        for later in &mut bad.steps[8..] {
+            later.registers_before_hi[16] ^= 1;
+            later.registers_after_hi[16] ^= 1;
+        }
+        assert!(constraint_system.check_trace(&bad).is_err());
+
+        // RV64-only instructions are rejected on RV32 profiles
+        assert!(vm::decode_instruction(program[7], IsaProfile::RV32IM).is_err());
+    }
+
+    #[test]
+    fn test_proof_commits_isa_profile() {
+        let program = vec![0x33, 0x01, 0x10, 0x00]; // ADD r2, r1, r1
+        let mut vm = VmState::new(1024).with_profile(IsaProfile::RV32IMC);
+        vm.memory.load_program(&program, 0).unwrap();
+
+        let prover = Prover::new(StarkProver::default());
+        let trace = prover.generate_execution_trace(&mut vm, 1).unwrap();
+        let proof = prover.prove_execution(&trace).unwrap();
+        assert_eq!(proof.isa_profile, IsaProfile::RV32IMC);
+
+        let verifier = Verifier::new(StarkVerifier::default());
+        let public_values = trace.public_values();
+        assert!(verifier.verify(&proof, &public_values).unwrap());
+
+        let mut forged = proof.clone();
+        forged.isa_profile = IsaProfile::RV32I;
+        assert!(!verifier.verify(&forged, &public_values).unwrap());
+    }
+
+    #[test]
+    fn test_rv32fd_execution() {
+        let program: Vec<u8> = [
+            &0x10000413u32.to_le_bytes()[..], // li s0, 256
+            &0x3fc00537u32.to_le_bytes(),     // lui a0, 0x3fc00
+            &0xf0050553u32.to_le_bytes(),     // fmv.w.x fa0, a0
+            &0x400005b7u32.to_le_bytes(),     // lui a1, 0x40000
+            &0xf00585d3u32.to_le_bytes(),     // fmv.w.x fa1, a1
+            &0x00b57653u32.to_le_bytes(),     // fadd.s fa2, fa0, fa1
+            &0x18b576d3u32.to_le_bytes(),     // fdiv.s fa3, fa0, fa1
+            &0xe0060653u32.to_le_bytes(),     // fmv.x.w a2, fa2
+            &0x42068753u32.to_le_bytes(),     // fcvt.d.s fa4, fa3
+            &0x5a0777d3u32.to_le_bytes(),     // fsqrt.d fa5, fa4
+            &0x10f03027u32.to_le_bytes(),     // fsd fa5, 256(zero)
+            &0x2004u16.to_le_bytes(),         // c.fld fs1, 0(s0)
+            &0xa297a6d3u32.to_le_bytes(),     // feq.d a3, fa5, fs1
+            &0x60b57043u32.to_le_bytes(),     // fmadd.s ft0, fa0, fa1, fa2
+            &0xc0000753u32.to_le_bytes(),     // fcvt.w.s a4, ft0, rne
+            &0x001027f3u32.to_le_bytes(),     // frflags a5
+            &0x0021d873u32.to_le_bytes(),     // fsrmi a6, rup
+            &0xc00078d3u32.to_le_bytes(),     // fcvt.w.s a7, ft0
+            &0xe00692d3u32.to_le_bytes(),     // fclass.s t0, fa3
+            &0xd0007153u32.to_le_bytes(),     // fcvt.s.w ft2, zero
+            &0x182570d3u32.to_le_bytes(),     // fdiv.s ft1, fa0, ft2
+            &0x00302373u32.to_le_bytes(),     // frcsr t1
+        ]
+        .concat();
+
+        let mut vm = VmState::new(1024).with_profile(IsaProfile::RV32IMAFDC);
+        vm.memory.load_program(&program, 0).unwrap();
+        let trace = vm.run_with_trace(22).unwrap();
+
+        // fadd.s records its operands, the result and the raised flags
+        assert_eq!(
+            trace[5].intermediate_values,
+            vec![0x3fc0_0000, 0x4000_0000, 0x4060_0000, 0]
+        );
+        assert_eq!(vm.registers[12], 0x4060_0000);
+        assert_eq!(vm.fregisters[15], 0.75f64.sqrt().to_bits());
+        assert_eq!(vm.fregisters[9], vm.fregisters[15]);
+        assert_eq!(vm.registers[13], 1);
+        // 1.5 * 2.0 + 3.5 = 6.5 rounds to even, then up under frm = RUP
+        assert_eq!(vm.fregisters[0], 0xffff_ffff_40d0_0000);
+        assert_eq!(vm.registers[14], 6);
+        assert_eq!(vm.registers[15], vm::FLAG_INEXACT as u64);
+        assert_eq!(vm.registers[16], 0);
+        assert_eq!(vm.registers[17], 7);
+        assert_eq!(vm.registers[5], 1 << 6);
+        assert_eq!(vm.fregisters[1], 0xffff_ffff_7f80_0000);
+        assert_eq!(
+            vm.registers[6],
+            (3 << 5 | vm::FLAG_DIV_BY_ZERO | vm::FLAG_INEXACT) as u64
+        );
+
+        // Single-precision reads of a double are not NaN-boxed
+        assert_eq!(vm.read_fregister(vm::F32, 15), vm::F32.canonical_nan());
+
+        assert!(vm::decode_instruction(0x00b57653, IsaProfile::RV32IMAC).is_err());
+    }
+
+    #[test]
+    fn test_softfloat_matches_ieee() {
+        use vm::{RoundingMode, F32, F64};
+
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            // Bias towards small exponents, specials and subnormals
+            match seed % 4 {
+                0 => seed & 0x800f_ffff_ffff_ffff,
+                1 => seed | 0x7ff0_0000_0000_0000,
+                2 => (seed & 0x801f_ffff_8000_00ff) | 0x3fe0_0000_0000_0000,
+                _ => seed,
+            }
+        };
+        let same64 = |bits: u64, expected: f64| {
+            if expected.is_nan() {
+                bits == F64.canonical_nan()
+            } else {
+                bits == expected.to_bits()
+            }
+        };
+        let same32 = |bits: u64, expected: f32| {
+            if expected.is_nan() {
+                bits == F32.canonical_nan()
+            } else {
+                bits == expected.to_bits() as u64
+            }
+        };
+        let rne = RoundingMode::NearestEven;
+
+        for _ in 0..20_000 {
+            let (a, b, c) = (next(), next(), next());
+            let (x, y, z) = (f64::from_bits(a), f64::from_bits(b), f64::from_bits(c));
+            assert!(same64(F64.add(a, b, rne).0, x + y));
+            assert!(same64(F64.sub(a, b, rne).0, x - y));
+            assert!(same64(F64.mul(a, b, rne).0, x * y));
+            assert!(same64(F64.div(a, b, rne).0, x / y));
+            assert!(same64(F64.sqrt(a, rne).0, x.sqrt()));
+            assert!(same64(F64.fma(a, b, c, rne).0, x.mul_add(y, z)));
+            assert!(same32(F32.convert_from(F64, a, rne).0, x as f32));
+
+            let (a, b, c) = (a >> 32, b >> 32, c >> 32);
+            let (x, y, z) = (
+                f32::from_bits(a as u32),
+                f32::from_bits(b as u32),
+                f32::from_bits(c as u32),
+            );
+            assert!(same32(F32.add(a, b, rne).0, x + y));
+            assert!(same32(F32.mul(a, b, rne).0, x * y));
+            assert!(same32(F32.div(a, b, rne).0, x / y));
+            assert!(same32(F32.sqrt(a, rne).0, x.sqrt()));
+            assert!(same32(F32.fma(a, b, c, rne).0, x.mul_add(y, z)));
+            assert!(same32(F32.from_int(b as u32, true, rne).0, b as i32 as f32));
+            if !x.is_nan() {
+                let rtz = RoundingMode::TowardZero;
+                assert_eq!(F32.to_int(a, true, rtz).0, x as i32 as u32);
+                assert_eq!(F32.to_int(a, false, rtz).0, x as u32);
+            }
+        }
+
+        // Tininess is detected after rounding
+        let (result, flags) = F32.mul(0x0080_0000, 0x3f7f_ffff, rne);
+        assert_eq!(
+            (result, flags),
+            (0x0080_0000, vm::FLAG_UNDERFLOW | vm::FLAG_INEXACT)
+        );
+        // inf * 0 is invalid even with a quiet NaN addend
+        assert_eq!(
+            F32.fma(0x7f80_0000, 0, 0x7fc0_0000, rne).1,
+            vm::FLAG_INVALID
+        );
+        assert_eq!(
+            F64.add(1, F64.sign_bit() | 1, RoundingMode::Down).0,
+            F64.sign_bit()
+        );
+    }
+
+    #[test]
+    fn test_codec_round_trip() {
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        enum Shape {
+            Empty,
+            Circle(u16),
+            Rect { width: i64, height: i8 },
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Input {
+            name: String,
+            flags: (bool, char),
+            shapes: Vec<Shape>,
+            limit: Option<u128>,
+            scale: f64,
+        }
+
+        let input = Input {
+            name: "zkvm".repeat(3) + "!",
+            flags: (true, 'λ'),
+            shapes: vec![
+                Shape::Empty,
+                Shape::Circle(7),
+                Shape::Rect {
+                    width: -3,
+                    height: -1,
+                },
+            ],
+            limit: Some(u128::MAX - 1),
+            scale: -0.5,
+        };
+        let bytes = codec::to_vec(&input).unwrap();
+        assert_eq!(bytes.len() % 4, 0);
+        assert_eq!(codec::from_slice::<Input>(&bytes), Ok(input));
+
+        // Integers up to 32 bits take one word, longer values are split
+        assert_eq!(codec::to_vec(&-2i8).unwrap(), (-2i32).to_le_bytes());
+        assert_eq!(
+            codec::to_vec(&(1u64 << 32)).unwrap(),
+            [0, 0, 0, 0, 1, 0, 0, 0]
+        );
+        assert_eq!(codec::to_vec("abcde").unwrap(), b"\x05\0\0\0abcde\0\0\0");
+
+        // Decoding accepts only the canonical encoding
+        assert_eq!(
+            codec::from_slice::<u8>(&256u32.to_le_bytes()),
+            Err(codec::Error::InvalidValue)
+        );
+        assert_eq!(
+            codec::from_slice::<i8>(&128u32.to_le_bytes()),
+            Err(codec::Error::InvalidValue)
+        );
+        assert_eq!(
+            codec::from_slice::<bool>(&2u32.to_le_bytes()),
+            Err(codec::Error::InvalidValue)
+        );
+        assert_eq!(
+            codec::from_slice::<String>(b"\x01\0\0\0a\0\0\x01"),
+            Err(codec::Error::InvalidPadding)
+        );
+        assert_eq!(
+            codec::from_slice::<u32>(&[0; 8]),
+            Err(codec::Error::TrailingData)
+        );
+        assert_eq!(
+            codec::from_slice::<u64>(&[0; 4]),
+            Err(codec::Error::UnexpectedEnd)
+        );
+
+        // Several committed values decode one after another
+        let mut journal = codec::to_vec(&7u32).unwrap();
+        journal.extend(codec::to_vec("done").unwrap());
+        let mut de = codec::Deserializer::new(&journal[..]);
+        assert_eq!(u32::deserialize(&mut de), Ok(7));
+        assert_eq!(String::deserialize(&mut de).as_deref(), Ok("done"));
+        assert!(de.into_inner().is_empty());
+    }
+
+    #[test]
+    fn test_executor_env() {
+        use std::io::Write;
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Clone, Default)]
+        struct Capture(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for Capture {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        //   li a7, 0x100; li a0, 21; ecall     (host doubles a0)
+        //   sw a0, -4(sp)
+        //   li a7, 4; li a0, 1; addi a1, sp, -4; li a2, 1; ecall
+        //   li a7, 0; li a0, 3; ecall
+        let program: Vec<u8> = [
+            0x10000893u32,
+            0x01500513,
+            0x00000073,
+            0xfea12e23,
+            0x00400893,
+            0x00100513,
+            0xffc10593,
+            0x00100613,
+            0x00000073,
+            0x00000893,
+            0x00300513,
+            0x00000073,
+        ]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+
+        let stdout = Capture::default();
+        let env = || {
+            ExecutorEnv::builder()
+                .with_image(&program)
+                .with_memory_size(1 << 20)
+                .with_syscall_handler(0x100, |context: &mut vm::SyscallContext| {
+                    Ok(context.arg(0) * 2)
+                })
+                .with_stdout(stdout.clone())
+        };
+
+        let session = env().build().unwrap().execute().unwrap();
+        assert_eq!(session.exit_status, ExitStatus::Halted(3));
+        assert_eq!(session.cycles, 12);
+        assert_eq!(session.gas_used, 12);
+        assert!(session.trace.is_none());
+        assert_eq!(session.stdout, [42]);
+        assert_eq!(*stdout.0.lock().unwrap(), [42]);
+
+        let session = env().with_trace().build().unwrap().execute().unwrap();
+        let trace = session.trace.unwrap();
+        assert_eq!(trace.steps.len(), 12);
+        assert_eq!(trace.steps[2].intermediate_values, [0x100, 21, 0, 0, 42]);
+
+        let session = env().with_max_steps(5).build().unwrap().execute().unwrap();
+        assert_eq!(session.exit_status, ExitStatus::StepLimit);
+        let session = env().with_gas_limit(3).build().unwrap().execute().unwrap();
+        assert_eq!(session.exit_status, ExitStatus::OutOfGas);
+
+        // The gas limit overrides the meter's, in either order
+        for session in [
+            env().with_gas_limit(3).with_gas_meter(GasMeter::default()),
+            env().with_gas_meter(GasMeter::default()).with_gas_limit(3),
+        ] {
+            let session = session.build().unwrap().execute().unwrap();
+            assert_eq!(session.exit_status, ExitStatus::OutOfGas);
+        }
+
+        assert_eq!(
+            ExecutorEnv::builder().build().err(),
+            Some("No program given")
+        );
+        assert_eq!(
+            ExecutorEnv::builder().with_elf(&program).build().err(),
+            Some("Not an ELF file")
+        );
+        let image = vm::Program::from_image(&program, 0);
+        assert_eq!(
+            ExecutorEnv::builder()
+                .with_program(image.clone())
+                .with_program(image.clone())
+                .build()
+                .err(),
+            Some("More than one program was given")
+        );
+        for builder in [
+            ExecutorEnv::builder()
+                .with_image(&program)
+                .with_program(image.clone()),
+            ExecutorEnv::builder()
+                .with_program(image)
+                .with_image(&program),
+            ExecutorEnv::builder()
+                .with_image(&program)
+                .with_image(&program),
+        ] {
+            assert_eq!(
+                builder.build().err(),
+                Some("More than one program was given")
+            );
+        }
+    }
+
+    #[test]
+    fn test_write_to_other_fd_fails() {
+        // WRITE to anything but stdout or stderr fails with EBADF
+        let mut vm = VmState::new(1 << 16);
+        vm.memory
+            .load_program(&0x00000073u32.to_le_bytes(), 0)
+            .unwrap();
+        vm.registers[17] = zkvm_guest::syscall::nr::WRITE as u64;
+        vm.registers[10] = vm::FIRST_FILE_FD as u64;
+        vm.registers[12] = 4;
+        assert_eq!(vm.run(1), Ok(ExitStatus::StepLimit));
+        assert_eq!(
+            vm.registers[10] as u32,
+            vm::errno::to_result(vm::errno::EBADF)
+        );
+    }
+
+    #[test]
+    fn test_syscall_lengths_are_bounded() {
+        // A WRITE longer than i32::MAX fails with EINVAL
+        let mut vm = VmState::new(1 << 16);
+        vm.memory
+            .load_program(&0x00000073u32.to_le_bytes(), 0)
+            .unwrap();
+        vm.registers[17] = zkvm_guest::syscall::nr::WRITE as u64;
+        vm.registers[10] = 1;
+        vm.registers[12] = 0x8000_0000;
+        assert_eq!(vm.run(1), Ok(ExitStatus::StepLimit));
+        assert_eq!(
+            vm.registers[10] as u32,
+            vm::errno::to_result(vm::errno::EINVAL)
+        );
+
+        // A WRITE past the end of memory faults before touching any of it
+        let mut vm = VmState::new(1 << 16);
+        vm.memory
+            .load_program(&0x00000073u32.to_le_bytes(), 0)
+            .unwrap();
+        vm.registers[17] = zkvm_guest::syscall::nr::WRITE as u64;
+        vm.registers[10] = 1;
+        vm.registers[11] = 0x100;
+        vm.registers[12] = 0x7fff_0000;
+        assert_eq!(vm.run(1), Err("Memory address out of bounds"));
+    }
+
+    #[test]
+    fn test_virtual_fs() {
+        //   sw "cfg", -16(sp); li a7, 6; addi a0, sp, -16; li a1, 3; ecall
+        //   mv s0, a0; li a7, 8; li a1, -4; li a2, 2; ecall
+        //   li a7, 7; mv a0, s0; addi a1, sp, -8; li a2, 8; ecall
+        //   mv a2, a0; li a7, 4; li a0, 1; addi a1, sp, -8; ecall
+        //   li a7, 9; mv a0, s0; ecall     (twice; the second fails)
+        //   li a7, 0; ecall
+        let program: Vec<u8> = [
+            0x006762b7u32,
+            0x66328293,
+            0xfe512823,
+            0x00600893,
+            0xff010513,
+            0x00300593,
+            0x00000073,
+            0x00050413,
+            0x00800893,
+            0xffc00593,
+            0x00200613,
+            0x00000073,
+            0x00700893,
+            0x00040513,
+            0xff810593,
+            0x00800613,
+            0x00000073,
+            0x00050613,
+            0x00400893,
+            0x00100513,
+            0xff810593,
+            0x00000073,
+            0x00900893,
+            0x00040513,
+            0x00000073,
+            0x00900893,
+            0x00040513,
+            0x00000073,
+            0x00000893,
+            0x00000073,
+        ]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+
+        let session = ExecutorEnv::builder()
+            .with_image(&program)
+            .with_memory_size(1 << 20)
+            .with_public_file("cfg", b"threshold=1000")
+            .with_file("secret", b"hunter2")
+            .with_trace()
+            .build()
+            .unwrap()
+            .execute()
+            .unwrap();
+        assert_eq!(session.stdout, b"1000");
+        assert_eq!(session.exit_status, ExitStatus::Halted(-9i32 as u32));
+        assert_eq!(
+            session.file_commitments,
+            [vm::FileCommitment {
+                path: "cfg".to_string(),
+                hash: zkp::sponge_hash(b"threshold=1000"),
+            }]
+        );
+
+        // The proof commits to the hash of every public file, and the
+        // bytes READ returned are those of the file with that hash
+        let trace = session.trace.unwrap();
+        let prover = Prover::new(StarkProver::default());
+        let proof = prover.prove_execution(&trace).unwrap();
+        let verifier = Verifier::new(StarkVerifier::default());
+        let public_values = trace.public_values();
+        assert_eq!(public_values.files, session.file_commitments);
+        assert!(verifier.verify(&proof, &public_values).unwrap());
+        let mut forged = public_values.clone();
+        forged.files[0].hash = zkp::sponge_hash(b"threshold=9999");
+        assert!(!verifier.verify(&proof, &forged).unwrap());
+
+        let mut files = vm::VirtualFs::new();
+        files.insert("data", b"abcdef".to_vec(), false);
+        assert_eq!(files.open("missing"), Err(vm::errno::ENOENT));
+        let fd = files.open("data").unwrap();
+        assert_eq!(fd, vm::FIRST_FILE_FD);
+        assert_eq!(files.read(fd, 4), Ok(&b"abcd"[..]));
+        assert_eq!(files.read(fd, 4), Ok(&b"ef"[..]));
+        assert_eq!(files.read(fd, 4), Ok(&b""[..]));
+        assert_eq!(files.seek(fd, 1, vm::seek::SET), Ok(1));
+        assert_eq!(files.seek(fd, -2, vm::seek::SET), Err(vm::errno::EINVAL));
+        assert_eq!(files.read(fd, 2), Ok(&b"bc"[..]));
+        assert!(files.commitments().is_empty());
+        assert_eq!(files.close(fd), Ok(()));
+        assert_eq!(files.read(fd, 1), Err(vm::errno::EBADF));
+    }
+
+    #[test]
+    fn test_authenticated_kv_store() {
+        //   li a7, 10; li a0, 5; addi a1, sp, -32; ecall
+        //   lw t0, -32(sp); addi t0, t0, 1; sw t0, -32(sp)
+        //   li a7, 11; li a0, 5; addi a1, sp, -32; ecall
+        //   li a7, 10; li a0, 99; addi a1, sp, -32; ecall     (out of range)
+        //   li a7, 0; ecall
+        let program: Vec<u8> = [
+            0x00a00893u32,
+            0x00500513,
+            0xfe010593,
+            0x00000073,
+            0xfe012283,
+            0x00128293,
+            0xfe512023,
+            0x00b00893,
+            0x00500513,
+            0xfe010593,
+            0x00000073,
+            0x00a00893,
+            0x06300513,
+            0xfe010593,
+            0x00000073,
+            0x00000893,
+            0x00000073,
+        ]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+
+        let mut store = vm::KvStore::new(3).unwrap();
+        let mut value = [7; vm::KV_VALUE_SIZE];
+        value[..4].copy_from_slice(&41u32.to_le_bytes());
+        store.set(5, value).unwrap();
+        let old_root = store.root();
+
+        let env = || {
+            ExecutorEnv::builder()
+                .with_image(&program)
+                .with_memory_size(1 << 20)
+                .with_kv_store(store.clone())
+        };
+        let session = env().with_trace().build().unwrap().execute().unwrap();
+        assert_eq!(session.exit_status, ExitStatus::Halted(-22i32 as u32));
+
+        value[..4].copy_from_slice(&42u32.to_le_bytes());
+        let mut expected = store.clone();
+        expected.set(5, value).unwrap();
+        let final_store = session.final_state.kv_store.as_ref().unwrap();
+        assert_eq!(final_store.get(5), Some(&value));
+        assert_eq!(
+            session.state_transition,
+            Some(vm::StateTransition {
+                old_root,
+                new_root: expected.root(),
+            })
+        );
+        assert_ne!(old_root, expected.root());
+
+        // The proof takes the roots as public values, and shows the guest
+        // moved the store from the old one to the new one
+        let trace = session.trace.unwrap();
+        assert_eq!(trace.state_transition(), session.state_transition);
+        let proof = Prover::new(StarkProver::default())
+            .prove_execution(&trace)
+            .unwrap();
+        let verifier = Verifier::new(StarkVerifier::default());
+        let public_values = trace.public_values();
+        assert_eq!(public_values.state_transition, session.state_transition);
+        assert_eq!(public_values.kv_keys, 8);
+        assert!(verifier.verify(&proof, &public_values).unwrap());
+        for forge in [
+            |transition: &mut vm::StateTransition| transition.old_root = transition.new_root,
+            |transition: &mut vm::StateTransition| transition.new_root = transition.old_root,
+        ] {
+            let mut forged = public_values.clone();
+            forge(forged.state_transition.as_mut().unwrap());
+            assert!(!verifier.verify(&proof, &forged).unwrap());
+        }
+
+        // The VM only accepts answers that match the root it tracks
+        let mut vm = env().build().unwrap().into_vm().unwrap();
+        vm.state_root = Some([1; 32]);
+        assert_eq!(vm.run(100), Err("Invalid state proof"));
+
+        let session = ExecutorEnv::builder()
+            .with_image(&program)
+            .with_memory_size(1 << 20)
+            .build()
+            .unwrap()
+            .execute();
+        assert_eq!(session.err(), Some("No key-value store"));
+        assert!(vm::KvStore::new(0).is_err());
+    }
+
+    #[test]
+    fn test_baby_bear_field() {
+        use zkp::{batch_inverse, coset_lde, intt, ntt, BabyBear, Field, TwoAdicField};
+
+        let p = zkp::BABY_BEAR_MODULUS;
+        let a = BabyBear::new(p - 1);
+        assert_eq!(a + BabyBear::ONE, BabyBear::ZERO);
+        assert_eq!(a * a, BabyBear::ONE);
+        assert_eq!(BabyBear::new(p + 5), BabyBear::new(5));
+        assert_eq!(
+            BabyBear::from_u64(u64::MAX).as_u32(),
+            (u64::MAX % p as u64) as u32
+        );
+        assert_eq!(
+            (BabyBear::new(123456789) * BabyBear::new(987654321)).as_u32(),
+            ((123456789u64 * 987654321) % p as u64) as u32
+        );
+
+        let x = BabyBear::new(0xdead_beef);
+        assert_eq!(x * x.inverse().unwrap(), BabyBear::ONE);
+        assert_eq!(BabyBear::ZERO.inverse(), None);
+        let values = [x, BabyBear::ZERO, BabyBear::new(7)];
+        let inverses = batch_inverse(&values);
+        assert_eq!(inverses[0], x.inverse().unwrap());
+        assert_eq!(inverses[1], BabyBear::ZERO);
+        assert_eq!(inverses[2] * BabyBear::new(7), BabyBear::ONE);
+
+        // ω_{2^27} has order exactly 2^27
+        let root = BabyBear::two_adic_generator(BabyBear::TWO_ADICITY);
+        assert_eq!(root.pow(1 << 27), BabyBear::ONE);
+        assert_ne!(root.pow(1 << 26), BabyBear::ONE);
+
+        let coeffs: Vec<BabyBear> = (1..=8).map(BabyBear::new).collect();
+        let mut evals = coeffs.clone();
+        ntt(&mut evals);
+        let omega = BabyBear::two_adic_generator(3);
+        let at_omega: BabyBear = coeffs.iter().zip(omega.powers()).map(|(&c, p)| c * p).sum();
+        assert_eq!(evals[1], at_omega);
+        intt(&mut evals);
+        assert_eq!(evals, coeffs);
+
+        // A degree-1 polynomial stays degree 1 on the extended coset
+        let line: Vec<BabyBear> = BabyBear::two_adic_generator(2).powers().take(4).collect();
+        let shift = BabyBear::generator();
+        let extended = coset_lde(&line, 4, shift);
+        assert_eq!(extended[0], shift);
+        assert_eq!(extended[8], -shift);
+
+        let bytes = bincode::serialize(&x).unwrap();
+        assert_eq!(bincode::deserialize::<BabyBear>(&bytes).unwrap(), x);
+        let out_of_range = bincode::serialize(&p).unwrap();
+        assert!(bincode::deserialize::<BabyBear>(&out_of_range).is_err());
+    }
+
+    #[test]
+    fn test_baby_bear_extension() {
+        use zkp::{BabyBear, BabyBearExt4, ExtensionField, Field};
+
+        let x = BabyBearExt4::from_base_slice(&[BabyBear::ZERO, BabyBear::ONE]);
+        assert_eq!(x.pow(4), BabyBearExt4::from(BabyBear::new(11)));
+
+        let a = BabyBearExt4::from_base_slice(&[1, 2, 3, 4].map(BabyBear::new));
+        let b = BabyBearExt4::from_base_slice(&[5, 0, 0x7000_0000, 9].map(BabyBear::new));
+        assert_eq!(a * a.inverse().unwrap(), BabyBearExt4::ONE);
+        assert_eq!(b * b.inverse().unwrap(), BabyBearExt4::ONE);
+        assert_eq!(x.inverse().unwrap() * x, BabyBearExt4::ONE);
+        assert_eq!((a * b) * b.inverse().unwrap(), a);
+        assert_eq!(BabyBearExt4::ZERO.inverse(), None);
+        assert_eq!(a * BabyBear::new(3), a * BabyBearExt4::from_u64(3));
+
+        // Challenges use every coordinate of the extension
+        let mut transcript = zkp::Transcript::new(b"test");
+        let challenge = transcript.challenge();
+        assert!(challenge.as_base_slice()[1..].iter().any(|c| !c.is_zero()));
+
+        let coeffs = [3, 1, 4].map(BabyBear::new);
+        assert_eq!(
+            zkp::evaluate_polynomial(&coeffs, x),
+            BabyBearExt4::from_base_slice(&coeffs)
+        );
+    }
+
+    #[test]
+    fn test_stark_proof_tampering() {
+        use zkp::{BabyBear, BabyBearExt4, Field, StarkProof};
+
+        let program = vec![0x33, 0x01, 0x10, 0x00]; // ADD r2, r1, r1
+        let mut vm = VmState::new(1024);
+        vm.memory.load_program(&program, 0).unwrap();
+        vm.registers[1] = 0x9000_0000;
+
+        let prover = Prover::new(StarkProver::default());
+        let trace = prover.generate_execution_trace(&mut vm, 1).unwrap();
+        let proof = prover.prove_execution(&trace).unwrap();
+        let verifier = Verifier::new(StarkVerifier::default());
+        let public_values = trace.public_values();
+        assert!(verifier.verify(&proof, &public_values).unwrap());
+
+        // A verifier that expects the run to stop elsewhere
+        let mut other = public_values.clone();
+        other.final_pc += 4;
+        assert!(!verifier.verify(&proof, &other).unwrap());
+
+        let stark: StarkProof = bincode::deserialize(&proof.witness).unwrap();
+        let forge = |edit: &dyn Fn(&mut StarkProof)| {
+            let mut forged = stark.clone();
+            edit(&mut forged);
+            let mut proof = proof.clone();
+            proof.witness = bincode::serialize(&forged).unwrap();
+            verifier.verify(&proof, &public_values).unwrap()
+        };
+        assert!(!forge(
+            &|p| p.trace_openings[0][1].values[2] += BabyBear::ONE
+        ));
+        assert!(!forge(&|p| p.fri_proof.final_layer[0] += BabyBearExt4::ONE));
+        assert!(!forge(&|p| p.trace_ood_values[1] += BabyBearExt4::ONE));
+        assert!(!forge(&|p| p.trace_commitment[0] ^= 1));
+        assert!(!forge(&|p| p.public_inputs[1] += BabyBear::ONE));
+        assert!(!forge(&|p| p.quotient_ood_values[0] += BabyBearExt4::ONE));
+        assert!(!forge(
+            &|p| p.quotient_openings[3][0].values[0] += BabyBear::ONE
+        ));
+        assert!(!forge(&|p| p.aux_commitment[0] ^= 1));
+        assert!(!forge(&|p| p.aux_ood_next_values[2] += BabyBearExt4::ONE));
+        assert!(!forge(&|p| p.aux_openings[1][1].values[0] += BabyBear::ONE));
+        assert!(!forge(
+            &|p| p.lookup_multiplicities[0].count += BabyBear::ONE
+        ));
+        assert!(!forge(&|p| {
+            p.trace_openings.truncate(10);
+            p.fri_proof.query_proofs.truncate(10);
+        }));
+    }
+
+    #[test]
+    fn test_air_rejects_bad_trace() {
+        let program = vec![
+            0x33, 0x01, 0x10, 0x00, // ADD r2, r1, r1
+            0xb3, 0x01, 0x21, 0x40, // SUB r3, r2, r2
+        ];
+        let mut vm = VmState::new(1024);
+        vm.memory.load_program(&program, 0).unwrap();
+        vm.registers[1] = 0x8000_0001;
+
+        let prover = Prover::new(StarkProver::default());
+        let trace = prover.generate_execution_trace(&mut vm, 2).unwrap();
+        let constraint_system = ConstraintSystem::new();
+        assert!(constraint_system.check_trace(&trace).is_ok());
+
+        // The AIR is the same for any trace length
+        let table = constraint_system.generate_trace(&trace).unwrap();
+        assert_eq!(table.len(), 4);
+        assert_eq!(table.width(), constraint_system.air.width());
+
+        // A wrong ADD result is caught by the checker and by the verifier
+        let mut bad = trace.clone();
+        bad.steps[0].registers_after[2] ^= 1;
+        bad.steps[1].registers_before[2] ^= 1;
+        assert_eq!(
+            constraint_system.check_trace(&bad),
+            Err("Transition constraint violated")
+        );
+        let proof = prover.prove_execution(&bad).unwrap();
+        let verifier = Verifier::new(StarkVerifier::default());
+        assert!(!verifier.verify(&proof, &trace.public_values()).unwrap());
+    }
+
+    #[test]
+    fn test_registers_carry_over() {
+        let program: Vec<u8> = [
+            0x00700293u32, // ADDI x5, x0, 7
+            0x00128313,    // ADDI x6, x5, 1
+            0x005303b3,    // ADD x7, x6, x5
+        ]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+        let profile = IsaProfile::RV32IMAFDC;
+        let mut vm = VmState::new(1024).with_profile(profile);
+        vm.memory.load_program(&program, 0).unwrap();
+        let prover = Prover::new(StarkProver::default());
+        let trace = prover.generate_execution_trace(&mut vm, 3).unwrap();
+        let constraint_system = ConstraintSystem::new().with_profile(profile);
+        assert!(constraint_system.check_trace(&trace).is_ok());
+
+        // A step cannot change a register it does not write: neither the x5
+        // it reads, nor x10, which no step touches
+        let verifier = Verifier::new(StarkVerifier::default());
+        for reg in [5, 10] {
+            let mut bad = trace.clone();
+            bad.steps[1].registers_after[reg] = 42;
+            bad.steps[2].registers_before[reg] = 42;
+            assert_eq!(
+                constraint_system.check_trace(&bad),
+                Err("Transition constraint violated")
+            );
+            let proof = prover.prove_execution(&bad).unwrap();
+            assert!(!verifier.verify(&proof, &trace.public_values()).unwrap());
+        }
+
+        // Nor can the next step start from different registers
+        let mut bad = trace.clone();
+        bad.steps[2].registers_before[5] = 42;
+        assert_eq!(
+            constraint_system.check_trace(&bad),
+            Err("Trace steps are not contiguous")
+        );
+
+        // The carry-over exempts only the step's rd, so a prover relabelling
+        // rd or the opcode to dodge it is caught by the instruction fetch:
+        // the ADD writes x8 instead of x7, or becomes a SUB writing 1
+        let mut relabelled_rd = trace.clone();
+        relabelled_rd.steps[2].instruction.rd = 8;
+        relabelled_rd.steps[2].registers_after[7] = 0;
+        relabelled_rd.steps[2].registers_after[8] = 15;
+        let mut relabelled_opcode = trace.clone();
+        relabelled_opcode.steps[2].instruction.opcode = Opcode::Sub;
+        relabelled_opcode.steps[2].registers_after[7] = 1;
+        for bad in [relabelled_rd, relabelled_opcode] {
+            assert_eq!(
+                constraint_system.check_trace(&bad),
+                Err("Instruction does not match the program")
+            );
+            let proof = prover.prove_execution(&bad).unwrap();
+            assert!(!verifier.verify(&proof, &bad.public_values()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_control_flow_constraints() {
+        let program: Vec<u8> = [
+            0xffd00093u32, // ADDI x1, x0, -3
+            0x00200113,    // ADDI x2, x0, 2
+            0x0020c463,    // BLT x1, x2, 8
+            0x00100193,    // ADDI x3, x0, 1 (skipped)
+            0x0020e463,    // BLTU x1, x2, 8 (not taken)
+            0x008002ef,    // JAL x5, 8
+            0x00200193,    // ADDI x3, x0, 2 (skipped)
+            0x00828367,    // JALR x6, 8(x5)
+        ]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+        let mut vm = VmState::new(1024);
+        vm.memory.load_program(&program, 0).unwrap();
+        let prover = Prover::new(StarkProver::default());
+        let trace = prover.generate_execution_trace(&mut vm, 6).unwrap();
+        let pcs: Vec<u32> = trace.steps.iter().map(|step| step.pc_before).collect();
+        assert_eq!(pcs, [0, 4, 8, 16, 20, 28]);
+        assert_eq!(vm.pc, 32);
+
+        let constraint_system = ConstraintSystem::new();
+        assert!(constraint_system.check_trace(&trace).is_ok());
+        let verifier = Verifier::new(StarkVerifier::default());
+        assert!(verifier
+            .verify(
+                &prover.prove_execution(&trace).unwrap(),
+                &trace.public_values()
+            )
+            .unwrap());
+        let rejected = |tamper: &dyn Fn(&mut ExecutionTrace)| {
+            let mut bad = trace.clone();
+            tamper(&mut bad);
+            assert_eq!(
+                constraint_system.check_trace(&bad),
+                Err("Transition constraint violated")
+            );
+            let proof = prover.prove_execution(&bad).unwrap();
+            assert!(!verifier.verify(&proof, &trace.public_values()).unwrap());
+        };
+        // Falling through a taken signed branch
+        rejected(&|t| {
+            t.steps[2].pc_after = 12;
+            t.steps[3].pc_before = 12;
+        });
+        // Taking an unsigned branch that is not taken
+        rejected(&|t| {
+            t.steps[3].pc_after = 24;
+            t.steps[4].pc_before = 24;
+        });
+        // A wrong return address
+        rejected(&|t| {
+            t.steps[4].registers_after[5] = 28;
+            t.steps[5].registers_before[5] = 28;
+        });
+        // Jumping somewhere else
+        rejected(&|t| t.steps[5].pc_after = 36);
+
+        // A JAL of another length, which returns elsewhere: with the C
+        // extension 2 bytes is a length, but not the instruction's, and 6 is
+        // none
+        let profile = IsaProfile::RV32IMC;
+        let mut vm = VmState::new(1024).with_profile(profile);
+        vm.memory.load_program(&program, 0).unwrap();
+        let trace = prover.generate_execution_trace(&mut vm, 6).unwrap();
+        let constraint_system = ConstraintSystem::new().with_profile(profile);
+        for (size, expected) in [
+            (2, "Instruction does not match the program"),
+            (6, "Transition constraint violated"),
+        ] {
+            let mut bad = trace.clone();
+            let link = 20 + size as u32;
+            bad.steps[4].instruction.size = size;
+            bad.steps[4].registers_after[5] = link;
+            bad.steps[5].registers_before[5] = link;
+            bad.steps[5].registers_after[5] = link;
+            bad.steps[5].pc_after = link + 8;
+            assert_eq!(constraint_system.check_trace(&bad), Err(expected));
+            let proof = prover.prove_execution(&bad).unwrap();
+            assert!(!verifier.verify(&proof, &bad.public_values()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_instruction_fetch_constraints() {
+        let program = |first: u32| -> Vec<u8> {
+            [
+                first,      // ADDI x10, x0, 5
+                0x00150593, // ADDI x11, x10, 1
+                0x00a58633, // ADD x12, x11, x10
+            ]
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect()
+        };
+        let run = |first: u32| {
+            let mut vm = VmState::new(1024);
+            vm.memory.load_program(&program(first), 0).unwrap();
+            ExecutionTrace::record(&mut vm, 3).unwrap()
+        };
+        let trace = run(0x00500513);
+        let constraint_system = ConstraintSystem::new();
+        assert!(constraint_system.check_trace(&trace).is_ok());
+        let prover = Prover::new(StarkProver::default());
+        let verifier = Verifier::new(StarkVerifier::default());
+        let public_values = trace.public_values();
+        assert!(verifier
+            .verify(&prover.prove_execution(&trace).unwrap(), &public_values)
+            .unwrap());
+
+        // Traces that are sound on their own but execute another first
+        // instruction than the program holds
+        for first in [
+            0x4d200513, // ADDI x10, x0, 1234: another immediate
+            0x00500693, // ADDI x13, x0, 5: another rd
+            0x00506513, // ORI x10, x0, 5: another opcode, same result
+            0x00508513, // ADDI x10, x1, 5: another rs1, same result
+        ] {
+            let mut bad = run(first);
+            bad.initial_state = trace.initial_state.clone();
+            assert_eq!(
+                constraint_system.check_trace(&bad),
+                Err("Instruction does not match the program"),
+                "{first:#x}"
+            );
+            let proof = prover.prove_execution(&bad).unwrap();
+            assert!(!verifier.verify(&proof, &public_values).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_arithmetic_constraints() {
+        use zkp::{BabyBear, Field};
+
+        let program: Vec<u8> = [
+            0xfff00093u32, // ADDI x1, x0, -1
+            0x00200113,    // ADDI x2, x0, 2
+            0x002081b3,    // ADD x3, x1, x2
+            0x40110233,    // SUB x4, x2, x1
+            0x022082b3,    // MUL x5, x1, x2
+            0x02209333,    // MULH x6, x1, x2
+            0x0220b3b3,    // MULHU x7, x1, x2
+            0x0220a433,    // MULHSU x8, x1, x2
+            0x0020a4b3,    // SLT x9, x1, x2
+            0x0020b533,    // SLTU x10, x1, x2
+            0x00208033,    // ADD x0, x1, x2
+        ]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+        let mut vm = VmState::new(1024);
+        vm.memory.load_program(&program, 0).unwrap();
+        let prover = Prover::new(StarkProver::default());
+        let trace = prover.generate_execution_trace(&mut vm, 11).unwrap();
+        assert_eq!(
+            vm.registers[3..11],
+            [1, 3, 0xffff_fffe, 0xffff_ffff, 1, 0xffff_ffff, 1, 0]
+        );
+
+        let constraint_system = ConstraintSystem::new();
+        assert!(constraint_system.check_trace(&trace).is_ok());
+        let verifier = Verifier::new(StarkVerifier::default());
+        assert!(verifier
+            .verify(
+                &prover.prove_execution(&trace).unwrap(),
+                &trace.public_values()
+            )
+            .unwrap());
+
+        // Results that are wrong modulo 2^32
+        for (step, register, value) in [
+            (2, 3, 0x1_0001),
+            (3, 4, 0xffff_ffff),
+            (4, 5, 0xffff_fffe_u32.wrapping_add(1 << 31)),
+            (5, 6, 1),
+            (6, 7, 0xffff_ffff),
+            (7, 8, 1),
+            (8, 9, 0),
+        ] {
+            let mut bad = trace.clone();
+            bad.steps[step].registers_after[register] = value;
+            for later in &mut bad.steps[step + 1..] {
+                later.registers_before[register] = value;
+                later.registers_after[register] = value;
+            }
+            assert_eq!(
+                constraint_system.check_trace(&bad),
+                Err("Transition constraint violated")
+            );
+        }
+
+        // A sum whose low limb is out of range, so no carry is needed
+        let mut table = constraint_system.generate_trace(&trace).unwrap();
+        let l = table.layout.clone();
+        for (j, limb) in [257, 255, 255, 255].into_iter().enumerate() {
+            table.set(2, l.result[j], BabyBear::from(limb));
+            table.set(2, l.rd_value[j], BabyBear::from(limb));
+            table.set(2, l.carries[j], BabyBear::ZERO);
+            for row in 3..table.len() {
+                table.set(row, l.registers[3][j], BabyBear::from(limb));
+            }
+        }
+        let public_inputs = constraint_system.public_inputs(&table);
+        assert_eq!(
+            constraint_system
+                .air
+                .check_trace(&table.rows, &public_inputs),
+            Err("Lookup value not in table")
+        );
+    }
+
+    #[test]
+    fn test_immediate_and_shift_constraints() {
+        use zkp::{BabyBear, Field};
+
+        let program: Vec<u8> = [
+            0xfff00093u32, // ADDI x1, x0, -1
+            0x0010a113,    // SLTI x2, x1, 1
+            0x0010b193,    // SLTIU x3, x1, 1
+            0x5550c213,    // XORI x4, x1, 0x555
+            0x80006293,    // ORI x5, x0, -2048
+            0x7f00f313,    // ANDI x6, x1, 0x7f0
+            0x00909393,    // SLLI x7, x1, 9
+            0x01c0d413,    // SRLI x8, x1, 28
+            0x4040d493,    // SRAI x9, x1, 4
+            0x80001537,    // LUI x10, 0x80001
+            0x12345597,    // AUIPC x11, 0x12345
+            0x02300613,    // ADDI x12, x0, 35
+            0x00c516b3,    // SLL x13, x10, x12
+            0x00c55733,    // SRL x14, x10, x12
+            0x40c557b3,    // SRA x15, x10, x12
+            0x40055833,    // SRA x16, x10, x0
+            0x41f55893,    // SRAI x17, x10, 31
+            0x00c08933,    // ADD x18, x1, x12
+        ]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+        let mut vm = VmState::new(1024);
+        vm.memory.load_program(&program, 0).unwrap();
+        let prover = Prover::new(StarkProver::default());
+        let trace = prover.generate_execution_trace(&mut vm, 18).unwrap();
+        assert_eq!(
+            vm.registers[1..19],
+            [
+                0xffff_ffff,
+                1,
+                0,
+                0xffff_faaa,
+                0xffff_f800,
+                0x7f0,
+                0xffff_fe00,
+                0xf,
+                0xffff_ffff,
+                0x8000_1000,
+                0x1234_5028,
+                35,
+                0x8000,
+                0x1000_0200,
+                0xf000_0200,
+                0x8000_1000,
+                0xffff_ffff,
+                34,
+            ]
+        );
+
+        let constraint_system = ConstraintSystem::new();
+        assert!(constraint_system.check_trace(&trace).is_ok());
+        let verifier = Verifier::new(StarkVerifier::default());
+        assert!(verifier
+            .verify(
+                &prover.prove_execution(&trace).unwrap(),
+                &trace.public_values()
+            )
+            .unwrap());
+
+        let table = constraint_system.generate_trace(&trace).unwrap();
+        let l = table.layout.clone();
+        let check = |table: &zkp::TraceTable| {
+            let public_inputs = constraint_system.public_inputs(table);
+            constraint_system
+                .air
+                .check_trace(&table.rows, &public_inputs)
+        };
+
+        // Results off by one bit in a limb
+        for row in 0..18 {
+            let register = row + 1;
+            let mut bad = table.clone();
+            let limb = BabyBear::from(table.get(row, l.result[1]).as_u32() ^ 1);
+            bad.set(row, l.result[1], limb);
+            bad.set(row, l.rd_value[1], limb);
+            for later in row + 1..bad.len() {
+                bad.set(later, l.registers[register][1], limb);
+            }
+            let expected = match row {
+                3..=5 => "Lookup value not in table",
+                _ => "Transition constraint violated",
+            };
+            assert_eq!(check(&bad), Err(expected), "step {row}");
+        }
+
+        // An ADD without its flag, or passed off as a padding row, which
+        // cannot write a register
+        let mut bad = table.clone();
+        bad.set(17, l.is_add, BabyBear::ZERO);
+        assert_eq!(check(&bad), Err("Transition constraint violated"));
+        bad.set(17, l.is_padding, BabyBear::ONE);
+        bad.set(17, l.opcode, BabyBear::from(Opcode::Nop as u32));
+        assert_eq!(check(&bad), Err("Transition constraint violated"));
+    }
+
+    #[test]
+    fn test_division_constraints() {
+        let program: Vec<u8> = [
+            0xff900093u32, // ADDI x1, x0, -7
+            0x00200113,    // ADDI x2, x0, 2
+            0xffe00193,    // ADDI x3, x0, -2
+            0x800002b7,    // LUI x5, 0x80000
+            0xfff00313,    // ADDI x6, x0, -1
+            0x0220c3b3,    // DIV x7, x1, x2
+            0x0220e433,    // REM x8, x1, x2
+            0x0230c4b3,    // DIV x9, x1, x3
+            0x0230e533,    // REM x10, x1, x3
+            0x0220d5b3,    // DIVU x11, x1, x2
+            0x0220f633,    // REMU x12, x1, x2
+            0x0200c6b3,    // DIV x13, x1, x0
+            0x0200e733,    // REM x14, x1, x0
+            0x020157b3,    // DIVU x15, x2, x0
+            0x02017833,    // REMU x16, x2, x0
+            0x0262c8b3,    // DIV x17, x5, x6
+            0x0262e933,    // REM x18, x5, x6
+        ]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+        let mut vm = VmState::new(1024);
+        vm.memory.load_program(&program, 0).unwrap();
+        let prover = Prover::new(StarkProver::default());
+        let trace = prover.generate_execution_trace(&mut vm, 17).unwrap();
+        assert_eq!(
+            vm.registers[7..19],
+            [
+                0xffff_fffd,
+                0xffff_ffff,
+                3,
+                0xffff_ffff,
+                0x7fff_fffc,
+                1,
+                0xffff_ffff,
+                0xffff_fff9,
+                0xffff_ffff,
+                2,
+                0x8000_0000,
+                0
+            ]
+        );
+        // Quotient and remainder are recorded after the operands
+        assert_eq!(
+            trace.steps[6].intermediate_values,
+            [0xffff_fff9, 2, 0xffff_fffd, 0xffff_ffff]
+        );
+
+        let constraint_system = ConstraintSystem::new();
+        assert!(constraint_system.check_trace(&trace).is_ok());
+        let verifier = Verifier::new(StarkVerifier::default());
+        assert!(verifier
+            .verify(
+                &prover.prove_execution(&trace).unwrap(),
+                &trace.public_values()
+            )
+            .unwrap());
+
+        // Quotients and remainders that satisfy `q * divisor + r = dividend`
+        // but break the spec
+        for (step, quotient, remainder) in [
+            // A positive remainder of a negative dividend
+            (5, 0xffff_fffc, 1),
+            // A remainder larger than the divisor
+            (10, 0x7fff_fffb, 3),
+            // Dividing by zero
+            (11, 0, 0xffff_fff9),
+            // Overflow
+            (15, 0x7fff_ffff, 0xffff_ffff),
+        ] {
+            let mut bad = trace.clone();
+            let instruction = bad.steps[step].instruction;
+            let result = match instruction.opcode {
+                Opcode::Div | Opcode::Divu => quotient,
+                _ => remainder,
+            };
+            bad.steps[step].intermediate_values[2..].copy_from_slice(&[quotient, remainder]);
+            let rd = instruction.rd as usize;
+            bad.steps[step].registers_after[rd] = result;
+            for later in &mut bad.steps[step + 1..] {
+                later.registers_before[rd] = result;
+                later.registers_after[rd] = result;
+            }
+            assert_eq!(
+                constraint_system.check_trace(&bad),
+                Err("Transition constraint violated")
+            );
+        }
+    }
+
+    #[test]
+    fn test_lookup_constraints() {
+        use zkp::{BabyBear, Field, LookupTable, StarkProof};
+
+        let program: Vec<u8> = [
+            0x123450b7u32, // LUI x1, 0x12345
+            0x67808093,    // ADDI x1, x1, 0x678
+            0xf0f0f137,    // LUI x2, 0xf0f0f
+            0x0f010113,    // ADDI x2, x2, 0xf0
+            0x0020f1b3,    // AND x3, x1, x2
+            0x0020e233,    // OR x4, x1, x2
+            0x0020c2b3,    // XOR x5, x1, x2
+        ]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+        let mut vm = VmState::new(1024);
+        vm.memory.load_program(&program, 0).unwrap();
+        let prover = Prover::new(StarkProver::default());
+        let trace = prover.generate_execution_trace(&mut vm, 7).unwrap();
+        assert_eq!(vm.registers[3..6], [0x1030_5070, 0xf2f4_f6f8, 0xe2c4_a688]);
+
+        let constraint_system = ConstraintSystem::new();
+        assert!(constraint_system.check_trace(&trace).is_ok());
+        let proof = prover.prove_execution(&trace).unwrap();
+        let verifier = Verifier::new(StarkVerifier::default());
+        let public_values = trace.public_values();
+        assert!(verifier.verify(&proof, &public_values).unwrap());
+
+        // Each byte of the operands is looked up once per bitwise opcode
+        let stark: StarkProof = bincode::deserialize(&proof.witness).unwrap();
+        let xor = stark
+            .lookup_multiplicities
+            .iter()
+            .find(|m| m.table == LookupTable::Xor8 && m.entry == 0x78 + (0xf0 << 8))
+            .unwrap();
+        assert_eq!(xor.count, BabyBear::ONE);
+
+        // Results that are not the bitwise operation of the operands
+        for (row, register) in [(4, 3), (5, 4), (6, 5)] {
+            let mut table = constraint_system.generate_trace(&trace).unwrap();
+            let l = table.layout.clone();
+            let limb = table.get(row, l.result[0]) + BabyBear::ONE;
+            table.set(row, l.result[0], limb);
+            table.set(row, l.rd_value[0], limb);
+            for later in row + 1..table.len() {
+                table.set(later, l.registers[register][0], limb);
+            }
+            let public_inputs = constraint_system.public_inputs(&table);
+            assert_eq!(
+                constraint_system
+                    .air
+                    .check_trace(&table.rows, &public_inputs),
+                Err("Lookup value not in table")
+            );
+        }
+
+        // Multiplicities that do not match the trace
+        let forge = |edit: &dyn Fn(&mut StarkProof)| {
+            let mut forged = stark.clone();
+            edit(&mut forged);
+            let mut proof = proof.clone();
+            proof.witness = bincode::serialize(&forged).unwrap();
+            verifier.verify(&proof, &public_values).unwrap()
+        };
+        assert!(!forge(&|p| {
+            p.lookup_multiplicities
+                .retain(|m| m.table != LookupTable::And8)
+        }));
+        assert!(!forge(&|p| {
+            let m = p
+                .lookup_multiplicities
+                .iter_mut()
+                .find(|m| m.table == LookupTable::Or8);
+            m.unwrap().entry += 1;
+        }));
+        assert!(!forge(&|p| p.lookup_multiplicities[0].entry = 1 << 8));
+    }
+
+    #[test]
+    fn test_sub_word_memory_constraints() {
+        use zkp::BabyBear;
+
+        let program: Vec<u8> = [
+            0x10000093u32, // ADDI x1, x0, 0x100
+            0xf8100113,    // ADDI x2, x0, -0x7f
+            0x002082a3,    // SB x2, 5(x1)
+            0x00209523,    // SH x2, 10(x1)
+            0x002086a3,    // SB x2, 13(x1)
+            0x00508183,    // LB x3, 5(x1)
+            0x0050c203,    // LBU x4, 5(x1)
+            0x00a09283,    // LH x5, 10(x1)
+            0x00a0d303,    // LHU x6, 10(x1)
+            0x00e0d383,    // LHU x7, 14(x1)
+            0x00f08403,    // LB x8, 15(x1)
+            0x00c0a483,    // LW x9, 12(x1)
+        ]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+        let mut vm = VmState::new(1024);
+        vm.memory.load_program(&program, 0).unwrap();
+        vm.memory.write_word(0x10c, 0x1234_5678).unwrap();
+        let prover = Prover::new(StarkProver::default());
+        let trace = prover.generate_execution_trace(&mut vm, 12).unwrap();
+        assert_eq!(
+            vm.registers[3..10],
+            [
+                0xffff_ff81,
+                0x81,
+                0xffff_ff81,
+                0xff81,
+                0x1234,
+                0x12,
+                0x1234_8178
+            ]
+        );
+
+        let constraint_system = ConstraintSystem::new();
+        assert!(constraint_system.check_trace(&trace).is_ok());
+        let proof = prover.prove_execution(&trace).unwrap();
+        let verifier = Verifier::new(StarkVerifier::default());
+        assert!(verifier.verify(&proof, &trace.public_values()).unwrap());
+
+        // A sub-word load whose register disagrees with the bytes it read
+        for step in [5, 6, 7, 8, 9, 10] {
+            let mut bad = trace.clone();
+            let rd = bad.steps[step].instruction.rd as usize;
+            bad.steps[step].registers_after[rd] ^= 0x100;
+            for later in &mut bad.steps[step + 1..] {
+                later.registers_before[rd] ^= 0x100;
+                later.registers_after[rd] ^= 0x100;
+            }
+            assert_eq!(
+                constraint_system.check_trace(&bad),
+                Err("Transition constraint violated")
+            );
+        }
+
+        let table = constraint_system.generate_trace(&trace).unwrap();
+        let ls = table.layout.load_store.clone();
+        let check = |table: &zkp::TraceTable| {
+            let public_inputs = constraint_system.public_inputs(table);
+            constraint_system
+                .air
+                .check_trace(&table.rows, &public_inputs)
+        };
+        // A byte store selecting another byte than its address
+        let mut bad = table.clone();
+        bad.set(2, ls.byte_is[1], BabyBear::from(0));
+        bad.set(2, ls.byte_is[2], BabyBear::from(1));
+        assert_eq!(check(&bad), Err("Transition constraint violated"));
+        // A halfword store that also changes the byte next to it
+        let mut bad = table.clone();
+        bad.set(3, ls.value_after[0], BabyBear::from(7));
+        assert_eq!(check(&bad), Err("Transition constraint violated"));
+    }
+
+    #[test]
+    fn test_memory_constraints() {
+        use zkp::{BabyBear, MemoryBoundary, StarkProof};
+
+        let program: Vec<u8> = [
+            0x10000093u32, // ADDI x1, x0, 0x100
+            0xffb00113,    // ADDI x2, x0, -5
+            0x0020a223,    // SW x2, 4(x1)
+            0x0010a023,    // SW x1, 0(x1)
+            0x0040a183,    // LW x3, 4(x1)
+            0x0080a203,    // LW x4, 8(x1)
+        ]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+        let mut vm = VmState::new(1024);
+        vm.memory.load_program(&program, 0).unwrap();
+        vm.memory.write_word(0x108, 0x1234_5678).unwrap();
+        let prover = Prover::new(StarkProver::default());
+        let trace = prover.generate_execution_trace(&mut vm, 6).unwrap();
+        assert_eq!(vm.registers[3..5], [0xffff_fffb, 0x1234_5678]);
+        let timestamps: Vec<u32> = trace
+            .steps
+            .iter()
+            .flat_map(|step| &step.memory_accesses)
+            .map(|access| access.timestamp)
+            .collect();
+        assert_eq!(timestamps, [1, 2, 3, 4]);
+
+        let constraint_system = ConstraintSystem::new();
+        assert!(constraint_system.check_trace(&trace).is_ok());
+        let proof = prover.prove_execution(&trace).unwrap();
+        let verifier = Verifier::new(StarkVerifier::default());
+        let public_values = trace.public_values();
+        assert!(verifier.verify(&proof, &public_values).unwrap());
+        let stark: StarkProof = bincode::deserialize(&proof.witness).unwrap();
+        let word = |addr, initial_value, final_value| MemoryBoundary {
+            addr,
+            initial_value,
+            final_value,
+        };
+        assert_eq!(
+            stark.memory_boundary,
+            [
+                word(0x100, 0, 0x100),
+                word(0x104, 0, 0xffff_fffb),
+                word(0x108, 0x1234_5678, 0x1234_5678),
+            ]
+        );
+
+        // A verifier that expects other memory before execution
+        let mut other = public_values.clone();
+        other.initial_memory.insert(0x108, 0x1234_5679);
+        assert!(!verifier.verify(&proof, &other).unwrap());
+
+        // Loads that return something else, with the register they load
+        // agreeing
+        let load = |step: usize, value: u32| {
+            let mut bad = trace.clone();
+            let access = &mut bad.steps[step].memory_accesses[0];
+            access.value_before = value;
+            access.value_after = value;
+            let rd = bad.steps[step].instruction.rd as usize;
+            bad.steps[step].registers_after[rd] = value;
+            for later in &mut bad.steps[step + 1..] {
+                later.registers_before[rd] = value;
+                later.registers_after[rd] = value;
+            }
+            constraint_system.check_trace(&bad)
+        };
+        // Not what was stored
+        assert_eq!(load(4, 7), Err("Transition constraint violated"));
+        // Not what memory held before execution
+        assert_eq!(load(5, 7), Err("Memory boundary does not match the trace"));
+
+        // A load whose register disagrees with the word it accessed
+        for step in [4, 5] {
+            let mut bad = trace.clone();
+            let rd = bad.steps[step].instruction.rd as usize;
+            bad.steps[step].registers_after[rd] ^= 1;
+            for later in &mut bad.steps[step + 1..] {
+                later.registers_before[rd] ^= 1;
+                later.registers_after[rd] ^= 1;
+            }
+            assert_eq!(
+                constraint_system.check_trace(&bad),
+                Err("Transition constraint violated")
+            );
+        }
+
+        // A write no load or store makes, which a later load then reads:
+        // the ADDI before the stores overwrites the word LW x4 loads
+        let mut bad = trace.clone();
+        let write = |timestamp, value_before, value_after| zkp::MemoryAccess {
+            addr: 0x108,
+            value_before,
+            value_after,
+            is_write: true,
+            timestamp,
+        };
+        bad.steps[1].memory_accesses.push(write(1, 0x1234_5678, 99));
+        for step in &mut bad.steps[2..] {
+            for access in &mut step.memory_accesses {
+                access.timestamp += 1;
+            }
+        }
+        let access = &mut bad.steps[5].memory_accesses[0];
+        access.value_before = 99;
+        access.value_after = 99;
+        bad.steps[5].registers_after[4] = 99;
+        bad.final_state.memory.write_word(0x108, 99).unwrap();
+        assert_eq!(
+            constraint_system.check_trace(&bad),
+            Err("Lookup bus not balanced")
+        );
+        let proof = prover.prove_execution(&bad).unwrap();
+        assert!(!verifier.verify(&proof, &public_values).unwrap());
+
+        // A load or store at another address than its access
+        let table = constraint_system.generate_trace(&trace).unwrap();
+        let l = table.layout.clone();
+        let check = |table: &zkp::TraceTable| {
+            let public_inputs = constraint_system.public_inputs(table);
+            constraint_system
+                .air
+                .check_trace(&table.rows, &public_inputs)
+        };
+        let mut bad = table.clone();
+        bad.set(4, l.load_store.word_low, BabyBear::from(0x108 / 4));
+        assert_eq!(check(&bad), Err("Transition constraint violated"));
+
+        // A log in execution order that the sorted log does not permute
+        let mut table = constraint_system.generate_trace(&trace).unwrap();
+        let m = &table.layout.memory;
+        for column in [m.value_before[0], m.value_after[0]] {
+            table.set(2, column, BabyBear::from(0xfc));
+        }
+        let public_inputs = constraint_system.public_inputs(&table);
+        assert_eq!(
+            constraint_system
+                .air
+                .check_trace(&table.rows, &public_inputs),
+            Err("Lookup bus not balanced")
+        );
+
+        // A proof claiming other memory before or after execution
+        let forge = |edit: &dyn Fn(&mut StarkProof)| {
+            let mut forged = stark.clone();
+            edit(&mut forged);
+            let mut proof = proof.clone();
+            proof.witness = bincode::serialize(&forged).unwrap();
+            verifier.verify(&proof, &public_values).unwrap()
+        };
+        assert!(!forge(&|p| p.memory_boundary[2].initial_value = 7));
+        assert!(!forge(&|p| p.memory_boundary[1].final_value = 0));
+        assert!(!forge(&|p| {
+            p.memory_boundary.remove(0);
+        }));
+        assert!(!forge(&|p| {
+            let extra = word(0x200, 0, 0);
+            p.memory_boundary.push(extra);
+        }));
+        assert!(!forge(&|p| p.memory_boundary.swap(0, 1)));
+    }
+
+    #[test]
+    fn test_syscall_constraints() {
+        use zkp::{BabyBear, PublicValues};
+        use zkvm_guest::syscall::nr;
+
+        let program: Vec<u8> = [
+            0x00100893u32, // ADDI x17, x0, READ_INPUT
+            0x10100513,    // ADDI x10, x0, 0x101
+            0x00600593,    // ADDI x11, x0, 6
+            0x00000073,    // ECALL
+            0x00200893,    // ADDI x17, x0, WRITE_JOURNAL
+            0x10200513,    // ADDI x10, x0, 0x102
+            0x00300593,    // ADDI x11, x0, 3
+            0x00000073,    // ECALL
+            0x00500893,    // ADDI x17, x0, BRK
+            0x20000513,    // ADDI x10, x0, 0x200
+            0x00000073,    // ECALL
+            0x00400893,    // ADDI x17, x0, WRITE
+            0x00100513,    // ADDI x10, x0, 1
+            0x10000593,    // ADDI x11, x0, 0x100
+            0x00200613,    // ADDI x12, x0, 2
+            0x00000073,    // ECALL
+            0x00000893,    // ADDI x17, x0, HALT
+            0x00700513,    // ADDI x10, x0, 7
+            0x00000073,    // ECALL
+        ]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+        let mut vm = VmState::new(1024);
+        vm.memory.load_program(&program, 0).unwrap();
+        vm.input = vec![1, 2, 3, 4, 5];
+        let prover = Prover::new(StarkProver::default());
+        let trace = prover.generate_execution_trace(&mut vm, 100).unwrap();
+        assert_eq!(trace.exit_status, ExitStatus::Halted(7));
+        assert_eq!(vm.journal, [2, 3, 4]);
+        assert_eq!(vm.stdout, [0, 1]);
+        assert_eq!(vm.brk, 0x200);
+
+        let constraint_system = ConstraintSystem::new();
+        assert!(constraint_system.check_trace(&trace).is_ok());
+        let proof = prover.prove_execution(&trace).unwrap();
+        let verifier = Verifier::new(StarkVerifier::default());
+        let public_values = trace.public_values();
+        assert_eq!(public_values.input, [1, 2, 3, 4, 5]);
+        assert_eq!(public_values.journal, [2, 3, 4]);
+        assert!(verifier.verify(&proof, &public_values).unwrap());
+
+        // A verifier that expects another journal, input, exit code,
+        // program break or syscall price
+        let verify = |edit: &dyn Fn(&mut PublicValues)| {
+            let mut other = public_values.clone();
+            edit(&mut other);
+            verifier.verify(&proof, &other).unwrap()
+        };
+        assert!(!verify(&|p| p.journal[1] = 5));
+        assert!(!verify(&|p| p.journal.push(5)));
+        assert!(!verify(&|p| p.input[2] = 5));
+        assert!(!verify(&|p| p.exit_status = ExitStatus::Halted(8)));
+        assert!(!verify(&|p| p.brk = 0x10));
+        assert!(!verify(&|p| {
+            p.syscall_prices.insert(nr::WRITE, 3);
+        }));
+
+        let table = constraint_system.generate_trace(&trace).unwrap();
+        let check = |table: &zkp::TraceTable| {
+            let public_inputs = constraint_system.public_inputs(table);
+            constraint_system
+                .air
+                .check_trace(&table.rows, &public_inputs)
+        };
+        let (m, sys) = (&table.layout.memory, &table.layout.syscall);
+        // READ_INPUT also writing the byte before a0
+        let mut bad = table.clone();
+        bad.set(0, m.value_after[0], BabyBear::from(9));
+        assert_eq!(check(&bad), Err("Transition constraint violated"));
+        // READ_INPUT copying fewer bytes than it returns
+        let mut bad = table.clone();
+        bad.set(3, sys.length, BabyBear::from(4));
+        assert_eq!(check(&bad), Err("Transition constraint violated"));
+        // The guest going on after it halted
+        let mut bad = table.clone();
+        bad.set(5, sys.exit_status, BabyBear::from(1));
+        assert_eq!(check(&bad), Err("Transition constraint violated"));
+    }
+
+    #[test]
+    fn test_trace_table() {
+        use zkp::{BabyBear, Field, MemoryBoundary};
+
+        let program: Vec<u8> = [
+            0x10000093u32, // ADDI x1, x0, 0x100
+            0xffb00113,    // ADDI x2, x0, -5
+            0x0020a223,    // SW x2, 4(x1)
+            0x0040a183,    // LW x3, 4(x1)
+            0x00118233,    // ADD x4, x3, x1
+        ]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+        let mut vm = VmState::new(1024);
+        vm.memory.load_program(&program, 0).unwrap();
+        let prover = Prover::new(StarkProver::default());
+        let trace = prover.generate_execution_trace(&mut vm, 5).unwrap();
+
+        let constraint_system = ConstraintSystem::new();
+        let table = constraint_system.generate_trace(&trace).unwrap();
+        let l = &table.layout;
+        assert_eq!(table.len(), 8);
+        assert_eq!(l.column("mem_timestamp"), Some(l.memory.timestamp));
+
+        // Decoded fields, operands and intermediate values of each step
+        assert_eq!(table.get(1, l.opcode), BabyBear::from(Opcode::Addi as u32));
+        assert_eq!(table.get(1, l.imm), -BabyBear::from(5));
+        let rd: Vec<BabyBear> = l
+            .rd_value
+            .iter()
+            .map(|&column| table.get(1, column))
+            .collect();
+        assert_eq!(rd, [0xfb, 0xff, 0xff, 0xff].map(BabyBear::from));
+        assert_eq!(table.get(4, l.rs1), BabyBear::from(3));
+        assert_eq!(table.get(4, l.rs1_is[3]), BabyBear::ONE);
+        let lw: Vec<BabyBear> = l.intermediate[..4]
+            .iter()
+            .map(|&column| table.get(3, column))
+            .collect();
+        assert_eq!(lw, [0x100, 4, 0x104, 0xffff_fffb].map(BabyBear::from));
+
+        // The memory log holds the store, then the load
+        let m = &l.memory;
+        assert_eq!(table.get(0, m.is_write), BabyBear::ONE);
+        assert_eq!(table.get(0, m.word), BabyBear::from(0x104 / 4));
+        assert_eq!(table.get(0, m.timestamp), BabyBear::from(1));
+        assert_eq!(table.get(1, m.is_write), BabyBear::ZERO);
+        let loaded: Vec<BabyBear> = m
+            .value_before
+            .iter()
+            .map(|&column| table.get(1, column))
+            .collect();
+        assert_eq!(loaded, [0xfb, 0xff, 0xff, 0xff].map(BabyBear::from));
+        assert_eq!(table.get(1, m.timestamp), BabyBear::from(2));
+        assert_eq!(table.get(2, m.used), BabyBear::ZERO);
+        assert_eq!(table.get(2, m.timestamp), BabyBear::from(3));
+
+        // Both accesses are to the same word, so the sorted log keeps them
+        // in order
+        let s = &l.sorted_memory;
+        assert_eq!(table.get(0, s.first), BabyBear::ONE);
+        assert_eq!(table.get(0, s.same_word), BabyBear::ONE);
+        assert_eq!(table.get(1, s.first), BabyBear::ZERO);
+        assert_eq!(table.get(1, s.timestamp), BabyBear::from(2));
+        assert_eq!(table.get(2, s.used), BabyBear::ZERO);
+        assert_eq!(
+            constraint_system.memory_boundary(&table),
+            [MemoryBoundary {
+                addr: 0x104,
+                initial_value: 0,
+                final_value: 0xffff_fffb
+            }]
+        );
+
+        // Padding rows hold the final state and execute nothing
+        assert_eq!(table.rows[5], table.rows[7]);
+        assert_eq!(table.get(5, l.pc), BabyBear::from(20));
+        assert_eq!(table.get(5, l.size), BabyBear::ZERO);
+        assert_eq!(table.get(5, l.opcode), BabyBear::from(Opcode::Nop as u32));
+        assert!(constraint_system.check_trace(&trace).is_ok());
+
+        // Each step costs one gas, and padding nothing
+        assert_eq!(table.get(1, l.gas), BabyBear::ONE);
+        assert_eq!(table.get(1, l.gas_price), BabyBear::ONE);
+        assert_eq!(table.get(7, l.gas), BabyBear::from(5));
+        assert_eq!(table.get(7, l.gas_price), BabyBear::ZERO);
+        assert_eq!(
+            constraint_system.public_inputs(&table)[2],
+            BabyBear::from(5)
+        );
+    }
+
     #[test]
     fn test_merkle_tree() {
         let data: Vec<&[u8]> = vec![b"leaf1", b"leaf2"];
@@ -83,6 +2080,26 @@ mod tests {
         assert_ne!(tree.root(), &[0u8; 32]);
     }
 
+    #[test]
+    fn test_merkle_update_leaf() {
+        // Updating a leaf in place gives the root of the rebuilt tree, also
+        // when the last leaf is padded with a copy
+        for len in [4, 5, 7] {
+            let leaves: Vec<Vec<u8>> = (0..len).map(|i: u8| vec![i]).collect();
+            let mut tree = MerkleTree::new(leaves.iter().map(Vec::as_slice).collect());
+            let mut updated = leaves.clone();
+            for index in [0, len as usize - 1] {
+                updated[index] = b"new".to_vec();
+                assert!(tree.update_leaf(index, b"new"));
+                let rebuilt = MerkleTree::new(updated.iter().map(Vec::as_slice).collect());
+                assert_eq!(tree.root(), rebuilt.root());
+                let (proof, expected) = (tree.generate_proof(index), rebuilt.generate_proof(index));
+                assert_eq!(proof.unwrap().proof, expected.unwrap().proof);
+            }
+            assert!(!tree.update_leaf(len as usize + 1, b"new"));
+        }
+    }
+
     #[test]
     fn test_hex_conversion() {
         let bytes = vec![0x12, 0x34, 0xab, 0xcd];
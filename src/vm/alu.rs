@@ -0,0 +1,191 @@
+use super::Opcode;
+
+/// Evaluates a register-register or register-immediate ALU operation. `b` is
+/// `rs2` or the immediate, depending on the opcode. Returns `None` for
+/// opcodes that are not pure ALU operations.
+pub fn alu(opcode: Opcode, a: u32, b: u32) -> Option<u32> {
+    use Opcode::*;
+
+    let shamt = b & 0x1f;
+    let result = match opcode {
+        Add | Addi => a.wrapping_add(b),
+        Sub => a.wrapping_sub(b),
+        Sll | Slli => a << shamt,
+        Slt | Slti => ((a as i32) < (b as i32)) as u32,
+        Sltu | Sltiu => (a < b) as u32,
+        Xor | Xori => a ^ b,
+        Srl | Srli => a >> shamt,
+        Sra | Srai => ((a as i32) >> shamt) as u32,
+        Or | Ori => a | b,
+        And | Andi => a & b,
+
+        Mul => a.wrapping_mul(b),
+        Mulh => ((a as i32 as i64 * b as i32 as i64) >> 32) as u32,
+        Mulhsu => ((a as i32 as i64 * b as i64) >> 32) as u32,
+        Mulhu => ((a as u64 * b as u64) >> 32) as u32,
+        Div => {
+            if b == 0 {
+                u32::MAX
+            } else {
+                (a as i32).wrapping_div(b as i32) as u32
+            }
+        }
+        Divu => a.checked_div(b).unwrap_or(u32::MAX),
+        Rem => {
+            if b == 0 {
+                a
+            } else {
+                (a as i32).wrapping_rem(b as i32) as u32
+            }
+        }
+        Remu => a.checked_rem(b).unwrap_or(a),
+
+        Sh1add => (a << 1).wrapping_add(b),
+        Sh2add => (a << 2).wrapping_add(b),
+        Sh3add => (a << 3).wrapping_add(b),
+
+        Andn => a & !b,
+        Orn => a | !b,
+        Xnor => !(a ^ b),
+        Clz => a.leading_zeros(),
+        Ctz => a.trailing_zeros(),
+        Cpop => a.count_ones(),
+        Max => (a as i32).max(b as i32) as u32,
+        Maxu => a.max(b),
+        Min => (a as i32).min(b as i32) as u32,
+        Minu => a.min(b),
+        SextB => a as u8 as i8 as i32 as u32,
+        SextH => a as u16 as i16 as i32 as u32,
+        ZextH => a & 0xffff,
+        Rol => a.rotate_left(shamt),
+        Ror | Rori => a.rotate_right(shamt),
+        OrcB => u32::from_le_bytes(a.to_le_bytes().map(|byte| if byte == 0 { 0 } else { 0xff })),
+        Rev8 => a.swap_bytes(),
+
+        Clmul => (0..32)
+            .filter(|i| (b >> i) & 1 == 1)
+            .fold(0, |acc, i| acc ^ (a << i)),
+        Clmulh => (1..32)
+            .filter(|i| (b >> i) & 1 == 1)
+            .fold(0, |acc, i| acc ^ (a >> (32 - i))),
+        Clmulr => (0..32)
+            .filter(|i| (b >> i) & 1 == 1)
+            .fold(0, |acc, i| acc ^ (a >> (31 - i))),
+
+        Bclr | Bclri => a & !(1 << shamt),
+        Bext | Bexti => (a >> shamt) & 1,
+        Binv | Binvi => a ^ (1 << shamt),
+        Bset | Bseti => a | (1 << shamt),
+
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// RV64 counterpart of [`alu`]. The `*W` opcodes operate on the low 32 bits
+/// and sign-extend the result.
+pub fn alu64(opcode: Opcode, a: u64, b: u64) -> Option<u64> {
+    use Opcode::*;
+
+    if opcode.is_word_op() {
+        let base = match opcode {
+            Addw | Addiw => Add,
+            Subw => Sub,
+            Sllw | Slliw => Sll,
+            Srlw | Srliw => Srl,
+            Sraw | Sraiw => Sra,
+            Mulw => Mul,
+            Divw => Div,
+            Divuw => Divu,
+            Remw => Rem,
+            _ => Remu,
+        };
+        return alu(base, a as u32, b as u32).map(|result| result as i32 as u64);
+    }
+
+    let shamt = (b & 0x3f) as u32;
+    let result = match opcode {
+        Add | Addi => a.wrapping_add(b),
+        Sub => a.wrapping_sub(b),
+        Sll | Slli => a << shamt,
+        Slt | Slti => ((a as i64) < (b as i64)) as u64,
+        Sltu | Sltiu => (a < b) as u64,
+        Xor | Xori => a ^ b,
+        Srl | Srli => a >> shamt,
+        Sra | Srai => ((a as i64) >> shamt) as u64,
+        Or | Ori => a | b,
+        And | Andi => a & b,
+
+        Mul => a.wrapping_mul(b),
+        Mulh => ((a as i64 as i128 * b as i64 as i128) >> 64) as u64,
+        Mulhsu => ((a as i64 as i128 * b as i128) >> 64) as u64,
+        Mulhu => ((a as u128 * b as u128) >> 64) as u64,
+        Div => {
+            if b == 0 {
+                u64::MAX
+            } else {
+                (a as i64).wrapping_div(b as i64) as u64
+            }
+        }
+        Divu => a.checked_div(b).unwrap_or(u64::MAX),
+        Rem => {
+            if b == 0 {
+                a
+            } else {
+                (a as i64).wrapping_rem(b as i64) as u64
+            }
+        }
+        Remu => a.checked_rem(b).unwrap_or(a),
+
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// Evaluates the condition of a conditional branch.
+pub fn branch_taken(opcode: Opcode, a: u32, b: u32) -> bool {
+    use Opcode::*;
+
+    match opcode {
+        Branch => a == b,
+        Bne => a != b,
+        Blt => (a as i32) < (b as i32),
+        Bge => (a as i32) >= (b as i32),
+        Bltu => a < b,
+        Bgeu => a >= b,
+        _ => false,
+    }
+}
+
+/// RV64 counterpart of [`branch_taken`].
+pub fn branch_taken64(opcode: Opcode, a: u64, b: u64) -> bool {
+    use Opcode::*;
+
+    match opcode {
+        Branch => a == b,
+        Bne => a != b,
+        Blt => (a as i64) < (b as i64),
+        Bge => (a as i64) >= (b as i64),
+        Bltu => a < b,
+        Bgeu => a >= b,
+        _ => false,
+    }
+}
+
+/// Combines the old memory value with `b` for an AMO instruction.
+pub fn amo(opcode: Opcode, old: u32, b: u32) -> u32 {
+    use Opcode::*;
+
+    match opcode {
+        AmoSwapW => b,
+        AmoAddW => old.wrapping_add(b),
+        AmoXorW => old ^ b,
+        AmoAndW => old & b,
+        AmoOrW => old | b,
+        AmoMinW => (old as i32).min(b as i32) as u32,
+        AmoMaxW => (old as i32).max(b as i32) as u32,
+        AmoMinuW => old.min(b),
+        AmoMaxuW => old.max(b),
+        _ => old,
+    }
+}
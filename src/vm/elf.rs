@@ -0,0 +1,203 @@
+use super::Memory;
+
+const PT_LOAD: u32 = 1;
+const EM_RISCV: u16 = 0xf3;
+const ET_EXEC: u16 = 2;
+const SHT_SYMTAB: u32 = 2;
+
+/// The symbol linkers define for `gp`-relative addressing.
+const GLOBAL_POINTER_SYMBOL: &[u8] = b"__global_pointer$";
+
+/// A program image ready to be loaded into memory.
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub entry: u32,
+    /// Loadable segments as `(address, bytes)`. Zero-initialised tails such
+    /// as `.bss` are not stored; VM memory starts zeroed.
+    pub segments: Vec<(u32, Vec<u8>)>,
+    /// First address past the image, zero-initialised tails included.
+    pub end: u32,
+    /// Value of `__global_pointer$`, if the program defines it.
+    pub global_pointer: Option<u32>,
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, &'static str> {
+    bytes
+        .get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or("Truncated ELF file")
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, &'static str> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or("Truncated ELF file")
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, &'static str> {
+    Ok(read_u32(bytes, offset)? as u64 | (read_u32(bytes, offset + 4)? as u64) << 32)
+}
+
+fn to_u32(value: u64) -> Result<u32, &'static str> {
+    u32::try_from(value).map_err(|_| "ELF address out of range")
+}
+
+/// Looks `name` up in the symbol table, if the file has one.
+fn find_symbol(elf: &[u8], is_64: bool, name: &[u8]) -> Result<Option<u32>, &'static str> {
+    let (shoff, shentsize, shnum) = if is_64 {
+        (
+            read_u64(elf, 40)? as usize,
+            read_u16(elf, 58)? as usize,
+            read_u16(elf, 60)? as usize,
+        )
+    } else {
+        (
+            read_u32(elf, 32)? as usize,
+            read_u16(elf, 46)? as usize,
+            read_u16(elf, 48)? as usize,
+        )
+    };
+    // (offset, size, link, entsize) of a section header
+    let section = |index: usize| -> Result<(usize, usize, usize, usize), &'static str> {
+        let header = shoff + index * shentsize;
+        Ok(if is_64 {
+            (
+                read_u64(elf, header + 24)? as usize,
+                read_u64(elf, header + 32)? as usize,
+                read_u32(elf, header + 40)? as usize,
+                read_u64(elf, header + 56)? as usize,
+            )
+        } else {
+            (
+                read_u32(elf, header + 16)? as usize,
+                read_u32(elf, header + 20)? as usize,
+                read_u32(elf, header + 24)? as usize,
+                read_u32(elf, header + 36)? as usize,
+            )
+        })
+    };
+
+    for index in 0..shnum {
+        if read_u32(elf, shoff + index * shentsize + 4)? != SHT_SYMTAB {
+            continue;
+        }
+        let (offset, size, link, entsize) = section(index)?;
+        let (strtab, strtab_size, _, _) = section(link)?;
+        let strings = elf
+            .get(strtab..strtab + strtab_size)
+            .ok_or("Truncated ELF file")?;
+        for symbol in (offset..offset + size).step_by(entsize.max(1)) {
+            let name_offset = read_u32(elf, symbol)? as usize;
+            let symbol_name = strings
+                .get(name_offset..)
+                .and_then(|rest| rest.split(|&byte| byte == 0).next());
+            if symbol_name == Some(name) {
+                let value = if is_64 {
+                    to_u32(read_u64(elf, symbol + 8)?)?
+                } else {
+                    read_u32(elf, symbol + 4)?
+                };
+                return Ok(Some(value));
+            }
+        }
+    }
+    Ok(None)
+}
+
+impl Program {
+    /// A flat binary image loaded at `base`, with execution starting there.
+    pub fn from_image(image: &[u8], base: u32) -> Self {
+        Self {
+            entry: base,
+            segments: vec![(base, image.to_vec())],
+            end: base.saturating_add(image.len() as u32),
+            global_pointer: None,
+        }
+    }
+
+    /// Parses a statically linked little-endian RISC-V executable (ELF32 or
+    /// ELF64).
+    pub fn from_elf(elf: &[u8]) -> Result<Self, &'static str> {
+        if elf.get(0..4) != Some(b"\x7fELF") {
+            return Err("Not an ELF file");
+        }
+        let is_64 = match elf[4] {
+            1 => false,
+            2 => true,
+            _ => return Err("Unsupported ELF class"),
+        };
+        if elf.get(5) != Some(&1) {
+            return Err("ELF file is not little-endian");
+        }
+        if read_u16(elf, 16)? != ET_EXEC {
+            return Err("ELF file is not an executable");
+        }
+        if read_u16(elf, 18)? != EM_RISCV {
+            return Err("ELF file is not a RISC-V executable");
+        }
+
+        let (entry, phoff, phentsize, phnum) = if is_64 {
+            (
+                to_u32(read_u64(elf, 24)?)?,
+                read_u64(elf, 32)? as usize,
+                read_u16(elf, 54)? as usize,
+                read_u16(elf, 56)? as usize,
+            )
+        } else {
+            (
+                read_u32(elf, 24)?,
+                read_u32(elf, 28)? as usize,
+                read_u16(elf, 42)? as usize,
+                read_u16(elf, 44)? as usize,
+            )
+        };
+
+        let mut segments = Vec::new();
+        let mut end = 0;
+        for i in 0..phnum {
+            let header = phoff + i * phentsize;
+            if read_u32(elf, header)? != PT_LOAD {
+                continue;
+            }
+            let (offset, vaddr, filesz, memsz) = if is_64 {
+                (
+                    read_u64(elf, header + 8)?,
+                    read_u64(elf, header + 16)?,
+                    read_u64(elf, header + 32)?,
+                    read_u64(elf, header + 40)?,
+                )
+            } else {
+                (
+                    read_u32(elf, header + 4)? as u64,
+                    read_u32(elf, header + 8)? as u64,
+                    read_u32(elf, header + 16)? as u64,
+                    read_u32(elf, header + 20)? as u64,
+                )
+            };
+            if filesz > memsz {
+                return Err("Malformed ELF segment");
+            }
+            let segment_end = to_u32(vaddr.checked_add(memsz).ok_or("ELF address out of range")?)?;
+            end = end.max(segment_end);
+            let data = elf
+                .get(offset as usize..(offset + filesz) as usize)
+                .ok_or("Truncated ELF file")?;
+            segments.push((to_u32(vaddr)?, data.to_vec()));
+        }
+
+        Ok(Self {
+            entry,
+            segments,
+            end,
+            global_pointer: find_symbol(elf, is_64, GLOBAL_POINTER_SYMBOL)?,
+        })
+    }
+
+    pub fn load(&self, memory: &mut Memory) -> Result<(), &'static str> {
+        for (addr, data) in &self.segments {
+            memory.load_program(data, *addr)?;
+        }
+        Ok(())
+    }
+}
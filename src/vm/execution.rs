@@ -1,18 +1,104 @@
-use super::{Instruction, Memory, Opcode};
+use super::replay::Replay;
+use super::syscall::{GuestPanic, HostHooks};
+use super::{
+    alu, alu64, amo, branch_taken, branch_taken64, Extension, GasMeter, Instruction, IsaProfile,
+    KvStore, Memory, MemoryMap, MemoryStats, Opcode, Program, ReplayLog, VirtualFs, PAGE_SIZE,
+};
+use crate::crypto::HashValue;
+use serde::{Deserialize, Serialize};
+
+/// Stack and global pointer registers.
+const REG_SP: usize = 2;
+const REG_GP: usize = 3;
+
+/// Memory usage observed so far, summarised by [`VmState::memory_stats`].
+#[derive(Debug, Clone, Default)]
+struct MemoryUsage {
+    peak_brk: u32,
+    lowest_sp: Option<u32>,
+    /// Bitmap of touched pages.
+    pages: Vec<u64>,
+}
+
+impl MemoryUsage {
+    fn touch(&mut self, addr: u32) {
+        let page = (addr / PAGE_SIZE) as usize;
+        if page / 64 >= self.pages.len() {
+            self.pages.resize(page / 64 + 1, 0);
+        }
+        self.pages[page / 64] |= 1 << (page % 64);
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct VmState {
-    pub registers: [u32; 32],
+    /// Integer registers. On RV32 profiles only the low 32 bits are used.
+    pub registers: [u64; 32],
+    /// Floating-point registers. Single-precision values are NaN-boxed.
+    pub fregisters: [u64; 32],
+    /// Floating-point control and status register (`frm` and `fflags`).
+    pub fcsr: u32,
     pub pc: u32,
     pub memory: Memory,
+    pub memory_map: MemoryMap,
+    /// Initial program break: the end of the loaded program.
+    pub heap_start: u32,
+    /// Current program break, moved by the `BRK` syscall.
+    pub brk: u32,
+    pub gas: GasMeter,
+    pub profile: IsaProfile,
+    /// Address reserved by the last LR.W, if any.
+    pub reservation: Option<u32>,
+    /// Set once the VM has stopped for good; `None` while it can keep running.
+    pub exit_status: Option<ExitStatus>,
+    /// Private input the guest reads through the `READ_INPUT` syscall.
+    pub input: Vec<u8>,
+    /// How many bytes of `input` the guest has consumed.
+    pub input_cursor: usize,
+    /// Files the guest can open; their contents are private input too.
+    pub files: VirtualFs,
+    /// The host's key-value store, and the root of it the VM has verified.
+    pub kv_store: Option<KvStore>,
+    pub state_root: Option<HashValue>,
+    /// Program arguments, as placed on the initial stack. They are public.
+    pub argv: Vec<String>,
+    /// Public output committed by the guest.
+    pub journal: Vec<u8>,
+    /// Output the guest wrote to its standard output and error.
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// Message and location passed to the `PANIC` syscall.
+    pub panic: Option<GuestPanic>,
+    /// Instructions executed so far.
+    pub cycles: u64,
+    /// Every syscall executed so far and its effects.
+    pub syscall_log: ReplayLog,
+    usage: MemoryUsage,
+    pub(super) hooks: HostHooks,
+    pub(super) replay: Option<Replay>,
+}
+
+/// Why a call to [`VmState::run`] returned.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExitStatus {
+    /// The step budget was exhausted; the VM can be resumed.
+    #[default]
+    StepLimit,
+    /// The next instruction could not be paid for. The VM state is left as it
+    /// was before that instruction.
+    OutOfGas,
+    /// The guest called `HALT` with this exit code.
+    Halted(u32),
+    /// The guest panicked; see `VmState::panic`.
+    Panicked,
 }
 
 #[derive(Debug, Clone)]
 pub struct ExecutionStep {
     pub pc_before: u32,
     pub pc_after: u32,
-    pub registers_before: [u32; 32],
-    pub registers_after: [u32; 32],
+    pub registers_before: [u64; 32],
+    pub registers_after: [u64; 32],
     pub instruction: Instruction,
     pub memory_reads: Vec<(u32, u32)>,
     pub memory_writes: Vec<(u32, u32, u32)>,
@@ -23,66 +109,120 @@ impl VmState {
     pub fn new(memory_size: usize) -> Self {
         Self {
             registers: [0; 32],
+            fregisters: [0; 32],
+            fcsr: 0,
             pc: 0,
             memory: Memory::new(memory_size),
+            memory_map: MemoryMap::with_size(memory_size),
+            heap_start: 0,
+            brk: 0,
+            gas: GasMeter::default(),
+            profile: IsaProfile::default(),
+            reservation: None,
+            exit_status: None,
+            input: Vec::new(),
+            input_cursor: 0,
+            files: VirtualFs::default(),
+            kv_store: None,
+            state_root: None,
+            argv: Vec::new(),
+            journal: Vec::new(),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            panic: None,
+            cycles: 0,
+            syscall_log: ReplayLog::default(),
+            usage: MemoryUsage::default(),
+            hooks: HostHooks::default(),
+            replay: None,
         }
     }
 
-    pub fn execute_instruction(&mut self, instruction: Instruction) -> Result<(), &'static str> {
-        match instruction.opcode {
-            Opcode::Add => {
-                let val1 = self.registers[instruction.rs1 as usize];
-                let val2 = self.registers[instruction.rs2 as usize];
-                self.registers[instruction.rd as usize] = val1.wrapping_add(val2);
-            }
-            Opcode::Sub => {
-                let val1 = self.registers[instruction.rs1 as usize];
-                let val2 = self.registers[instruction.rs2 as usize];
-                self.registers[instruction.rd as usize] = val1.wrapping_sub(val2);
-            }
-            Opcode::Mul => {
-                let val1 = self.registers[instruction.rs1 as usize];
-                let val2 = self.registers[instruction.rs2 as usize];
-                self.registers[instruction.rd as usize] = val1.wrapping_mul(val2);
-            }
-            Opcode::Div => {
-                let val1 = self.registers[instruction.rs1 as usize];
-                let val2 = self.registers[instruction.rs2 as usize];
-                if val2 == 0 {
-                    return Err("Division by zero");
-                }
-                self.registers[instruction.rd as usize] = val1 / val2;
-            }
-            Opcode::Load => {
-                let addr =
-                    self.registers[instruction.rs1 as usize].wrapping_add(instruction.imm as u32);
-                let value = self.memory.read_word(addr)?;
-                self.registers[instruction.rd as usize] = value;
-            }
-            Opcode::Store => {
-                let addr =
-                    self.registers[instruction.rs1 as usize].wrapping_add(instruction.imm as u32);
-                let value = self.registers[instruction.rs2 as usize];
-                self.memory.write_word(addr, value)?;
-            }
-            Opcode::Branch => {
-                let val1 = self.registers[instruction.rs1 as usize];
-                let val2 = self.registers[instruction.rs2 as usize];
-                if val1 == val2 {
-                    self.pc = self.pc.wrapping_add(instruction.imm as u32);
-                    return Ok(());
-                }
-            }
-            Opcode::Jump => {
-                self.pc = self.pc.wrapping_add(instruction.imm as u32);
-                return Ok(());
-            }
-            Opcode::Nop => {}
+    pub fn with_profile(mut self, profile: IsaProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn with_gas_meter(mut self, gas: GasMeter) -> Self {
+        self.gas = gas;
+        self
+    }
+
+    pub fn with_input(mut self, input: Vec<u8>) -> Self {
+        self.input = input;
+        self
+    }
+
+    pub fn with_files(mut self, files: VirtualFs) -> Self {
+        self.files = files;
+        self
+    }
+
+    /// Replaces memory with an empty one laid out as `memory_map`.
+    pub fn with_memory_map(mut self, memory_map: MemoryMap) -> Self {
+        self.memory = Memory::new(memory_map.memory_size);
+        self.memory_map = memory_map;
+        self
+    }
+
+    /// Loads `program` into memory, points `pc` at its entry, starts the
+    /// heap after it and sets up `gp` and an initial stack without
+    /// arguments.
+    pub fn load(&mut self, program: &Program) -> Result<(), &'static str> {
+        self.load_with_args(program, &[], &[])
+    }
+
+    /// Like [`VmState::load`], with `argv` and `envp` on the initial stack.
+    pub fn load_with_args(
+        &mut self,
+        program: &Program,
+        argv: &[String],
+        envp: &[String],
+    ) -> Result<(), &'static str> {
+        program.load(&mut self.memory)?;
+        self.pc = program.entry;
+        self.heap_start = program.end.checked_next_multiple_of(16).unwrap_or(u32::MAX);
+        self.brk = self.heap_start;
+        self.usage.peak_brk = self.brk;
+        if let Some(gp) = self.memory_map.global_pointer.or(program.global_pointer) {
+            self.registers[REG_GP] = gp as u64;
         }
+        self.init_stack(argv, envp)
+    }
 
-        self.registers[0] = 0;
-        self.pc = self.pc.wrapping_add(4);
-        Ok(())
+    /// Moves the program break to `addr` if it lies between the heap start
+    /// and the memory map's `heap_end`. Returns the resulting break, so
+    /// `set_brk(0)` queries it.
+    pub fn set_brk(&mut self, addr: u32) -> u32 {
+        if (self.heap_start..=self.memory_map.heap_end).contains(&addr) {
+            self.brk = addr;
+            self.usage.peak_brk = self.usage.peak_brk.max(addr);
+        }
+        self.brk
+    }
+
+    pub fn memory_stats(&self) -> MemoryStats {
+        MemoryStats {
+            peak_heap: self.usage.peak_brk.saturating_sub(self.heap_start),
+            peak_stack: self
+                .usage
+                .lowest_sp
+                .map_or(0, |sp| self.memory_map.stack_top - sp),
+            touched_pages: self
+                .usage
+                .pages
+                .iter()
+                .map(|word| word.count_ones() as usize)
+                .sum(),
+        }
+    }
+
+    pub fn load_elf(&mut self, elf: &[u8]) -> Result<(), &'static str> {
+        self.load(&Program::from_elf(elf)?)
+    }
+
+    pub fn execute_instruction(&mut self, instruction: Instruction) -> Result<(), &'static str> {
+        self.execute_with_trace(instruction).map(|_| ())
     }
 
     pub fn execute_with_trace(
@@ -95,110 +235,191 @@ impl VmState {
         let mut memory_writes = Vec::new();
         let mut intermediate_values = Vec::new();
 
+        let rd = instruction.rd as usize;
+        let val1 = self.registers[instruction.rs1 as usize];
+        let val2 = self.registers[instruction.rs2 as usize];
+        let imm = self.to_xlen(instruction.imm as i64);
+        let mut next_pc = self.pc.wrapping_add(instruction.size as u32);
+
         match instruction.opcode {
-            Opcode::Add => {
-                let val1 = self.registers[instruction.rs1 as usize];
-                let val2 = self.registers[instruction.rs2 as usize];
-                intermediate_values.push(val1);
-                intermediate_values.push(val2);
-                let result = val1.wrapping_add(val2);
-                intermediate_values.push(result);
-                self.registers[instruction.rd as usize] = result;
+            Opcode::Lui => {
+                self.push_value(&mut intermediate_values, imm);
+                self.registers[rd] = imm;
+            }
+            Opcode::Auipc => {
+                let pc = self.pc as u64;
+                let result = self.to_xlen(pc.wrapping_add(imm) as i64);
+                for value in [pc, imm, result] {
+                    self.push_value(&mut intermediate_values, value);
+                }
+                self.registers[rd] = result;
+            }
+            Opcode::Jump => {
+                let target = self.effective_address(self.pc as u64, instruction.imm)?;
+                self.registers[rd] = next_pc as u64;
+                next_pc = target;
+            }
+            Opcode::Jalr => {
+                let target = self.effective_address(val1, instruction.imm)? & !1;
+                for value in [val1, imm, target as u64] {
+                    self.push_value(&mut intermediate_values, value);
+                }
+                self.registers[rd] = next_pc as u64;
+                next_pc = target;
             }
-            Opcode::Sub => {
-                let val1 = self.registers[instruction.rs1 as usize];
-                let val2 = self.registers[instruction.rs2 as usize];
-                intermediate_values.push(val1);
-                intermediate_values.push(val2);
-                let result = val1.wrapping_sub(val2);
-                intermediate_values.push(result);
-                self.registers[instruction.rd as usize] = result;
+            op if op.is_branch() => {
+                let taken = if self.profile.xlen() == 64 {
+                    branch_taken64(op, val1, val2)
+                } else {
+                    branch_taken(op, val1 as u32, val2 as u32)
+                };
+                for value in [val1, val2, taken as u64] {
+                    self.push_value(&mut intermediate_values, value);
+                }
+                if taken {
+                    next_pc = self.effective_address(self.pc as u64, instruction.imm)?;
+                }
             }
-            Opcode::Mul => {
-                let val1 = self.registers[instruction.rs1 as usize];
-                let val2 = self.registers[instruction.rs2 as usize];
-                intermediate_values.push(val1);
-                intermediate_values.push(val2);
-                let result = val1.wrapping_mul(val2);
-                intermediate_values.push(result);
-                self.registers[instruction.rd as usize] = result;
+            op if op.is_load() => {
+                let addr = self.effective_address(val1, instruction.imm)?;
+                for value in [val1, imm, addr as u64] {
+                    self.push_value(&mut intermediate_values, value);
+                }
+                let value = match op {
+                    Opcode::Lb => self.to_xlen(self.memory.read_byte(addr)? as i8 as i64),
+                    Opcode::Lbu => self.memory.read_byte(addr)? as u64,
+                    Opcode::Lh => self.to_xlen(self.memory.read_half(addr)? as i16 as i64),
+                    Opcode::Lhu => self.memory.read_half(addr)? as u64,
+                    Opcode::Load => self.to_xlen(self.memory.read_word(addr)? as i32 as i64),
+                    Opcode::Lwu => self.memory.read_word(addr)? as u64,
+                    _ => self.memory.read_dword(addr)?,
+                };
+                // Accesses are recorded at word granularity
+                let word_addr = addr & !3;
+                memory_reads.push((word_addr, self.memory.read_word(word_addr)?));
+                if op == Opcode::Ld {
+                    memory_reads.push((addr + 4, self.memory.read_word(addr + 4)?));
+                }
+                self.push_value(&mut intermediate_values, value);
+                self.registers[rd] = value;
             }
-            Opcode::Div => {
-                let val1 = self.registers[instruction.rs1 as usize];
-                let val2 = self.registers[instruction.rs2 as usize];
-                if val2 == 0 {
-                    return Err("Division by zero");
+            op if op.is_store() => {
+                let addr = self.effective_address(val1, instruction.imm)?;
+                for value in [val1, imm, addr as u64, val2] {
+                    self.push_value(&mut intermediate_values, value);
+                }
+                let word_addr = addr & !3;
+                let words = if op == Opcode::Sd { 2 } else { 1 };
+                let old_values: Vec<u32> = (0..words)
+                    .map(|i| self.memory.read_word(word_addr + 4 * i).unwrap_or(0))
+                    .collect();
+                match op {
+                    Opcode::Sb => self.memory.write_byte(addr, val2 as u8)?,
+                    Opcode::Sh => self.memory.write_half(addr, val2 as u16)?,
+                    Opcode::Store => self.memory.write_word(addr, val2 as u32)?,
+                    _ => self.memory.write_dword(addr, val2)?,
+                }
+                for (i, old_value) in (0..words).zip(old_values) {
+                    let addr = word_addr + 4 * i;
+                    memory_writes.push((addr, old_value, self.memory.read_word(addr)?));
                 }
-                intermediate_values.push(val1);
-                intermediate_values.push(val2);
-                let result = val1 / val2;
-                intermediate_values.push(result);
-                self.registers[instruction.rd as usize] = result;
             }
-            Opcode::Load => {
-                let base_addr = self.registers[instruction.rs1 as usize];
-                let addr = base_addr.wrapping_add(instruction.imm as u32);
-                intermediate_values.push(base_addr);
-                intermediate_values.push(instruction.imm as u32);
-                intermediate_values.push(addr);
+            Opcode::LrW => {
+                let addr = self.effective_address(val1, 0)?;
                 let value = self.memory.read_word(addr)?;
                 memory_reads.push((addr, value));
-                intermediate_values.push(value);
-                self.registers[instruction.rd as usize] = value;
+                intermediate_values.extend([addr, value]);
+                self.reservation = Some(addr);
+                self.registers[rd] = value as u64;
             }
-            Opcode::Store => {
-                let base_addr = self.registers[instruction.rs1 as usize];
-                let addr = base_addr.wrapping_add(instruction.imm as u32);
-                let value = self.registers[instruction.rs2 as usize];
-                intermediate_values.push(base_addr);
-                intermediate_values.push(instruction.imm as u32);
-                intermediate_values.push(addr);
-                intermediate_values.push(value);
-                let old_value = self.memory.read_word(addr).unwrap_or(0);
-                self.memory.write_word(addr, value)?;
-                memory_writes.push((addr, old_value, value));
-            }
-            Opcode::Branch => {
-                let val1 = self.registers[instruction.rs1 as usize];
-                let val2 = self.registers[instruction.rs2 as usize];
-                intermediate_values.push(val1);
-                intermediate_values.push(val2);
-                let is_equal = val1 == val2;
-                intermediate_values.push(if is_equal { 1 } else { 0 });
-                if is_equal {
-                    self.pc = self.pc.wrapping_add(instruction.imm as u32);
-                    let pc_after = self.pc;
-                    return Ok(ExecutionStep {
-                        pc_before,
-                        pc_after,
-                        registers_before,
-                        registers_after: self.registers,
-                        instruction,
-                        memory_reads,
-                        memory_writes,
-                        intermediate_values,
-                    });
+            Opcode::ScW => {
+                let addr = self.effective_address(val1, 0)?;
+                let success = self.reservation.take() == Some(addr);
+                intermediate_values.extend([addr, val2 as u32, success as u32]);
+                if success {
+                    let old_value = self.memory.read_word(addr)?;
+                    self.memory.write_word(addr, val2 as u32)?;
+                    memory_writes.push((addr, old_value, val2 as u32));
                 }
+                self.registers[rd] = !success as u64;
             }
-            Opcode::Jump => {
-                self.pc = self.pc.wrapping_add(instruction.imm as u32);
-                let pc_after = self.pc;
-                return Ok(ExecutionStep {
-                    pc_before,
-                    pc_after,
-                    registers_before,
-                    registers_after: self.registers,
-                    instruction,
-                    memory_reads,
-                    memory_writes,
-                    intermediate_values,
-                });
+            op if op.is_atomic() => {
+                let addr = self.effective_address(val1, 0)?;
+                let old_value = self.memory.read_word(addr)?;
+                let new_value = amo(op, old_value, val2 as u32);
+                self.memory.write_word(addr, new_value)?;
+                memory_writes.push((addr, old_value, new_value));
+                intermediate_values.extend([addr, old_value, val2 as u32, new_value]);
+                self.registers[rd] = old_value as u64;
             }
+            op if op.is_float() || op.is_csr() => self.execute_float(
+                &instruction,
+                &mut memory_reads,
+                &mut memory_writes,
+                &mut intermediate_values,
+            )?,
             Opcode::Nop => {}
+            Opcode::Ecall => self.execute_syscall(
+                &mut memory_reads,
+                &mut memory_writes,
+                &mut intermediate_values,
+            )?,
+            Opcode::Ebreak => return Err("Breakpoint"),
+            op => {
+                let operand = if op.uses_immediate() { imm } else { val2 };
+                let evaluate = |op| {
+                    if self.profile.xlen() == 64 {
+                        alu64(op, val1, operand)
+                    } else {
+                        alu(op, val1 as u32, operand as u32).map(u64::from)
+                    }
+                    .ok_or("Unsupported instruction")
+                };
+                let result = evaluate(op)?;
+                // Division records its quotient and remainder, whichever it
+                // returns
+                let values = match op {
+                    Opcode::Div | Opcode::Rem => {
+                        vec![
+                            val1,
+                            operand,
+                            evaluate(Opcode::Div)?,
+                            evaluate(Opcode::Rem)?,
+                        ]
+                    }
+                    Opcode::Divu | Opcode::Remu => {
+                        vec![
+                            val1,
+                            operand,
+                            evaluate(Opcode::Divu)?,
+                            evaluate(Opcode::Remu)?,
+                        ]
+                    }
+                    _ => vec![val1, operand, result],
+                };
+                for value in values {
+                    self.push_value(&mut intermediate_values, value);
+                }
+                self.registers[rd] = result;
+            }
         }
 
         self.registers[0] = 0;
-        self.pc = self.pc.wrapping_add(4);
+        self.pc = next_pc;
+        self.cycles += 1;
+
+        self.usage.touch(pc_before);
+        for addr in memory_reads.iter().map(|(addr, _)| addr) {
+            self.usage.touch(*addr);
+        }
+        for addr in memory_writes.iter().map(|(addr, ..)| addr) {
+            self.usage.touch(*addr);
+        }
+        // A zero stack pointer has not been set up yet
+        let sp = self.registers[REG_SP] as u32;
+        if sp != 0 && sp <= self.memory_map.stack_top {
+            self.usage.lowest_sp = Some(self.usage.lowest_sp.map_or(sp, |low| low.min(sp)));
+        }
 
         Ok(ExecutionStep {
             pc_before,
@@ -212,23 +433,83 @@ impl VmState {
         })
     }
 
-    pub fn run(&mut self, max_steps: usize) -> Result<(), &'static str> {
+    /// Truncates a sign-extended value to the register width. RV32 register
+    /// values are kept zero-extended in the 64-bit register file.
+    fn to_xlen(&self, value: i64) -> u64 {
+        if self.profile.xlen() == 64 {
+            value as u64
+        } else {
+            value as u32 as u64
+        }
+    }
+
+    /// Records a register-width value, split into 32-bit limbs on RV64.
+    fn push_value(&self, values: &mut Vec<u32>, value: u64) {
+        values.push(value as u32);
+        if self.profile.xlen() == 64 {
+            values.push((value >> 32) as u32);
+        }
+    }
+
+    /// Computes `base + offset` at register width and maps it into the 32-bit
+    /// address space backing `Memory` and `pc`.
+    pub(super) fn effective_address(&self, base: u64, offset: i32) -> Result<u32, &'static str> {
+        let addr = self.to_xlen(base.wrapping_add(offset as i64 as u64) as i64);
+        u32::try_from(addr).map_err(|_| "Memory address out of bounds")
+    }
+
+    /// Fetches the raw instruction at `pc`. With the C extension the fetch
+    /// only needs half-word alignment and may return a 16-bit instruction.
+    fn fetch(&self) -> Result<u32, &'static str> {
+        if !self.profile.has(Extension::C) {
+            return self.memory.read_word(self.pc);
+        }
+        let low = self.memory.read_half(self.pc)? as u32;
+        if low & 0x3 != 0x3 {
+            return Ok(low);
+        }
+        let high = self.memory.read_half(self.pc.wrapping_add(2))? as u32;
+        Ok(low | (high << 16))
+    }
+
+    /// Fetches and decodes the next instruction and charges its gas. Returns
+    /// `None` (and records the exit status) if the VM cannot proceed.
+    fn fetch_metered(&mut self) -> Result<Option<Instruction>, &'static str> {
+        if self.exit_status.is_some() {
+            return Ok(None);
+        }
+        let instruction_word = self.fetch()?;
+        let instruction = super::decode_instruction(instruction_word, self.profile)?;
+        // A syscall is paid for up front, together with its `ecall`
+        let mut price = self.gas.opcode_price(instruction.opcode);
+        if instruction.opcode == Opcode::Ecall {
+            price = price.saturating_add(self.gas.syscall_price(self.pending_syscall()));
+        }
+        if !self.gas.charge(price) {
+            self.exit_status = Some(ExitStatus::OutOfGas);
+            return Ok(None);
+        }
+        Ok(Some(instruction))
+    }
+
+    pub fn run(&mut self, max_steps: usize) -> Result<ExitStatus, &'static str> {
         for _ in 0..max_steps {
-            let instruction_word = self.memory.read_word(self.pc)?;
-            let instruction = super::decode_instruction(instruction_word)?;
-            self.execute_instruction(instruction)?;
+            match self.fetch_metered()? {
+                Some(instruction) => self.execute_instruction(instruction)?,
+                None => break,
+            }
         }
-        Ok(())
+        Ok(self.exit_status.unwrap_or(ExitStatus::StepLimit))
     }
 
     pub fn run_with_trace(&mut self, max_steps: usize) -> Result<Vec<ExecutionStep>, &'static str> {
         let mut trace = Vec::new();
 
         for _ in 0..max_steps {
-            let instruction_word = self.memory.read_word(self.pc)?;
-            let instruction = super::decode_instruction(instruction_word)?;
-            let step = self.execute_with_trace(instruction)?;
-            trace.push(step);
+            match self.fetch_metered()? {
+                Some(instruction) => trace.push(self.execute_with_trace(instruction)?),
+                None => break,
+            }
         }
 
         Ok(trace)
@@ -0,0 +1,391 @@
+use super::{
+    ExitStatus, FileCommitment, GasMeter, GuestPanic, IsaProfile, KvStore, LinuxSyscalls,
+    MemoryMap, MemoryStats, Program, ReplayLog, StateTransition, SyscallHandler, VirtualFs,
+    VmState,
+};
+use crate::zkp::ExecutionTrace;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::Write;
+use zkvm_guest::codec;
+
+/// Step limit used when none is configured.
+pub const DEFAULT_MAX_STEPS: usize = 10_000_000;
+
+/// Everything needed to run a guest program.
+pub struct ExecutorEnv {
+    program: Program,
+    input: Vec<u8>,
+    argv: Vec<String>,
+    envp: Vec<String>,
+    files: VirtualFs,
+    kv_store: Option<KvStore>,
+    memory_map: MemoryMap,
+    max_heap: Option<u32>,
+    profile: IsaProfile,
+    gas: GasMeter,
+    max_steps: usize,
+    handlers: Vec<(u32, Box<dyn SyscallHandler>)>,
+    replay: Option<ReplayLog>,
+    stdout: Option<Box<dyn Write + Send>>,
+    stderr: Option<Box<dyn Write + Send>>,
+    trace: bool,
+}
+
+/// Builds an [`ExecutorEnv`]. Errors from the `with_*` methods are reported
+/// by [`ExecutorEnvBuilder::build`].
+pub struct ExecutorEnvBuilder {
+    program: Option<Program>,
+    image: Option<Vec<u8>>,
+    input: Vec<u8>,
+    argv: Vec<String>,
+    envp: Vec<String>,
+    files: VirtualFs,
+    kv_store: Option<KvStore>,
+    memory_map: MemoryMap,
+    max_heap: Option<u32>,
+    profile: IsaProfile,
+    gas: GasMeter,
+    gas_limit: Option<u64>,
+    max_steps: usize,
+    handlers: Vec<(u32, Box<dyn SyscallHandler>)>,
+    replay: Option<ReplayLog>,
+    stdout: Option<Box<dyn Write + Send>>,
+    stderr: Option<Box<dyn Write + Send>>,
+    trace: bool,
+    error: Option<&'static str>,
+}
+
+/// The outcome of running an [`ExecutorEnv`].
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub exit_status: ExitStatus,
+    pub journal: Vec<u8>,
+    /// The program arguments, which are public like the journal.
+    pub argv: Vec<String>,
+    /// Hashes of the files added with `with_public_file`, which proofs
+    /// carry as [`PublicValues::files`](crate::zkp::PublicValues::files).
+    pub file_commitments: Vec<FileCommitment>,
+    /// Roots of the key-value store before and after the run, if one was
+    /// given, which proofs carry as
+    /// [`PublicValues::state_transition`](crate::zkp::PublicValues::state_transition).
+    pub state_transition: Option<StateTransition>,
+    /// Everything the guest wrote to its standard output and error.
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// Every syscall of the run, for replaying it with `with_replay`.
+    pub replay_log: ReplayLog,
+    /// Set when the guest panicked.
+    pub panic: Option<GuestPanic>,
+    /// Instructions executed.
+    pub cycles: u64,
+    pub gas_used: u64,
+    pub memory: MemoryStats,
+    /// Present when the environment was built with `with_trace`.
+    pub trace: Option<ExecutionTrace>,
+    pub final_state: VmState,
+}
+
+impl ExecutorEnv {
+    pub fn builder() -> ExecutorEnvBuilder {
+        ExecutorEnvBuilder {
+            program: None,
+            image: None,
+            input: Vec::new(),
+            argv: Vec::new(),
+            envp: Vec::new(),
+            files: VirtualFs::default(),
+            kv_store: None,
+            memory_map: MemoryMap::default(),
+            max_heap: None,
+            profile: IsaProfile::default(),
+            gas: GasMeter::default(),
+            gas_limit: None,
+            max_steps: DEFAULT_MAX_STEPS,
+            handlers: Vec::new(),
+            replay: None,
+            stdout: None,
+            stderr: None,
+            trace: false,
+            error: None,
+        }
+    }
+
+    /// Sets up a VM for the program without running it.
+    pub fn into_vm(self) -> Result<VmState, &'static str> {
+        let mut vm = VmState::new(self.memory_map.memory_size)
+            .with_memory_map(self.memory_map)
+            .with_profile(self.profile)
+            .with_gas_meter(self.gas)
+            .with_input(self.input)
+            .with_files(self.files);
+        if let Some(store) = self.kv_store {
+            vm = vm.with_kv_store(store);
+        }
+        for (syscall, handler) in self.handlers {
+            vm = vm.with_syscall_handler(syscall, handler);
+        }
+        if let Some(log) = self.replay {
+            vm = vm.with_replay(log);
+        }
+        if let Some(stdout) = self.stdout {
+            vm = vm.with_stdout(stdout);
+        }
+        if let Some(stderr) = self.stderr {
+            vm = vm.with_stderr(stderr);
+        }
+        vm.load_with_args(&self.program, &self.argv, &self.envp)?;
+        if let Some(max_heap) = self.max_heap {
+            let limit = vm.heap_start.saturating_add(max_heap);
+            vm.memory_map.heap_end = vm.memory_map.heap_end.min(limit);
+        }
+        Ok(vm)
+    }
+
+    /// Runs the program to completion or until a limit is hit.
+    pub fn execute(self) -> Result<Session, &'static str> {
+        let (max_steps, trace) = (self.max_steps, self.trace);
+        let mut vm = self.into_vm()?;
+        let gas_before = vm.gas.used;
+        let old_root = vm.state_root;
+        let (exit_status, trace) = if trace {
+            let trace = ExecutionTrace::record(&mut vm, max_steps)?;
+            (trace.exit_status, Some(trace))
+        } else {
+            (vm.run(max_steps)?, None)
+        };
+        vm.check_replay_finished()?;
+
+        Ok(Session {
+            exit_status,
+            journal: vm.journal.clone(),
+            argv: vm.argv.clone(),
+            file_commitments: vm.files.commitments(),
+            state_transition: old_root
+                .zip(vm.state_root)
+                .map(|(old_root, new_root)| StateTransition { old_root, new_root }),
+            stdout: vm.stdout.clone(),
+            stderr: vm.stderr.clone(),
+            replay_log: vm.syscall_log.clone(),
+            panic: vm.panic.clone(),
+            cycles: vm.cycles,
+            gas_used: vm.gas.used - gas_before,
+            memory: vm.memory_stats(),
+            trace,
+            final_state: vm,
+        })
+    }
+}
+
+impl ExecutorEnvBuilder {
+    /// The program to run. Giving more than one program, here or through
+    /// `with_elf` or `with_image`, makes `build` fail.
+    pub fn with_program(mut self, program: Program) -> Self {
+        if self.program.replace(program).is_some() || self.image.is_some() {
+            self.error = self.error.or(Some("More than one program was given"));
+        }
+        self
+    }
+
+    /// A statically linked RISC-V ELF executable.
+    pub fn with_elf(mut self, elf: &[u8]) -> Self {
+        match Program::from_elf(elf) {
+            Ok(program) => return self.with_program(program),
+            Err(err) => self.error = self.error.or(Some(err)),
+        }
+        self
+    }
+
+    /// A flat binary image, loaded and entered at the memory map's
+    /// `text_start`. Counts as a program for the check `with_program`
+    /// makes.
+    pub fn with_image(mut self, image: &[u8]) -> Self {
+        if self.image.replace(image.to_vec()).is_some() || self.program.is_some() {
+            self.error = self.error.or(Some("More than one program was given"));
+        }
+        self
+    }
+
+    /// Encodes `value` with the [`codec`] and appends it to the input.
+    pub fn with_input<T: Serialize + ?Sized>(mut self, value: &T) -> Self {
+        match codec::to_vec(value) {
+            Ok(bytes) => self.input.extend_from_slice(&bytes),
+            Err(err) => self.error = self.error.or(Some(err.as_str())),
+        }
+        self
+    }
+
+    /// Appends raw bytes to the input.
+    pub fn with_input_bytes(mut self, bytes: &[u8]) -> Self {
+        self.input.extend_from_slice(bytes);
+        self
+    }
+
+    /// Appends program arguments, starting with the program name by
+    /// convention. They are placed on the initial stack and reported in
+    /// [`Session::argv`].
+    pub fn with_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.argv.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Sets an environment variable. The environment stays private.
+    pub fn with_env(mut self, name: &str, value: &str) -> Self {
+        self.envp.push(format!("{name}={value}"));
+        self
+    }
+
+    /// Adds a file the guest can open. Its contents stay private.
+    pub fn with_file(mut self, path: &str, contents: &[u8]) -> Self {
+        self.files.insert(path, contents.to_vec(), false);
+        self
+    }
+
+    /// Adds a file the guest can open and reports the hash of its contents
+    /// in [`Session::file_commitments`].
+    pub fn with_public_file(mut self, path: &str, contents: &[u8]) -> Self {
+        self.files.insert(path, contents.to_vec(), true);
+        self
+    }
+
+    /// Gives the guest authenticated access to `store`; see [`KvStore`].
+    pub fn with_kv_store(mut self, store: KvStore) -> Self {
+        self.kv_store = Some(store);
+        self
+    }
+
+    /// Resizes memory to `memory_size` bytes, with the stack at its top.
+    pub fn with_memory_size(mut self, memory_size: usize) -> Self {
+        self.memory_map = MemoryMap::with_size(memory_size);
+        self
+    }
+
+    pub fn with_memory_map(mut self, memory_map: MemoryMap) -> Self {
+        self.memory_map = memory_map;
+        self
+    }
+
+    /// Caps the heap at `max_heap` bytes above the end of the program.
+    pub fn with_max_heap(mut self, max_heap: u32) -> Self {
+        self.max_heap = Some(max_heap);
+        self
+    }
+
+    pub fn with_profile(mut self, profile: IsaProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Limits the run to `limit` gas. The limit overrides that of the meter
+    /// given to `with_gas_meter`, whichever is called first.
+    pub fn with_gas_limit(mut self, limit: u64) -> Self {
+        self.gas_limit = Some(limit);
+        self
+    }
+
+    /// Meters the run with `gas`, and its limit unless `with_gas_limit`
+    /// sets one.
+    pub fn with_gas_meter(mut self, gas: GasMeter) -> Self {
+        self.gas = gas;
+        self
+    }
+
+    pub fn with_syscall_handler(
+        mut self,
+        syscall: u32,
+        handler: impl SyscallHandler + 'static,
+    ) -> Self {
+        self.handlers.push((syscall, Box::new(handler)));
+        self
+    }
+
+    /// Emulates the Linux syscalls newlib and picolibc use; see
+    /// [`LinuxSyscalls`].
+    pub fn with_linux_syscalls(mut self) -> Self {
+        for syscall in LinuxSyscalls::NUMBERS {
+            self = self.with_syscall_handler(syscall, LinuxSyscalls);
+        }
+        self
+    }
+
+    /// Re-executes a run from its [`Session::replay_log`]. Syscalls take
+    /// their effects from the log rather than from handlers, input or files,
+    /// and execution fails if the guest diverges from it.
+    pub fn with_replay(mut self, log: ReplayLog) -> Self {
+        self.replay = Some(log);
+        self
+    }
+
+    /// Streams the guest's standard output to `writer` while it runs.
+    pub fn with_stdout(mut self, writer: impl Write + Send + 'static) -> Self {
+        self.stdout = Some(Box::new(writer));
+        self
+    }
+
+    /// Streams the guest's standard error to `writer` while it runs.
+    pub fn with_stderr(mut self, writer: impl Write + Send + 'static) -> Self {
+        self.stderr = Some(Box::new(writer));
+        self
+    }
+
+    /// Records an [`ExecutionTrace`] for proving.
+    pub fn with_trace(mut self) -> Self {
+        self.trace = true;
+        self
+    }
+
+    pub fn build(self) -> Result<ExecutorEnv, &'static str> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        if self.memory_map.stack_top as usize > self.memory_map.memory_size {
+            return Err("Stack top lies outside memory");
+        }
+        let program = match (self.program, self.image) {
+            (Some(_), Some(_)) => return Err("More than one program was given"),
+            (Some(program), None) => program,
+            (None, Some(image)) => Program::from_image(&image, self.memory_map.text_start),
+            (None, None) => return Err("No program given"),
+        };
+
+        let mut gas = self.gas;
+        if let Some(limit) = self.gas_limit {
+            gas.limit = limit;
+        }
+
+        Ok(ExecutorEnv {
+            program,
+            input: self.input,
+            argv: self.argv,
+            envp: self.envp,
+            files: self.files,
+            kv_store: self.kv_store,
+            memory_map: self.memory_map,
+            max_heap: self.max_heap,
+            profile: self.profile,
+            gas,
+            max_steps: self.max_steps,
+            handlers: self.handlers,
+            replay: self.replay,
+            stdout: self.stdout,
+            stderr: self.stderr,
+            trace: self.trace,
+        })
+    }
+}
+
+impl Session {
+    /// Decodes the journal as a single value committed with `env::commit`.
+    pub fn decode_journal<T: DeserializeOwned>(&self) -> Result<T, &'static str> {
+        codec::from_slice(&self.journal).map_err(|err| err.as_str())
+    }
+}
@@ -0,0 +1,238 @@
+use super::{FloatFormat, Instruction, Opcode, RoundingMode, VmState, F32, F64};
+
+const CSR_FFLAGS: u32 = 0x001;
+const CSR_FRM: u32 = 0x002;
+const CSR_FCSR: u32 = 0x003;
+
+/// Upper half of a NaN-boxed single-precision value.
+const NAN_BOX: u64 = 0xffff_ffff_0000_0000;
+
+impl VmState {
+    /// Reads `reg` as a value of `format`. Single-precision values that are
+    /// not properly NaN-boxed read as the canonical NaN.
+    pub fn read_fregister(&self, format: FloatFormat, reg: u8) -> u64 {
+        let value = self.fregisters[reg as usize];
+        if format == F64 {
+            value
+        } else if value & NAN_BOX == NAN_BOX {
+            value & 0xffff_ffff
+        } else {
+            F32.canonical_nan()
+        }
+    }
+
+    pub fn write_fregister(&mut self, format: FloatFormat, reg: u8, bits: u64) {
+        self.fregisters[reg as usize] = if format == F64 { bits } else { bits | NAN_BOX };
+    }
+
+    /// Resolves an instruction's `rm` field, falling back to `frm` for the
+    /// dynamic mode.
+    fn rounding_mode(&self, rm: i32) -> Result<RoundingMode, &'static str> {
+        let rm = if rm == 7 {
+            (self.fcsr >> 5) & 0x7
+        } else {
+            rm as u32
+        };
+        RoundingMode::from_bits(rm).ok_or("Invalid rounding mode")
+    }
+
+    fn read_csr(&self, csr: u32) -> Result<u32, &'static str> {
+        match csr {
+            CSR_FFLAGS => Ok(self.fcsr & 0x1f),
+            CSR_FRM => Ok((self.fcsr >> 5) & 0x7),
+            CSR_FCSR => Ok(self.fcsr & 0xff),
+            _ => Err("Unsupported CSR"),
+        }
+    }
+
+    fn write_csr(&mut self, csr: u32, value: u32) {
+        self.fcsr = match csr {
+            CSR_FFLAGS => (self.fcsr & !0x1f) | (value & 0x1f),
+            CSR_FRM => (self.fcsr & !0xe0) | ((value & 0x7) << 5),
+            _ => value & 0xff,
+        };
+    }
+
+    /// Executes an F, D or floating-point CSR instruction. Operands, results
+    /// and the raised `fflags` are recorded in `values`, with 64-bit values
+    /// split into 32-bit limbs.
+    pub(super) fn execute_float(
+        &mut self,
+        instruction: &Instruction,
+        memory_reads: &mut Vec<(u32, u32)>,
+        memory_writes: &mut Vec<(u32, u32, u32)>,
+        values: &mut Vec<u32>,
+    ) -> Result<(), &'static str> {
+        use Opcode::*;
+
+        let op = instruction.opcode;
+        let format = if op.is_double() { F64 } else { F32 };
+        let (rd, rs1, rs2) = (instruction.rd, instruction.rs1, instruction.rs2);
+        let int1 = self.registers[rs1 as usize] as u32;
+        // FCVT.D.S is the one D instruction with a single-precision operand
+        let operand_format = if op == FcvtDS { F32 } else { format };
+        let a = self.read_fregister(operand_format, rs1);
+        let b = self.read_fregister(operand_format, rs2);
+        let push = |values: &mut Vec<u32>, format: FloatFormat, bits: u64| {
+            values.push(bits as u32);
+            if format == F64 {
+                values.push((bits >> 32) as u32);
+            }
+        };
+
+        if op.is_csr() {
+            let csr = instruction.imm as u32 & 0xfff;
+            let old = self.read_csr(csr)?;
+            let (source, writes) = match op {
+                Csrrwi | Csrrsi | Csrrci => (rs1 as u32, op == Csrrwi || rs1 != 0),
+                _ => (int1, op == Csrrw || rs1 != 0),
+            };
+            let new = match op {
+                Csrrw | Csrrwi => source,
+                Csrrs | Csrrsi => old | source,
+                _ => old & !source,
+            };
+            if writes {
+                self.write_csr(csr, new);
+            }
+            values.extend([csr, old, source, self.read_csr(csr)?]);
+            self.registers[rd as usize] = old as u64;
+            return Ok(());
+        }
+
+        match op {
+            Flw | Fld => {
+                let addr = self.effective_address(int1 as u64, instruction.imm)?;
+                let value = if op == Flw {
+                    self.memory.read_word(addr)? as u64
+                } else {
+                    self.memory.read_dword(addr)?
+                };
+                memory_reads.push((addr, value as u32));
+                if op == Fld {
+                    memory_reads.push((addr + 4, (value >> 32) as u32));
+                }
+                values.push(addr);
+                push(values, format, value);
+                self.write_fregister(format, rd, value);
+                return Ok(());
+            }
+            Fsw | Fsd => {
+                let addr = self.effective_address(int1 as u64, instruction.imm)?;
+                // Stores copy the raw register bits without unboxing
+                let value = self.fregisters[rs2 as usize];
+                let words = if op == Fsd { 2 } else { 1 };
+                let old_values: Vec<u32> = (0..words)
+                    .map(|i| self.memory.read_word(addr + 4 * i).unwrap_or(0))
+                    .collect();
+                if op == Fsw {
+                    self.memory.write_word(addr, value as u32)?;
+                } else {
+                    self.memory.write_dword(addr, value)?;
+                }
+                for (i, old_value) in (0..words).zip(old_values) {
+                    let addr = addr + 4 * i;
+                    memory_writes.push((addr, old_value, self.memory.read_word(addr)?));
+                }
+                values.push(addr);
+                push(values, format, value);
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let rm = if op.has_rounding_mode() {
+            self.rounding_mode(instruction.imm)?
+        } else {
+            RoundingMode::NearestEven
+        };
+        let sign = format.sign_bit();
+        let c = self.read_fregister(format, instruction.rs3);
+        let is_fma = matches!(
+            op,
+            FmaddS | FmsubS | FnmsubS | FnmaddS | FmaddD | FmsubD | FnmsubD | FnmaddD
+        );
+
+        push(values, operand_format, a);
+        push(values, operand_format, b);
+        if is_fma {
+            push(values, format, c);
+        }
+
+        // Results that land in the floating-point register file
+        let float_result = match op {
+            FaddS | FaddD => Some(format.add(a, b, rm)),
+            FsubS | FsubD => Some(format.sub(a, b, rm)),
+            FmulS | FmulD => Some(format.mul(a, b, rm)),
+            FdivS | FdivD => Some(format.div(a, b, rm)),
+            FsqrtS | FsqrtD => Some(format.sqrt(a, rm)),
+            FminS | FminD => Some(format.min(a, b)),
+            FmaxS | FmaxD => Some(format.max(a, b)),
+            FsgnjS | FsgnjD => Some(((a & !sign) | (b & sign), 0)),
+            FsgnjnS | FsgnjnD => Some(((a & !sign) | (!b & sign), 0)),
+            FsgnjxS | FsgnjxD => Some((a ^ (b & sign), 0)),
+            _ if is_fma => {
+                let negate_product = matches!(op, FnmsubS | FnmaddS | FnmsubD | FnmaddD);
+                let negate_addend = matches!(op, FmsubS | FnmaddS | FmsubD | FnmaddD);
+                let a = if negate_product { a ^ sign } else { a };
+                let c = if negate_addend { c ^ sign } else { c };
+                Some(format.fma(a, b, c, rm))
+            }
+            FcvtSW | FcvtSWu | FcvtDW | FcvtDWu => {
+                let signed = matches!(op, FcvtSW | FcvtDW);
+                Some(format.from_int(int1, signed, rm))
+            }
+            FcvtSD => Some(F32.convert_from(F64, a, rm)),
+            FcvtDS => Some(F64.convert_from(F32, a, rm)),
+            // The payload moves untouched, so only the low 32 bits are read
+            FmvWX => Some((int1 as u64, 0)),
+            _ => None,
+        };
+
+        // FCVT.S.D writes a single-precision result from double operands
+        let result_format = match op {
+            FcvtSD => F32,
+            _ => format,
+        };
+
+        let (result, flags) = match float_result {
+            Some((bits, flags)) => {
+                self.write_fregister(result_format, rd, bits);
+                (Some(bits), flags)
+            }
+            None => {
+                let (value, flags) = match op {
+                    FeqS | FeqD => {
+                        let (result, flags) = format.eq(a, b);
+                        (result as u32, flags)
+                    }
+                    FltS | FltD => {
+                        let (result, flags) = format.lt(a, b);
+                        (result as u32, flags)
+                    }
+                    FleS | FleD => {
+                        let (result, flags) = format.le(a, b);
+                        (result as u32, flags)
+                    }
+                    FclassS | FclassD => (format.classify(a), 0),
+                    FcvtWS | FcvtWD => format.to_int(a, true, rm),
+                    FcvtWuS | FcvtWuD => format.to_int(a, false, rm),
+                    // Moves the raw low bits, NaN-boxed or not
+                    FmvXW => (self.fregisters[rs1 as usize] as u32, 0),
+                    _ => return Err("Unsupported instruction"),
+                };
+                self.registers[rd as usize] = value as u64;
+                values.push(value);
+                (None, flags)
+            }
+        };
+
+        if let Some(result) = result {
+            push(values, result_format, result);
+        }
+        values.push(flags);
+        self.fcsr |= flags;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+use super::Opcode;
+use std::collections::HashMap;
+
+/// Deterministic gas accounting for a single VM run.
+///
+/// Every executed instruction is charged its opcode price and every syscall
+/// is additionally charged its syscall price. Prices fall back to the
+/// defaults when no explicit price has been configured.
+#[derive(Debug, Clone)]
+pub struct GasMeter {
+    pub limit: u64,
+    pub used: u64,
+    pub default_opcode_price: u64,
+    pub default_syscall_price: u64,
+    opcode_prices: HashMap<Opcode, u64>,
+    syscall_prices: HashMap<u32, u64>,
+}
+
+impl Default for GasMeter {
+    fn default() -> Self {
+        Self::new(u64::MAX)
+    }
+}
+
+impl GasMeter {
+    pub fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            used: 0,
+            default_opcode_price: 1,
+            default_syscall_price: 0,
+            opcode_prices: HashMap::new(),
+            syscall_prices: HashMap::new(),
+        }
+    }
+
+    pub fn with_opcode_price(mut self, opcode: Opcode, price: u64) -> Self {
+        self.opcode_prices.insert(opcode, price);
+        self
+    }
+
+    pub fn with_syscall_price(mut self, syscall: u32, price: u64) -> Self {
+        self.syscall_prices.insert(syscall, price);
+        self
+    }
+
+    pub fn opcode_price(&self, opcode: Opcode) -> u64 {
+        self.opcode_prices
+            .get(&opcode)
+            .copied()
+            .unwrap_or(self.default_opcode_price)
+    }
+
+    pub fn syscall_price(&self, syscall: u32) -> u64 {
+        self.syscall_prices
+            .get(&syscall)
+            .copied()
+            .unwrap_or(self.default_syscall_price)
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.limit.saturating_sub(self.used)
+    }
+
+    /// Charges `amount` gas. Returns `false` without consuming anything if the
+    /// remaining budget cannot cover it.
+    pub fn charge(&mut self, amount: u64) -> bool {
+        if amount > self.remaining() {
+            return false;
+        }
+        self.used += amount;
+        true
+    }
+}
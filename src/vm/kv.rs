@@ -0,0 +1,161 @@
+//! A Merkle-committed key-value store the guest reads and updates through
+//! the `KV_GET` and `KV_SET` syscalls.
+//!
+//! The host's [`KvStore`] is untrusted: it answers every access with a
+//! [`MerkleProof`], which the VM checks against the state root it tracks
+//! itself. Only that root evolves inside the VM, so a run moves from the
+//! old root to the new one without loading the whole state.
+//!
+//! The tree hashes with the sponge ([`SpongeHasher`]), which the AIR
+//! checks: proofs take the old and new roots as public values, and the
+//! sponge rows check the path of every access against the root the run has
+//! reached, so a proof shows the guest moved the store from one root to
+//! the other. The roots are also reported in
+//! [`Session::state_transition`](super::Session::state_transition).
+
+use super::{errno, VmState};
+use crate::crypto::{HashValue, MerkleHasher, MerkleProof, MerkleTree};
+use crate::zkp::SpongeHasher;
+use serde::{Deserialize, Serialize};
+
+pub use zkvm_guest::syscall::KV_VALUE_SIZE;
+
+pub type KvValue = [u8; KV_VALUE_SIZE];
+
+/// Deepest tree a [`KvStore`] accepts.
+pub const MAX_KV_DEPTH: u32 = 20;
+
+/// The Merkle leaf committing to `value` at `key`.
+pub fn kv_leaf(key: u32, value: &KvValue) -> Vec<u8> {
+    let mut leaf = key.to_le_bytes().to_vec();
+    leaf.extend_from_slice(value);
+    leaf
+}
+
+/// `2^depth` slots of [`KvValue`]s, zeroed until set, keyed by slot index.
+#[derive(Debug, Clone)]
+pub struct KvStore {
+    values: Vec<KvValue>,
+    tree: MerkleTree<SpongeHasher>,
+}
+
+impl KvStore {
+    pub fn new(depth: u32) -> Result<Self, &'static str> {
+        if depth == 0 || depth > MAX_KV_DEPTH {
+            return Err("Key-value store depth out of range");
+        }
+        let values = vec![[0; KV_VALUE_SIZE]; 1 << depth];
+        let tree = Self::build_tree(&values);
+        Ok(Self { values, tree })
+    }
+
+    fn build_tree(values: &[KvValue]) -> MerkleTree<SpongeHasher> {
+        let leaves: Vec<Vec<u8>> = (0..)
+            .zip(values)
+            .map(|(key, value)| kv_leaf(key, value))
+            .collect();
+        MerkleTree::with_hasher(leaves.iter().map(Vec::as_slice).collect(), SpongeHasher)
+    }
+
+    /// Number of keys.
+    pub fn capacity(&self) -> u32 {
+        self.values.len() as u32
+    }
+
+    pub fn root(&self) -> HashValue {
+        *self.tree.root()
+    }
+
+    pub fn get(&self, key: u32) -> Option<&KvValue> {
+        self.values.get(key as usize)
+    }
+
+    pub fn set(&mut self, key: u32, value: KvValue) -> Result<(), &'static str> {
+        let slot = self
+            .values
+            .get_mut(key as usize)
+            .ok_or("Key out of range")?;
+        *slot = value;
+        self.tree.update_leaf(key as usize, &kv_leaf(key, &value));
+        Ok(())
+    }
+
+    /// The value at `key` and its inclusion proof.
+    pub fn prove(&self, key: u32) -> Option<(KvValue, MerkleProof)> {
+        let value = *self.get(key)?;
+        Some((value, self.tree.generate_proof(key as usize)?))
+    }
+}
+
+/// The state roots before and after a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateTransition {
+    pub old_root: HashValue,
+    pub new_root: HashValue,
+}
+
+impl VmState {
+    /// Gives the guest access to `store`, starting from its current root.
+    pub fn with_kv_store(mut self, store: KvStore) -> Self {
+        self.state_root = Some(store.root());
+        self.kv_store = Some(store);
+        self
+    }
+
+    /// Asks the store for `key` and checks the answer against `state_root`.
+    /// Returns `None` if `key` is out of range.
+    fn verified_entry(&self, key: u32) -> Result<Option<(KvValue, MerkleProof)>, &'static str> {
+        let store = self.kv_store.as_ref().ok_or("No key-value store")?;
+        if key >= store.capacity() {
+            return Ok(None);
+        }
+        let (value, proof) = store.prove(key).ok_or("Missing state proof")?;
+        let valid = proof.index == key as usize
+            && proof.leaf == SpongeHasher.hash_leaf(&kv_leaf(key, &value))
+            && Some(proof.root_with(&SpongeHasher, proof.leaf)) == self.state_root;
+        if !valid {
+            return Err("Invalid state proof");
+        }
+        Ok(Some((value, proof)))
+    }
+
+    /// `KV_GET`: copies the verified value at `key` to `addr`.
+    pub(super) fn kv_get(
+        &mut self,
+        key: u32,
+        addr: u32,
+        memory_writes: &mut Vec<(u32, u32, u32)>,
+    ) -> Result<u32, &'static str> {
+        let Some((value, _)) = self.verified_entry(key)? else {
+            return Ok(errno::to_result(errno::EINVAL));
+        };
+        self.write_guest_bytes(addr, &value, memory_writes)?;
+        Ok(0)
+    }
+
+    /// `KV_SET`: stores the value at `addr` under `key` and moves
+    /// `state_root` along the verified path of the old value.
+    pub(super) fn kv_set(
+        &mut self,
+        key: u32,
+        addr: u32,
+        memory_reads: &mut Vec<(u32, u32)>,
+    ) -> Result<u32, &'static str> {
+        let Some((_, proof)) = self.verified_entry(key)? else {
+            return Ok(errno::to_result(errno::EINVAL));
+        };
+        let bytes = self.read_guest_bytes(addr, KV_VALUE_SIZE as u32, memory_reads)?;
+        let mut value = [0; KV_VALUE_SIZE];
+        value.copy_from_slice(&bytes);
+        let leaf = SpongeHasher.hash_leaf(&kv_leaf(key, &value));
+        let new_root = proof.root_with(&SpongeHasher, leaf);
+
+        let store = self.kv_store.as_mut().ok_or("No key-value store")?;
+        store.set(key, value)?;
+        if store.root() != new_root {
+            return Err("Invalid state proof");
+        }
+        self.state_root = Some(new_root);
+        Ok(0)
+    }
+}
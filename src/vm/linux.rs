@@ -0,0 +1,134 @@
+//! Emulation of the Linux syscalls that newlib and picolibc issue, so C
+//! programs built for `riscv32-unknown-elf` run unmodified.
+//!
+//! File descriptor 0 reads the input stream and descriptors 1 and 2 are the
+//! guest's standard output and error. Files in the [`VirtualFs`] can be
+//! opened read-only by their exact path; `dirfd` is ignored. Failures are
+//! reported to the guest as negative errno values, as the kernel would.
+//!
+//! [`VirtualFs`]: super::VirtualFs
+
+use super::{errno, SyscallContext, SyscallHandler};
+use zkvm_guest::syscall::fd;
+
+/// Linux syscall numbers for RISC-V.
+pub mod linux_nr {
+    pub const OPENAT: u32 = 56;
+    pub const CLOSE: u32 = 57;
+    /// `lseek(fd, offset, whence)` with a 32-bit offset, as libgloss issues
+    /// it.
+    pub const LSEEK: u32 = 62;
+    pub const READ: u32 = 63;
+    pub const WRITE: u32 = 64;
+    pub const FSTAT: u32 = 80;
+    pub const EXIT: u32 = 93;
+    pub const EXIT_GROUP: u32 = 94;
+    pub const BRK: u32 = 214;
+}
+
+const STDIN: u32 = 0;
+
+/// `struct stat` as laid out for riscv32, and the offsets of `st_mode` and
+/// `st_size`.
+const STAT_SIZE: usize = 128;
+const STAT_MODE_OFFSET: usize = 16;
+const STAT_SIZE_OFFSET: usize = 48;
+/// Character device: libc line-buffers such streams.
+const S_IFCHR: u32 = 0o020000;
+const S_IFREG: u32 = 0o100000;
+
+/// Access mode bits of the `openat` flags; only `O_RDONLY` is supported.
+const O_ACCMODE: u32 = 3;
+/// Longest path accepted by `openat`, including the NUL.
+const PATH_MAX: u32 = 4096;
+
+fn stat(mode: u32, size: u32) -> [u8; STAT_SIZE] {
+    let mut stat = [0; STAT_SIZE];
+    stat[STAT_MODE_OFFSET..STAT_MODE_OFFSET + 4].copy_from_slice(&mode.to_le_bytes());
+    stat[STAT_SIZE_OFFSET..STAT_SIZE_OFFSET + 4].copy_from_slice(&size.to_le_bytes());
+    stat
+}
+
+/// Length of the NUL-terminated path at `addr`, or the errno for a path that
+/// runs out of memory or is longer than `PATH_MAX`.
+fn c_str_len(context: &SyscallContext, addr: u32) -> Result<u32, i32> {
+    let memory = &context.state().memory;
+    for i in 0..PATH_MAX {
+        match memory.read_byte(addr.wrapping_add(i)) {
+            Ok(0) => return Ok(i),
+            Ok(_) => {}
+            Err(_) => return Err(errno::EFAULT),
+        }
+    }
+    Err(errno::ENAMETOOLONG)
+}
+
+/// A [`SyscallHandler`] for the Linux syscall ABI. Install it for every
+/// number in [`LinuxSyscalls::NUMBERS`], e.g. with
+/// `ExecutorEnvBuilder::with_linux_syscalls`. The native zkVM syscalls use
+/// different numbers and stay available.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LinuxSyscalls;
+
+impl LinuxSyscalls {
+    pub const NUMBERS: [u32; 9] = [
+        linux_nr::OPENAT,
+        linux_nr::CLOSE,
+        linux_nr::LSEEK,
+        linux_nr::READ,
+        linux_nr::WRITE,
+        linux_nr::FSTAT,
+        linux_nr::EXIT,
+        linux_nr::EXIT_GROUP,
+        linux_nr::BRK,
+    ];
+
+    /// The `struct stat` that `fstat` writes for the standard streams.
+    pub fn std_stat() -> [u8; STAT_SIZE] {
+        stat(S_IFCHR, 0)
+    }
+}
+
+impl SyscallHandler for LinuxSyscalls {
+    fn handle(&mut self, context: &mut SyscallContext) -> Result<u32, &'static str> {
+        let (a0, a1, a2) = (context.arg(0), context.arg(1), context.arg(2));
+        let is_std = a0 <= fd::STDERR;
+
+        Ok(match context.syscall() {
+            linux_nr::WRITE => context.write_output(a0, a1, a2)?,
+            linux_nr::READ if a0 == STDIN => context.read_input(a1, a2)?,
+            linux_nr::READ => context.read_file(a0, a1, a2)?,
+            linux_nr::FSTAT if is_std => {
+                context.write_bytes(a1, &Self::std_stat())?;
+                0
+            }
+            linux_nr::FSTAT => match context.files().size(a0) {
+                Ok(size) => {
+                    context.write_bytes(a1, &stat(S_IFREG, size))?;
+                    0
+                }
+                Err(code) => errno::to_result(code),
+            },
+            linux_nr::OPENAT if a2 & O_ACCMODE != 0 => errno::to_result(errno::EROFS),
+            linux_nr::OPENAT => match c_str_len(context, a1) {
+                Ok(len) => context.open_file(a1, len)?,
+                Err(code) => errno::to_result(code),
+            },
+            linux_nr::LSEEK => context
+                .files()
+                .seek(a0, a1 as i32, a2)
+                .unwrap_or_else(errno::to_result),
+            linux_nr::CLOSE if is_std => 0,
+            linux_nr::CLOSE => context
+                .files()
+                .close(a0)
+                .map_or_else(errno::to_result, |_| 0),
+            linux_nr::EXIT | linux_nr::EXIT_GROUP => {
+                context.halt(a0);
+                a0
+            }
+            linux_nr::BRK => context.set_brk(a0),
+            _ => return Err("Unsupported environment call"),
+        })
+    }
+}
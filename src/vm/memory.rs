@@ -1,5 +1,59 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use zkvm_guest::memory::{HEAP_END, MEMORY_SIZE, TEXT_START};
 
+/// Granularity of the touched-page accounting.
+pub const PAGE_SIZE: u32 = 4096;
+
+/// Layout of guest memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryMap {
+    pub memory_size: usize,
+    /// Where raw program images are loaded.
+    pub text_start: u32,
+    /// Highest address the program break may reach.
+    pub heap_end: u32,
+    /// Top of the stack. The initial stack, holding the program arguments,
+    /// is laid out below it.
+    pub stack_top: u32,
+    /// Initial value of `gp`. `None` uses the program's `__global_pointer$`.
+    pub global_pointer: Option<u32>,
+}
+
+impl MemoryMap {
+    /// The default layout shrunk or grown to `memory_size` bytes, with the
+    /// stack at the top of memory.
+    pub fn with_size(memory_size: usize) -> Self {
+        let top = u32::try_from(memory_size).unwrap_or(u32::MAX);
+        Self {
+            memory_size,
+            text_start: TEXT_START,
+            heap_end: HEAP_END.min(top),
+            stack_top: top,
+            global_pointer: None,
+        }
+    }
+}
+
+impl Default for MemoryMap {
+    /// The layout `zkvm_guest`'s linker script targets.
+    fn default() -> Self {
+        Self::with_size(MEMORY_SIZE)
+    }
+}
+
+/// Memory usage of a run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// Highest program break reached, in bytes above the heap start.
+    pub peak_heap: u32,
+    /// Deepest stack pointer reached, in bytes below the stack top.
+    pub peak_stack: u32,
+    /// Distinct pages fetched from, read or written.
+    pub touched_pages: usize,
+}
+
+/// Byte-addressed memory. Addresses are 32-bit for every profile; RV64
+/// guests run in the low 4 GiB and the VM rejects wider effective addresses.
 #[derive(Debug, Clone)]
 pub struct Memory {
     memory: HashMap<u32, u8>,
@@ -14,6 +68,11 @@ impl Memory {
         }
     }
 
+    /// Size in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
     pub fn read_byte(&self, addr: u32) -> Result<u8, &'static str> {
         if addr as usize >= self.size {
             return Err("Memory address out of bounds");
@@ -29,6 +88,25 @@ impl Memory {
         Ok(())
     }
 
+    pub fn read_half(&self, addr: u32) -> Result<u16, &'static str> {
+        if !addr.is_multiple_of(2) {
+            return Err("Unaligned memory access");
+        }
+
+        let lo = self.read_byte(addr)? as u16;
+        let hi = self.read_byte(addr + 1)? as u16;
+        Ok(lo | (hi << 8))
+    }
+
+    pub fn write_half(&mut self, addr: u32, value: u16) -> Result<(), &'static str> {
+        if !addr.is_multiple_of(2) {
+            return Err("Unaligned memory access");
+        }
+
+        self.write_byte(addr, value as u8)?;
+        self.write_byte(addr + 1, (value >> 8) as u8)
+    }
+
     pub fn read_word(&self, addr: u32) -> Result<u32, &'static str> {
         if !addr.is_multiple_of(4) {
             return Err("Unaligned memory access");
@@ -54,6 +132,37 @@ impl Memory {
         Ok(())
     }
 
+    pub fn read_dword(&self, addr: u32) -> Result<u64, &'static str> {
+        if !addr.is_multiple_of(8) {
+            return Err("Unaligned memory access");
+        }
+
+        let lo = self.read_word(addr)? as u64;
+        let hi = self.read_word(addr + 4)? as u64;
+        Ok(lo | (hi << 32))
+    }
+
+    pub fn write_dword(&mut self, addr: u32, value: u64) -> Result<(), &'static str> {
+        if !addr.is_multiple_of(8) {
+            return Err("Unaligned memory access");
+        }
+
+        self.write_word(addr, value as u32)?;
+        self.write_word(addr + 4, (value >> 32) as u32)
+    }
+
+    /// Every word holding a nonzero value, by address.
+    pub fn words(&self) -> BTreeMap<u32, u32> {
+        let mut words = BTreeMap::new();
+        for &addr in self.memory.keys() {
+            let addr = addr & !3;
+            if let Ok(word @ 1..) = self.read_word(addr) {
+                words.insert(addr, word);
+            }
+        }
+        words
+    }
+
     pub fn load_program(&mut self, program: &[u8], start_addr: u32) -> Result<(), &'static str> {
         for (i, &byte) in program.iter().enumerate() {
             self.write_byte(start_addr + i as u32, byte)?;
@@ -1,7 +1,31 @@
+pub mod alu;
+pub mod elf;
 pub mod execution;
+pub mod executor;
+mod float;
+pub mod gas;
+pub mod kv;
+pub mod linux;
 pub mod memory;
 pub mod opcodes;
+pub mod profile;
+pub mod replay;
+pub mod softfloat;
+pub mod stack;
+pub mod syscall;
+pub mod vfs;
 
+pub use alu::*;
+pub use elf::*;
 pub use execution::*;
+pub use executor::*;
+pub use gas::*;
+pub use kv::*;
+pub use linux::*;
 pub use memory::*;
 pub use opcodes::*;
+pub use profile::*;
+pub use replay::*;
+pub use softfloat::*;
+pub use syscall::*;
+pub use vfs::*;
@@ -1,14 +1,394 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use super::{Extension, IsaProfile};
+use serde::{Deserialize, Serialize};
+
+/// Decoded operations. `Load`, `Store`, `Branch` and `Jump` are the word-sized
+/// and equality forms (LW, SW, BEQ, JAL); their siblings have their own
+/// variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum Opcode {
+    // RV32I
+    Lui,
+    Auipc,
+    Jump,
+    Jalr,
+    Branch,
+    Bne,
+    Blt,
+    Bge,
+    Bltu,
+    Bgeu,
+    Lb,
+    Lh,
+    Load,
+    Lbu,
+    Lhu,
+    Sb,
+    Sh,
+    Store,
+    Addi,
+    Slti,
+    Sltiu,
+    Xori,
+    Ori,
+    Andi,
+    Slli,
+    Srli,
+    Srai,
     Add,
     Sub,
+    Sll,
+    Slt,
+    Sltu,
+    Xor,
+    Srl,
+    Sra,
+    Or,
+    And,
+    Nop,
+    Ecall,
+    Ebreak,
+    // RV64I
+    Ld,
+    Lwu,
+    Sd,
+    Addiw,
+    Slliw,
+    Srliw,
+    Sraiw,
+    Addw,
+    Subw,
+    Sllw,
+    Srlw,
+    Sraw,
+    // M
     Mul,
+    Mulh,
+    Mulhsu,
+    Mulhu,
     Div,
-    Load,
-    Store,
-    Branch,
-    Jump,
-    Nop,
+    Divu,
+    Rem,
+    Remu,
+    // RV64M
+    Mulw,
+    Divw,
+    Divuw,
+    Remw,
+    Remuw,
+    // A
+    LrW,
+    ScW,
+    AmoSwapW,
+    AmoAddW,
+    AmoXorW,
+    AmoAndW,
+    AmoOrW,
+    AmoMinW,
+    AmoMaxW,
+    AmoMinuW,
+    AmoMaxuW,
+    // Zba
+    Sh1add,
+    Sh2add,
+    Sh3add,
+    // Zbb
+    Andn,
+    Orn,
+    Xnor,
+    Clz,
+    Ctz,
+    Cpop,
+    Max,
+    Maxu,
+    Min,
+    Minu,
+    SextB,
+    SextH,
+    ZextH,
+    Rol,
+    Ror,
+    Rori,
+    OrcB,
+    Rev8,
+    // Zbc
+    Clmul,
+    Clmulh,
+    Clmulr,
+    // Zbs
+    Bclr,
+    Bclri,
+    Bext,
+    Bexti,
+    Binv,
+    Binvi,
+    Bset,
+    Bseti,
+    // F
+    Flw,
+    Fsw,
+    FmaddS,
+    FmsubS,
+    FnmsubS,
+    FnmaddS,
+    FaddS,
+    FsubS,
+    FmulS,
+    FdivS,
+    FsqrtS,
+    FsgnjS,
+    FsgnjnS,
+    FsgnjxS,
+    FminS,
+    FmaxS,
+    FcvtWS,
+    FcvtWuS,
+    FmvXW,
+    FeqS,
+    FltS,
+    FleS,
+    FclassS,
+    FcvtSW,
+    FcvtSWu,
+    FmvWX,
+    // D
+    Fld,
+    Fsd,
+    FmaddD,
+    FmsubD,
+    FnmsubD,
+    FnmaddD,
+    FaddD,
+    FsubD,
+    FmulD,
+    FdivD,
+    FsqrtD,
+    FsgnjD,
+    FsgnjnD,
+    FsgnjxD,
+    FminD,
+    FmaxD,
+    FcvtSD,
+    FcvtDS,
+    FeqD,
+    FltD,
+    FleD,
+    FclassD,
+    FcvtWD,
+    FcvtWuD,
+    FcvtDW,
+    FcvtDWu,
+    // Zicsr, limited to the floating-point CSRs
+    Csrrw,
+    Csrrs,
+    Csrrc,
+    Csrrwi,
+    Csrrsi,
+    Csrrci,
+}
+
+impl Opcode {
+    /// The extension that introduces this opcode, or `None` for the base ISA.
+    pub fn extension(&self) -> Option<Extension> {
+        use Opcode::*;
+        match self {
+            Mul | Mulh | Mulhsu | Mulhu | Div | Divu | Rem | Remu | Mulw | Divw | Divuw | Remw
+            | Remuw => Some(Extension::M),
+            LrW | ScW | AmoSwapW | AmoAddW | AmoXorW | AmoAndW | AmoOrW | AmoMinW | AmoMaxW
+            | AmoMinuW | AmoMaxuW => Some(Extension::A),
+            Sh1add | Sh2add | Sh3add => Some(Extension::Zba),
+            Andn | Orn | Xnor | Clz | Ctz | Cpop | Max | Maxu | Min | Minu | SextB | SextH
+            | ZextH | Rol | Ror | Rori | OrcB | Rev8 => Some(Extension::Zbb),
+            Clmul | Clmulh | Clmulr => Some(Extension::Zbc),
+            Bclr | Bclri | Bext | Bexti | Binv | Binvi | Bset | Bseti => Some(Extension::Zbs),
+            op if op.is_double() => Some(Extension::D),
+            op if op.is_float() || op.is_csr() => Some(Extension::F),
+            _ => None,
+        }
+    }
+
+    /// Whether the second ALU operand is the immediate rather than `rs2`.
+    pub fn uses_immediate(&self) -> bool {
+        use Opcode::*;
+        matches!(
+            self,
+            Addi | Slti
+                | Addiw
+                | Slliw
+                | Srliw
+                | Sraiw
+                | Sltiu
+                | Xori
+                | Ori
+                | Andi
+                | Slli
+                | Srli
+                | Srai
+                | Rori
+                | Bclri
+                | Bexti
+                | Binvi
+                | Bseti
+                | Clz
+                | Ctz
+                | Cpop
+                | SextB
+                | SextH
+                | OrcB
+                | Rev8
+        )
+    }
+
+    /// Whether the opcode only exists in RV64.
+    pub fn is_rv64_only(&self) -> bool {
+        use Opcode::*;
+        matches!(
+            self,
+            Ld | Lwu
+                | Sd
+                | Addiw
+                | Slliw
+                | Srliw
+                | Sraiw
+                | Addw
+                | Subw
+                | Sllw
+                | Srlw
+                | Sraw
+                | Mulw
+                | Divw
+                | Divuw
+                | Remw
+                | Remuw
+        )
+    }
+
+    /// Whether the opcode computes on the low 32 bits and sign-extends the
+    /// result (the RV64 `*W` instructions).
+    pub fn is_word_op(&self) -> bool {
+        self.is_rv64_only() && !matches!(self, Opcode::Ld | Opcode::Lwu | Opcode::Sd)
+    }
+
+    pub fn is_branch(&self) -> bool {
+        use Opcode::*;
+        matches!(self, Branch | Bne | Blt | Bge | Bltu | Bgeu)
+    }
+
+    pub fn is_load(&self) -> bool {
+        use Opcode::*;
+        matches!(self, Lb | Lh | Load | Lbu | Lhu | Ld | Lwu)
+    }
+
+    pub fn is_store(&self) -> bool {
+        use Opcode::*;
+        matches!(self, Sb | Sh | Store | Sd)
+    }
+
+    pub fn is_atomic(&self) -> bool {
+        self.extension() == Some(Extension::A)
+    }
+
+    /// Whether the opcode belongs to the F or D extension.
+    pub fn is_float(&self) -> bool {
+        use Opcode::*;
+        matches!(
+            self,
+            Flw | Fsw
+                | FmaddS
+                | FmsubS
+                | FnmsubS
+                | FnmaddS
+                | FaddS
+                | FsubS
+                | FmulS
+                | FdivS
+                | FsqrtS
+                | FsgnjS
+                | FsgnjnS
+                | FsgnjxS
+                | FminS
+                | FmaxS
+                | FcvtWS
+                | FcvtWuS
+                | FmvXW
+                | FeqS
+                | FltS
+                | FleS
+                | FclassS
+                | FcvtSW
+                | FcvtSWu
+                | FmvWX
+        ) || self.is_double()
+    }
+
+    /// Whether the opcode belongs to the D extension.
+    pub fn is_double(&self) -> bool {
+        use Opcode::*;
+        matches!(
+            self,
+            Fld | Fsd
+                | FmaddD
+                | FmsubD
+                | FnmsubD
+                | FnmaddD
+                | FaddD
+                | FsubD
+                | FmulD
+                | FdivD
+                | FsqrtD
+                | FsgnjD
+                | FsgnjnD
+                | FsgnjxD
+                | FminD
+                | FmaxD
+                | FcvtSD
+                | FcvtDS
+                | FeqD
+                | FltD
+                | FleD
+                | FclassD
+                | FcvtWD
+                | FcvtWuD
+                | FcvtDW
+                | FcvtDWu
+        )
+    }
+
+    pub fn is_csr(&self) -> bool {
+        use Opcode::*;
+        matches!(self, Csrrw | Csrrs | Csrrc | Csrrwi | Csrrsi | Csrrci)
+    }
+
+    /// Whether the instruction carries a rounding-mode field.
+    pub fn has_rounding_mode(&self) -> bool {
+        use Opcode::*;
+        self.is_float()
+            && !matches!(
+                self,
+                Flw | Fsw
+                    | Fld
+                    | Fsd
+                    | FsgnjS
+                    | FsgnjnS
+                    | FsgnjxS
+                    | FsgnjD
+                    | FsgnjnD
+                    | FsgnjxD
+                    | FminS
+                    | FmaxS
+                    | FminD
+                    | FmaxD
+                    | FeqS
+                    | FltS
+                    | FleS
+                    | FeqD
+                    | FltD
+                    | FleD
+                    | FclassS
+                    | FclassD
+                    | FmvXW
+                    | FmvWX
+            )
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -17,7 +397,13 @@ pub struct Instruction {
     pub rd: u8,
     pub rs1: u8,
     pub rs2: u8,
+    /// For floating-point instructions with a rounding mode, `imm` holds the
+    /// `rm` field; for CSR instructions it holds the CSR number.
     pub imm: i32,
+    /// Third source register of the fused multiply-add instructions.
+    pub rs3: u8,
+    /// Encoded size in bytes: 4, or 2 for compressed instructions.
+    pub size: u8,
 }
 
 impl Instruction {
@@ -28,34 +414,567 @@ impl Instruction {
             rs1,
             rs2,
             imm,
+            rs3: 0,
+            size: 4,
+        }
+    }
+
+    pub fn compressed(mut self) -> Self {
+        self.size = 2;
+        self
+    }
+
+    pub fn with_rs3(mut self, rs3: u8) -> Self {
+        self.rs3 = rs3;
+        self
+    }
+
+    /// The operand fields that name integer registers. Floating-point
+    /// operands and CSR immediates are not subject to the base ISA's register
+    /// limit.
+    pub fn int_registers(&self) -> Vec<u8> {
+        use Opcode::*;
+        match self.opcode {
+            Flw | Fld | Fsw | Fsd | FcvtSW | FcvtSWu | FmvWX | FcvtDW | FcvtDWu => vec![self.rs1],
+            FcvtWS | FcvtWuS | FmvXW | FeqS | FltS | FleS | FclassS | FcvtWD | FcvtWuD | FeqD
+            | FltD | FleD | FclassD => vec![self.rd],
+            Csrrw | Csrrs | Csrrc => vec![self.rd, self.rs1],
+            Csrrwi | Csrrsi | Csrrci => vec![self.rd],
+            op if op.is_float() => vec![],
+            _ => vec![self.rd, self.rs1, self.rs2],
+        }
+    }
+
+    /// The integer register the instruction writes, if any. Syscalls return
+    /// their result in a0.
+    pub fn int_destination(&self) -> Option<u8> {
+        use Opcode::*;
+        match self.opcode {
+            Ecall => Some(10),
+            Nop | Ebreak => None,
+            FcvtWS | FcvtWuS | FmvXW | FeqS | FltS | FleS | FclassS | FcvtWD | FcvtWuD | FeqD
+            | FltD | FleD | FclassD => Some(self.rd),
+            op if op.is_float() || op.is_store() || op.is_branch() => None,
+            _ => Some(self.rd),
+        }
+    }
+}
+
+fn imm_i(word: u32) -> i32 {
+    (word as i32) >> 20
+}
+
+fn imm_s(word: u32) -> i32 {
+    (((word as i32) >> 25) << 5) | ((word >> 7) & 0x1f) as i32
+}
+
+fn imm_b(word: u32) -> i32 {
+    (((word as i32) >> 31) << 12)
+        | (((word >> 7) & 0x1) << 11) as i32
+        | (((word >> 25) & 0x3f) << 5) as i32
+        | (((word >> 8) & 0xf) << 1) as i32
+}
+
+fn imm_u(word: u32) -> i32 {
+    (word & 0xffff_f000) as i32
+}
+
+fn imm_j(word: u32) -> i32 {
+    (((word as i32) >> 31) << 20)
+        | (word & 0x000f_f000) as i32
+        | (((word >> 20) & 0x1) << 11) as i32
+        | (((word >> 21) & 0x3ff) << 1) as i32
+}
+
+/// Decodes one instruction. `word` holds the instruction in its low bits;
+/// compressed instructions only use the low half-word.
+pub fn decode_instruction(word: u32, profile: IsaProfile) -> Result<Instruction, &'static str> {
+    let instruction = if word & 0x3 != 0x3 {
+        if !profile.has(Extension::C) {
+            return Err("Compressed instructions not enabled in ISA profile");
         }
+        decode_compressed(word as u16)?
+    } else {
+        decode_full(word, profile.xlen())?
+    };
+
+    if !profile.supports(instruction.opcode) {
+        return Err("Instruction not enabled in ISA profile");
+    }
+    if !profile.allows(&instruction) {
+        return Err("Register not available in ISA profile");
     }
+
+    Ok(instruction)
 }
 
-pub fn decode_instruction(word: u32) -> Result<Instruction, &'static str> {
+fn decode_full(word: u32, xlen: u32) -> Result<Instruction, &'static str> {
+    use Opcode::*;
+
     let opcode_bits = word & 0x7f;
     let rd = ((word >> 7) & 0x1f) as u8;
     let rs1 = ((word >> 15) & 0x1f) as u8;
     let rs2 = ((word >> 20) & 0x1f) as u8;
-    let imm = (word as i32) >> 20;
-
-    let opcode = match opcode_bits {
-        0x33 => match (word >> 25) & 0x7f {
-            0x00 => Opcode::Add,
-            0x20 => Opcode::Sub,
-            0x01 => match (word >> 12) & 0x7 {
-                0x0 => Opcode::Mul,
-                0x4 => Opcode::Div,
-                _ => return Err("Unknown M-extension instruction"),
-            },
-            _ => return Err("Unknown R-type instruction"),
+    let funct3 = (word >> 12) & 0x7;
+    let funct7 = word >> 25;
+
+    let instruction = match opcode_bits {
+        0x37 => Instruction::new(Lui, rd, 0, 0, imm_u(word)),
+        0x17 => Instruction::new(Auipc, rd, 0, 0, imm_u(word)),
+        0x6f => Instruction::new(Jump, rd, 0, 0, imm_j(word)),
+        0x67 if funct3 == 0 => Instruction::new(Jalr, rd, rs1, 0, imm_i(word)),
+        0x63 => {
+            let opcode = match funct3 {
+                0x0 => Branch,
+                0x1 => Bne,
+                0x4 => Blt,
+                0x5 => Bge,
+                0x6 => Bltu,
+                0x7 => Bgeu,
+                _ => return Err("Unknown branch instruction"),
+            };
+            Instruction::new(opcode, 0, rs1, rs2, imm_b(word))
+        }
+        0x03 => {
+            let opcode = match funct3 {
+                0x0 => Lb,
+                0x1 => Lh,
+                0x2 => Load,
+                0x3 => Ld,
+                0x4 => Lbu,
+                0x5 => Lhu,
+                0x6 => Lwu,
+                _ => return Err("Unknown load instruction"),
+            };
+            Instruction::new(opcode, rd, rs1, 0, imm_i(word))
+        }
+        0x23 => {
+            let opcode = match funct3 {
+                0x0 => Sb,
+                0x1 => Sh,
+                0x2 => Store,
+                0x3 => Sd,
+                _ => return Err("Unknown store instruction"),
+            };
+            Instruction::new(opcode, 0, rs1, rs2, imm_s(word))
+        }
+        0x13 if xlen == 64 && (funct3 == 0x1 || funct3 == 0x5) => {
+            // RV64 shifts take a 6-bit shift amount
+            let shamt = ((word >> 20) & 0x3f) as i32;
+            let opcode = match (funct3, word >> 26) {
+                (0x1, 0x00) => Slli,
+                (0x5, 0x00) => Srli,
+                (0x5, 0x10) => Srai,
+                _ => return Err("Unknown shift-immediate instruction"),
+            };
+            Instruction::new(opcode, rd, rs1, 0, shamt)
+        }
+        0x13 => decode_op_imm(word, rd, rs1, funct3, funct7)?,
+        0x1b => {
+            let shamt = rs2 as i32;
+            let (opcode, imm) = match (funct7, funct3) {
+                (_, 0x0) => (Addiw, imm_i(word)),
+                (0x00, 0x1) => (Slliw, shamt),
+                (0x00, 0x5) => (Srliw, shamt),
+                (0x20, 0x5) => (Sraiw, shamt),
+                _ => return Err("Unknown word-immediate instruction"),
+            };
+            Instruction::new(opcode, rd, rs1, 0, imm)
+        }
+        0x3b => {
+            let opcode = match (funct7, funct3) {
+                (0x00, 0x0) => Addw,
+                (0x20, 0x0) => Subw,
+                (0x00, 0x1) => Sllw,
+                (0x00, 0x5) => Srlw,
+                (0x20, 0x5) => Sraw,
+                (0x01, 0x0) => Mulw,
+                (0x01, 0x4) => Divw,
+                (0x01, 0x5) => Divuw,
+                (0x01, 0x6) => Remw,
+                (0x01, 0x7) => Remuw,
+                _ => return Err("Unknown word instruction"),
+            };
+            Instruction::new(opcode, rd, rs1, rs2, 0)
+        }
+        0x33 => {
+            let opcode = match (funct7, funct3) {
+                (0x00, 0x0) => Add,
+                (0x20, 0x0) => Sub,
+                (0x00, 0x1) => Sll,
+                (0x00, 0x2) => Slt,
+                (0x00, 0x3) => Sltu,
+                (0x00, 0x4) => Xor,
+                (0x00, 0x5) => Srl,
+                (0x20, 0x5) => Sra,
+                (0x00, 0x6) => Or,
+                (0x00, 0x7) => And,
+                (0x01, 0x0) => Mul,
+                (0x01, 0x1) => Mulh,
+                (0x01, 0x2) => Mulhsu,
+                (0x01, 0x3) => Mulhu,
+                (0x01, 0x4) => Div,
+                (0x01, 0x5) => Divu,
+                (0x01, 0x6) => Rem,
+                (0x01, 0x7) => Remu,
+                (0x10, 0x2) => Sh1add,
+                (0x10, 0x4) => Sh2add,
+                (0x10, 0x6) => Sh3add,
+                (0x20, 0x7) => Andn,
+                (0x20, 0x6) => Orn,
+                (0x20, 0x4) => Xnor,
+                (0x05, 0x6) => Max,
+                (0x05, 0x7) => Maxu,
+                (0x05, 0x4) => Min,
+                (0x05, 0x5) => Minu,
+                (0x05, 0x1) => Clmul,
+                (0x05, 0x3) => Clmulh,
+                (0x05, 0x2) => Clmulr,
+                (0x04, 0x4) if rs2 == 0 => ZextH,
+                (0x30, 0x1) => Rol,
+                (0x30, 0x5) => Ror,
+                (0x24, 0x1) => Bclr,
+                (0x24, 0x5) => Bext,
+                (0x34, 0x1) => Binv,
+                (0x14, 0x1) => Bset,
+                _ => return Err("Unknown R-type instruction"),
+            };
+            Instruction::new(opcode, rd, rs1, rs2, 0)
+        }
+        0x2f if funct3 == 0x2 => {
+            let opcode = match funct7 >> 2 {
+                0x02 if rs2 == 0 => LrW,
+                0x03 => ScW,
+                0x01 => AmoSwapW,
+                0x00 => AmoAddW,
+                0x04 => AmoXorW,
+                0x0c => AmoAndW,
+                0x08 => AmoOrW,
+                0x10 => AmoMinW,
+                0x14 => AmoMaxW,
+                0x18 => AmoMinuW,
+                0x1c => AmoMaxuW,
+                _ => return Err("Unknown atomic instruction"),
+            };
+            Instruction::new(opcode, rd, rs1, rs2, 0)
+        }
+        0x0f if funct3 <= 0x1 => Instruction::new(Nop, 0, 0, 0, 0),
+        0x73 if funct3 == 0 && rd == 0 && rs1 == 0 => match imm_i(word) {
+            0 => Instruction::new(Ecall, 0, 0, 0, 0),
+            1 => Instruction::new(Ebreak, 0, 0, 0, 0),
+            _ => return Err("Unknown system instruction"),
         },
-        0x03 => Opcode::Load,
-        0x23 => Opcode::Store,
-        0x63 => Opcode::Branch,
-        0x6f => Opcode::Jump,
-        _ => Opcode::Nop,
+        0x73 if funct3 != 0 && funct3 != 0x4 => {
+            let opcode = match funct3 {
+                0x1 => Csrrw,
+                0x2 => Csrrs,
+                0x3 => Csrrc,
+                0x5 => Csrrwi,
+                0x6 => Csrrsi,
+                _ => Csrrci,
+            };
+            Instruction::new(opcode, rd, rs1, 0, (word >> 20) as i32)
+        }
+        0x07 => {
+            let opcode = match funct3 {
+                0x2 => Flw,
+                0x3 => Fld,
+                _ => return Err("Unknown floating-point load"),
+            };
+            Instruction::new(opcode, rd, rs1, 0, imm_i(word))
+        }
+        0x27 => {
+            let opcode = match funct3 {
+                0x2 => Fsw,
+                0x3 => Fsd,
+                _ => return Err("Unknown floating-point store"),
+            };
+            Instruction::new(opcode, 0, rs1, rs2, imm_s(word))
+        }
+        0x43 | 0x47 | 0x4b | 0x4f => {
+            let double = match (word >> 25) & 0x3 {
+                0x0 => false,
+                0x1 => true,
+                _ => return Err("Unknown fused multiply-add format"),
+            };
+            let opcode = match (opcode_bits, double) {
+                (0x43, false) => FmaddS,
+                (0x47, false) => FmsubS,
+                (0x4b, false) => FnmsubS,
+                (0x4f, false) => FnmaddS,
+                (0x43, true) => FmaddD,
+                (0x47, true) => FmsubD,
+                (0x4b, true) => FnmsubD,
+                _ => FnmaddD,
+            };
+            Instruction::new(opcode, rd, rs1, rs2, funct3 as i32).with_rs3((word >> 27) as u8)
+        }
+        0x53 => decode_op_fp(rd, rs1, rs2, funct3, funct7)?,
+        _ => return Err("Unknown instruction"),
+    };
+
+    Ok(instruction)
+}
+
+fn decode_op_imm(
+    word: u32,
+    rd: u8,
+    rs1: u8,
+    funct3: u32,
+    funct7: u32,
+) -> Result<Instruction, &'static str> {
+    use Opcode::*;
+
+    let shamt = ((word >> 20) & 0x1f) as i32;
+    let (opcode, imm) = match funct3 {
+        0x0 => (Addi, imm_i(word)),
+        0x2 => (Slti, imm_i(word)),
+        0x3 => (Sltiu, imm_i(word)),
+        0x4 => (Xori, imm_i(word)),
+        0x6 => (Ori, imm_i(word)),
+        0x7 => (Andi, imm_i(word)),
+        0x1 => match (funct7, shamt) {
+            (0x00, _) => (Slli, shamt),
+            (0x24, _) => (Bclri, shamt),
+            (0x34, _) => (Binvi, shamt),
+            (0x14, _) => (Bseti, shamt),
+            (0x30, 0x00) => (Clz, 0),
+            (0x30, 0x01) => (Ctz, 0),
+            (0x30, 0x02) => (Cpop, 0),
+            (0x30, 0x04) => (SextB, 0),
+            (0x30, 0x05) => (SextH, 0),
+            _ => return Err("Unknown shift-immediate instruction"),
+        },
+        _ => match (funct7, shamt) {
+            (0x00, _) => (Srli, shamt),
+            (0x20, _) => (Srai, shamt),
+            (0x30, _) => (Rori, shamt),
+            (0x24, _) => (Bexti, shamt),
+            (0x14, 0x07) => (OrcB, 0),
+            (0x34, 0x18) => (Rev8, 0),
+            _ => return Err("Unknown shift-immediate instruction"),
+        },
+    };
+
+    Ok(Instruction::new(opcode, rd, rs1, 0, imm))
+}
+
+fn decode_op_fp(
+    rd: u8,
+    rs1: u8,
+    rs2: u8,
+    funct3: u32,
+    funct7: u32,
+) -> Result<Instruction, &'static str> {
+    use Opcode::*;
+
+    let (opcode, rs2) = match (funct7, funct3, rs2) {
+        (0x00, _, _) => (FaddS, rs2),
+        (0x04, _, _) => (FsubS, rs2),
+        (0x08, _, _) => (FmulS, rs2),
+        (0x0c, _, _) => (FdivS, rs2),
+        (0x2c, _, 0) => (FsqrtS, 0),
+        (0x10, 0x0, _) => (FsgnjS, rs2),
+        (0x10, 0x1, _) => (FsgnjnS, rs2),
+        (0x10, 0x2, _) => (FsgnjxS, rs2),
+        (0x14, 0x0, _) => (FminS, rs2),
+        (0x14, 0x1, _) => (FmaxS, rs2),
+        (0x60, _, 0) => (FcvtWS, 0),
+        (0x60, _, 1) => (FcvtWuS, 0),
+        (0x70, 0x0, 0) => (FmvXW, 0),
+        (0x70, 0x1, 0) => (FclassS, 0),
+        (0x50, 0x2, _) => (FeqS, rs2),
+        (0x50, 0x1, _) => (FltS, rs2),
+        (0x50, 0x0, _) => (FleS, rs2),
+        (0x68, _, 0) => (FcvtSW, 0),
+        (0x68, _, 1) => (FcvtSWu, 0),
+        (0x78, 0x0, 0) => (FmvWX, 0),
+        (0x01, _, _) => (FaddD, rs2),
+        (0x05, _, _) => (FsubD, rs2),
+        (0x09, _, _) => (FmulD, rs2),
+        (0x0d, _, _) => (FdivD, rs2),
+        (0x2d, _, 0) => (FsqrtD, 0),
+        (0x11, 0x0, _) => (FsgnjD, rs2),
+        (0x11, 0x1, _) => (FsgnjnD, rs2),
+        (0x11, 0x2, _) => (FsgnjxD, rs2),
+        (0x15, 0x0, _) => (FminD, rs2),
+        (0x15, 0x1, _) => (FmaxD, rs2),
+        (0x20, _, 1) => (FcvtSD, 0),
+        (0x21, _, 0) => (FcvtDS, 0),
+        (0x51, 0x2, _) => (FeqD, rs2),
+        (0x51, 0x1, _) => (FltD, rs2),
+        (0x51, 0x0, _) => (FleD, rs2),
+        (0x71, 0x1, 0) => (FclassD, 0),
+        (0x61, _, 0) => (FcvtWD, 0),
+        (0x61, _, 1) => (FcvtWuD, 0),
+        (0x69, _, 0) => (FcvtDW, 0),
+        (0x69, _, 1) => (FcvtDWu, 0),
+        _ => return Err("Unknown floating-point instruction"),
+    };
+    let rm = if opcode.has_rounding_mode() {
+        funct3 as i32
+    } else {
+        0
+    };
+
+    Ok(Instruction::new(opcode, rd, rs1, rs2, rm))
+}
+
+fn bits(half: u16, hi: u32, lo: u32) -> u32 {
+    ((half as u32) >> lo) & ((1 << (hi - lo + 1)) - 1)
+}
+
+/// Sign-extends the low `width` bits of `value`.
+fn sext(value: u32, width: u32) -> i32 {
+    let shift = 32 - width;
+    ((value << shift) as i32) >> shift
+}
+
+/// Expands a 16-bit RVC instruction into its 32-bit equivalent.
+fn decode_compressed(half: u16) -> Result<Instruction, &'static str> {
+    use Opcode::*;
+
+    if half == 0 {
+        return Err("Illegal compressed instruction");
+    }
+
+    // Register fields: full 5-bit and 3-bit "popular register" (x8-x15) forms.
+    let rd = bits(half, 11, 7) as u8;
+    let rs2 = bits(half, 6, 2) as u8;
+    let rd_p = 8 + bits(half, 4, 2) as u8;
+    let rs1_p = 8 + bits(half, 9, 7) as u8;
+    let funct3 = bits(half, 15, 13);
+
+    // CI-format 6-bit signed immediate (C.ADDI, C.LI, C.ANDI)
+    let imm6 = sext((bits(half, 12, 12) << 5) | bits(half, 6, 2), 6);
+    // CJ-format jump offset
+    let imm_cj = sext(
+        (bits(half, 12, 12) << 11)
+            | (bits(half, 11, 11) << 4)
+            | (bits(half, 10, 9) << 8)
+            | (bits(half, 8, 8) << 10)
+            | (bits(half, 7, 7) << 6)
+            | (bits(half, 6, 6) << 7)
+            | (bits(half, 5, 3) << 1)
+            | (bits(half, 2, 2) << 5),
+        12,
+    );
+    // CB-format branch offset
+    let imm_cb = sext(
+        (bits(half, 12, 12) << 8)
+            | (bits(half, 11, 10) << 3)
+            | (bits(half, 6, 5) << 6)
+            | (bits(half, 4, 3) << 1)
+            | (bits(half, 2, 2) << 5),
+        9,
+    );
+    // CL/CS-format word offset
+    let uimm_w =
+        ((bits(half, 12, 10) << 3) | (bits(half, 6, 6) << 2) | (bits(half, 5, 5) << 6)) as i32;
+    // CL/CS-format double-word offset
+    let uimm_d = ((bits(half, 12, 10) << 3) | (bits(half, 6, 5) << 6)) as i32;
+
+    let instruction = match (half & 0x3, funct3) {
+        (0x0, 0x0) => {
+            let nzuimm = (bits(half, 12, 11) << 4)
+                | (bits(half, 10, 7) << 6)
+                | (bits(half, 6, 6) << 2)
+                | (bits(half, 5, 5) << 3);
+            if nzuimm == 0 {
+                return Err("Illegal compressed instruction");
+            }
+            Instruction::new(Addi, rd_p, 2, 0, nzuimm as i32)
+        }
+        (0x0, 0x1) => Instruction::new(Fld, rd_p, rs1_p, 0, uimm_d),
+        (0x0, 0x2) => Instruction::new(Load, rd_p, rs1_p, 0, uimm_w),
+        (0x0, 0x3) => Instruction::new(Flw, rd_p, rs1_p, 0, uimm_w),
+        (0x0, 0x5) => Instruction::new(Fsd, 0, rs1_p, rd_p, uimm_d),
+        (0x0, 0x6) => Instruction::new(Store, 0, rs1_p, rd_p, uimm_w),
+        (0x0, 0x7) => Instruction::new(Fsw, 0, rs1_p, rd_p, uimm_w),
+        (0x1, 0x0) => Instruction::new(Addi, rd, rd, 0, imm6),
+        (0x1, 0x1) => Instruction::new(Jump, 1, 0, 0, imm_cj),
+        (0x1, 0x2) => Instruction::new(Addi, rd, 0, 0, imm6),
+        (0x1, 0x3) if rd == 2 => {
+            let nzimm = sext(
+                (bits(half, 12, 12) << 9)
+                    | (bits(half, 6, 6) << 4)
+                    | (bits(half, 5, 5) << 6)
+                    | (bits(half, 4, 3) << 7)
+                    | (bits(half, 2, 2) << 5),
+                10,
+            );
+            if nzimm == 0 {
+                return Err("Illegal compressed instruction");
+            }
+            Instruction::new(Addi, 2, 2, 0, nzimm)
+        }
+        (0x1, 0x3) => {
+            if imm6 == 0 {
+                return Err("Illegal compressed instruction");
+            }
+            Instruction::new(Lui, rd, 0, 0, imm6 << 12)
+        }
+        (0x1, 0x4) => {
+            let shamt = bits(half, 6, 2) as i32;
+            match bits(half, 11, 10) {
+                0x0 if bits(half, 12, 12) == 0 => Instruction::new(Srli, rs1_p, rs1_p, 0, shamt),
+                0x1 if bits(half, 12, 12) == 0 => Instruction::new(Srai, rs1_p, rs1_p, 0, shamt),
+                0x2 => Instruction::new(Andi, rs1_p, rs1_p, 0, imm6),
+                0x3 if bits(half, 12, 12) == 0 => {
+                    let opcode = match bits(half, 6, 5) {
+                        0x0 => Sub,
+                        0x1 => Xor,
+                        0x2 => Or,
+                        _ => And,
+                    };
+                    Instruction::new(opcode, rs1_p, rs1_p, rd_p, 0)
+                }
+                _ => return Err("Unknown compressed instruction"),
+            }
+        }
+        (0x1, 0x5) => Instruction::new(Jump, 0, 0, 0, imm_cj),
+        (0x1, 0x6) => Instruction::new(Branch, 0, rs1_p, 0, imm_cb),
+        (0x1, 0x7) => Instruction::new(Bne, 0, rs1_p, 0, imm_cb),
+        (0x2, 0x0) if bits(half, 12, 12) == 0 => {
+            Instruction::new(Slli, rd, rd, 0, bits(half, 6, 2) as i32)
+        }
+        (0x2, 0x1) => {
+            let uimm =
+                (bits(half, 12, 12) << 5) | (bits(half, 6, 5) << 3) | (bits(half, 4, 2) << 6);
+            Instruction::new(Fld, rd, 2, 0, uimm as i32)
+        }
+        (0x2, 0x2) if rd != 0 => {
+            let uimm =
+                (bits(half, 12, 12) << 5) | (bits(half, 6, 4) << 2) | (bits(half, 3, 2) << 6);
+            Instruction::new(Load, rd, 2, 0, uimm as i32)
+        }
+        (0x2, 0x3) => {
+            let uimm =
+                (bits(half, 12, 12) << 5) | (bits(half, 6, 4) << 2) | (bits(half, 3, 2) << 6);
+            Instruction::new(Flw, rd, 2, 0, uimm as i32)
+        }
+        (0x2, 0x4) => match (bits(half, 12, 12), rd, rs2) {
+            (0, 0, _) => return Err("Illegal compressed instruction"),
+            (0, _, 0) => Instruction::new(Jalr, 0, rd, 0, 0),
+            (0, _, _) => Instruction::new(Add, rd, 0, rs2, 0),
+            (_, 0, 0) => Instruction::new(Ebreak, 0, 0, 0, 0),
+            (_, _, 0) => Instruction::new(Jalr, 1, rd, 0, 0),
+            _ => Instruction::new(Add, rd, rd, rs2, 0),
+        },
+        (0x2, 0x5) => {
+            let uimm = (bits(half, 12, 10) << 3) | (bits(half, 9, 7) << 6);
+            Instruction::new(Fsd, 0, 2, rs2, uimm as i32)
+        }
+        (0x2, 0x6) => {
+            let uimm = (bits(half, 12, 9) << 2) | (bits(half, 8, 7) << 6);
+            Instruction::new(Store, 0, 2, rs2, uimm as i32)
+        }
+        (0x2, 0x7) => {
+            let uimm = (bits(half, 12, 9) << 2) | (bits(half, 8, 7) << 6);
+            Instruction::new(Fsw, 0, 2, rs2, uimm as i32)
+        }
+        _ => return Err("Unknown compressed instruction"),
     };
 
-    Ok(Instruction::new(opcode, rd, rs1, rs2, imm))
+    Ok(instruction.compressed())
 }
@@ -0,0 +1,203 @@
+use super::{Instruction, Opcode};
+use std::fmt;
+
+/// Base integer ISA, which fixes the size of the register file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BaseIsa {
+    #[default]
+    Rv32I,
+    /// Embedded base ISA with only x0-x15.
+    Rv32E,
+    Rv64I,
+}
+
+impl BaseIsa {
+    pub fn num_registers(&self) -> usize {
+        match self {
+            BaseIsa::Rv32I | BaseIsa::Rv64I => 32,
+            BaseIsa::Rv32E => 16,
+        }
+    }
+
+    /// Register width in bits.
+    pub fn xlen(&self) -> u32 {
+        match self {
+            BaseIsa::Rv32I | BaseIsa::Rv32E => 32,
+            BaseIsa::Rv64I => 64,
+        }
+    }
+
+    /// RV64 mode only implements the M extension.
+    fn supports(&self, extension: Extension) -> bool {
+        *self != BaseIsa::Rv64I || extension == Extension::M
+    }
+}
+
+/// Optional standard extensions on top of the base ISA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Extension {
+    M,
+    A,
+    C,
+    Zba,
+    Zbb,
+    Zbc,
+    Zbs,
+    /// Single-precision floating point, with the `fcsr` CSRs.
+    F,
+    /// Double-precision floating point. Requires F.
+    D,
+}
+
+impl Extension {
+    pub const ALL: [Extension; 9] = [
+        Extension::M,
+        Extension::A,
+        Extension::C,
+        Extension::Zba,
+        Extension::Zbb,
+        Extension::Zbc,
+        Extension::Zbs,
+        Extension::F,
+        Extension::D,
+    ];
+
+    const fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+/// The ISA a VM instance executes. The decoder, the execution trace and the
+/// constraint system are all sized and gated from the same profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IsaProfile {
+    pub base: BaseIsa,
+    extensions: u32,
+}
+
+impl Default for IsaProfile {
+    fn default() -> Self {
+        Self::RV32IM
+    }
+}
+
+impl IsaProfile {
+    pub const RV32I: Self = Self::new(BaseIsa::Rv32I);
+    pub const RV32IM: Self = Self::RV32I.with(Extension::M);
+    pub const RV32IMC: Self = Self::RV32IM.with(Extension::C);
+    pub const RV32IMAC: Self = Self::RV32IMC.with(Extension::A);
+    pub const RV32E: Self = Self::new(BaseIsa::Rv32E);
+    pub const RV32IMFD: Self = Self::RV32IM.with(Extension::F).with(Extension::D);
+    pub const RV32IMAFDC: Self = Self::RV32IMAC.with(Extension::F).with(Extension::D);
+    pub const RV64I: Self = Self::new(BaseIsa::Rv64I);
+    pub const RV64IM: Self = Self::RV64I.with(Extension::M);
+
+    pub const fn new(base: BaseIsa) -> Self {
+        Self {
+            base,
+            extensions: 0,
+        }
+    }
+
+    pub const fn with(mut self, extension: Extension) -> Self {
+        self.extensions |= extension.bit();
+        self
+    }
+
+    /// Adds the B extension, i.e. Zba, Zbb and Zbs.
+    pub const fn with_b(self) -> Self {
+        self.with(Extension::Zba)
+            .with(Extension::Zbb)
+            .with(Extension::Zbs)
+    }
+
+    pub fn has(&self, extension: Extension) -> bool {
+        let requires_f = extension == Extension::D && !self.has(Extension::F);
+        self.extensions & extension.bit() != 0 && self.base.supports(extension) && !requires_f
+    }
+
+    pub fn xlen(&self) -> u32 {
+        self.base.xlen()
+    }
+
+    pub fn num_registers(&self) -> usize {
+        self.base.num_registers()
+    }
+
+    pub fn has_register(&self, reg: u8) -> bool {
+        (reg as usize) < self.num_registers()
+    }
+
+    pub fn supports(&self, opcode: Opcode) -> bool {
+        opcode.extension().is_none_or(|ext| self.has(ext))
+            && (!opcode.is_rv64_only() || self.xlen() == 64)
+    }
+
+    /// Whether `instruction` may appear in a program for this profile,
+    /// including its encoding size and register operands.
+    pub fn allows(&self, instruction: &Instruction) -> bool {
+        self.supports(instruction.opcode)
+            && (instruction.size == 4 || self.has(Extension::C))
+            && instruction
+                .int_registers()
+                .iter()
+                .all(|&reg| self.has_register(reg))
+    }
+
+    /// Stable numeric identifier, used to commit the profile into proofs.
+    pub fn id(&self) -> u32 {
+        let base = match self.base {
+            BaseIsa::Rv32I => 0,
+            BaseIsa::Rv32E => 1,
+            BaseIsa::Rv64I => 2,
+        };
+        (self.extensions << 2) | base
+    }
+
+    pub fn from_id(id: u32) -> Option<Self> {
+        let base = match id & 0x3 {
+            0 => BaseIsa::Rv32I,
+            1 => BaseIsa::Rv32E,
+            2 => BaseIsa::Rv64I,
+            _ => return None,
+        };
+        let extensions = id >> 2;
+        let known = Extension::ALL.iter().fold(0, |acc, ext| acc | ext.bit());
+        if extensions & !known != 0 {
+            return None;
+        }
+        Some(Self { base, extensions })
+    }
+}
+
+impl fmt::Display for IsaProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.base {
+            BaseIsa::Rv32I => write!(f, "RV32I")?,
+            BaseIsa::Rv32E => write!(f, "RV32E")?,
+            BaseIsa::Rv64I => write!(f, "RV64I")?,
+        }
+        for (ext, name) in [
+            (Extension::M, "M"),
+            (Extension::A, "A"),
+            (Extension::F, "F"),
+            (Extension::D, "D"),
+            (Extension::C, "C"),
+        ] {
+            if self.has(ext) {
+                write!(f, "{name}")?;
+            }
+        }
+        for (ext, name) in [
+            (Extension::Zba, "_Zba"),
+            (Extension::Zbb, "_Zbb"),
+            (Extension::Zbc, "_Zbc"),
+            (Extension::Zbs, "_Zbs"),
+        ] {
+            if self.has(ext) {
+                write!(f, "{name}")?;
+            }
+        }
+        Ok(())
+    }
+}
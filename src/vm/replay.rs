@@ -0,0 +1,201 @@
+//! Recording and replaying the effects of syscalls.
+//!
+//! Every syscall a [`VmState`] executes is appended to its `syscall_log`
+//! together with everything it changed. A VM built with
+//! [`VmState::with_replay`] applies those effects from the log instead of
+//! running the syscall, so an execution can be reproduced without the host's
+//! handlers or files; its input is the bytes the log consumed. A replay that
+//! issues a different syscall, or whose syscall reads different guest memory,
+//! fails.
+
+use super::{ExitStatus, GuestPanic, VmState};
+use crate::crypto::HashValue;
+use serde::{Deserialize, Serialize};
+
+/// One executed syscall and its effects on the VM.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyscallRecord {
+    pub syscall: u32,
+    /// a0-a2 when the syscall was issued.
+    pub args: [u32; 3],
+    /// The value written to a0.
+    pub result: u32,
+    /// Guest memory words the syscall read.
+    pub memory_reads: Vec<(u32, u32)>,
+    /// Guest memory words the syscall wrote, with their new values.
+    pub memory_writes: Vec<(u32, u32)>,
+    /// Bytes consumed from the input, and those appended to the journal and
+    /// the standard streams.
+    pub input: Vec<u8>,
+    pub journal: Vec<u8>,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// Program break, input cursor and key-value state root afterwards.
+    pub brk: u32,
+    pub input_cursor: usize,
+    pub state_root: Option<HashValue>,
+    /// Set when the syscall stopped the guest.
+    pub exit_status: Option<ExitStatus>,
+    pub panic: Option<GuestPanic>,
+}
+
+/// The syscalls of one execution, in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplayLog {
+    pub records: Vec<SyscallRecord>,
+}
+
+impl ReplayLog {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, &'static str> {
+        bincode::serialize(self).map_err(|_| "Serialization failed")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        bincode::deserialize(bytes).map_err(|_| "Invalid replay log")
+    }
+}
+
+/// A log being replayed and the index of the next record.
+#[derive(Debug, Clone)]
+pub(super) struct Replay {
+    log: ReplayLog,
+    next: usize,
+}
+
+/// Lengths of the VM's output buffers before a syscall.
+pub(super) struct OutputMark {
+    input: usize,
+    journal: usize,
+    stdout: usize,
+    stderr: usize,
+}
+
+impl VmState {
+    /// Replays syscalls from `log` instead of executing them. Syscall
+    /// handlers and files are not consulted, and the input becomes the bytes
+    /// the log consumed.
+    pub fn with_replay(mut self, log: ReplayLog) -> Self {
+        self.input = log
+            .records
+            .iter()
+            .flat_map(|record| record.input.iter().copied())
+            .collect();
+        self.replay = Some(Replay { log, next: 0 });
+        self
+    }
+
+    /// Records of the log being replayed that have not been reached yet.
+    pub fn replay_remaining(&self) -> Option<usize> {
+        self.replay
+            .as_ref()
+            .map(|replay| replay.log.records.len() - replay.next)
+    }
+
+    /// Fails if the guest stopped before every record of the replay log was
+    /// reached.
+    pub fn check_replay_finished(&self) -> Result<(), &'static str> {
+        let stopped = matches!(
+            self.exit_status,
+            Some(ExitStatus::Halted(_) | ExitStatus::Panicked)
+        );
+        match self.replay_remaining() {
+            Some(remaining) if stopped && remaining > 0 => {
+                Err("Replay diverged: guest stopped before the end of the log")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(super) fn output_mark(&self) -> OutputMark {
+        OutputMark {
+            input: self.input_cursor,
+            journal: self.journal.len(),
+            stdout: self.stdout.len(),
+            stderr: self.stderr.len(),
+        }
+    }
+
+    /// Appends the syscall just executed to `syscall_log`.
+    pub(super) fn record_syscall(
+        &mut self,
+        args: [u32; 3],
+        result: u32,
+        mark: OutputMark,
+        memory_reads: &[(u32, u32)],
+        memory_writes: &[(u32, u32, u32)],
+    ) {
+        let record = SyscallRecord {
+            syscall: self.pending_syscall(),
+            args,
+            result,
+            memory_reads: memory_reads.to_vec(),
+            memory_writes: memory_writes
+                .iter()
+                .map(|&(addr, _, value)| (addr, value))
+                .collect(),
+            input: self
+                .input
+                .get(mark.input..self.input_cursor)
+                .unwrap_or_default()
+                .to_vec(),
+            journal: self.journal[mark.journal..].to_vec(),
+            stdout: self.stdout[mark.stdout..].to_vec(),
+            stderr: self.stderr[mark.stderr..].to_vec(),
+            brk: self.brk,
+            input_cursor: self.input_cursor,
+            state_root: self.state_root,
+            exit_status: self.exit_status,
+            panic: self.panic.clone(),
+        };
+        self.syscall_log.records.push(record);
+    }
+
+    /// Applies the next record of the replay log to the VM and returns its
+    /// result.
+    pub(super) fn replay_syscall(
+        &mut self,
+        args: [u32; 3],
+        memory_reads: &mut Vec<(u32, u32)>,
+        memory_writes: &mut Vec<(u32, u32, u32)>,
+    ) -> Result<u32, &'static str> {
+        let replay = self.replay.as_mut().ok_or("Not replaying")?;
+        let record = replay
+            .log
+            .records
+            .get(replay.next)
+            .ok_or("Replay diverged: more syscalls than were recorded")?
+            .clone();
+        replay.next += 1;
+
+        if record.syscall != self.pending_syscall() || record.args != args {
+            return Err("Replay diverged: syscall or arguments differ");
+        }
+        for &(addr, value) in &record.memory_reads {
+            if self.memory.read_word(addr)? != value {
+                return Err("Replay diverged: syscall read different memory");
+            }
+            memory_reads.push((addr, value));
+        }
+        for &(addr, value) in &record.memory_writes {
+            memory_writes.push((addr, self.memory.read_word(addr)?, value));
+            self.memory.write_word(addr, value)?;
+        }
+        self.journal.extend_from_slice(&record.journal);
+        self.stdout.extend_from_slice(&record.stdout);
+        self.stderr.extend_from_slice(&record.stderr);
+        if self.set_brk(record.brk) != record.brk {
+            return Err("Replay diverged: program break outside the heap");
+        }
+        self.input_cursor = record.input_cursor;
+        self.state_root = record.state_root;
+        if record.exit_status.is_some() {
+            self.exit_status = record.exit_status;
+        }
+        if record.panic.is_some() {
+            self.panic = record.panic.clone();
+        }
+        let result = record.result;
+        self.syscall_log.records.push(record);
+        Ok(result)
+    }
+}
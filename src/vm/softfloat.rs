@@ -0,0 +1,674 @@
+//! IEEE-754 binary32/binary64 arithmetic on integers.
+//!
+//! Results are bit-exact and independent of the host FPU, which is what the
+//! F and D extensions need to stay deterministic and provable. NaN results are
+//! always the RISC-V canonical NaN. Every operation returns the raised
+//! exception flags alongside its result, in `fflags` bit order.
+
+use std::cmp::Ordering;
+
+pub const FLAG_INEXACT: u32 = 0x01;
+pub const FLAG_UNDERFLOW: u32 = 0x02;
+pub const FLAG_OVERFLOW: u32 = 0x04;
+pub const FLAG_DIV_BY_ZERO: u32 = 0x08;
+pub const FLAG_INVALID: u32 = 0x10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundingMode {
+    NearestEven,
+    TowardZero,
+    Down,
+    Up,
+    NearestMaxMagnitude,
+}
+
+impl RoundingMode {
+    /// Decodes the RISC-V `rm`/`frm` encoding. The dynamic mode (7) has to be
+    /// resolved by the caller.
+    pub fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            0 => Some(RoundingMode::NearestEven),
+            1 => Some(RoundingMode::TowardZero),
+            2 => Some(RoundingMode::Down),
+            3 => Some(RoundingMode::Up),
+            4 => Some(RoundingMode::NearestMaxMagnitude),
+            _ => None,
+        }
+    }
+}
+
+/// An IEEE-754 binary interchange format. Values are passed around as raw
+/// bits in the low bits of a `u64`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FloatFormat {
+    pub exp_bits: u32,
+    pub mant_bits: u32,
+}
+
+pub const F32: FloatFormat = FloatFormat {
+    exp_bits: 8,
+    mant_bits: 23,
+};
+
+pub const F64: FloatFormat = FloatFormat {
+    exp_bits: 11,
+    mant_bits: 52,
+};
+
+/// Unpacked operand. A finite value is `sig * 2^exp`.
+#[derive(Debug, Clone, Copy)]
+enum Value {
+    Nan { signaling: bool },
+    Inf(bool),
+    Zero(bool),
+    Finite { sign: bool, sig: u128, exp: i32 },
+}
+
+fn bit_length(value: u128) -> i32 {
+    128 - value.leading_zeros() as i32
+}
+
+/// Shifts right, OR-ing every discarded bit into the result's lowest bit.
+fn shift_right_jam(value: u128, shift: u32) -> u128 {
+    if shift == 0 {
+        value
+    } else if shift >= 128 {
+        (value != 0) as u128
+    } else {
+        (value >> shift) | ((value & ((1 << shift) - 1) != 0) as u128)
+    }
+}
+
+/// Drops the low `shift` bits of `sig`, rounding according to `rm`. Returns
+/// the rounded value and whether any discarded bit was set.
+fn shift_right_round(sig: u128, shift: u32, sign: bool, rm: RoundingMode) -> (u128, bool) {
+    if shift == 0 {
+        return (sig, false);
+    }
+    let (quotient, half_cmp) = if shift > 128 {
+        (0, Ordering::Less)
+    } else if shift == 128 {
+        (0, sig.cmp(&(1 << 127)))
+    } else {
+        let rem = sig & ((1 << shift) - 1);
+        (sig >> shift, rem.cmp(&(1 << (shift - 1))))
+    };
+    let inexact = if shift >= 128 {
+        sig != 0
+    } else {
+        sig & ((1 << shift) - 1) != 0
+    };
+    let round_up = match rm {
+        RoundingMode::NearestEven => {
+            half_cmp == Ordering::Greater || (half_cmp == Ordering::Equal && quotient & 1 == 1)
+        }
+        RoundingMode::TowardZero => false,
+        RoundingMode::Down => sign && inexact,
+        RoundingMode::Up => !sign && inexact,
+        RoundingMode::NearestMaxMagnitude => inexact && half_cmp != Ordering::Less,
+    };
+    (quotient + round_up as u128, inexact)
+}
+
+fn isqrt(value: u128) -> u128 {
+    if value == 0 {
+        return 0;
+    }
+    let mut remainder = value;
+    let mut root = 0u128;
+    let mut bit = 1u128 << ((bit_length(value) - 1) & !1);
+    while bit != 0 {
+        if remainder >= root + bit {
+            remainder -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    root
+}
+
+/// Exact (or correctly jammed) sum of two signed finite values. Returns
+/// `None` when the sum is exactly zero.
+fn add_finite(
+    (sign_a, sig_a, exp_a): (bool, u128, i32),
+    (sign_b, sig_b, exp_b): (bool, u128, i32),
+) -> Option<(bool, u128, i32)> {
+    // Align both operands in a 126-bit window below the larger top bit. The
+    // larger operand always fits exactly; a much smaller one is jammed into
+    // a sticky bit far below the rounding position.
+    let top = (exp_a + bit_length(sig_a)).max(exp_b + bit_length(sig_b));
+    let exp = exp_a.min(exp_b).max(top - 126);
+    let align = |sig: u128, e: i32| {
+        if e >= exp {
+            sig << (e - exp)
+        } else {
+            shift_right_jam(sig, (exp - e) as u32)
+        }
+    };
+    let a = align(sig_a, exp_a);
+    let b = align(sig_b, exp_b);
+
+    if sign_a == sign_b {
+        Some((sign_a, a + b, exp))
+    } else {
+        match a.cmp(&b) {
+            Ordering::Greater => Some((sign_a, a - b, exp)),
+            Ordering::Less => Some((sign_b, b - a, exp)),
+            Ordering::Equal => None,
+        }
+    }
+}
+
+impl FloatFormat {
+    fn width(&self) -> u32 {
+        1 + self.exp_bits + self.mant_bits
+    }
+
+    fn bias(&self) -> i32 {
+        (1 << (self.exp_bits - 1)) - 1
+    }
+
+    fn max_exp_field(&self) -> u64 {
+        (1 << self.exp_bits) - 1
+    }
+
+    pub fn sign_bit(&self) -> u64 {
+        1 << (self.width() - 1)
+    }
+
+    fn mask(&self) -> u64 {
+        if self.width() == 64 {
+            u64::MAX
+        } else {
+            (1 << self.width()) - 1
+        }
+    }
+
+    pub fn canonical_nan(&self) -> u64 {
+        (self.max_exp_field() << self.mant_bits) | (1 << (self.mant_bits - 1))
+    }
+
+    fn infinity(&self, sign: bool) -> u64 {
+        self.with_sign(self.max_exp_field() << self.mant_bits, sign)
+    }
+
+    fn zero(&self, sign: bool) -> u64 {
+        self.with_sign(0, sign)
+    }
+
+    fn max_finite(&self, sign: bool) -> u64 {
+        self.with_sign(self.infinity(false) - 1, sign)
+    }
+
+    fn with_sign(&self, magnitude: u64, sign: bool) -> u64 {
+        if sign {
+            magnitude | self.sign_bit()
+        } else {
+            magnitude
+        }
+    }
+
+    fn unpack(&self, bits: u64) -> Value {
+        let bits = bits & self.mask();
+        let sign = bits & self.sign_bit() != 0;
+        let exp_field = (bits >> self.mant_bits) & self.max_exp_field();
+        let frac = bits & ((1 << self.mant_bits) - 1);
+        let mant_bits = self.mant_bits as i32;
+
+        if exp_field == self.max_exp_field() {
+            if frac == 0 {
+                Value::Inf(sign)
+            } else {
+                Value::Nan {
+                    signaling: frac & (1 << (self.mant_bits - 1)) == 0,
+                }
+            }
+        } else if exp_field == 0 {
+            if frac == 0 {
+                Value::Zero(sign)
+            } else {
+                Value::Finite {
+                    sign,
+                    sig: frac as u128,
+                    exp: 1 - self.bias() - mant_bits,
+                }
+            }
+        } else {
+            Value::Finite {
+                sign,
+                sig: (frac | (1 << self.mant_bits)) as u128,
+                exp: exp_field as i32 - self.bias() - mant_bits,
+            }
+        }
+    }
+
+    fn is_signaling(&self, bits: u64) -> bool {
+        matches!(self.unpack(bits), Value::Nan { signaling: true })
+    }
+
+    fn is_nan(&self, bits: u64) -> bool {
+        matches!(self.unpack(bits), Value::Nan { .. })
+    }
+
+    /// Rounds the non-zero value `(-1)^sign * sig * 2^exp` into this format.
+    fn round_pack(&self, sign: bool, sig: u128, exp: i32, rm: RoundingMode) -> (u64, u32) {
+        let mant_bits = self.mant_bits as i32;
+        let emin = 1 - self.bias();
+        let msb = bit_length(sig) - 1;
+        // The value lies in [2^e, 2^(e+1))
+        let e = exp + msb;
+        let tiny = e < emin;
+
+        // Number of low bits of `sig` below the result's last place
+        let shift = if tiny {
+            (emin - mant_bits) - exp
+        } else {
+            msb - mant_bits
+        };
+        let (rounded, inexact) = if shift <= 0 {
+            (sig << -shift, false)
+        } else {
+            shift_right_round(sig, shift as u32, sign, rm)
+        };
+
+        // A carry out of the significand lands in the exponent field on its own
+        let magnitude = if tiny {
+            rounded
+        } else {
+            (((e + self.bias() - 1) as u128) << self.mant_bits) + rounded
+        };
+
+        let mut flags = 0;
+        if inexact {
+            flags |= FLAG_INEXACT;
+        }
+
+        if magnitude >= (self.max_exp_field() << self.mant_bits) as u128 {
+            let to_infinity = match rm {
+                RoundingMode::NearestEven | RoundingMode::NearestMaxMagnitude => true,
+                RoundingMode::TowardZero => false,
+                RoundingMode::Down => sign,
+                RoundingMode::Up => !sign,
+            };
+            let result = if to_infinity {
+                self.infinity(sign)
+            } else {
+                self.max_finite(sign)
+            };
+            return (result, FLAG_OVERFLOW | FLAG_INEXACT);
+        }
+
+        // Tininess is detected after rounding: a value that only reaches the
+        // smallest normal through rounding is not tiny.
+        if tiny && inexact {
+            let rounds_to_normal = e == emin - 1 && {
+                let (normal, _) = shift_right_round(sig, (msb - mant_bits).max(0) as u32, sign, rm);
+                normal >> (mant_bits + 1) != 0 && msb > mant_bits
+            };
+            if !rounds_to_normal {
+                flags |= FLAG_UNDERFLOW;
+            }
+        }
+
+        (self.with_sign(magnitude as u64, sign), flags)
+    }
+
+    fn nan_result(&self, operands: &[u64]) -> (u64, u32) {
+        let flags = if operands.iter().any(|&bits| self.is_signaling(bits)) {
+            FLAG_INVALID
+        } else {
+            0
+        };
+        (self.canonical_nan(), flags)
+    }
+
+    pub fn add(&self, a: u64, b: u64, rm: RoundingMode) -> (u64, u32) {
+        match (self.unpack(a), self.unpack(b)) {
+            (Value::Nan { .. }, _) | (_, Value::Nan { .. }) => self.nan_result(&[a, b]),
+            (Value::Inf(sa), Value::Inf(sb)) if sa != sb => (self.canonical_nan(), FLAG_INVALID),
+            (Value::Inf(sign), _) | (_, Value::Inf(sign)) => (self.infinity(sign), 0),
+            (Value::Zero(sa), Value::Zero(sb)) => {
+                let sign = if sa == sb {
+                    sa
+                } else {
+                    rm == RoundingMode::Down
+                };
+                (self.zero(sign), 0)
+            }
+            (Value::Zero(_), Value::Finite { .. }) => (b & self.mask(), 0),
+            (Value::Finite { .. }, Value::Zero(_)) => (a & self.mask(), 0),
+            (
+                Value::Finite {
+                    sign: sa,
+                    sig: ma,
+                    exp: ea,
+                },
+                Value::Finite {
+                    sign: sb,
+                    sig: mb,
+                    exp: eb,
+                },
+            ) => match add_finite((sa, ma, ea), (sb, mb, eb)) {
+                Some((sign, sig, exp)) => self.round_pack(sign, sig, exp, rm),
+                None => (self.zero(rm == RoundingMode::Down), 0),
+            },
+        }
+    }
+
+    pub fn sub(&self, a: u64, b: u64, rm: RoundingMode) -> (u64, u32) {
+        self.add(a, b ^ self.sign_bit(), rm)
+    }
+
+    pub fn mul(&self, a: u64, b: u64, rm: RoundingMode) -> (u64, u32) {
+        match (self.unpack(a), self.unpack(b)) {
+            (Value::Nan { .. }, _) | (_, Value::Nan { .. }) => self.nan_result(&[a, b]),
+            (Value::Inf(_), Value::Zero(_)) | (Value::Zero(_), Value::Inf(_)) => {
+                (self.canonical_nan(), FLAG_INVALID)
+            }
+            (Value::Inf(sa), other) | (other, Value::Inf(sa)) => {
+                (self.infinity(sa ^ value_sign(&other)), 0)
+            }
+            (Value::Zero(sa), other) | (other, Value::Zero(sa)) => {
+                (self.zero(sa ^ value_sign(&other)), 0)
+            }
+            (
+                Value::Finite {
+                    sign: sa,
+                    sig: ma,
+                    exp: ea,
+                },
+                Value::Finite {
+                    sign: sb,
+                    sig: mb,
+                    exp: eb,
+                },
+            ) => self.round_pack(sa ^ sb, ma * mb, ea + eb, rm),
+        }
+    }
+
+    pub fn div(&self, a: u64, b: u64, rm: RoundingMode) -> (u64, u32) {
+        match (self.unpack(a), self.unpack(b)) {
+            (Value::Nan { .. }, _) | (_, Value::Nan { .. }) => self.nan_result(&[a, b]),
+            (Value::Inf(_), Value::Inf(_)) | (Value::Zero(_), Value::Zero(_)) => {
+                (self.canonical_nan(), FLAG_INVALID)
+            }
+            (Value::Inf(sa), other) => (self.infinity(sa ^ value_sign(&other)), 0),
+            (other, Value::Inf(sb)) => (self.zero(sb ^ value_sign(&other)), 0),
+            (Value::Zero(sa), other) => (self.zero(sa ^ value_sign(&other)), 0),
+            (other, Value::Zero(sb)) => (self.infinity(sb ^ value_sign(&other)), FLAG_DIV_BY_ZERO),
+            (
+                Value::Finite {
+                    sign: sa,
+                    sig: ma,
+                    exp: ea,
+                },
+                Value::Finite {
+                    sign: sb,
+                    sig: mb,
+                    exp: eb,
+                },
+            ) => {
+                // Scale the dividend so the quotient has two bits beyond the
+                // result precision, then fold the remainder into a sticky bit.
+                let shift = (self.mant_bits as i32 + 3 + bit_length(mb) - bit_length(ma)).max(0);
+                let dividend = ma << shift;
+                let quotient = dividend / mb;
+                let sticky = !dividend.is_multiple_of(mb) as u128;
+                self.round_pack(sa ^ sb, (quotient << 1) | sticky, ea - eb - shift - 1, rm)
+            }
+        }
+    }
+
+    pub fn sqrt(&self, a: u64, rm: RoundingMode) -> (u64, u32) {
+        match self.unpack(a) {
+            Value::Nan { .. } => self.nan_result(&[a]),
+            Value::Zero(sign) => (self.zero(sign), 0),
+            Value::Inf(false) => (self.infinity(false), 0),
+            Value::Inf(true) | Value::Finite { sign: true, .. } => {
+                (self.canonical_nan(), FLAG_INVALID)
+            }
+            Value::Finite { sig, exp, .. } => {
+                let mut shift = 2 * (self.mant_bits as i32 + 3) - bit_length(sig);
+                if (exp - shift) % 2 != 0 {
+                    shift += 1;
+                }
+                let radicand = sig << shift;
+                let root = isqrt(radicand);
+                let sticky = (root * root != radicand) as u128;
+                self.round_pack(false, (root << 1) | sticky, (exp - shift) / 2 - 1, rm)
+            }
+        }
+    }
+
+    /// Fused `a * b + c` with a single rounding.
+    pub fn fma(&self, a: u64, b: u64, c: u64, rm: RoundingMode) -> (u64, u32) {
+        let (va, vb, vc) = (self.unpack(a), self.unpack(b), self.unpack(c));
+
+        // inf * 0 is invalid even when the addend is a quiet NaN
+        let invalid_product = matches!(
+            (va, vb),
+            (Value::Inf(_), Value::Zero(_)) | (Value::Zero(_), Value::Inf(_))
+        );
+        if invalid_product || [va, vb, vc].iter().any(|v| matches!(v, Value::Nan { .. })) {
+            let (nan, flags) = self.nan_result(&[a, b, c]);
+            let invalid = if invalid_product { FLAG_INVALID } else { 0 };
+            return (nan, flags | invalid);
+        }
+
+        let product_sign = value_sign(&va) ^ value_sign(&vb);
+        let product = match (va, vb) {
+            (Value::Inf(_), _) | (_, Value::Inf(_)) => Value::Inf(product_sign),
+            (Value::Zero(_), _) | (_, Value::Zero(_)) => Value::Zero(product_sign),
+            (
+                Value::Finite {
+                    sig: ma, exp: ea, ..
+                },
+                Value::Finite {
+                    sig: mb, exp: eb, ..
+                },
+            ) => Value::Finite {
+                sign: product_sign,
+                sig: ma * mb,
+                exp: ea + eb,
+            },
+            _ => unreachable!("NaN operands are handled above"),
+        };
+
+        match (product, vc) {
+            (Value::Inf(sp), Value::Inf(sc)) if sp != sc => (self.canonical_nan(), FLAG_INVALID),
+            (Value::Inf(sign), _) | (_, Value::Inf(sign)) => (self.infinity(sign), 0),
+            (Value::Zero(sp), Value::Zero(sc)) => {
+                let sign = if sp == sc {
+                    sp
+                } else {
+                    rm == RoundingMode::Down
+                };
+                (self.zero(sign), 0)
+            }
+            (Value::Zero(_), Value::Finite { .. }) => (c & self.mask(), 0),
+            (Value::Finite { sign, sig, exp }, Value::Zero(_)) => {
+                self.round_pack(sign, sig, exp, rm)
+            }
+            (
+                Value::Finite {
+                    sign: sp,
+                    sig: mp,
+                    exp: ep,
+                },
+                Value::Finite {
+                    sign: sc,
+                    sig: mc,
+                    exp: ec,
+                },
+            ) => match add_finite((sp, mp, ep), (sc, mc, ec)) {
+                Some((sign, sig, exp)) => self.round_pack(sign, sig, exp, rm),
+                None => (self.zero(rm == RoundingMode::Down), 0),
+            },
+            _ => unreachable!("NaN operands are handled above"),
+        }
+    }
+
+    /// Total order key for non-NaN values, with -0 == +0.
+    fn order_key(&self, bits: u64) -> i128 {
+        let magnitude = (bits & self.mask() & !self.sign_bit()) as i128;
+        if bits & self.sign_bit() != 0 {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+
+    /// Quiet equality: only signaling NaNs raise the invalid flag.
+    pub fn eq(&self, a: u64, b: u64) -> (bool, u32) {
+        if self.is_nan(a) || self.is_nan(b) {
+            return (false, self.nan_result(&[a, b]).1);
+        }
+        (self.order_key(a) == self.order_key(b), 0)
+    }
+
+    /// Signaling less-than: any NaN raises the invalid flag.
+    pub fn lt(&self, a: u64, b: u64) -> (bool, u32) {
+        if self.is_nan(a) || self.is_nan(b) {
+            return (false, FLAG_INVALID);
+        }
+        (self.order_key(a) < self.order_key(b), 0)
+    }
+
+    /// Signaling less-or-equal: any NaN raises the invalid flag.
+    pub fn le(&self, a: u64, b: u64) -> (bool, u32) {
+        if self.is_nan(a) || self.is_nan(b) {
+            return (false, FLAG_INVALID);
+        }
+        (self.order_key(a) <= self.order_key(b), 0)
+    }
+
+    /// IEEE 754-2019 minimumNumber/maximumNumber, with -0 < +0.
+    fn min_max(&self, a: u64, b: u64, max: bool) -> (u64, u32) {
+        let (_, flags) = self.nan_result(&[a, b]);
+        let (a, b) = (a & self.mask(), b & self.mask());
+        let result = match (self.is_nan(a), self.is_nan(b)) {
+            (true, true) => self.canonical_nan(),
+            (true, false) => b,
+            (false, true) => a,
+            (false, false) => {
+                let (ka, kb) = (self.order_key(a), self.order_key(b));
+                let a_sign = a & self.sign_bit() != 0;
+                let pick_a = match ka.cmp(&kb) {
+                    Ordering::Less => !max,
+                    Ordering::Greater => max,
+                    Ordering::Equal => a_sign != max,
+                };
+                if pick_a {
+                    a
+                } else {
+                    b
+                }
+            }
+        };
+        (result, flags)
+    }
+
+    pub fn min(&self, a: u64, b: u64) -> (u64, u32) {
+        self.min_max(a, b, false)
+    }
+
+    pub fn max(&self, a: u64, b: u64) -> (u64, u32) {
+        self.min_max(a, b, true)
+    }
+
+    /// The RISC-V `fclass` mask.
+    pub fn classify(&self, bits: u64) -> u32 {
+        let subnormal = (bits >> self.mant_bits) & self.max_exp_field() == 0;
+        match self.unpack(bits) {
+            Value::Inf(true) => 1 << 0,
+            Value::Finite { sign: true, .. } if !subnormal => 1 << 1,
+            Value::Finite { sign: true, .. } => 1 << 2,
+            Value::Zero(true) => 1 << 3,
+            Value::Zero(false) => 1 << 4,
+            Value::Finite { .. } if subnormal => 1 << 5,
+            Value::Finite { .. } => 1 << 6,
+            Value::Inf(false) => 1 << 7,
+            Value::Nan { signaling: true } => 1 << 8,
+            Value::Nan { signaling: false } => 1 << 9,
+        }
+    }
+
+    /// Converts to a 32-bit integer. Out-of-range inputs and NaN saturate and
+    /// raise the invalid flag.
+    pub fn to_int(&self, bits: u64, signed: bool, rm: RoundingMode) -> (u32, u32) {
+        let (min, max): (i64, i64) = if signed {
+            (i32::MIN as i64, i32::MAX as i64)
+        } else {
+            (0, u32::MAX as i64)
+        };
+        let saturate = |negative: bool| {
+            let value = if negative { min } else { max };
+            (value as u32, FLAG_INVALID)
+        };
+
+        match self.unpack(bits) {
+            Value::Nan { .. } => saturate(false),
+            Value::Inf(sign) => saturate(sign),
+            Value::Zero(_) => (0, 0),
+            Value::Finite { sign, sig, exp } => {
+                if exp > 64 {
+                    return saturate(sign);
+                }
+                let (magnitude, inexact) = if exp >= 0 {
+                    (sig << exp, false)
+                } else {
+                    shift_right_round(sig, (-exp) as u32, sign, rm)
+                };
+                if magnitude > i64::MAX as u128 {
+                    return saturate(sign);
+                }
+                let value = if sign {
+                    -(magnitude as i64)
+                } else {
+                    magnitude as i64
+                };
+                if value < min || value > max {
+                    return saturate(sign);
+                }
+                let flags = if inexact { FLAG_INEXACT } else { 0 };
+                (value as u32, flags)
+            }
+        }
+    }
+
+    /// Converts a 32-bit integer, interpreted as signed or unsigned.
+    pub fn from_int(&self, value: u32, signed: bool, rm: RoundingMode) -> (u64, u32) {
+        let (sign, magnitude) = if signed && (value as i32) < 0 {
+            (true, (value as i32).unsigned_abs())
+        } else {
+            (false, value)
+        };
+        if magnitude == 0 {
+            return (self.zero(false), 0);
+        }
+        self.round_pack(sign, magnitude as u128, 0, rm)
+    }
+
+    /// Converts a value of format `from` into this format.
+    pub fn convert_from(&self, from: FloatFormat, bits: u64, rm: RoundingMode) -> (u64, u32) {
+        match from.unpack(bits) {
+            Value::Nan { signaling } => {
+                let flags = if signaling { FLAG_INVALID } else { 0 };
+                (self.canonical_nan(), flags)
+            }
+            Value::Inf(sign) => (self.infinity(sign), 0),
+            Value::Zero(sign) => (self.zero(sign), 0),
+            Value::Finite { sign, sig, exp } => self.round_pack(sign, sig, exp, rm),
+        }
+    }
+}
+
+fn value_sign(value: &Value) -> bool {
+    match *value {
+        Value::Inf(sign) | Value::Zero(sign) => sign,
+        Value::Finite { sign, .. } => sign,
+        Value::Nan { .. } => false,
+    }
+}
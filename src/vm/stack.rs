@@ -0,0 +1,93 @@
+//! The initial process stack of the RISC-V psABI.
+//!
+//! From `sp` upwards: `argc`, the `argv` pointers and a null, the `envp`
+//! pointers and a null, and an empty auxiliary vector. The strings they
+//! point to sit above, just below the memory map's `stack_top`. Pointers and
+//! `argc` are XLEN bits wide and `sp` is 16-byte aligned.
+
+use super::VmState;
+use std::collections::BTreeMap;
+
+/// Stack pointer register.
+const REG_SP: usize = 2;
+
+impl VmState {
+    /// Lays out `argv` and `envp` (as `NAME=value` strings) below
+    /// `stack_top` and points `sp` at `argc`.
+    pub fn init_stack(&mut self, argv: &[String], envp: &[String]) -> Result<(), &'static str> {
+        let word = self.profile.xlen() / 8;
+        let mut strings = Vec::new();
+        let mut offsets = Vec::new();
+        for arg in argv.iter().chain(envp) {
+            if arg.contains('\0') {
+                return Err("Argument contains a NUL byte");
+            }
+            offsets.push(strings.len() as u32);
+            strings.extend_from_slice(arg.as_bytes());
+            strings.push(0);
+        }
+
+        // argc, both pointer arrays with their nulls and the AT_NULL pair
+        let words = 1 + argv.len() + 1 + envp.len() + 1 + 2;
+        let strings_start = self
+            .memory_map
+            .stack_top
+            .checked_sub(strings.len() as u32)
+            .ok_or("Arguments do not fit on the stack")?;
+        let sp = (strings_start & !15)
+            .checked_sub((words as u32 * word).next_multiple_of(16))
+            .filter(|&sp| sp >= self.heap_start)
+            .ok_or("Arguments do not fit on the stack")?;
+
+        let mut vector = vec![argv.len() as u64];
+        let mut pointers = offsets.iter().map(|offset| (strings_start + offset) as u64);
+        vector.extend(pointers.by_ref().take(argv.len()));
+        vector.push(0);
+        vector.extend(pointers);
+        vector.extend([0, 0, 0]);
+
+        self.memory.load_program(&strings, strings_start)?;
+        for (addr, value) in (sp..).step_by(word as usize).zip(vector) {
+            if word == 8 {
+                self.memory.write_dword(addr, value)?;
+            } else {
+                self.memory.write_word(addr, value as u32)?;
+            }
+        }
+        self.registers[REG_SP] = sp as u64;
+        self.argv = argv.to_vec();
+        Ok(())
+    }
+}
+
+/// The `argv` of the initial stack at `sp`, read from `memory` given as
+/// its nonzero words by address, or `None` if `sp` does not point at one.
+pub fn read_argv(memory: &BTreeMap<u32, u32>, sp: u32, xlen: u32) -> Option<Vec<String>> {
+    let word = |addr: u32| memory.get(&addr).copied().unwrap_or(0);
+    let byte = |addr: u32| (word(addr & !3) >> (8 * (addr & 3))) as u8;
+    let size = xlen / 8;
+    let pointer = |index: u32| {
+        let addr = sp.checked_add(index.checked_mul(size)?)?;
+        if size == 8 && word(addr.checked_add(4)?) != 0 {
+            return None;
+        }
+        Some(word(addr))
+    };
+
+    // Every argument has a nonzero pointer, so argc cannot exceed the words
+    let argc = pointer(0)?;
+    if sp == 0 || argc as usize > memory.len() || pointer(argc + 1)? != 0 {
+        return None;
+    }
+    (1..=argc)
+        .map(|index| {
+            let mut bytes = Vec::new();
+            let mut addr = pointer(index).filter(|&addr| addr != 0)?;
+            while byte(addr) != 0 {
+                bytes.push(byte(addr));
+                addr = addr.checked_add(1)?;
+            }
+            String::from_utf8(bytes).ok()
+        })
+        .collect()
+}
@@ -0,0 +1,438 @@
+use super::{errno, BaseIsa, ExitStatus, VirtualFs, VmState};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use zkvm_guest::codec;
+use zkvm_guest::syscall::{fd, nr};
+
+/// Registers of the syscall ABI: the number in a7, arguments from a0.
+/// RV32E has no a7, so the number goes in t0 there instead.
+const REG_SYSCALL: usize = 17;
+const REG_SYSCALL_RV32E: usize = 5;
+const REG_A0: usize = 10;
+const REG_A1: usize = 11;
+const REG_A2: usize = 12;
+
+/// The register an `ecall` reads its syscall number from under `base`.
+pub fn syscall_register(base: BaseIsa) -> usize {
+    match base {
+        BaseIsa::Rv32E => REG_SYSCALL_RV32E,
+        _ => REG_SYSCALL,
+    }
+}
+
+/// A host-side implementation of a syscall.
+///
+/// Handlers are consulted before the built-in syscalls, so they can also
+/// replace them. The value returned is written to a0.
+pub trait SyscallHandler: Send {
+    fn handle(&mut self, context: &mut SyscallContext) -> Result<u32, &'static str>;
+}
+
+impl<F> SyscallHandler for F
+where
+    F: FnMut(&mut SyscallContext) -> Result<u32, &'static str> + Send,
+{
+    fn handle(&mut self, context: &mut SyscallContext) -> Result<u32, &'static str> {
+        self(context)
+    }
+}
+
+/// The guest state visible to a [`SyscallHandler`]. Memory accessed through
+/// it is recorded in the trace.
+pub struct SyscallContext<'a> {
+    vm: &'a mut VmState,
+    memory_reads: &'a mut Vec<(u32, u32)>,
+    memory_writes: &'a mut Vec<(u32, u32, u32)>,
+}
+
+impl SyscallContext<'_> {
+    pub fn syscall(&self) -> u32 {
+        self.vm.pending_syscall()
+    }
+
+    /// Argument register `a{index}`.
+    pub fn arg(&self, index: usize) -> u32 {
+        assert!(index < 6, "syscall arguments are a0-a5");
+        self.vm.registers[REG_A0 + index] as u32
+    }
+
+    pub fn state(&self) -> &VmState {
+        self.vm
+    }
+
+    pub fn read_bytes(&mut self, addr: u32, len: u32) -> Result<Vec<u8>, &'static str> {
+        self.vm.read_guest_bytes(addr, len, self.memory_reads)
+    }
+
+    pub fn write_bytes(&mut self, addr: u32, bytes: &[u8]) -> Result<(), &'static str> {
+        self.vm.write_guest_bytes(addr, bytes, self.memory_writes)
+    }
+
+    /// Copies up to `len` bytes of the input stream to `addr`, as
+    /// `READ_INPUT` does. Returns the count copied.
+    pub fn read_input(&mut self, addr: u32, len: u32) -> Result<u32, &'static str> {
+        self.vm.read_input(addr, len, self.memory_writes)
+    }
+
+    /// Writes `len` bytes at `addr` to `fd::STDOUT` or `fd::STDERR`, as
+    /// `WRITE` does. Returns the count written or a negated errno value.
+    pub fn write_output(&mut self, fd: u32, addr: u32, len: u32) -> Result<u32, &'static str> {
+        self.vm.write_output(fd, addr, len, self.memory_reads)
+    }
+
+    pub fn files(&mut self) -> &mut VirtualFs {
+        &mut self.vm.files
+    }
+
+    /// Opens the file named by the `len` bytes at `addr`. Returns the file
+    /// descriptor or a negated errno value.
+    pub fn open_file(&mut self, addr: u32, len: u32) -> Result<u32, &'static str> {
+        self.vm.open_file(addr, len, self.memory_reads)
+    }
+
+    /// Copies up to `len` bytes from file descriptor `fd` to `addr`. Returns
+    /// the count copied or a negated errno value.
+    pub fn read_file(&mut self, fd: u32, addr: u32, len: u32) -> Result<u32, &'static str> {
+        self.vm.read_file(fd, addr, len, self.memory_writes)
+    }
+
+    /// Moves the program break; see [`VmState::set_brk`].
+    pub fn set_brk(&mut self, addr: u32) -> u32 {
+        self.vm.set_brk(addr)
+    }
+
+    /// Stops the guest with exit code `code`.
+    pub fn halt(&mut self, code: u32) {
+        self.vm.exit_status = Some(ExitStatus::Halted(code));
+    }
+}
+
+/// Where a guest panic happened.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PanicLocation {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+/// A panic reported by the guest through the `PANIC` syscall.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GuestPanic {
+    pub message: String,
+    pub location: Option<PanicLocation>,
+}
+
+impl fmt::Display for GuestPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some(location) => write!(
+                f,
+                "guest panicked at {}:{}:{}:\n{}",
+                location.file, location.line, location.column, self.message
+            ),
+            None => write!(f, "guest panicked:\n{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for GuestPanic {}
+
+type SharedHandler = Arc<Mutex<Box<dyn SyscallHandler>>>;
+type SharedWriter = Arc<Mutex<Box<dyn Write + Send>>>;
+
+/// Host hooks installed on a [`VmState`]. Clones of the state share them.
+#[derive(Clone, Default)]
+pub(super) struct HostHooks {
+    handlers: BTreeMap<u32, SharedHandler>,
+    stdout: Option<SharedWriter>,
+    stderr: Option<SharedWriter>,
+}
+
+impl fmt::Debug for HostHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HostHooks")
+            .field("handlers", &self.handlers.keys().collect::<Vec<_>>())
+            .field("stdout", &self.stdout.is_some())
+            .field("stderr", &self.stderr.is_some())
+            .finish()
+    }
+}
+
+/// Word-aligned addresses covering `len` bytes from `addr`, which must not
+/// overflow.
+fn words_covering(addr: u32, len: u32) -> impl Iterator<Item = u32> {
+    let end = addr + len;
+    (addr & !3..end).step_by(4)
+}
+
+impl VmState {
+    /// Installs `handler` for syscall number `syscall`.
+    pub fn with_syscall_handler(mut self, syscall: u32, handler: Box<dyn SyscallHandler>) -> Self {
+        self.hooks
+            .handlers
+            .insert(syscall, Arc::new(Mutex::new(handler)));
+        self
+    }
+
+    /// Streams the guest's standard output to `writer` as it is written, in
+    /// addition to capturing it in `stdout`.
+    pub fn with_stdout(mut self, writer: Box<dyn Write + Send>) -> Self {
+        self.hooks.stdout = Some(Arc::new(Mutex::new(writer)));
+        self
+    }
+
+    /// Streams the guest's standard error to `writer`, in addition to
+    /// capturing it in `stderr`.
+    pub fn with_stderr(mut self, writer: Box<dyn Write + Send>) -> Self {
+        self.hooks.stderr = Some(Arc::new(Mutex::new(writer)));
+        self
+    }
+
+    /// Encodes `value` with the [`codec`] and appends it to the input stream,
+    /// for the guest to read back with `env::read`.
+    pub fn write_input<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), &'static str> {
+        let bytes = codec::to_vec(value).map_err(|err| err.as_str())?;
+        self.input.extend_from_slice(&bytes);
+        Ok(())
+    }
+
+    /// Decodes the journal as a single value committed with `env::commit`.
+    pub fn decode_journal<T: DeserializeOwned>(&self) -> Result<T, &'static str> {
+        codec::from_slice(&self.journal).map_err(|err| err.as_str())
+    }
+
+    /// The syscall number the next `ecall` would issue.
+    pub fn pending_syscall(&self) -> u32 {
+        self.registers[syscall_register(self.profile.base)] as u32
+    }
+
+    /// Checks that the `len` bytes from `addr` lie in guest memory, before
+    /// a syscall touches any of them.
+    fn check_guest_range(&self, addr: u32, len: u32) -> Result<(), &'static str> {
+        match addr.checked_add(len) {
+            Some(end) if end as usize <= self.memory.size() => Ok(()),
+            _ => Err("Memory address out of bounds"),
+        }
+    }
+
+    /// Reads guest memory for a syscall, recording the words touched.
+    pub(super) fn read_guest_bytes(
+        &self,
+        addr: u32,
+        len: u32,
+        memory_reads: &mut Vec<(u32, u32)>,
+    ) -> Result<Vec<u8>, &'static str> {
+        self.check_guest_range(addr, len)?;
+        for word in words_covering(addr, len) {
+            memory_reads.push((word, self.memory.read_word(word)?));
+        }
+        (0..len).map(|i| self.memory.read_byte(addr + i)).collect()
+    }
+
+    /// Writes guest memory for a syscall, recording the words touched.
+    pub(super) fn write_guest_bytes(
+        &mut self,
+        addr: u32,
+        bytes: &[u8],
+        memory_writes: &mut Vec<(u32, u32, u32)>,
+    ) -> Result<(), &'static str> {
+        let len = u32::try_from(bytes.len()).map_err(|_| "Memory address out of bounds")?;
+        self.check_guest_range(addr, len)?;
+        let end = addr + len;
+        for word in words_covering(addr, len) {
+            let old_value = self.memory.read_word(word)?;
+            let (start, stop) = (word.max(addr), word.saturating_add(4).min(end));
+            let chunk = &bytes[(start - addr) as usize..(stop - addr) as usize];
+            self.memory.load_program(chunk, start)?;
+            memory_writes.push((word, old_value, self.memory.read_word(word)?));
+        }
+        Ok(())
+    }
+
+    fn read_input(
+        &mut self,
+        addr: u32,
+        len: u32,
+        memory_writes: &mut Vec<(u32, u32, u32)>,
+    ) -> Result<u32, &'static str> {
+        let remaining = &self.input[self.input_cursor.min(self.input.len())..];
+        let len = remaining.len().min(len as usize);
+        let bytes = remaining[..len].to_vec();
+        self.write_guest_bytes(addr, &bytes, memory_writes)?;
+        self.input_cursor += len;
+        Ok(len as u32)
+    }
+
+    fn open_file(
+        &mut self,
+        addr: u32,
+        len: u32,
+        memory_reads: &mut Vec<(u32, u32)>,
+    ) -> Result<u32, &'static str> {
+        let path = self.read_guest_bytes(addr, len, memory_reads)?;
+        Ok(match std::str::from_utf8(&path) {
+            Ok(path) => self.files.open(path).unwrap_or_else(errno::to_result),
+            Err(_) => errno::to_result(errno::ENOENT),
+        })
+    }
+
+    fn read_file(
+        &mut self,
+        fd: u32,
+        addr: u32,
+        len: u32,
+        memory_writes: &mut Vec<(u32, u32, u32)>,
+    ) -> Result<u32, &'static str> {
+        let bytes = match self.files.read(fd, len) {
+            Ok(bytes) => bytes.to_vec(),
+            Err(code) => return Ok(errno::to_result(code)),
+        };
+        self.write_guest_bytes(addr, &bytes, memory_writes)?;
+        Ok(bytes.len() as u32)
+    }
+
+    /// Captures the `len` bytes at `addr` written to standard output or
+    /// error and streams them to the installed writer, if any.
+    fn write_output(
+        &mut self,
+        fd: u32,
+        addr: u32,
+        len: u32,
+        memory_reads: &mut Vec<(u32, u32)>,
+    ) -> Result<u32, &'static str> {
+        if fd != fd::STDOUT && fd != fd::STDERR {
+            return Ok(errno::to_result(errno::EBADF));
+        }
+        // The count written must not read back as a negated errno value
+        if len > i32::MAX as u32 {
+            return Ok(errno::to_result(errno::EINVAL));
+        }
+        let bytes = self.read_guest_bytes(addr, len, memory_reads)?;
+        let (captured, writer) = match fd {
+            fd::STDOUT => (&mut self.stdout, &self.hooks.stdout),
+            _ => (&mut self.stderr, &self.hooks.stderr),
+        };
+        captured.extend_from_slice(&bytes);
+        if let Some(writer) = writer {
+            let mut writer = writer.lock().map_err(|_| "Output writer panicked")?;
+            writer
+                .write_all(&bytes)
+                .and_then(|_| writer.flush())
+                .map_err(|_| "Failed to write guest output")?;
+        }
+        Ok(len)
+    }
+
+    /// Reads the `zkvm_guest::syscall::PanicLocation` at `addr`.
+    fn read_panic_location(
+        &self,
+        addr: u32,
+        memory_reads: &mut Vec<(u32, u32)>,
+    ) -> Result<PanicLocation, &'static str> {
+        let words: Vec<u32> = self
+            .read_guest_bytes(addr, 16, memory_reads)?
+            .chunks(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+        let file = self.read_guest_bytes(words[0], words[1], memory_reads)?;
+        Ok(PanicLocation {
+            file: String::from_utf8_lossy(&file).into_owned(),
+            line: words[2],
+            column: words[3],
+        })
+    }
+
+    /// Executes the `ecall` syscall selected by a7. The trace records
+    /// `[syscall, a0, a1, a2, result]`.
+    pub(super) fn execute_syscall(
+        &mut self,
+        memory_reads: &mut Vec<(u32, u32)>,
+        memory_writes: &mut Vec<(u32, u32, u32)>,
+        values: &mut Vec<u32>,
+    ) -> Result<(), &'static str> {
+        let syscall = self.pending_syscall();
+        let a0 = self.registers[REG_A0] as u32;
+        let a1 = self.registers[REG_A1] as u32;
+        let a2 = self.registers[REG_A2] as u32;
+
+        let result = if self.replay.is_some() {
+            self.replay_syscall([a0, a1, a2], memory_reads, memory_writes)?
+        } else {
+            let (reads_start, writes_start) = (memory_reads.len(), memory_writes.len());
+            let mark = self.output_mark();
+            let result = match self.hooks.handlers.get(&syscall).cloned() {
+                Some(handler) => {
+                    let mut handler = handler.lock().map_err(|_| "Syscall handler panicked")?;
+                    handler.handle(&mut SyscallContext {
+                        vm: self,
+                        memory_reads,
+                        memory_writes,
+                    })?
+                }
+                None => self.builtin_syscall(syscall, [a0, a1, a2], memory_reads, memory_writes)?,
+            };
+            self.record_syscall(
+                [a0, a1, a2],
+                result,
+                mark,
+                &memory_reads[reads_start..],
+                &memory_writes[writes_start..],
+            );
+            result
+        };
+
+        values.extend([syscall, a0, a1, a2, result]);
+        self.registers[REG_A0] = result as u64;
+        Ok(())
+    }
+
+    fn builtin_syscall(
+        &mut self,
+        syscall: u32,
+        [a0, a1, a2]: [u32; 3],
+        memory_reads: &mut Vec<(u32, u32)>,
+        memory_writes: &mut Vec<(u32, u32, u32)>,
+    ) -> Result<u32, &'static str> {
+        Ok(match syscall {
+            nr::HALT => {
+                self.exit_status = Some(ExitStatus::Halted(a0));
+                a0
+            }
+            nr::READ_INPUT => self.read_input(a0, a1, memory_writes)?,
+            nr::WRITE_JOURNAL => {
+                let bytes = self.read_guest_bytes(a0, a1, memory_reads)?;
+                self.journal.extend_from_slice(&bytes);
+                0
+            }
+            nr::PANIC => {
+                let message = self.read_guest_bytes(a0, a1, memory_reads)?;
+                let location = match a2 {
+                    0 => None,
+                    addr => Some(self.read_panic_location(addr, memory_reads)?),
+                };
+                self.panic = Some(GuestPanic {
+                    message: String::from_utf8_lossy(&message).into_owned(),
+                    location,
+                });
+                self.exit_status = Some(ExitStatus::Panicked);
+                0
+            }
+            nr::WRITE => self.write_output(a0, a1, a2, memory_reads)?,
+            nr::BRK => self.set_brk(a0),
+            nr::OPEN => self.open_file(a0, a1, memory_reads)?,
+            nr::READ => self.read_file(a0, a1, a2, memory_writes)?,
+            nr::SEEK => self
+                .files
+                .seek(a0, a1 as i32, a2)
+                .unwrap_or_else(errno::to_result),
+            nr::CLOSE => self.files.close(a0).map_or_else(errno::to_result, |_| 0),
+            nr::KV_GET => self.kv_get(a0, a1, memory_writes)?,
+            nr::KV_SET => self.kv_set(a0, a1, memory_reads)?,
+            _ => return Err("Unsupported environment call"),
+        })
+    }
+}
@@ -0,0 +1,166 @@
+//! A read-only, in-memory filesystem the host populates before the guest
+//! runs.
+//!
+//! File contents are part of the private input. Files added as public also
+//! have their hash reported in [`VirtualFs::commitments`], which proofs
+//! take as public values: the AIR hashes every public file the guest opens
+//! and checks that OPEN, READ, SEEK and CLOSE act on those contents. A run
+//! that opens a private file, or a path that is not a public file, cannot
+//! be proven.
+
+use crate::crypto::HashValue;
+use crate::zkp::sponge_hash;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The first descriptor handed out; 0-2 are the standard streams.
+pub const FIRST_FILE_FD: u32 = 3;
+
+/// `whence` values accepted by [`VirtualFs::seek`].
+pub mod seek {
+    pub const SET: u32 = 0;
+    pub const CUR: u32 = 1;
+    pub const END: u32 = 2;
+}
+
+/// Linux errno values returned to the guest, negated.
+pub mod errno {
+    pub const ENOENT: i32 = 2;
+    pub const EBADF: i32 = 9;
+    pub const EFAULT: i32 = 14;
+    pub const EMFILE: i32 = 24;
+    pub const EINVAL: i32 = 22;
+    pub const EROFS: i32 = 30;
+    pub const ENAMETOOLONG: i32 = 36;
+
+    /// `-code` as the guest sees it in a0.
+    pub fn to_result(code: i32) -> u32 {
+        (-code) as u32
+    }
+}
+
+/// Most files a guest can have open at once.
+const MAX_OPEN_FILES: usize = 64;
+
+#[derive(Debug, Clone)]
+struct VirtualFile {
+    contents: Vec<u8>,
+    public: bool,
+}
+
+#[derive(Debug, Clone)]
+struct OpenFile {
+    path: String,
+    offset: u32,
+}
+
+/// The [`sponge_hash`] of a public file's contents.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileCommitment {
+    pub path: String,
+    pub hash: HashValue,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VirtualFs {
+    files: BTreeMap<String, VirtualFile>,
+    open: BTreeMap<u32, OpenFile>,
+}
+
+impl VirtualFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds or replaces the file at `path`. Only the hash of a `public` file
+    /// is exposed.
+    pub fn insert(&mut self, path: &str, contents: Vec<u8>, public: bool) {
+        self.files
+            .insert(path.to_string(), VirtualFile { contents, public });
+    }
+
+    pub fn contents(&self, path: &str) -> Option<&[u8]> {
+        self.files.get(path).map(|file| file.contents.as_slice())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Paths and contents of the public files, ordered by path.
+    pub fn public_files(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.files
+            .iter()
+            .filter(|(_, file)| file.public)
+            .map(|(path, file)| (path.as_str(), file.contents.as_slice()))
+    }
+
+    /// Hashes of the public files, ordered by path.
+    pub fn commitments(&self) -> Vec<FileCommitment> {
+        self.public_files()
+            .map(|(path, contents)| FileCommitment {
+                path: path.to_string(),
+                hash: sponge_hash(contents),
+            })
+            .collect()
+    }
+
+    pub fn has_open_files(&self) -> bool {
+        !self.open.is_empty()
+    }
+
+    /// Opens `path` on the lowest free descriptor.
+    pub fn open(&mut self, path: &str) -> Result<u32, i32> {
+        if !self.files.contains_key(path) {
+            return Err(errno::ENOENT);
+        }
+        if self.open.len() >= MAX_OPEN_FILES {
+            return Err(errno::EMFILE);
+        }
+        let fd = (FIRST_FILE_FD..)
+            .find(|fd| !self.open.contains_key(fd))
+            .ok_or(errno::EMFILE)?;
+        let path = path.to_string();
+        self.open.insert(fd, OpenFile { path, offset: 0 });
+        Ok(fd)
+    }
+
+    /// Returns up to `len` bytes from the file offset of `fd` and advances
+    /// it.
+    pub fn read(&mut self, fd: u32, len: u32) -> Result<&[u8], i32> {
+        let open = self.open.get_mut(&fd).ok_or(errno::EBADF)?;
+        let contents = &self.files[&open.path].contents;
+        let start = (open.offset as usize).min(contents.len());
+        let end = start + (len as usize).min(contents.len() - start);
+        open.offset = open.offset.max(end as u32);
+        Ok(&contents[start..end])
+    }
+
+    /// Moves the file offset of `fd` as `lseek` does and returns the new
+    /// offset. Seeking past the end is allowed.
+    pub fn seek(&mut self, fd: u32, offset: i32, whence: u32) -> Result<u32, i32> {
+        let open = self.open.get_mut(&fd).ok_or(errno::EBADF)?;
+        let base = match whence {
+            seek::SET => 0,
+            seek::CUR => open.offset as i64,
+            seek::END => self.files[&open.path].contents.len() as i64,
+            _ => return Err(errno::EINVAL),
+        };
+        let offset = base + offset as i64;
+        if !(0..=i32::MAX as i64).contains(&offset) {
+            return Err(errno::EINVAL);
+        }
+        open.offset = offset as u32;
+        Ok(open.offset)
+    }
+
+    /// The size of the file open on `fd`.
+    pub fn size(&self, fd: u32) -> Result<u32, i32> {
+        let open = self.open.get(&fd).ok_or(errno::EBADF)?;
+        Ok(self.files[&open.path].contents.len() as u32)
+    }
+
+    pub fn close(&mut self, fd: u32) -> Result<(), i32> {
+        self.open.remove(&fd).map(|_| ()).ok_or(errno::EBADF)
+    }
+}
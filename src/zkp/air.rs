@@ -0,0 +1,348 @@
+//! Algebraic intermediate representation.
+//!
+//! An [`Air`] fixes the columns of a trace and the polynomial constraints
+//! every row must satisfy. Transition constraints relate a row to the next
+//! one and hold on every row but the last; boundary constraints pin a
+//! column of the first or last row to a public input or a constant, and
+//! lookups claim that values of every row are entries of tables. The same `Air`
+//! describes traces of any length, so its constraints can be turned into
+//! polynomials over the trace domain.
+
+use super::{
+    batch_inverse, BabyBear, BabyBearExt4, ExtensionField, Field, Lookup, LookupChallenges,
+    LookupTable, Multiplicity,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// A polynomial over the columns of the current and the next row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AirExpr {
+    Constant(BabyBear),
+    Current(usize),
+    Next(usize),
+    Add(Box<AirExpr>, Box<AirExpr>),
+    Sub(Box<AirExpr>, Box<AirExpr>),
+    Mul(Box<AirExpr>, Box<AirExpr>),
+}
+
+impl AirExpr {
+    pub fn constant(value: u32) -> Self {
+        Self::Constant(BabyBear::from(value))
+    }
+
+    /// Total degree in the trace columns.
+    pub fn degree(&self) -> usize {
+        match self {
+            Self::Constant(_) => 0,
+            Self::Current(_) | Self::Next(_) => 1,
+            Self::Add(left, right) | Self::Sub(left, right) => left.degree().max(right.degree()),
+            Self::Mul(left, right) => left.degree() + right.degree(),
+        }
+    }
+
+    /// Evaluates the expression on rows over BabyBear or its extension.
+    pub fn evaluate<F: ExtensionField<BabyBear>>(&self, current: &[F], next: &[F]) -> F {
+        match self {
+            Self::Constant(value) => F::from(*value),
+            Self::Current(column) => current[*column],
+            Self::Next(column) => next[*column],
+            Self::Add(left, right) => left.evaluate(current, next) + right.evaluate(current, next),
+            Self::Sub(left, right) => left.evaluate(current, next) - right.evaluate(current, next),
+            Self::Mul(left, right) => left.evaluate(current, next) * right.evaluate(current, next),
+        }
+    }
+}
+
+impl Add for AirExpr {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::Add(Box::new(self), Box::new(rhs))
+    }
+}
+
+impl Sub for AirExpr {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::Sub(Box::new(self), Box::new(rhs))
+    }
+}
+
+impl Mul for AirExpr {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::Mul(Box::new(self), Box::new(rhs))
+    }
+}
+
+impl Neg for AirExpr {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::constant(0) - self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoundaryRow {
+    First,
+    Last,
+}
+
+/// What a boundary constraint pins its column to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoundaryValue {
+    /// Public input number `n`.
+    PublicInput(usize),
+    Constant(BabyBear),
+}
+
+/// `column` of `row` equals `value`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoundaryConstraint {
+    pub column: usize,
+    pub row: BoundaryRow,
+    pub value: BoundaryValue,
+}
+
+impl BoundaryConstraint {
+    pub fn value(&self, public_inputs: &[BabyBear]) -> BabyBear {
+        match self.value {
+            BoundaryValue::PublicInput(index) => public_inputs[index],
+            BoundaryValue::Constant(value) => value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Air {
+    /// Column names, in layout order.
+    pub columns: Vec<String>,
+    /// Expressions that vanish on every pair of consecutive rows.
+    pub transitions: Vec<AirExpr>,
+    pub boundaries: Vec<BoundaryConstraint>,
+    pub num_public_inputs: usize,
+    pub lookups: Vec<Lookup>,
+}
+
+impl Air {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a column and returns its index.
+    pub fn add_column(&mut self, name: impl Into<String>) -> usize {
+        self.columns.push(name.into());
+        self.columns.len() - 1
+    }
+
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column == name)
+    }
+
+    pub fn width(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn add_transition(&mut self, constraint: AirExpr) {
+        self.transitions.push(constraint);
+    }
+
+    /// Pins `column` of `row` to a new public input and returns its index.
+    pub fn add_boundary(&mut self, column: usize, row: BoundaryRow) -> usize {
+        let public_input = self.num_public_inputs;
+        self.num_public_inputs += 1;
+        self.boundaries.push(BoundaryConstraint {
+            column,
+            row,
+            value: BoundaryValue::PublicInput(public_input),
+        });
+        public_input
+    }
+
+    /// Pins `column` of `row` to `value`.
+    pub fn add_boundary_constant(&mut self, column: usize, row: BoundaryRow, value: u32) {
+        self.boundaries.push(BoundaryConstraint {
+            column,
+            row,
+            value: BoundaryValue::Constant(value.into()),
+        });
+    }
+
+    pub fn add_lookup(&mut self, table: LookupTable, values: Vec<AirExpr>, multiplicity: AirExpr) {
+        self.lookups.push(Lookup {
+            table,
+            values,
+            multiplicity,
+        });
+    }
+
+    /// Columns of the auxiliary trace, over the extension field: one per
+    /// lookup and their running sum.
+    pub fn aux_width(&self) -> usize {
+        match self.lookups.len() {
+            0 => 0,
+            lookups => lookups + 1,
+        }
+    }
+
+    /// Highest degree of any constraint.
+    pub fn max_degree(&self) -> usize {
+        self.transitions
+            .iter()
+            .map(AirExpr::degree)
+            .chain(self.boundaries.iter().map(|_| 1))
+            .chain(self.lookups.iter().map(Lookup::degree))
+            .max()
+            .unwrap_or(1)
+    }
+
+    /// How often every entry of a fixed table is looked up in `rows`.
+    pub fn multiplicities(
+        &self,
+        rows: &[Vec<BabyBear>],
+    ) -> Result<Vec<Multiplicity>, &'static str> {
+        let mut counts = BTreeMap::new();
+        for (i, row) in rows.iter().enumerate() {
+            let next = &rows[(i + 1) % rows.len()];
+            for lookup in self.lookups.iter().filter(|lookup| lookup.table.is_fixed()) {
+                let multiplicity = lookup.multiplicity.evaluate(row, next);
+                if multiplicity.is_zero() {
+                    continue;
+                }
+                let values: Vec<BabyBear> = lookup
+                    .values
+                    .iter()
+                    .map(|value| value.evaluate(row, next))
+                    .collect();
+                let entry = lookup
+                    .table
+                    .index(&values)
+                    .ok_or("Lookup value not in table")?;
+                *counts
+                    .entry((lookup.table, entry))
+                    .or_insert(BabyBear::ZERO) += multiplicity;
+            }
+        }
+        Ok(counts
+            .into_iter()
+            .map(|((table, entry), count)| Multiplicity {
+                table,
+                entry: entry as u32,
+                count,
+            })
+            .collect())
+    }
+
+    /// Values looked up in `table` in `rows`, with how often; those looked
+    /// up as often as they are taken back do not appear.
+    pub fn claims(
+        &self,
+        rows: &[Vec<BabyBear>],
+        table: LookupTable,
+    ) -> BTreeMap<Vec<u32>, BabyBear> {
+        let mut claims = BTreeMap::new();
+        for (i, row) in rows.iter().enumerate() {
+            let next = &rows[(i + 1) % rows.len()];
+            for lookup in self.lookups.iter().filter(|lookup| lookup.table == table) {
+                let multiplicity = lookup.multiplicity.evaluate(row, next);
+                if multiplicity.is_zero() {
+                    continue;
+                }
+                let values = lookup
+                    .values
+                    .iter()
+                    .map(|value| value.evaluate(row, next).as_u32())
+                    .collect();
+                *claims.entry(values).or_insert(BabyBear::ZERO) += multiplicity;
+            }
+        }
+        claims.retain(|_, count| !count.is_zero());
+        claims
+    }
+
+    /// The auxiliary trace of `rows`: on each row, `m / (gamma -
+    /// fingerprint(values))` for every lookup, then the running sum of
+    /// those up to and including the row.
+    pub fn lookup_trace(
+        &self,
+        rows: &[Vec<BabyBear>],
+        challenges: &LookupChallenges,
+    ) -> Vec<Vec<BabyBearExt4>> {
+        if self.lookups.is_empty() {
+            return vec![Vec::new(); rows.len()];
+        }
+        let mut denominators = Vec::with_capacity(rows.len() * self.lookups.len());
+        let mut multiplicities = Vec::with_capacity(denominators.capacity());
+        for (i, row) in rows.iter().enumerate() {
+            let next = &rows[(i + 1) % rows.len()];
+            for lookup in &self.lookups {
+                let values: Vec<BabyBear> = lookup
+                    .values
+                    .iter()
+                    .map(|value| value.evaluate(row, next))
+                    .collect();
+                denominators.push(challenges.gamma - challenges.fingerprint(lookup.table, &values));
+                multiplicities.push(lookup.multiplicity.evaluate(row, next));
+            }
+        }
+        let inverses = batch_inverse(&denominators);
+
+        let mut sum = BabyBearExt4::ZERO;
+        let terms = inverses.iter().zip(&multiplicities);
+        let terms: Vec<BabyBearExt4> = terms.map(|(&inverse, &m)| inverse * m).collect();
+        terms
+            .chunks(self.lookups.len())
+            .map(|helpers| {
+                sum += helpers.iter().copied().sum();
+                let mut row = helpers.to_vec();
+                row.push(sum);
+                row
+            })
+            .collect()
+    }
+
+    /// Checks a concrete trace against every constraint.
+    pub fn check_trace(
+        &self,
+        rows: &[Vec<BabyBear>],
+        public_inputs: &[BabyBear],
+    ) -> Result<(), &'static str> {
+        if rows.is_empty() || rows.iter().any(|row| row.len() != self.width()) {
+            return Err("Trace does not match the AIR layout");
+        }
+        if public_inputs.len() != self.num_public_inputs {
+            return Err("Wrong number of public inputs");
+        }
+        for pair in rows.windows(2) {
+            for constraint in &self.transitions {
+                if constraint.evaluate(&pair[0], &pair[1]) != BabyBear::ZERO {
+                    return Err("Transition constraint violated");
+                }
+            }
+        }
+        for boundary in &self.boundaries {
+            let row = match boundary.row {
+                BoundaryRow::First => &rows[0],
+                BoundaryRow::Last => &rows[rows.len() - 1],
+            };
+            if row[boundary.column] != boundary.value(public_inputs) {
+                return Err("Boundary constraint violated");
+            }
+        }
+        self.multiplicities(rows)?;
+        let buses: BTreeSet<LookupTable> = self
+            .lookups
+            .iter()
+            .map(|lookup| lookup.table)
+            .filter(|table| table.is_bus())
+            .collect();
+        if buses.iter().any(|&bus| !self.claims(rows, bus).is_empty()) {
+            return Err("Lookup bus not balanced");
+        }
+        Ok(())
+    }
+}
@@ -1,37 +1,23 @@
-use super::{ExecutionTrace, TraceStep};
-use crate::vm::Opcode;
+//! The AIR of the RISC-V machine, over the columns of a [`TraceLayout`].
 
-#[derive(Debug, Clone)]
-pub struct ConstraintSystem {
-    pub constraints: Vec<Constraint>,
-    pub witness_columns: Vec<String>,
-    pub public_columns: Vec<String>,
-}
-
-#[derive(Debug, Clone)]
-pub enum Constraint {
-    Equality {
-        left: Expression,
-        right: Expression,
-    },
-    RangeCheck {
-        value: Expression,
-        max_bits: u32,
-    },
-    MemoryConsistency {
-        addr: Expression,
-        value_read: Expression,
-        value_written: Expression,
-    },
-}
+use super::{digest_of, mix_coefficient, DIGEST_SIZE, FILE_SLOTS, SPONGE_RATE, SPONGE_WIDTH};
+use super::{
+    exit_values, Air, AirExpr, BabyBear, BoundaryRow, ExecutionTrace, Field, LookupTable,
+    MemoryBoundary, Multiplicity, PublicValues, TraceLayout, TraceTable, BABY_BEAR_MODULUS,
+    LIMB_BITS, PUBLIC_TABLES,
+};
+use crate::crypto::HashValue;
+use crate::vm::{
+    decode_instruction, errno, syscall_register, Extension, IsaProfile, LinuxSyscalls, Opcode,
+    StateTransition, FIRST_FILE_FD, KV_VALUE_SIZE,
+};
+use std::collections::BTreeMap;
+use zkvm_guest::syscall::nr;
 
 #[derive(Debug, Clone)]
-pub enum Expression {
-    Constant(u32),
-    Variable(String),
-    Add(Box<Expression>, Box<Expression>),
-    Mul(Box<Expression>, Box<Expression>),
-    Sub(Box<Expression>, Box<Expression>),
+pub struct ConstraintSystem {
+    pub air: Air,
+    pub layout: TraceLayout,
 }
 
 impl Default for ConstraintSystem {
@@ -40,216 +26,2349 @@ impl Default for ConstraintSystem {
     }
 }
 
+fn current(column: usize) -> AirExpr {
+    AirExpr::Current(column)
+}
+
+fn next(column: usize) -> AirExpr {
+    AirExpr::Next(column)
+}
+
+/// `sum(selectors[i] * values[i])`
+fn select(selectors: &[usize], values: impl Fn(usize) -> AirExpr) -> AirExpr {
+    selectors
+        .iter()
+        .enumerate()
+        .fold(AirExpr::constant(0), |acc, (i, &selector)| {
+            acc + current(selector) * values(i)
+        })
+}
+
 impl ConstraintSystem {
     pub fn new() -> Self {
-        Self {
-            constraints: Vec::new(),
-            witness_columns: Vec::new(),
-            public_columns: Vec::new(),
-        }
+        Self::for_profile(IsaProfile::default())
     }
 
-    pub fn add_witness_column(&mut self, name: String) {
-        self.witness_columns.push(name);
+    pub fn with_profile(self, profile: IsaProfile) -> Self {
+        Self::for_profile(profile)
     }
 
-    pub fn add_public_column(&mut self, name: String) {
-        self.public_columns.push(name);
+    fn for_profile(profile: IsaProfile) -> Self {
+        let layout = TraceLayout::new(profile);
+        let mut air = Air::new();
+        for name in &layout.names {
+            air.add_column(name.as_str());
+        }
+        let mut system = Self { air, layout };
+        system.add_constraints();
+        system
     }
 
-    pub fn add_constraint(&mut self, constraint: Constraint) {
-        self.constraints.push(constraint);
+    fn add_constraints(&mut self) {
+        let c = &self.layout;
+        let air = &mut self.air;
+        selector_constraints(air, c);
+        fetch_constraints(air, c);
+        gas_constraints(air, c);
+        range_constraints(air, c);
+        register_constraints(air, c);
+        immediate_constraints(air, c);
+        arithmetic_constraints(air, c);
+        shift_constraints(air, c);
+        division_constraints(air, c);
+        comparison_constraints(air, c);
+        word_constraints(air, c);
+        bitwise_constraints(air, c);
+        control_flow_constraints(air, c);
+        memory_constraints(air, c);
+        load_store_constraints(air, c);
+        syscall_constraints(air, c);
+        file_constraints(air, c);
+        kv_constraints(air, c);
+        transfer_constraints(air, c);
+        hash_constraints(air, c);
+        air.add_boundary(c.pc, BoundaryRow::First);
+        air.add_boundary(c.pc, BoundaryRow::Last);
+        air.add_boundary(c.gas, BoundaryRow::Last);
+        for &limb in c.registers.iter().flatten() {
+            air.add_boundary(limb, BoundaryRow::First);
+        }
+        for (column, row) in syscall_boundaries(c).into_iter().chain(kv_boundaries(c)) {
+            air.add_boundary(column, row);
+        }
     }
 
-    pub fn generate_constraints_for_trace(&mut self, trace: &ExecutionTrace) {
-        // Add standard witness columns
-        self.add_witness_column("step".to_string());
-        self.add_witness_column("pc_before".to_string());
-        self.add_witness_column("pc_after".to_string());
+    /// The trace laid out as the AIR's columns.
+    pub fn generate_trace(&self, trace: &ExecutionTrace) -> Result<TraceTable, &'static str> {
+        TraceTable::generate(&self.layout, trace)
+    }
 
-        for i in 0..32 {
-            self.add_witness_column(format!("reg_{i}_before"));
-            self.add_witness_column(format!("reg_{i}_after"));
-        }
+    /// The public inputs of the boundary constraints for `rows`: the
+    /// initial and the final pc, the gas used, the limbs of the initial
+    /// registers, the syscall state the run starts and ends with, and the
+    /// key-value store roots it goes from and to and its number of keys.
+    pub fn public_inputs(&self, table: &TraceTable) -> Vec<BabyBear> {
+        let (pc, gas) = (self.layout.pc, self.layout.gas);
+        let last = table.len() - 1;
+        let row = |row: BoundaryRow| match row {
+            BoundaryRow::First => 0,
+            BoundaryRow::Last => last,
+        };
+        let registers = self.layout.registers.iter().flatten();
+        let syscall = syscall_boundaries(&self.layout)
+            .into_iter()
+            .chain(kv_boundaries(&self.layout));
+        [table.get(0, pc), table.get(last, pc), table.get(last, gas)]
+            .into_iter()
+            .chain(registers.map(|&limb| table.get(0, limb)))
+            .chain(syscall.map(|(column, boundary)| table.get(row(boundary), column)))
+            .collect()
+    }
 
-        // Add public columns for initial and final state
-        self.add_public_column("initial_pc".to_string());
-        self.add_public_column("final_pc".to_string());
+    /// The public inputs a trace of a run with `public_values` that used
+    /// `gas_used` has. Runs without a key-value store have zero roots, as
+    /// do roots that are not digests, which the verifier rejects.
+    pub fn expected_public_inputs(
+        &self,
+        public_values: &PublicValues,
+        gas_used: u32,
+    ) -> Vec<BabyBear> {
+        let registers = self
+            .layout
+            .registers
+            .iter()
+            .zip(public_values.initial_registers)
+            .flat_map(|(_, value)| self.layout.limbs(value));
+        let (exit_status, exit_code) = exit_values(public_values.exit_status);
+        let syscall = [
+            public_values.input.len() as u32,
+            public_values.journal.len() as u32,
+            exit_status,
+            exit_code & 0xffff,
+            exit_code >> 16,
+            public_values.brk & 0xffff,
+            public_values.brk >> 16,
+            public_values.heap_start & 0xffff,
+            public_values.heap_start >> 16,
+            public_values.heap_end & 0xffff,
+            public_values.heap_end >> 16,
+        ];
+        let root = |root: fn(&StateTransition) -> &HashValue| {
+            public_values
+                .state_transition
+                .as_ref()
+                .and_then(|transition| digest_of(root(transition)))
+                .unwrap_or_default()
+        };
+        let roots = [root(|t| &t.old_root), root(|t| &t.new_root)];
+        [public_values.initial_pc, public_values.final_pc, gas_used]
+            .map(BabyBear::from)
+            .into_iter()
+            .chain(registers.map(BabyBear::from_u64))
+            .chain(syscall.map(BabyBear::from))
+            .chain(roots.into_iter().flatten())
+            .chain(std::iter::once(public_values.kv_keys.into()))
+            .collect()
+    }
 
-        for (step_idx, step) in trace.steps.iter().enumerate() {
-            self.generate_constraints_for_step(step, step_idx);
+    /// Every word the sorted memory log of `table` accesses, with the value
+    /// its first access reads and its last access leaves.
+    pub fn memory_boundary(&self, table: &TraceTable) -> Vec<MemoryBoundary> {
+        let s = &self.layout.sorted_memory;
+        let value = |row: &[BabyBear], limbs: &[usize]| {
+            limbs
+                .iter()
+                .rev()
+                .fold(0, |acc, &column| acc << LIMB_BITS | row[column].as_u32())
+        };
+        let mut boundary: Vec<MemoryBoundary> = Vec::new();
+        for row in table.rows.iter().filter(|row| row[s.used] == BabyBear::ONE) {
+            if row[s.first] == BabyBear::ONE {
+                boundary.push(MemoryBoundary {
+                    addr: row[s.word].as_u32() * 4,
+                    initial_value: value(row, &s.value_before),
+                    final_value: 0,
+                });
+            }
+            if let Some(word) = boundary.last_mut() {
+                word.final_value = value(row, &s.value_after);
+            }
         }
+        boundary
     }
 
-    fn generate_constraints_for_step(&mut self, step: &TraceStep, step_idx: usize) {
-        let _step_var = Expression::Variable(format!("step_{step_idx}"));
-        let pc_before = Expression::Variable(format!("pc_before_{step_idx}"));
-        let pc_after = Expression::Variable(format!("pc_after_{step_idx}"));
-
-        // PC progression constraint for most instructions
-        match step.instruction.opcode {
-            Opcode::Branch | Opcode::Jump => {
-                // Branch/Jump instructions handle PC differently
+    /// The entry of the program table at `pc`: the instruction `memory`
+    /// holds there, fetched and decoded as the VM does, and its price in
+    /// `gas_prices`, or `None` if it does not decode under the profile or
+    /// has no price in the field.
+    pub fn program_entry(
+        &self,
+        memory: &BTreeMap<u32, u32>,
+        gas_prices: &BTreeMap<Opcode, u64>,
+        pc: u32,
+    ) -> Option<Vec<BabyBear>> {
+        let profile = self.layout.profile;
+        let half = |addr: u32| {
+            let word = memory.get(&(addr & !3)).copied().unwrap_or(0);
+            word >> (8 * (addr & 2)) & 0xffff
+        };
+        let word = if !profile.has(Extension::C) {
+            if !pc.is_multiple_of(4) {
+                return None;
             }
-            _ => {
-                // Normal instructions: pc_after = pc_before + 4
-                self.add_constraint(Constraint::Equality {
-                    left: pc_after.clone(),
-                    right: Expression::Add(
-                        Box::new(pc_before.clone()),
-                        Box::new(Expression::Constant(4)),
-                    ),
-                });
+            memory.get(&pc).copied().unwrap_or(0)
+        } else {
+            if !pc.is_multiple_of(2) {
+                return None;
             }
-        }
+            match half(pc) {
+                low if low & 0x3 != 0x3 => low,
+                low => low | half(pc.wrapping_add(2)) << 16,
+            }
+        };
+        let instruction = decode_instruction(word, profile).ok()?;
+        let gas_price = u32::try_from(*gas_prices.get(&instruction.opcode)?)
+            .ok()
+            .filter(|&price| price < BABY_BEAR_MODULUS)?;
+        Some(TraceLayout::instruction_entry(pc, &instruction, gas_price))
+    }
 
-        // Instruction-specific constraints
-        match step.instruction.opcode {
-            Opcode::Add => {
-                let rs1_val = Expression::Variable(format!(
-                    "reg_{}_before_{}",
-                    step.instruction.rs1, step_idx
-                ));
-                let rs2_val = Expression::Variable(format!(
-                    "reg_{}_before_{}",
-                    step.instruction.rs2, step_idx
-                ));
-                let rd_val =
-                    Expression::Variable(format!("reg_{}_after_{}", step.instruction.rd, step_idx));
-
-                // rd = rs1 + rs2
-                self.add_constraint(Constraint::Equality {
-                    left: rd_val,
-                    right: Expression::Add(Box::new(rs1_val), Box::new(rs2_val)),
-                });
+    /// The entry of a public table under `key`, from `public_values`: the
+    /// instruction at pc `key`, the byte of the input or journal with `key`
+    /// bytes left from it on, syscall `key` with its price, the byte of a
+    /// public file's path under `file * 2^24` plus the bytes left, or its
+    /// length under `file * 2^24`, or public file `key` with the elements
+    /// of its hash. `None` if there is no such entry.
+    pub fn public_entry(
+        &self,
+        public_values: &PublicValues,
+        table: LookupTable,
+        key: u32,
+    ) -> Option<Vec<BabyBear>> {
+        let byte = |stream: &[u8]| {
+            let index = stream.len().checked_sub(key as usize)?;
+            (key > 0).then(|| vec![key.into(), u32::from(stream[index]).into()])
+        };
+        match table {
+            LookupTable::Program => self.program_entry(
+                &public_values.initial_memory,
+                &public_values.gas_prices,
+                key,
+            ),
+            LookupTable::Input => byte(&public_values.input),
+            LookupTable::Journal => byte(&public_values.journal),
+            // The key-value syscalls need a store
+            LookupTable::Syscall
+                if matches!(key, nr::KV_GET | nr::KV_SET)
+                    && public_values.state_transition.is_none() =>
+            {
+                None
             }
-            Opcode::Sub => {
-                let rs1_val = Expression::Variable(format!(
-                    "reg_{}_before_{}",
-                    step.instruction.rs1, step_idx
-                ));
-                let rs2_val = Expression::Variable(format!(
-                    "reg_{}_before_{}",
-                    step.instruction.rs2, step_idx
-                ));
-                let rd_val =
-                    Expression::Variable(format!("reg_{}_after_{}", step.instruction.rd, step_idx));
-
-                // rd = rs1 - rs2
-                self.add_constraint(Constraint::Equality {
-                    left: rd_val,
-                    right: Expression::Sub(Box::new(rs1_val), Box::new(rs2_val)),
-                });
+            LookupTable::Syscall => {
+                let price = u32::try_from(*public_values.syscall_prices.get(&key)?)
+                    .ok()
+                    .filter(|&price| price < BABY_BEAR_MODULUS)?;
+                Some(vec![key.into(), price.into()])
             }
-            Opcode::Mul => {
-                let rs1_val = Expression::Variable(format!(
-                    "reg_{}_before_{}",
-                    step.instruction.rs1, step_idx
-                ));
-                let rs2_val = Expression::Variable(format!(
-                    "reg_{}_before_{}",
-                    step.instruction.rs2, step_idx
-                ));
-                let rd_val =
-                    Expression::Variable(format!("reg_{}_after_{}", step.instruction.rd, step_idx));
-
-                // rd = rs1 * rs2
-                self.add_constraint(Constraint::Equality {
-                    left: rd_val,
-                    right: Expression::Mul(Box::new(rs1_val), Box::new(rs2_val)),
-                });
+            LookupTable::FilePath => {
+                let path = &public_values.files.get(key as usize >> 24)?.path;
+                match key & 0xff_ffff {
+                    0 => u32::try_from(path.len())
+                        .ok()
+                        .filter(|&len| len < 1 << 24)
+                        .map(|len| vec![key.into(), len.into()]),
+                    left => {
+                        let index = path.len().checked_sub(left as usize)?;
+                        Some(vec![key.into(), u32::from(path.as_bytes()[index]).into()])
+                    }
+                }
+            }
+            LookupTable::File => {
+                let digest = digest_of(&public_values.files.get(key as usize)?.hash)?;
+                Some(std::iter::once(key.into()).chain(digest).collect())
             }
-            Opcode::Load | Opcode::Store => {
-                // Memory access constraints
-                for mem_access in &step.memory_accesses {
-                    self.add_constraint(Constraint::MemoryConsistency {
-                        addr: Expression::Constant(mem_access.addr),
-                        value_read: Expression::Constant(mem_access.value_before),
-                        value_written: Expression::Constant(mem_access.value_after),
+            _ => None,
+        }
+    }
+
+    /// The entries of the public tables under the keys `multiplicities`
+    /// count. Those without an entry are left out.
+    pub fn public_entries(
+        &self,
+        public_values: &PublicValues,
+        multiplicities: &[Multiplicity],
+    ) -> BTreeMap<(LookupTable, u32), Vec<BabyBear>> {
+        multiplicities
+            .iter()
+            .filter(|multiplicity| multiplicity.table.is_public())
+            .filter_map(|multiplicity| {
+                let (table, key) = (multiplicity.table, multiplicity.entry);
+                Some(((table, key), self.public_entry(public_values, table, key)?))
+            })
+            .collect()
+    }
+
+    /// How often the rows of `table` look up each key of the public tables.
+    pub fn public_multiplicities(&self, table: &TraceTable) -> Vec<Multiplicity> {
+        let mut counts = BTreeMap::new();
+        for public in PUBLIC_TABLES {
+            for (values, count) in self.air.claims(&table.rows, public) {
+                *counts.entry((public, values[0])).or_insert(BabyBear::ZERO) += count;
+            }
+        }
+        counts
+            .into_iter()
+            .map(|((table, entry), count)| Multiplicity {
+                table,
+                entry,
+                count,
+            })
+            .collect()
+    }
+
+    /// Checks that `trace` satisfies the AIR, that it executes the program
+    /// in its initial memory and reads and writes its input and journal,
+    /// and that its memory boundary is the memory before and after
+    /// execution.
+    pub fn check_trace(&self, trace: &ExecutionTrace) -> Result<(), &'static str> {
+        let table = self.generate_trace(trace)?;
+        self.air
+            .check_trace(&table.rows, &self.public_inputs(&table))?;
+        let public_values = trace.public_values();
+        for public in PUBLIC_TABLES {
+            for values in self.air.claims(&table.rows, public).into_keys() {
+                let entry = self.public_entry(&public_values, public, values[0]);
+                if entry.map(|entry| entry.iter().map(BabyBear::as_u32).collect()) != Some(values) {
+                    return Err(match public {
+                        LookupTable::Program => "Instruction does not match the program",
+                        _ => "Lookup does not match the public values",
                     });
                 }
             }
-            _ => {}
         }
+        for word in self.memory_boundary(&table) {
+            if trace.initial_state.memory.read_word(word.addr)? != word.initial_value
+                || trace.final_state.memory.read_word(word.addr)? != word.final_value
+            {
+                return Err("Memory boundary does not match the trace");
+            }
+        }
+        Ok(())
+    }
+}
+
+fn one() -> AirExpr {
+    AirExpr::constant(1)
+}
+
+fn power_of_two(exponent: u32) -> AirExpr {
+    AirExpr::Constant(BabyBear::from_u64(1 << exponent))
+}
+
+/// `sum(columns[j] * 2^(LIMB_BITS * j))`
+fn join(columns: &[usize]) -> AirExpr {
+    columns
+        .iter()
+        .enumerate()
+        .fold(AirExpr::constant(0), |acc, (j, &column)| {
+            acc + current(column) * power_of_two(LIMB_BITS * j as u32)
+        })
+}
+
+/// `sum(columns[i])`
+fn sum(columns: &[usize]) -> AirExpr {
+    columns
+        .iter()
+        .fold(AirExpr::constant(0), |acc, &column| acc + current(column))
+}
+
+fn boolean(air: &mut Air, column: usize) {
+    air.add_transition(current(column) * (current(column) - one()));
+}
+
+/// Set on the instructions that read the I-type immediate in place of rs2.
+fn i_type(c: &TraceLayout) -> AirExpr {
+    sum(&[
+        c.is_addi, c.is_slti, c.is_sltiu, c.is_xori, c.is_ori, c.is_andi, c.is_slli, c.is_srli,
+        c.is_srai, c.is_addiw, c.is_slliw, c.is_srliw, c.is_sraiw,
+    ])
+}
+
+/// Set on LUI and AUIPC, which read the U-type immediate in place of rs2.
+fn u_type(c: &TraceLayout) -> AirExpr {
+    current(c.is_lui) + current(c.is_auipc)
+}
+
+/// Set on loads, which read the loaded value in place of rs2.
+fn loads(c: &TraceLayout) -> AirExpr {
+    sum(&[
+        c.is_lw, c.is_lb, c.is_lh, c.is_lbu, c.is_lhu, c.is_ld, c.is_lwu,
+    ])
+}
+
+/// Set on stores.
+fn stores(c: &TraceLayout) -> AirExpr {
+    sum(&[c.is_sw, c.is_sb, c.is_sh, c.is_sd])
+}
+
+/// Set on the RV64 word instructions that read rs2 from a register.
+fn word_register_ops(c: &TraceLayout) -> AirExpr {
+    sum(&[
+        c.is_addw, c.is_subw, c.is_sllw, c.is_srlw, c.is_sraw, c.is_mulw, c.is_divw, c.is_divuw,
+        c.is_remw, c.is_remuw,
+    ])
+}
+
+/// Set on the RV64 word instructions.
+fn word_ops(c: &TraceLayout) -> AirExpr {
+    word_register_ops(c) + sum(&[c.is_addiw, c.is_slliw, c.is_srliw, c.is_sraiw])
+}
+
+/// Flags and selectors are bits that agree with the decoded instruction.
+fn selector_constraints(air: &mut Air, c: &TraceLayout) {
+    let (cmp, d) = (&c.comparison, &c.division);
 
-        // Register 0 is always 0 constraint
-        self.add_constraint(Constraint::Equality {
-            left: Expression::Variable(format!("reg_0_after_{step_idx}")),
-            right: Expression::Constant(0),
+    // Exactly one opcode flag is set and gives the opcode, or the row is
+    // padding, which executes a NOP
+    let flags = c.opcode_flags();
+    let columns: Vec<usize> = flags.iter().map(|&(flag, _)| flag).collect();
+    air.add_transition(sum(&columns) + current(c.is_padding) - one());
+    air.add_transition(
+        current(c.opcode)
+            - current(c.is_padding) * AirExpr::constant(Opcode::Nop as u32)
+            - flags
+                .iter()
+                .fold(AirExpr::constant(0), |acc, &(flag, opcode)| {
+                    acc + current(flag) * AirExpr::constant(opcode as u32)
+                }),
+    );
+
+    // Padding neither moves nor writes a register
+    air.add_transition(current(c.is_padding) * current(c.size));
+    air.add_transition(current(c.is_padding) * (one() - current(c.rd_is[0])));
+    let bits = [
+        c.is_padding,
+        c.shift.zero,
+        c.shift.bit5,
+        c.word.result_sign,
+        c.load_store.word_odd,
+        c.memory.used,
+        c.memory.is_write,
+        c.sorted_memory.used,
+        c.sorted_memory.same_word,
+        c.taken,
+        c.target_lsb,
+        c.rs1_sign,
+        c.rs2_sign,
+        c.multiplicand_sign,
+        cmp.less,
+        cmp.equal,
+        d.remainder_sign,
+        d.negative_dividend,
+        d.negative_divisor,
+        d.negative_remainder,
+        d.divisor_zero,
+        d.by_zero,
+        d.overflow,
+    ];
+    let selectors = [&c.rs1_is, &c.rs2_is, &c.rd_is];
+    for &column in bits
+        .iter()
+        .chain(&columns)
+        .chain(&c.shift.bits)
+        .chain(&c.shift.limb_is)
+        .chain(&c.load_store.byte_is)
+        .chain(&c.carries)
+        .chain(&cmp.borrows)
+        .chain(&d.carries)
+        .chain(&d.remainder_abs_carries)
+        .chain(&d.divisor_abs_carries)
+        .chain(&d.gap_borrows)
+        .chain(selectors.iter().copied().flatten())
+    {
+        boolean(air, column);
+    }
+
+    // Each register selector picks exactly one register, the one the
+    // decoded instruction names
+    for (index, selector) in [(c.rs1, &c.rs1_is), (c.rs2, &c.rs2_is), (c.rd, &c.rd_is)] {
+        air.add_transition(select(selector, |_| one()) - one());
+        air.add_transition(current(index) - select(selector, |i| AirExpr::constant(i as u32)));
+    }
+
+    // Only log rows holding an access can write
+    air.add_transition(current(c.memory.is_write) * (one() - current(c.memory.used)));
+}
+
+/// Every row but padding executes the instruction the program image holds
+/// at its pc, looked up in the program table.
+fn fetch_constraints(air: &mut Air, c: &TraceLayout) {
+    air.add_lookup(
+        LookupTable::Program,
+        c.instruction_columns().map(current).to_vec(),
+        one() - current(c.is_padding),
+    );
+    // Transitions leave the last row unchecked, so it is pinned to padding
+    air.add_boundary_constant(c.is_padding, BoundaryRow::Last, 1);
+}
+
+/// Each step adds the price of its instruction, and that of its syscall, to
+/// the gas used, which starts at 0 and ends at the public gas used. Padding
+/// rows cost nothing.
+fn gas_constraints(air: &mut Air, c: &TraceLayout) {
+    air.add_transition(current(c.is_padding) * current(c.gas_price));
+    air.add_transition(
+        next(c.gas) - current(c.gas) - current(c.gas_price) - current(c.syscall.price),
+    );
+    air.add_boundary_constant(c.gas, BoundaryRow::First, 0);
+}
+
+/// Range-checked columns are looked up in the smallest range table that
+/// holds them. A range narrower than the table also looks the value up
+/// shifted to the table's top, which only fits if the high bits are clear.
+fn range_constraints(air: &mut Air, c: &TraceLayout) {
+    for check in &c.range_checks {
+        let (table, table_bits) = if check.bits <= 8 {
+            (LookupTable::Range8, 8)
+        } else {
+            (LookupTable::Range16, 16)
+        };
+        let value = current(check.column);
+        air.add_lookup(table, vec![value.clone()], one());
+        if check.bits < table_bits {
+            let shifted = value * power_of_two(table_bits - check.bits);
+            air.add_lookup(table, vec![shifted], one());
+        }
+    }
+}
+
+/// AND, OR and XOR look every limb of their operands and result up in the
+/// byte tables.
+fn bitwise_constraints(air: &mut Air, c: &TraceLayout) {
+    let tables = [
+        (c.is_and, c.is_andi, LookupTable::And8),
+        (c.is_or, c.is_ori, LookupTable::Or8),
+        (c.is_xor, c.is_xori, LookupTable::Xor8),
+    ];
+    for (flag, immediate_flag, table) in tables {
+        for limb in 0..c.result.len() {
+            let values = [c.rs1_value[limb], c.rs2_value[limb], c.result[limb]];
+            air.add_lookup(
+                table,
+                values.map(current).to_vec(),
+                current(flag) + current(immediate_flag),
+            );
+        }
+        // Transitions leave the last row's flags unchecked
+        air.add_boundary_constant(flag, BoundaryRow::Last, 0);
+        air.add_boundary_constant(immediate_flag, BoundaryRow::Last, 0);
+    }
+}
+
+fn register_constraints(air: &mut Air, c: &TraceLayout) {
+    // Operands are read from the selected registers, unless they are the
+    // pc, an immediate, a loaded value or what a syscall compares, and the
+    // result is what the next row holds in rd.
+    // Word instructions read only the low words, and write only the low
+    // word of the result
+    let immediate = i_type(c) + u_type(c) + loads(c);
+    let ecall = current(c.is_ecall);
+    for limb in 0..c.rs1_value.len() {
+        let register = |i: usize| current(c.registers[i][limb]);
+        let next_register = |i: usize| next(c.registers[i][limb]);
+        let (mut rs1_read, mut rs2_read, mut written) = (
+            one() - current(c.is_auipc) - ecall.clone(),
+            one() - immediate.clone() - ecall.clone(),
+            one(),
+        );
+        if limb >= 4 {
+            rs1_read = rs1_read - word_ops(c);
+            rs2_read = rs2_read - word_register_ops(c);
+            written = written - word_ops(c);
+        }
+        air.add_transition(rs1_read * (current(c.rs1_value[limb]) - select(&c.rs1_is, register)));
+        air.add_transition(rs2_read * (current(c.rs2_value[limb]) - select(&c.rs2_is, register)));
+        air.add_transition(current(c.rd_value[limb]) - select(&c.rd_is, next_register));
+        air.add_transition(
+            (one() - current(c.rd_is[0]))
+                * written
+                * (current(c.rd_value[limb]) - current(c.result[limb])),
+        );
+    }
+
+    // Every register but rd keeps its value
+    for (i, &selector) in c.rd_is.iter().enumerate().skip(1) {
+        for &column in &c.registers[i] {
+            air.add_transition((one() - current(selector)) * (next(column) - current(column)));
+        }
+    }
+
+    // Register 0 is always 0
+    for &column in &c.registers[0] {
+        air.add_transition(current(column));
+    }
+}
+
+/// The immediate forms read `imm`, sign-extended to XLEN, as rs2. LUI and
+/// AUIPC add it like ADD, to 0 and to the pc read as rs1.
+fn immediate_constraints(air: &mut Air, c: &TraceLayout) {
+    let (imm, sign) = (current(c.imm), current(c.rs2_sign));
+    let b = |j: usize| current(c.rs2_value[j]);
+    let fill = || AirExpr::constant((1 << LIMB_BITS) - 1);
+
+    // I-type immediates fit in the two low limbs
+    let i = i_type(c);
+    air.add_transition(
+        i.clone()
+            * (b(0) + b(1) * power_of_two(LIMB_BITS)
+                - imm.clone()
+                - sign.clone() * power_of_two(16)),
+    );
+    for j in 2..c.rs2_value.len() {
+        air.add_transition(i.clone() * (b(j) - sign.clone() * fill()));
+    }
+
+    // U-type immediates fill the low word but its low limb, which is 0, so
+    // the word is 2^8 times a value below 2^24 and unique in the field
+    let u = u_type(c);
+    air.add_transition(u.clone() * b(0));
+    air.add_transition(
+        u.clone()
+            * (join(&c.rs2_value[..4])
+                - imm
+                - sign.clone() * AirExpr::Constant(BabyBear::from_u64(1 << 32))),
+    );
+    for j in 4..c.rs2_value.len() {
+        air.add_transition(u.clone() * (b(j) - sign.clone() * fill()));
+    }
+
+    // AUIPC adds to the pc, with the low word below 2^30 as for return
+    // addresses, and LUI to 0
+    let auipc = current(c.is_auipc);
+    air.add_transition(auipc.clone() * (join(&c.rs1_value[..4]) - current(c.pc)));
+    air.add_transition(auipc.clone() * (current(c.rs1_value[3]) - current(c.link_top)));
+    for &limb in &c.rs1_value[4..] {
+        air.add_transition(auipc.clone() * current(limb));
+    }
+    for &limb in &c.rs1_value {
+        air.add_transition(current(c.is_lui) * current(limb));
+    }
+}
+
+/// Arithmetic limb by limb, with the carries out of each limb.
+fn arithmetic_constraints(air: &mut Air, c: &TraceLayout) {
+    let limbs = c.rs1_value.len();
+    let a = |j: usize| current(c.rs1_value[j]);
+    let b = |j: usize| current(c.rs2_value[j]);
+    let r = |j: usize| current(c.result[j]);
+    let limb = || power_of_two(LIMB_BITS);
+
+    // The top limbs hold the sign bits
+    for (value, sign, top) in [
+        (&c.rs1_value, c.rs1_sign, c.rs1_top),
+        (&c.rs2_value, c.rs2_sign, c.rs2_top),
+        (&c.multiplicand, c.multiplicand_sign, c.multiplicand_top),
+        (
+            &c.division.remainder,
+            c.division.remainder_sign,
+            c.division.remainder_top,
+        ),
+    ] {
+        air.add_transition(
+            current(value[limbs - 1]) - current(sign) * power_of_two(LIMB_BITS - 1) - current(top),
+        );
+    }
+
+    // a + b + carry_in = r + carry_out * 2^8, and
+    // a - b - borrow_in = r - borrow_out * 2^8
+    let add = sum(&[c.is_add, c.is_addi, c.is_addw, c.is_addiw]) + u_type(c);
+    let sub = current(c.is_sub) + current(c.is_subw);
+    for j in 0..limbs {
+        let carry_in = match j {
+            0 => AirExpr::constant(0),
+            _ => current(c.carries[j - 1]),
+        };
+        let carry_out = current(c.carries[j]) * limb();
+        air.add_transition(
+            add.clone() * (a(j) + b(j) + carry_in.clone() - r(j) - carry_out.clone()),
+        );
+        air.add_transition(sub.clone() * (a(j) - b(j) - carry_in + carry_out - r(j)));
+    }
+
+    // Divisions multiply the quotient, everything else rs1
+    let division = divisions(c);
+    for (j, &column) in c.multiplicand.iter().enumerate() {
+        air.add_transition((one() - division.clone()) * (current(column) - a(j)));
+    }
+
+    // The factors sign-extended to twice their width, as MULH, MULHSU,
+    // signed division and arithmetic right shifts read them
+    let signed_division = signed_divisions(c);
+    let arithmetic_shift = sum(&[c.is_sra, c.is_srai, c.is_sraw, c.is_sraiw]);
+    air.add_transition(
+        current(c.multiplicand_extended)
+            - (current(c.is_mulh)
+                + current(c.is_mulhsu)
+                + signed_division.clone()
+                + arithmetic_shift)
+                * current(c.multiplicand_sign),
+    );
+    air.add_transition(
+        current(c.rs2_extended) - (current(c.is_mulh) + signed_division) * current(c.rs2_sign),
+    );
+    let fill = AirExpr::constant((1 << LIMB_BITS) - 1);
+    let wide = |value: &[usize], extended: usize, i: usize| {
+        if i < limbs {
+            current(value[i])
+        } else {
+            current(extended) * fill.clone()
+        }
+    };
+
+    // Their product, modulo 2^(2 * XLEN)
+    for k in 0..2 * limbs {
+        let mut sum = (0..=k).fold(AirExpr::constant(0), |acc, i| {
+            acc + wide(&c.multiplicand, c.multiplicand_extended, i)
+                * wide(&c.factor, c.rs2_extended, k - i)
         });
+        if k > 0 {
+            sum = sum + current(c.product_carries[k - 1]);
+        }
+        air.add_transition(sum - current(c.product[k]) - current(c.product_carries[k]) * limb());
+    }
+    let high = current(c.is_mulh) + current(c.is_mulhsu) + current(c.is_mulhu);
+    let mul = current(c.is_mul) + current(c.is_mulw);
+    for j in 0..limbs {
+        air.add_transition(mul.clone() * (r(j) - current(c.product[j])));
+        air.add_transition(high.clone() * (r(j) - current(c.product[limbs + j])));
+    }
+}
 
-        // Range checks for register values (32-bit)
-        for i in 0..32 {
-            self.add_constraint(Constraint::RangeCheck {
-                value: Expression::Variable(format!("reg_{i}_after_{step_idx}")),
-                max_bits: 32,
-            });
+/// Shifts multiply rs1 by a power of two: left shifts by `2^shamt`, taking
+/// the low half of the product, and right shifts by `2^(XLEN - shamt)`,
+/// taking the high half, with rs1 sign-extended by SRA. Everything else
+/// multiplies by rs2.
+fn shift_constraints(air: &mut Air, c: &TraceLayout) {
+    let sh = &c.shift;
+    let limbs = c.rs1_value.len();
+    let xlen = || AirExpr::constant(c.profile.xlen());
+    let left = sum(&[c.is_sll, c.is_slli, c.is_sllw, c.is_slliw]);
+    let right = sum(&[
+        c.is_srl, c.is_srli, c.is_sra, c.is_srai, c.is_srlw, c.is_srliw, c.is_sraw, c.is_sraiw,
+    ]);
+    let shift = left.clone() + right.clone();
+
+    // shamt is the low bits of rs2, five of them on RV32 and for word
+    // shifts, and may be 0
+    air.add_transition(
+        current(c.rs2_value[0])
+            - current(sh.amount)
+            - current(sh.bit5) * power_of_two(5)
+            - current(sh.high) * power_of_two(6),
+    );
+    let mut shamt = current(sh.amount);
+    if c.profile.xlen() == 64 {
+        let word_shift = sum(&[
+            c.is_sllw, c.is_slliw, c.is_srlw, c.is_srliw, c.is_sraw, c.is_sraiw,
+        ]);
+        shamt = shamt + (one() - word_shift) * current(sh.bit5) * power_of_two(5);
+    }
+    air.add_transition(current(sh.zero) - one() + shamt.clone() * current(sh.inverse));
+    air.add_transition(current(sh.zero) * shamt.clone());
+
+    // The factor is 2^exponent on shifts
+    let [b0, b1, b2] = [0, 1, 2].map(|i| current(sh.bits[i]));
+    air.add_transition(
+        current(sh.power)
+            - (one() + b0.clone())
+                * (one() + b1.clone() * AirExpr::constant(3))
+                * (one() + b2.clone() * AirExpr::constant(15)),
+    );
+    air.add_transition(select(&sh.limb_is, |_| one()) - shift.clone());
+    let exponent = select(&sh.limb_is, |j| AirExpr::constant(LIMB_BITS * j as u32))
+        + b0
+        + b1 * AirExpr::constant(2)
+        + b2 * AirExpr::constant(4);
+    air.add_transition(left.clone() * (exponent.clone() - shamt.clone()));
+    air.add_transition(right.clone() * (one() - current(sh.zero)) * (exponent - xlen() + shamt));
+    for j in 0..limbs {
+        air.add_transition(
+            current(c.factor[j])
+                - (one() - shift.clone()) * current(c.rs2_value[j])
+                - current(sh.limb_is[j]) * current(sh.power),
+        );
+    }
+
+    // Shifting by 0 to the right keeps rs1
+    for j in 0..limbs {
+        let r = current(c.result[j]);
+        air.add_transition(left.clone() * (r.clone() - current(c.product[j])));
+        air.add_transition(
+            right.clone()
+                * (one() - current(sh.zero))
+                * (r.clone() - current(c.product[limbs + j])),
+        );
+        air.add_transition(right.clone() * current(sh.zero) * (r - current(c.rs1_value[j])));
+    }
+}
+
+/// `rs1 = quotient * rs2 + remainder` with `|remainder| < |rs2|` and the
+/// remainder taking the sign of rs1, except when dividing by zero.
+fn division_constraints(air: &mut Air, c: &TraceLayout) {
+    let d = &c.division;
+    let limbs = c.rs1_value.len();
+    let limb = || power_of_two(LIMB_BITS);
+    let fill = || AirExpr::constant((1 << LIMB_BITS) - 1);
+    let signed = signed_divisions(c);
+    let division = divisions(c);
+    for (negative, sign) in [
+        (d.negative_dividend, c.rs1_sign),
+        (d.negative_divisor, c.rs2_sign),
+        (d.negative_remainder, d.remainder_sign),
+    ] {
+        air.add_transition(current(negative) - signed.clone() * current(sign));
+    }
+
+    // The product plus the remainder is the dividend, sign-extended; on
+    // overflow only modulo 2^XLEN
+    for k in 0..2 * limbs {
+        let (remainder, dividend, gate) = if k < limbs {
+            (
+                current(d.remainder[k]),
+                current(c.rs1_value[k]),
+                division.clone(),
+            )
+        } else {
+            (
+                current(d.negative_remainder) * fill(),
+                current(d.negative_dividend) * fill(),
+                division.clone() - current(d.overflow),
+            )
+        };
+        let carry_in = match k {
+            0 => AirExpr::constant(0),
+            _ => current(d.carries[k - 1]),
+        };
+        air.add_transition(
+            gate * (current(c.product[k]) + remainder + carry_in
+                - dividend
+                - current(d.carries[k]) * limb()),
+        );
+    }
+
+    // Overflow is the most negative value divided by -1
+    air.add_transition(current(d.overflow) * (one() - signed));
+    for j in 0..limbs {
+        let top = if j == limbs - 1 {
+            1 << (LIMB_BITS - 1)
+        } else {
+            0
+        };
+        air.add_transition(
+            current(d.overflow) * (current(c.rs1_value[j]) - AirExpr::constant(top)),
+        );
+        air.add_transition(current(d.overflow) * (current(c.rs2_value[j]) - fill()));
+    }
+
+    // Dividing by zero gives a quotient of all ones
+    let limb_sum = sum(&c.rs2_value);
+    air.add_transition(
+        current(d.divisor_zero) - one() + limb_sum.clone() * current(d.divisor_inverse),
+    );
+    air.add_transition(current(d.divisor_zero) * limb_sum);
+    air.add_transition(current(d.by_zero) - division.clone() * current(d.divisor_zero));
+    for &limb in &c.multiplicand {
+        air.add_transition(current(d.by_zero) * (current(limb) - fill()));
+    }
+
+    // Magnitudes of the remainder and divisor: negated values add up to
+    // 2^XLEN, or both are 0
+    for (value, negative, abs, carries) in [
+        (
+            &d.remainder,
+            d.negative_dividend,
+            &d.remainder_abs,
+            &d.remainder_abs_carries,
+        ),
+        (
+            &c.rs2_value,
+            d.negative_divisor,
+            &d.divisor_abs,
+            &d.divisor_abs_carries,
+        ),
+    ] {
+        for j in 0..limbs {
+            let carry_in = match j {
+                0 => AirExpr::constant(0),
+                _ => current(carries[j - 1]),
+            };
+            air.add_transition((one() - current(negative)) * (current(value[j]) - current(abs[j])));
+            air.add_transition(
+                current(negative)
+                    * (current(value[j]) + current(abs[j]) + carry_in
+                        - current(carries[j]) * limb()),
+            );
         }
     }
 
-    #[allow(clippy::only_used_in_recursion)]
-    pub fn evaluate_expression(
-        &self,
-        expr: &Expression,
-        witness: &std::collections::HashMap<String, u32>,
-    ) -> Option<u32> {
-        match expr {
-            Expression::Constant(val) => Some(*val),
-            Expression::Variable(name) => witness.get(name).copied(),
-            Expression::Add(left, right) => {
-                let left_val = self.evaluate_expression(left, witness)?;
-                let right_val = self.evaluate_expression(right, witness)?;
-                Some(left_val.wrapping_add(right_val))
-            }
-            Expression::Mul(left, right) => {
-                let left_val = self.evaluate_expression(left, witness)?;
-                let right_val = self.evaluate_expression(right, witness)?;
-                Some(left_val.wrapping_mul(right_val))
-            }
-            Expression::Sub(left, right) => {
-                let left_val = self.evaluate_expression(left, witness)?;
-                let right_val = self.evaluate_expression(right, witness)?;
-                Some(left_val.wrapping_sub(right_val))
-            }
+    // |divisor| - |remainder| - 1 does not borrow past the top limb
+    for j in 0..limbs {
+        let borrow_in = match j {
+            0 => one(),
+            _ => current(d.gap_borrows[j - 1]),
+        };
+        air.add_transition(
+            division.clone()
+                * (current(d.divisor_abs[j]) - current(d.remainder_abs[j]) - borrow_in
+                    + current(d.gap_borrows[j]) * limb()
+                    - current(d.gap[j])),
+        );
+    }
+    air.add_transition((division - current(d.by_zero)) * current(d.gap_borrows[limbs - 1]));
+
+    // DIV and DIVU write the quotient, REM and REMU the remainder
+    let quotient = sum(&[c.is_div, c.is_divu, c.is_divw, c.is_divuw]);
+    let remainder = sum(&[c.is_rem, c.is_remu, c.is_remw, c.is_remuw]);
+    for j in 0..limbs {
+        let result = current(c.result[j]);
+        air.add_transition(quotient.clone() * (result.clone() - current(c.multiplicand[j])));
+        air.add_transition(remainder.clone() * (result - current(d.remainder[j])));
+    }
+}
+
+/// Set on the signed divisions.
+fn signed_divisions(c: &TraceLayout) -> AirExpr {
+    sum(&[c.is_div, c.is_rem, c.is_divw, c.is_remw])
+}
+
+/// Set on the divisions.
+fn divisions(c: &TraceLayout) -> AirExpr {
+    signed_divisions(c) + sum(&[c.is_divu, c.is_remu, c.is_divuw, c.is_remuw])
+}
+
+/// The RV64 word instructions compute as the instructions they are the
+/// word forms of, on the low words of their operands extended to 64 bits:
+/// zero-extended by SRLW, SRLIW, DIVUW and REMUW, sign-extended by the
+/// others. rd gets the low word of the result, sign-extended.
+fn word_constraints(air: &mut Air, c: &TraceLayout) {
+    let w = &c.word;
+    let limbs = c.rs1_value.len();
+    if limbs <= 4 {
+        return;
+    }
+    let fill = || AirExpr::constant((1 << LIMB_BITS) - 1);
+    let rs1_unsigned = sum(&[c.is_srlw, c.is_srliw, c.is_divuw, c.is_remuw]);
+    let rs2_unsigned = current(c.is_divuw) + current(c.is_remuw);
+    let operands = [
+        (
+            &c.rs1_value,
+            c.rs1_sign,
+            w.rs1_top,
+            word_ops(c),
+            rs1_unsigned,
+        ),
+        (
+            &c.rs2_value,
+            c.rs2_sign,
+            w.rs2_top,
+            word_register_ops(c),
+            rs2_unsigned,
+        ),
+    ];
+    for (value, sign, top, word, unsigned) in operands {
+        let signed = word - unsigned.clone();
+        air.add_transition(
+            signed.clone()
+                * (current(value[3]) - current(sign) * power_of_two(LIMB_BITS - 1) - current(top)),
+        );
+        for &limb in &value[4..] {
+            air.add_transition(signed.clone() * (current(limb) - current(sign) * fill()));
+            air.add_transition(unsigned.clone() * current(limb));
         }
     }
 
-    pub fn verify_constraints(&self, witness: &std::collections::HashMap<String, u32>) -> bool {
-        for constraint in &self.constraints {
-            if !self.verify_constraint(constraint, witness) {
-                return false;
-            }
+    let word = word_ops(c);
+    air.add_transition(
+        word.clone()
+            * (current(c.result[3])
+                - current(w.result_sign) * power_of_two(LIMB_BITS - 1)
+                - current(w.result_top)),
+    );
+    for &limb in &c.rd_value[4..] {
+        air.add_transition(
+            (one() - current(c.rd_is[0]))
+                * word.clone()
+                * (current(limb) - current(w.result_sign) * fill()),
+        );
+    }
+}
+
+/// `rs1 - rs2 + (less + bias) * 2^XLEN = D`, subtracted limb by limb.
+fn comparison_constraints(air: &mut Air, c: &TraceLayout) {
+    let cmp = &c.comparison;
+    let signed = current(c.is_blt) + current(c.is_bge) + current(c.is_slt) + current(c.is_slti);
+    air.add_transition(current(cmp.bias) - signed * (current(c.rs2_sign) - current(c.rs1_sign)));
+
+    let last = cmp.difference.len() - 1;
+    for (j, &limb) in cmp.difference.iter().enumerate() {
+        let borrow_in = match j {
+            0 => AirExpr::constant(0),
+            _ => current(cmp.borrows[j - 1]),
+        };
+        let borrow_out = if j == last {
+            current(cmp.less) + current(cmp.bias)
+        } else {
+            current(cmp.borrows[j])
+        };
+        air.add_transition(
+            current(c.rs1_value[j]) - current(c.rs2_value[j]) - borrow_in
+                + borrow_out * power_of_two(LIMB_BITS)
+                - current(limb),
+        );
+    }
+
+    // The operands are equal exactly when D is 0
+    let difference = sum(&cmp.difference);
+    air.add_transition(current(cmp.equal) - one() + difference.clone() * current(cmp.inverse));
+    air.add_transition(current(cmp.equal) * difference);
+
+    // SLT, SLTU and their immediate forms write the comparison
+    let set = sum(&[c.is_slt, c.is_sltu, c.is_slti, c.is_sltiu]);
+    air.add_transition(set.clone() * (current(c.result[0]) - current(cmp.less)));
+    for &limb in &c.result[1..] {
+        air.add_transition(set.clone() * current(limb));
+    }
+}
+
+fn control_flow_constraints(air: &mut Air, c: &TraceLayout) {
+    let cmp = &c.comparison;
+    let (pc, size, imm) = (current(c.pc), current(c.size), current(c.imm));
+
+    // Instructions are two or four bytes long
+    air.add_transition(
+        (one() - current(c.is_padding))
+            * (size.clone() - AirExpr::constant(2))
+            * (size.clone() - AirExpr::constant(4)),
+    );
+
+    // Which branches are taken
+    air.add_transition(
+        current(c.taken)
+            - current(c.is_beq) * current(cmp.equal)
+            - current(c.is_bne) * (one() - current(cmp.equal))
+            - (current(c.is_blt) + current(c.is_bltu)) * current(cmp.less)
+            - (current(c.is_bge) + current(c.is_bgeu)) * (one() - current(cmp.less)),
+    );
+
+    // JAL and JALR compute the return address, with its low word below
+    // 2^30 so that it is unique in the field
+    let jump = current(c.is_jal) + current(c.is_jalr);
+    air.add_transition(jump.clone() * (join(&c.result[..4]) - pc.clone() - size.clone()));
+    air.add_transition(jump.clone() * (current(c.result[3]) - current(c.link_top)));
+    for &limb in &c.result[4..] {
+        air.add_transition(jump.clone() * current(limb));
+    }
+
+    // next_pc = pc + size, or the branch or jump target
+    let jalr_target = join(&c.rs1_value[..4]) + imm.clone() - current(c.target_lsb);
+    air.add_transition(
+        current(c.next_pc)
+            - pc.clone()
+            - size.clone()
+            - (current(c.taken) + current(c.is_jal)) * (imm - size.clone())
+            - current(c.is_jalr) * (jalr_target - pc - size),
+    );
+
+    // The next row starts at next_pc
+    air.add_transition(next(c.pc) - current(c.next_pc));
+}
+
+/// Offline memory checking. The sorted log is a permutation of the log in
+/// execution order, through the memory bus. In it, each access to a word
+/// reads what the previous one left, the first reads the word's initial
+/// value and the last leaves its final value, both looked up in the public
+/// memory boundary.
+fn memory_constraints(air: &mut Air, c: &TraceLayout) {
+    let (m, s) = (&c.memory, &c.sorted_memory);
+
+    // Accesses are timestamped 1, 2, ... in execution order, and reads
+    // leave the word unchanged
+    air.add_boundary_constant(m.timestamp, BoundaryRow::First, 1);
+    air.add_transition(next(m.timestamp) - current(m.timestamp) - current(m.used));
+    for (&before, &after) in m.value_before.iter().zip(&m.value_after) {
+        air.add_transition((one() - current(m.is_write)) * (current(after) - current(before)));
+    }
+
+    // Sorted accesses come first, then by word and timestamp
+    air.add_transition((one() - current(s.used)) * next(s.used));
+    air.add_transition(current(s.same_word) * (one() - current(s.used)));
+    air.add_transition(current(s.same_word) * (one() - next(s.used)));
+    air.add_transition(current(s.same_word) * (next(s.word) - current(s.word)));
+    air.add_transition(current(s.new_word) - next(s.used) * (one() - current(s.same_word)));
+    air.add_transition(next(s.first) + current(s.same_word) - one());
+    air.add_boundary_constant(s.first, BoundaryRow::First, 1);
+    let gap = |column: usize| {
+        next(column) - current(column) - one() - current(s.gap_high) * power_of_two(16)
+    };
+    air.add_lookup(
+        LookupTable::Range16,
+        vec![gap(s.timestamp)],
+        current(s.same_word) * current(s.used),
+    );
+    air.add_lookup(
+        LookupTable::Range16,
+        vec![gap(s.word)],
+        current(s.new_word) * current(s.used),
+    );
+
+    // Read-after-write consistency
+    for (&before, &after) in s.value_before.iter().zip(&s.value_after) {
+        air.add_transition(current(s.same_word) * (next(before) - current(after)));
+    }
+
+    // The logs hold the same accesses; the last row, which transitions
+    // leave unchecked, holds none
+    let access = |word: usize, timestamp: usize, before: &[usize], after: &[usize]| {
+        [word, timestamp]
+            .iter()
+            .chain(before)
+            .chain(after)
+            .map(|&column| current(column))
+            .collect::<Vec<_>>()
+    };
+    air.add_lookup(
+        LookupTable::Memory,
+        access(m.word, m.timestamp, &m.value_before, &m.value_after),
+        current(m.used),
+    );
+    air.add_lookup(
+        LookupTable::Memory,
+        access(s.word, s.timestamp, &s.value_before, &s.value_after),
+        -current(s.used),
+    );
+    air.add_boundary_constant(m.used, BoundaryRow::Last, 0);
+    air.add_boundary_constant(s.used, BoundaryRow::Last, 0);
+
+    // Every access in the log but those of transfers is one a load or
+    // store claims
+    let transfer = current(c.transfer.first) + current(c.transfer.rest);
+    air.add_lookup(
+        LookupTable::Access,
+        access(m.word, m.timestamp, &m.value_before, &m.value_after),
+        transfer - current(m.used),
+    );
+
+    // Memory boundary
+    let word = |limbs: &[usize]| {
+        std::iter::once(s.word)
+            .chain(limbs.iter().copied())
+            .map(current)
+            .collect()
+    };
+    air.add_lookup(
+        LookupTable::InitialMemory,
+        word(&s.value_before),
+        current(s.first) * current(s.used),
+    );
+    air.add_lookup(
+        LookupTable::FinalMemory,
+        word(&s.value_after),
+        current(s.used) * (one() - current(s.same_word)),
+    );
+}
+
+/// The byte of the word the load/store group points at.
+fn byte_offset(c: &TraceLayout) -> AirExpr {
+    let byte_is = |k: usize| current(c.load_store.byte_is[k]);
+    byte_is(1) + byte_is(2) * AirExpr::constant(2) + byte_is(3) * AirExpr::constant(3)
+}
+
+/// The word the load/store group points at, as the memory log numbers it.
+fn word_address(c: &TraceLayout) -> AirExpr {
+    let ls = &c.load_store;
+    current(ls.word_odd)
+        + current(ls.word_low) * AirExpr::constant(2)
+        + current(ls.word_high) * power_of_two(14)
+}
+
+/// Set on READ_INPUT, WRITE_JOURNAL, OPEN, READ, KV_GET, KV_SET, Linux
+/// read and fstat rows that copy bytes.
+fn transfers(c: &TraceLayout) -> AirExpr {
+    let sys = &c.syscall;
+    sum(&[
+        sys.is_read_input,
+        sys.is_write_journal,
+        sys.is_linux_read,
+        sys.is_linux_fstat,
+        sys.is_open,
+        sys.is_read,
+        sys.is_kv_get,
+        sys.is_kv_set,
+    ]) * current(sys.nonzero)
+}
+
+/// Loads and stores access the word holding `rs1 + imm`, at their step's
+/// timestamp: loads read a byte, halfword or word of it and load it
+/// through rs2, stores merge the low bytes of rs2 into it. LD and SD also
+/// access the next word, at the next timestamp, for the high word of rs2.
+/// Each puts its accesses on the access bus, which the memory log takes
+/// every access but those of transfers back from, so the log holds exactly
+/// their accesses. Transfers point the address columns at their first byte
+/// and take the timestamps up to the end of the transfer.
+fn load_store_constraints(air: &mut Air, c: &TraceLayout) {
+    let ls = &c.load_store;
+    let a = |j: usize| current(c.rs1_value[j]);
+    let b = |j: usize| current(c.rs2_value[j]);
+    let byte_is = |k: usize| current(ls.byte_is[k]);
+    let (sw, sb, sh, sd) = (
+        current(c.is_sw),
+        current(c.is_sb),
+        current(c.is_sh),
+        current(c.is_sd),
+    );
+    let byte = current(c.is_lb) + current(c.is_lbu);
+    let half = current(c.is_lh) + current(c.is_lhu);
+    let word = current(c.is_lw) + current(c.is_lwu);
+    let dword = current(c.is_ld) + sd.clone();
+    let access = loads(c) + stores(c);
+    let transfer = transfers(c);
+
+    // The address points at one byte of the word, aligned to the width of
+    // the access
+    air.add_transition(sum(&ls.byte_is) - access.clone() - transfer.clone());
+    air.add_transition((half.clone() + sh.clone()) * (byte_is(1) + byte_is(3)));
+    air.add_transition((word.clone() + sw.clone() + dword.clone()) * (one() - byte_is(0)));
+    air.add_transition(dword.clone() * current(ls.word_odd));
+    let offset = byte_offset(c);
+
+    // The address, added in halves that stay far below the modulus
+    let (carry, wrap) = (current(ls.carry), current(ls.wrap));
+    for value in [carry.clone(), wrap.clone()] {
+        air.add_transition(value.clone() * (value.clone() * value - one()));
+    }
+    air.add_transition(
+        access.clone()
+            * (a(0) + a(1) * power_of_two(LIMB_BITS) + current(c.imm)
+                - current(ls.word_low) * AirExpr::constant(8)
+                - current(ls.word_odd) * AirExpr::constant(4)
+                - offset
+                - carry.clone() * power_of_two(16)),
+    );
+    air.add_transition(
+        access.clone()
+            * (a(2) + a(3) * power_of_two(LIMB_BITS) + carry
+                - current(ls.word_high)
+                - wrap.clone() * power_of_two(16)),
+    );
+    // On RV64 the address wraps to the low 4 GiB
+    for j in 4..c.rs1_value.len() {
+        air.add_transition(access.clone() * (a(j) - wrap.clone() * AirExpr::constant(0xff)));
+    }
+
+    // Loads read the limbs from the selected byte on as rs2, extended, and
+    // write it to rd
+    let before = |k: usize| current(ls.value_before[k]);
+    let low_byte = select(&ls.byte_is, before);
+    let high_byte = byte_is(0) * before(1) + byte_is(2) * before(3);
+    let fill = current(c.rs2_sign) * AirExpr::constant(0xff);
+    for (j, &result) in c.result.iter().enumerate() {
+        let (byte_value, half_value, word_value) = match j {
+            0 => (low_byte.clone(), low_byte.clone(), before(0)),
+            1 => (fill.clone(), high_byte.clone(), before(1)),
+            2 | 3 => (fill.clone(), fill.clone(), before(j)),
+            _ => (fill.clone(), fill.clone(), fill.clone()),
+        };
+        air.add_transition(byte.clone() * (b(j) - byte_value));
+        air.add_transition(half.clone() * (b(j) - half_value));
+        air.add_transition(word.clone() * (b(j) - word_value));
+        if j < 4 {
+            air.add_transition(current(c.is_ld) * (b(j) - before(j)));
         }
-        true
+        air.add_transition(loads(c) * (current(result) - b(j)));
+    }
+    // Unsigned loads zero-extend, signed ones extend the top bit they read
+    let unsigned = sum(&[c.is_lbu, c.is_lhu, c.is_lwu]);
+    air.add_transition(unsigned * current(c.rs2_sign));
+    for (flag, top) in [
+        (c.is_lb, low_byte),
+        (c.is_lh, high_byte),
+        (c.is_lw, before(3)),
+    ] {
+        air.add_transition(
+            current(flag)
+                * (top - current(c.rs2_sign) * power_of_two(LIMB_BITS - 1) - current(ls.value_top)),
+        );
     }
 
-    pub fn verify_constraint(
-        &self,
-        constraint: &Constraint,
-        witness: &std::collections::HashMap<String, u32>,
-    ) -> bool {
-        match constraint {
-            Constraint::Equality { left, right } => {
-                let left_val = self.evaluate_expression(left, witness);
-                let right_val = self.evaluate_expression(right, witness);
-                left_val == right_val && left_val.is_some()
-            }
-            Constraint::RangeCheck { value, max_bits } => {
-                if let Some(val) = self.evaluate_expression(value, witness) {
-                    if *max_bits >= 32 {
-                        true // u32 values are always within 32-bit range
-                    } else {
-                        val < (1u32 << max_bits)
-                    }
-                } else {
-                    false
-                }
-            }
-            Constraint::MemoryConsistency { .. } => {
-                // For now, assume memory consistency is always satisfied
-                // In a real implementation, this would check memory operation ordering
-                true
-            }
+    // Stores replace the bytes from the selected one on with rs2's low
+    // bytes; loads leave the word as it was
+    for (k, &after) in ls.value_after.iter().enumerate() {
+        let stored = |limb: usize| b(limb) - before(k);
+        let half_start = byte_is(k & !1);
+        let change = sb.clone() * byte_is(k) * stored(0)
+            + sh.clone() * half_start * stored(k & 1)
+            + (sw.clone() + sd.clone()) * stored(k);
+        air.add_transition(current(after) - before(k) - change);
+    }
+
+    // Each access takes the next timestamp
+    air.add_boundary_constant(ls.timestamp, BoundaryRow::First, 1);
+    air.add_transition(
+        (one() - transfer)
+            * (next(ls.timestamp) - current(ls.timestamp) - access.clone() - dword.clone()),
+    );
+
+    let address = word_address(c);
+    let entry = |word: AirExpr, timestamp: AirExpr, before: &[usize], after: &[usize]| {
+        [word, timestamp]
+            .into_iter()
+            .chain(before.iter().chain(after).map(|&limb| current(limb)))
+            .collect()
+    };
+    air.add_lookup(
+        LookupTable::Access,
+        entry(
+            address.clone(),
+            current(ls.timestamp),
+            &ls.value_before,
+            &ls.value_after,
+        ),
+        access,
+    );
+    for flag in [
+        c.is_lw, c.is_sw, c.is_lb, c.is_lh, c.is_lbu, c.is_lhu, c.is_sb, c.is_sh, c.is_ld,
+        c.is_lwu, c.is_sd,
+    ] {
+        air.add_boundary_constant(flag, BoundaryRow::Last, 0);
+    }
+
+    // LD and SD access the high word of rs2 in the next word
+    if c.rs2_value.len() <= 4 {
+        return;
+    }
+    for (k, (&before, &after)) in ls.high_before.iter().zip(&ls.high_after).enumerate() {
+        air.add_transition(
+            current(c.is_ld) * (b(k + 4) - current(before))
+                + sd.clone() * (b(k + 4) - current(after)),
+        );
+        air.add_transition((one() - sd.clone()) * (current(after) - current(before)));
+    }
+    air.add_lookup(
+        LookupTable::Access,
+        entry(
+            address + one(),
+            current(ls.timestamp) + one(),
+            &ls.high_before,
+            &ls.high_after,
+        ),
+        dword,
+    );
+}
+
+/// The syscall columns the public values pin: the input and journal the
+/// run starts with, the exit status and code it ends with, and the program
+/// break and heap it starts with.
+fn syscall_boundaries(c: &TraceLayout) -> [(usize, BoundaryRow); 11] {
+    let sys = &c.syscall;
+    let (first, last) = (BoundaryRow::First, BoundaryRow::Last);
+    [
+        (sys.input_remaining, first),
+        (sys.journal_remaining, first),
+        (sys.exit_status, last),
+        (sys.exit_code_low, last),
+        (sys.exit_code_high, last),
+        (sys.brk_low, first),
+        (sys.brk_high, first),
+        (sys.heap_start_low, first),
+        (sys.heap_start_high, first),
+        (sys.heap_end_low, first),
+        (sys.heap_end_high, first),
+    ]
+}
+
+/// The key-value store columns the public values pin: the root the run
+/// starts and ends with, and the number of keys.
+fn kv_boundaries(c: &TraceLayout) -> Vec<(usize, BoundaryRow)> {
+    let kv = &c.kv;
+    let roots = [BoundaryRow::First, BoundaryRow::Last]
+        .into_iter()
+        .flat_map(|row| kv.root.iter().map(move |&column| (column, row)));
+    roots.chain([(kv.keys, BoundaryRow::First)]).collect()
+}
+
+/// ECALL runs the built-in syscall its flag selects, whose number is in
+/// the syscall register and whose price is in the public syscall table.
+/// Syscalls read `a0` to `a2` and return `a0` as 32-bit values:
+///
+/// - HALT returns `a0`, which is the exit code, and stops the guest
+/// - READ_INPUT copies `min(a1, input left)` bytes of input to `a0`
+/// - WRITE_JOURNAL appends `a1` bytes at `a0` to the journal
+/// - PANIC stops the guest
+/// - WRITE returns `a2`, or an errno if `a0` is not stdout or stderr or
+///   `a2` does not fit an `i32`
+/// - BRK moves the break to `a0` if it is in the heap, and returns it
+/// - OPEN, READ, SEEK and CLOSE act on descriptor slots, as
+///   [`file_constraints`] checks
+/// - KV_GET and KV_SET access the key-value store, as [`kv_constraints`]
+///   checks
+///
+/// What WRITE and PANIC read is for the host, and left out of the memory
+/// log. READ_INPUT, WRITE_JOURNAL, OPEN, READ, KV_GET and KV_SET copy
+/// their bytes through the transfer bus.
+fn syscall_constraints(air: &mut Air, c: &TraceLayout) {
+    let sys = &c.syscall;
+    let cmp = &c.comparison;
+    let ecall = current(c.is_ecall);
+    let flags = sys.flags();
+    let [halt, read_input, journal, panic, write, brk, linux_read, linux_write, exit, exit_group, linux_brk, linux_close, fstat, open, read_file, seek, _, lseek, kv_get, kv_set] =
+        flags.map(|(flag, _)| current(flag));
+    let seek = seek + lseek;
+    // The Linux syscalls act like the built-in ones
+    let halt = halt + exit + exit_group;
+    let read = read_input.clone() + linux_read.clone();
+    let write = write + linux_write;
+    let brk = brk + linux_brk;
+    let a = |register: usize, j: usize| current(c.registers[register][j]);
+    let half = |register: usize, h: usize| {
+        a(register, 2 * h) + a(register, 2 * h + 1) * power_of_two(LIMB_BITS)
+    };
+    let (rs1, rs2, result) = (
+        |j: usize| current(c.rs1_value[j]),
+        |j: usize| current(c.rs2_value[j]),
+        |j: usize| current(c.result[j]),
+    );
+    let result_half = |h: usize| result(2 * h) + result(2 * h + 1) * power_of_two(LIMB_BITS);
+    let less = current(cmp.less);
+
+    // One flag selects the syscall in the syscall register, priced by the
+    // public syscall table
+    let columns: Vec<usize> = flags.iter().map(|&(flag, _)| flag).collect();
+    for &column in columns.iter().chain(&[sys.gap_borrow, sys.above]) {
+        boolean(air, column);
+    }
+    air.add_transition(sum(&columns) - ecall.clone());
+    let number = flags
+        .iter()
+        .fold(AirExpr::constant(0), |acc, &(flag, syscall)| {
+            acc + current(flag) * AirExpr::constant(syscall)
+        });
+    let register = syscall_register(c.profile.base);
+    air.add_transition(ecall.clone() * (half(register, 0) - number.clone()));
+    air.add_transition(ecall.clone() * half(register, 1));
+    air.add_lookup(
+        LookupTable::Syscall,
+        vec![number, current(sys.price)],
+        ecall.clone(),
+    );
+    air.add_transition((one() - ecall.clone()) * current(sys.price));
+    for &limb in [&c.rs1_value, &c.rs2_value, &c.result]
+        .into_iter()
+        .flat_map(|value| &value[4..])
+    {
+        air.add_transition(ecall.clone() * current(limb));
+    }
+
+    // HALT returns a0 and exits with it; the exit code is the same on every
+    // row, and the status set once the guest stops, after which only
+    // padding follows
+    for j in 0..4 {
+        air.add_transition(halt.clone() * (result(j) - a(10, j)));
+    }
+    for (h, column) in [sys.exit_code_low, sys.exit_code_high]
+        .into_iter()
+        .enumerate()
+    {
+        air.add_transition(halt.clone() * (current(column) - half(10, h)));
+        air.add_transition(next(column) - current(column));
+    }
+    air.add_boundary_constant(sys.exit_status, BoundaryRow::First, 0);
+    air.add_transition(
+        next(sys.exit_status)
+            - current(sys.exit_status)
+            - halt
+            - panic.clone() * AirExpr::constant(2),
+    );
+    air.add_transition(current(sys.exit_status) * (one() - current(c.is_padding)));
+    for j in 0..4 {
+        air.add_transition((panic.clone() + journal.clone()) * result(j));
+    }
+
+    // READ_INPUT copies the lesser of a1 and the input left, and returns
+    // it, as does Linux read of a2 bytes from stdin
+    let length = current(sys.length);
+    for j in 0..4 {
+        air.add_transition(read_input.clone() * (rs1(j) - a(11, j)));
+        air.add_transition(linux_read.clone() * (rs1(j) - a(12, j)));
+        air.add_transition(linux_read.clone() * a(10, j));
+        air.add_transition(
+            read.clone() * (result(j) - less.clone() * rs1(j) - (one() - less.clone()) * rs2(j)),
+        );
+    }
+    air.add_transition(read.clone() * (join(&c.rs2_value[..4]) - current(sys.input_remaining)));
+    air.add_transition(read.clone() * rs2(3));
+    air.add_transition(read.clone() * (length.clone() - join(&c.result[..4])));
+
+    // WRITE_JOURNAL copies a1 bytes, below 2^24
+    air.add_transition(journal.clone() * (length.clone() - join(&c.registers[11][..4])));
+    air.add_transition(journal.clone() * a(11, 3));
+    let copies =
+        read.clone() + journal.clone() + fstat.clone() + open + read_file + kv_get + kv_set;
+    air.add_transition((one() - copies.clone()) * length.clone());
+    air.add_transition(
+        next(sys.input_remaining) - current(sys.input_remaining) + read.clone() * length.clone(),
+    );
+    air.add_transition(
+        next(sys.journal_remaining) - current(sys.journal_remaining)
+            + journal.clone() * length.clone(),
+    );
+    air.add_boundary_constant(sys.journal_remaining, BoundaryRow::Last, 0);
+
+    // nonzero tests the length of a transfer, on WRITE a value that is 0
+    // exactly on stdout and stderr, and on SEEK one that is 0 exactly on a
+    // whence
+    let fd =
+        (a(10, 0) - one()) * (a(10, 0) - AirExpr::constant(2)) + a(10, 1) + a(10, 2) + a(10, 3);
+    let tested =
+        copies * length.clone() + write.clone() * fd + seek * current(c.files.whence_tested);
+    let nonzero = current(sys.nonzero);
+    air.add_transition(nonzero.clone() - tested.clone() * current(sys.inverse));
+    air.add_transition((one() - nonzero.clone()) * tested);
+
+    // WRITE compares a2 with 2^31
+    let [einval, ebadf] =
+        [errno::EINVAL, errno::EBADF].map(|code| errno::to_result(code).to_le_bytes());
+    for j in 0..4 {
+        let top = if j == 3 { 0x80 } else { 0 };
+        air.add_transition(write.clone() * (rs1(j) - a(12, j)));
+        air.add_transition(write.clone() * (rs2(j) - AirExpr::constant(top)));
+        let accepted =
+            less.clone() * rs1(j) + (one() - less.clone()) * AirExpr::constant(einval[j] as u32);
+        air.add_transition(
+            write.clone()
+                * (result(j)
+                    - (one() - nonzero.clone()) * accepted
+                    - nonzero.clone() * AirExpr::constant(ebadf[j] as u32)),
+        );
+    }
+
+    // Linux close and fstat compare a0 with 3: below it they act on a
+    // standard stream, fstat writing its struct stat to a1, and otherwise
+    // on a descriptor, failing unless close finds it open; fstat on an open
+    // one cannot be proven
+    let std_stream = linux_close + fstat.clone();
+    let descriptor_open = current(c.files.descriptor_open);
+    for (j, code) in ebadf.into_iter().enumerate() {
+        let three = if j == 0 { 3 } else { 0 };
+        air.add_transition(std_stream.clone() * (rs1(j) - a(10, j)));
+        air.add_transition(std_stream.clone() * (rs2(j) - AirExpr::constant(three)));
+        let failed = (one() - less.clone()) * (one() - descriptor_open.clone());
+        air.add_transition(
+            std_stream.clone() * (result(j) - failed * AirExpr::constant(code as u32)),
+        );
+    }
+    air.add_transition(fstat.clone() * descriptor_open);
+    let stat = LinuxSyscalls::std_stat().len() as u32;
+    air.add_transition(fstat.clone() * (length - less.clone() * AirExpr::constant(stat)));
+
+    // BRK compares a0 with the heap start, and subtracts it from the heap
+    // end, borrowing out of the top if it is above
+    for j in 0..4 {
+        air.add_transition(brk.clone() * (rs1(j) - a(10, j)));
+    }
+    let halves = [
+        (sys.heap_start_low, sys.heap_end_low, sys.brk_low),
+        (sys.heap_start_high, sys.heap_end_high, sys.brk_high),
+    ];
+    let (borrow, above) = (current(sys.gap_borrow), current(sys.above));
+    let accepted = (one() - less) * (one() - above.clone());
+    for (h, (heap_start, heap_end, program_break)) in halves.into_iter().enumerate() {
+        let rs2_half = rs2(2 * h) + rs2(2 * h + 1) * power_of_two(LIMB_BITS);
+        air.add_transition(brk.clone() * (rs2_half - current(heap_start)));
+        let (borrow_in, borrow_out, gap) = match h {
+            0 => (AirExpr::constant(0), borrow.clone(), sys.gap_low),
+            _ => (borrow.clone(), above.clone(), sys.gap_high),
+        };
+        air.add_transition(
+            brk.clone()
+                * (current(heap_end) - half(10, h) - borrow_in + borrow_out * power_of_two(16)
+                    - current(gap)),
+        );
+        let moved =
+            current(program_break) + accepted.clone() * (half(10, h) - current(program_break));
+        air.add_transition(brk.clone() * (result_half(h) - moved));
+        air.add_transition(
+            next(program_break)
+                - current(program_break)
+                - brk.clone() * (result_half(h) - current(program_break)),
+        );
+        air.add_transition(next(heap_start) - current(heap_start));
+        air.add_transition(next(heap_end) - current(heap_end));
+    }
+
+    // Transitions leave the last row's flags unchecked
+    for column in [
+        c.is_ecall,
+        sys.is_read_input,
+        sys.is_write_journal,
+        sys.is_linux_read,
+        sys.is_linux_fstat,
+        sys.is_open,
+        sys.is_read,
+        sys.is_kv_get,
+        sys.is_kv_set,
+    ] {
+        air.add_boundary_constant(column, BoundaryRow::Last, 0);
+    }
+}
+
+/// OPEN, READ, SEEK and CLOSE act on descriptor slots, one per descriptor
+/// from 3 on, which start free and change only through the slot a row
+/// selects:
+///
+/// - OPEN looks the path of `a1` bytes at `a0` up among the public files,
+///   takes the lowest free slot and returns its descriptor
+/// - READ copies `min(a2, size - offset)` bytes of the file to `a1` and
+///   advances the offset by as many
+/// - SEEK moves the offset to `a1` from the start, the offset or the end as
+///   `a2` is 0, 1 or 2, or returns EINVAL if that is not in `0..2^31`
+/// - CLOSE frees the slot and returns 0
+///
+/// READ, SEEK and CLOSE return EBADF on a descriptor whose slot is free or
+/// which has none, shown by `fd_tested`. OPEN takes the file's size from
+/// the sponge rows hashing it, and READ the bytes it copies, so both are
+/// those of the file whose hash the public values hold.
+fn file_constraints(air: &mut Air, c: &TraceLayout) {
+    let (sys, f) = (&c.syscall, &c.files);
+    let [open, read, seek, lseek, close, linux_close, fstat] = [
+        sys.is_open,
+        sys.is_read,
+        sys.is_seek,
+        sys.is_linux_lseek,
+        sys.is_close,
+        sys.is_linux_close,
+        sys.is_linux_fstat,
+    ]
+    .map(current);
+    let seek = seek + lseek;
+    let closes = close.clone() + linux_close;
+    let descriptor = read.clone() + seek.clone() + closes.clone() + fstat;
+    let a = |register: usize, j: usize| current(c.registers[register][j]);
+    let half = |register: usize, h: usize| {
+        a(register, 2 * h) + a(register, 2 * h + 1) * power_of_two(LIMB_BITS)
+    };
+    let (rs1, rs2, result) = (
+        |j: usize| current(c.rs1_value[j]),
+        |j: usize| current(c.rs2_value[j]),
+        |j: usize| current(c.result[j]),
+    );
+    let result_half = |h: usize| result(2 * h) + result(2 * h + 1) * power_of_two(LIMB_BITS);
+    let (file, size, offset) = (
+        current(f.selected_file),
+        current(f.selected_size),
+        current(f.selected_offset),
+    );
+    let descriptor_open = current(f.descriptor_open);
+    let length = current(sys.length);
+    let less = current(c.comparison.less);
+    let [ebadf, einval] =
+        [errno::EBADF, errno::EINVAL].map(|code| errno::to_result(code).to_le_bytes());
+    for &column in f.slot_is.iter().chain(&f.whence_is).chain(&[
+        f.descriptor_open,
+        f.above,
+        f.offset_sign,
+        f.sum_carry,
+        f.sum_top,
+        f.wrap,
+        f.accepted,
+    ]) {
+        boolean(air, column);
+    }
+
+    // A row selects at most one slot, on the syscalls taking one, and
+    // reads what it holds
+    let in_range = sum(&f.slot_is);
+    air.add_transition(in_range.clone() * (in_range.clone() - one()));
+    for &slot_is in &f.slot_is {
+        air.add_transition((one() - descriptor.clone() - open.clone()) * current(slot_is));
+    }
+    for (column, slots) in [
+        (f.selected_file, &f.slot_file),
+        (f.selected_size, &f.slot_size),
+        (f.selected_offset, &f.slot_offset),
+    ] {
+        air.add_transition(current(column) - select(&f.slot_is, |k| current(slots[k])));
+    }
+    air.add_transition(descriptor_open.clone() - file.clone() * current(f.descriptor_inverse));
+    air.add_transition((one() - descriptor_open.clone()) * file.clone());
+
+    // The slot of a descriptor is a0 - 3; fd_tested is zero exactly on
+    // the descriptors that have one
+    let a0 = join(&c.registers[10][..4]);
+    let fd_tested = (0..FILE_SLOTS).fold(one(), |acc, k| {
+        acc * (a0.clone() - AirExpr::constant(FIRST_FILE_FD + k as u32))
+    });
+    air.add_transition(current(f.fd_tested) - fd_tested);
+    let slot = select(&f.slot_is, |k| AirExpr::constant(FIRST_FILE_FD + k as u32));
+    air.add_transition(descriptor.clone() * (half(10, 0) * in_range.clone() - slot.clone()));
+    air.add_transition(descriptor.clone() * in_range.clone() * half(10, 1));
+    air.add_transition(
+        descriptor
+            * (one() - in_range.clone())
+            * (current(f.fd_tested) * current(f.fd_inverse) - one()),
+    );
+
+    // OPEN takes the lowest free slot for a public file, whose path it
+    // reads and whose size the sponge rows put on the bus
+    air.add_transition(open.clone() * (one() - in_range));
+    air.add_transition(open.clone() * file.clone());
+    for k in 0..FILE_SLOTS {
+        air.add_transition(
+            open.clone()
+                * sum(&f.slot_is[k + 1..])
+                * (current(f.slot_file[k]) * current(f.slot_inverse[k]) - one()),
+        );
+    }
+    air.add_transition(open.clone() * (result(0) - slot));
+    for j in 1..4 {
+        air.add_transition(open.clone() * result(j));
+    }
+    air.add_transition(open.clone() * (length.clone() - join(&c.registers[11][..4])));
+    air.add_transition(open.clone() * a(11, 3));
+    air.add_lookup(
+        LookupTable::FilePath,
+        vec![
+            current(f.file) * power_of_two(24),
+            join(&c.registers[11][..4]),
+        ],
+        open.clone(),
+    );
+    air.add_lookup(
+        LookupTable::FileSize,
+        vec![current(f.file), current(f.size)],
+        open.clone(),
+    );
+
+    // READ compares a2 with the bytes from the offset to the end, none if
+    // the offset is past it, and copies the lesser from its file
+    let (above, distance) = (
+        current(f.above),
+        current(f.distance_low) + current(f.distance_high) * power_of_two(16),
+    );
+    let available = (one() - above.clone()) * (size.clone() - offset.clone());
+    for (j, code) in ebadf.into_iter().enumerate() {
+        air.add_transition(read.clone() * (rs1(j) - a(12, j)));
+        air.add_transition(
+            read.clone()
+                * descriptor_open.clone()
+                * (result(j) - less.clone() * rs1(j) - (one() - less.clone()) * rs2(j)),
+        );
+        air.add_transition(
+            read.clone()
+                * (one() - descriptor_open.clone())
+                * (result(j) - AirExpr::constant(code as u32)),
+        );
+    }
+    air.add_transition(read.clone() * (join(&c.rs2_value[..4]) - available));
+    air.add_transition(read.clone() * rs2(3));
+    air.add_transition(
+        read.clone() * (length.clone() - descriptor_open.clone() * join(&c.result[..4])),
+    );
+    air.add_transition(
+        read.clone()
+            * descriptor_open.clone()
+            * (distance
+                - (one() - above.clone() * AirExpr::constant(2)) * (size.clone() - offset.clone())
+                + above),
+    );
+    air.add_transition(
+        read.clone() * descriptor_open.clone() * (current(f.file) + one() - file.clone()),
+    );
+
+    // SEEK adds a1 to the offset a2 picks half by half; the sum is an
+    // offset if a1 is not negative and its top bit is clear, or if a1 is
+    // negative and it carries out of the top
+    let whence = join(&c.registers[12][..4]);
+    let nonzero = current(sys.nonzero);
+    air.add_transition(
+        seek.clone()
+            * (current(f.whence_tested)
+                - whence.clone()
+                    * (whence.clone() - one())
+                    * (whence.clone() - AirExpr::constant(2))),
+    );
+    air.add_transition(seek.clone() * (sum(&f.whence_is) - one() + nonzero.clone()));
+    air.add_transition(
+        seek.clone()
+            * (one() - nonzero.clone())
+            * (whence - select(&f.whence_is, |k| AirExpr::constant(k as u32))),
+    );
+    let base = current(f.whence_is[1]) * offset.clone() + current(f.whence_is[2]) * size.clone();
+    air.add_transition(
+        seek.clone() * (current(f.base_low) + current(f.base_high) * power_of_two(16) - base),
+    );
+    let (sign, top, wrap) = (current(f.offset_sign), current(f.sum_top), current(f.wrap));
+    air.add_transition(
+        seek.clone() * (half(11, 1) - sign.clone() * power_of_two(15) - current(f.offset_rest)),
+    );
+    air.add_transition(
+        seek.clone()
+            * (current(f.base_low) + half(11, 0)
+                - current(f.sum_low)
+                - current(f.sum_carry) * power_of_two(16)),
+    );
+    let sum_high = top.clone() * power_of_two(15)
+        + current(f.sum_mid) * power_of_two(13)
+        + current(f.sum_rest);
+    air.add_transition(
+        seek.clone()
+            * (current(f.base_high) + half(11, 1) + current(f.sum_carry)
+                - sum_high.clone()
+                - wrap.clone() * power_of_two(16)),
+    );
+    air.add_transition(
+        seek.clone()
+            * (current(f.in_bounds) - (one() - sign.clone()) * (one() - top) - sign * wrap),
+    );
+    let accepted = current(f.accepted);
+    air.add_transition(
+        seek.clone()
+            * (accepted.clone()
+                - descriptor_open.clone() * (one() - nonzero) * current(f.in_bounds)),
+    );
+    air.add_transition((one() - seek.clone()) * accepted.clone());
+    // Offsets stay below 2^29
+    air.add_transition(accepted.clone() * current(f.sum_mid));
+    for j in 0..4 {
+        air.add_transition(
+            seek.clone()
+                * (one() - descriptor_open.clone())
+                * (result(j) - AirExpr::constant(ebadf[j] as u32)),
+        );
+        air.add_transition(
+            seek.clone()
+                * descriptor_open.clone()
+                * (one() - accepted.clone())
+                * (result(j) - AirExpr::constant(einval[j] as u32)),
+        );
+    }
+    air.add_transition(accepted.clone() * (result_half(0) - current(f.sum_low)));
+    air.add_transition(accepted.clone() * (result_half(1) - sum_high.clone()));
+
+    // CLOSE frees an open slot; Linux close returns as syscall_constraints
+    // has it
+    let closing = current(f.closing);
+    air.add_transition(closing.clone() - closes * descriptor_open.clone());
+    for (j, code) in ebadf.into_iter().enumerate() {
+        air.add_transition(
+            close.clone()
+                * (result(j) - (one() - descriptor_open.clone()) * AirExpr::constant(code as u32)),
+        );
+    }
+
+    // The selected slot changes as the syscall has it, the others not
+    let new_offset = current(f.sum_low) + sum_high * power_of_two(16);
+    air.add_transition(
+        current(f.file_after) - file.clone() + closing.clone() * file
+            - open.clone() * (current(f.file) + one()),
+    );
+    air.add_transition(
+        current(f.size_after) - size.clone() + closing.clone() * size - open * current(f.size),
+    );
+    air.add_transition(
+        current(f.offset_after) - offset.clone() + closing * offset.clone()
+            - read * length
+            - accepted * (new_offset - offset),
+    );
+    for k in 0..FILE_SLOTS {
+        for (slots, after) in [
+            (&f.slot_file, f.file_after),
+            (&f.slot_size, f.size_after),
+            (&f.slot_offset, f.offset_after),
+        ] {
+            air.add_transition(
+                next(slots[k])
+                    - current(slots[k])
+                    - current(f.slot_is[k]) * (current(after) - current(slots[k])),
+            );
+            air.add_boundary_constant(slots[k], BoundaryRow::First, 0);
+        }
+    }
+}
+
+/// KV_GET and KV_SET compare the key in `a0` with the number of keys of
+/// the store. Below it, KV_GET copies the value under the key to `a1` and
+/// KV_SET stores the one at `a1` under it, and both return 0; otherwise
+/// both return EINVAL. The sponge rows check the path of the key's leaf
+/// against the root the run has reached, keyed by `timestamp * 64`, and
+/// put the value on the value bus; KV_SET moves the root to the one the
+/// path of the value it stores leads to, over the same siblings.
+fn kv_constraints(air: &mut Air, c: &TraceLayout) {
+    let (sys, ls, kv) = (&c.syscall, &c.load_store, &c.kv);
+    let [get, set] = [sys.is_kv_get, sys.is_kv_set].map(current);
+    let access = get + set.clone();
+    let less = current(c.comparison.less);
+    let einval = errno::to_result(errno::EINVAL).to_le_bytes();
+    for (j, code) in einval.into_iter().enumerate() {
+        air.add_transition(
+            access.clone() * (current(c.rs1_value[j]) - current(c.registers[10][j])),
+        );
+        air.add_transition(
+            access.clone()
+                * (current(c.result[j]) - (one() - less.clone()) * AirExpr::constant(code as u32)),
+        );
+    }
+    air.add_transition(access.clone() * (join(&c.rs2_value[..4]) - current(kv.keys)));
+    air.add_transition(
+        access.clone()
+            * (current(sys.length) - less.clone() * AirExpr::constant(KV_VALUE_SIZE as u32)),
+    );
+    air.add_transition(
+        access.clone()
+            * (current(kv.timestamp_low) + current(kv.timestamp_high) * power_of_two(16)
+                - current(ls.timestamp)),
+    );
+
+    // The access checks the root, and KV_SET moves it; nothing else does
+    let key = current(ls.timestamp) * AirExpr::constant(64);
+    let entry = |new: u32, root: fn(usize) -> AirExpr| {
+        [
+            key.clone(),
+            AirExpr::constant(new),
+            join(&c.rs1_value[..4]),
+            current(kv.keys),
+        ]
+        .into_iter()
+        .chain(kv.root.iter().map(|&column| root(column)))
+        .collect()
+    };
+    air.add_lookup(
+        LookupTable::KvRoot,
+        entry(0, current),
+        access * less.clone(),
+    );
+    air.add_lookup(
+        LookupTable::KvRoot,
+        entry(1, next),
+        set.clone() * less.clone(),
+    );
+    for &root in &kv.root {
+        air.add_transition((one() - set.clone() * less.clone()) * (next(root) - current(root)));
+    }
+    air.add_transition(next(kv.keys) - current(kv.keys));
+}
+
+/// READ_INPUT, WRITE_JOURNAL and Linux read and fstat put their transfer
+/// on the transfer bus: the timestamp it starts at, the word and byte of
+/// its address, the length, the input, journal or struct stat left, which
+/// of the three it is, and the timestamp after it. The memory log takes it
+/// back on the first of consecutive rows, one per word, each holding from
+/// a byte of its word on the next bytes of the transfer, which are looked
+/// up in the public input or journal, or the stat table, by the bytes left
+/// from them on. Input and stat transfers write those bytes and leave the
+/// rest of the word, journal transfers read them.
+fn transfer_constraints(air: &mut Air, c: &TraceLayout) {
+    let (sys, ls, m, t) = (&c.syscall, &c.load_store, &c.memory, &c.transfer);
+    let f = &c.files;
+    let [read_input, journal, linux_read, fstat, open, read_file, kv_get, kv_set] = [
+        sys.is_read_input,
+        sys.is_write_journal,
+        sys.is_linux_read,
+        sys.is_linux_fstat,
+        sys.is_open,
+        sys.is_read,
+        sys.is_kv_get,
+        sys.is_kv_set,
+    ]
+    .map(current);
+    let read = read_input.clone() + linux_read.clone();
+    let kv = kv_get.clone() + kv_set.clone();
+    let half = |register: usize, h: usize| {
+        current(c.registers[register][2 * h])
+            + current(c.registers[register][2 * h + 1]) * power_of_two(LIMB_BITS)
+    };
+    let address = |h: usize| {
+        (read_input.clone() + journal.clone() + open.clone()) * half(10, h)
+            + (linux_read.clone() + fstat.clone() + read_file.clone() + kv.clone()) * half(11, h)
+    };
+
+    // The transfer starts at a0, or at a1 for Linux read, fstat, READ,
+    // KV_GET and KV_SET
+    let transfer = transfers(c);
+    air.add_transition(
+        transfer.clone()
+            * (address(0)
+                - current(ls.word_low) * AirExpr::constant(8)
+                - current(ls.word_odd) * AirExpr::constant(4)
+                - byte_offset(c)),
+    );
+    air.add_transition(transfer.clone() * (address(1) - current(ls.word_high)));
+    let stat = LinuxSyscalls::std_stat().len() as u32;
+    air.add_lookup(
+        LookupTable::Transfer,
+        vec![
+            current(ls.timestamp),
+            word_address(c),
+            byte_offset(c),
+            current(sys.length),
+            read.clone() * current(sys.input_remaining)
+                + journal.clone() * current(sys.journal_remaining)
+                + fstat.clone() * AirExpr::constant(stat)
+                + open.clone() * (current(f.file) * power_of_two(24) + current(sys.length))
+                + read_file.clone()
+                    * (current(f.file) * power_of_two(24) + current(f.selected_size)
+                        - current(f.selected_offset))
+                + kv * (current(ls.timestamp) * AirExpr::constant(64) + current(sys.length)),
+            read + journal * AirExpr::constant(2)
+                + fstat * AirExpr::constant(3)
+                + open * AirExpr::constant(4)
+                + read_file * AirExpr::constant(5)
+                + kv_get * AirExpr::constant(6)
+                + kv_set * AirExpr::constant(7),
+            next(ls.timestamp),
+        ],
+        transfer,
+    );
+
+    // Transfer rows hold accesses, the writes of the input, struct stat,
+    // files and values KV_GET reads and the reads of the journal, paths and
+    // values KV_SET stores, and start at one byte for as many bytes as fit
+    // the word
+    let (first, rest) = (current(t.first), current(t.rest));
+    let (input, journal, stat) = (current(t.input), current(t.journal), current(t.stat));
+    let (path, file) = (current(t.path), current(t.file));
+    let (kv_get, kv_set) = (current(t.kv_get), current(t.kv_set));
+    let writes = input.clone() + stat.clone() + file.clone() + kv_get.clone();
+    let reads = journal.clone() + path.clone() + kv_set.clone();
+    let chained = first.clone() + rest.clone();
+    let kinds = [
+        t.first, t.rest, t.input, t.journal, t.stat, t.path, t.file, t.kv_get, t.kv_set,
+    ];
+    for &column in kinds.iter().chain(&t.start_is).chain(&t.count_is) {
+        boolean(air, column);
+    }
+    air.add_transition(first.clone() * rest);
+    air.add_transition(chained.clone() * (one() - current(m.used)));
+    air.add_transition(writes.clone() + reads.clone() - chained.clone());
+    air.add_transition(sum(&t.start_is) - chained.clone());
+    air.add_transition(sum(&t.count_is) - chained.clone());
+    air.add_transition(writes.clone() * (one() - current(m.is_write)));
+    air.add_transition(reads * current(m.is_write));
+    for (j, &start) in t.start_is.iter().enumerate() {
+        for &count in &t.count_is[4 - j..] {
+            air.add_transition(current(start) * current(count));
         }
     }
+    let start = select(&t.start_is, |j| AirExpr::constant(j as u32));
+    let count = select(&t.count_is, |n| AirExpr::constant(n as u32 + 1));
+
+    // A row followed by more of the transfer fills its word, and the next
+    // one goes on in the next word from its first byte
+    let more = next(t.rest);
+    air.add_transition(more.clone() * (one() - chained.clone()));
+    for (column, step) in [
+        (m.word, -one()),
+        (t.position, count.clone()),
+        (t.remaining, count.clone()),
+        (t.input, AirExpr::constant(0)),
+        (t.stat, AirExpr::constant(0)),
+        (t.path, AirExpr::constant(0)),
+        (t.file, AirExpr::constant(0)),
+        (t.kv_get, AirExpr::constant(0)),
+        (t.kv_set, AirExpr::constant(0)),
+        (t.end, AirExpr::constant(0)),
+    ] {
+        air.add_transition(more.clone() * (next(column) - current(column) + step));
+    }
+    air.add_transition(more.clone() * (one() - next(t.start_is[0])));
+    air.add_transition(more.clone() * (start.clone() + count.clone() - AirExpr::constant(4)));
+
+    // The last row holds the last bytes, and the transfer ends after it
+    let last = chained * (one() - more);
+    air.add_transition(last.clone() * (current(t.remaining) - count));
+    air.add_transition(last * (current(m.timestamp) + one() - current(t.end)));
+
+    // The bytes of the transfer are those of the input, the journal, the
+    // struct stat, a path, a file or a value
+    for k in 0..4 {
+        let active = (0..=k).fold(AirExpr::constant(0), |acc, j| {
+            acc + current(t.start_is[j]) * sum(&t.count_is[k - j..])
+        });
+        let (before, after) = (current(m.value_before[k]), current(m.value_after[k]));
+        let left = current(t.position) - AirExpr::constant(k as u32) + start.clone();
+        air.add_transition(
+            writes.clone() * (one() - active.clone()) * (after.clone() - before.clone()),
+        );
+        air.add_lookup(
+            LookupTable::Input,
+            vec![left.clone(), after.clone()],
+            input.clone() * active.clone(),
+        );
+        air.add_lookup(
+            LookupTable::Stat,
+            vec![left.clone(), after.clone()],
+            stat.clone() * active.clone(),
+        );
+        air.add_lookup(
+            LookupTable::Journal,
+            vec![left.clone(), before.clone()],
+            journal.clone() * active.clone(),
+        );
+        air.add_lookup(
+            LookupTable::FilePath,
+            vec![left.clone(), before.clone()],
+            path.clone() * active.clone(),
+        );
+        air.add_lookup(
+            LookupTable::FileContent,
+            vec![left.clone(), after.clone()],
+            file.clone() * active.clone(),
+        );
+        air.add_lookup(
+            LookupTable::KvValue,
+            vec![left.clone(), after],
+            kv_get.clone() * active.clone(),
+        );
+        air.add_lookup(
+            LookupTable::KvValue,
+            vec![left, before],
+            kv_set.clone() * active,
+        );
+    }
+    air.add_lookup(
+        LookupTable::Transfer,
+        vec![
+            current(m.timestamp),
+            current(m.word),
+            start,
+            current(t.remaining),
+            current(t.position),
+            input
+                + journal * AirExpr::constant(2)
+                + stat * AirExpr::constant(3)
+                + path * AirExpr::constant(4)
+                + file * AirExpr::constant(5)
+                + kv_get * AirExpr::constant(6)
+                + kv_set * AirExpr::constant(7),
+            current(t.end),
+        ],
+        -first,
+    );
+    air.add_boundary_constant(t.rest, BoundaryRow::First, 0);
+    for column in kinds {
+        air.add_boundary_constant(column, BoundaryRow::Last, 0);
+    }
+}
+
+/// The sponge rows hash the public files OPEN opens and the Merkle paths
+/// of the key-value store that KV_GET and KV_SET check, one round of the
+/// permutation per row, with the round's constants looked up by round. A
+/// file's or leaf's first block starts a state whose capacity holds its
+/// size, and each next one is added to the output of the permutation
+/// before. The first round of a block puts its bytes on the file content
+/// or value bus, keyed by file or access and bytes left, as often as READ
+/// or the access copies them; the first block of a file puts the size on
+/// the file size bus for OPEN, and the last output is looked up in the
+/// public file table.
+///
+/// A leaf's key is in its first bytes, and the path climbs from it a node
+/// per permutation, the output on the left or the right of the sibling as
+/// the path's next bit is 0 or 1, until the node covers all the keys. The
+/// root is put on the root bus. The path KV_SET updates from puts its
+/// siblings on the sibling bus, and the one it updates to takes them back.
+fn hash_constraints(air: &mut Air, c: &TraceLayout) {
+    let h = &c.hash;
+    let [active, begin, last, start, chain, chained] =
+        [h.active, h.begin, h.last, h.start, h.chain, h.chained].map(current);
+    let [leaf, node, climb] = [h.leaf, h.node, h.climb].map(current);
+    for column in [
+        h.active, h.chain, h.leaf, h.node, h.climb, h.bit, h.paired, h.update,
+    ] {
+        boolean(air, column);
+    }
+
+    // Rounds fill the leading rows, each permutation's on consecutive rows
+    // from round 0
+    air.add_lookup(
+        LookupTable::RoundConstants,
+        [current(h.round), begin.clone(), last.clone()]
+            .into_iter()
+            .chain(h.constants.iter().map(|&column| current(column)))
+            .collect(),
+        active.clone(),
+    );
+    air.add_transition((one() - active.clone()) * begin.clone());
+    air.add_transition((one() - active.clone()) * last.clone());
+    air.add_transition((one() - active.clone()) * next(h.active));
+    let more = active.clone() - last.clone();
+    air.add_transition(more.clone() * (one() - next(h.active)));
+    air.add_transition(more.clone() * (next(h.round) - current(h.round) - one()));
+    air.add_transition(last.clone() * next(h.round));
+    air.add_boundary_constant(h.round, BoundaryRow::First, 0);
+    air.add_boundary_constant(h.active, BoundaryRow::Last, 0);
+
+    // A round adds the constants, raises to the 7th power through the
+    // cubes, and mixes
+    let shifted = |j: usize| current(h.state[j]) + current(h.constants[j]);
+    for (j, &cube) in h.cube.iter().enumerate() {
+        let cubed = shifted(j) * shifted(j) * shifted(j);
+        air.add_transition(active.clone() * (current(cube) - cubed));
+    }
+    let mixed = |i: usize| {
+        (0..SPONGE_WIDTH).fold(AirExpr::constant(0), |acc, j| {
+            acc + AirExpr::constant(mix_coefficient(i, j))
+                * current(h.cube[j])
+                * current(h.cube[j])
+                * shifted(j)
+        })
+    };
+    for (i, &state) in h.state.iter().enumerate() {
+        air.add_transition(more.clone() * (next(state) - mixed(i)));
+    }
+    for (i, &output) in h.output.iter().enumerate() {
+        air.add_transition(last.clone() * (current(output) - mixed(i)));
+    }
+
+    // Permutations hash a file, a leaf or a node, and a path's keep its
+    // key, whether KV_SET updates from or to it, and where it is
+    air.add_transition((one() - active.clone()) * leaf.clone());
+    air.add_transition((one() - active.clone()) * node.clone());
+    air.add_transition(leaf.clone() * node.clone());
+    air.add_transition(current(h.paired) * current(h.update));
+    let file_rows = active.clone() - leaf.clone() - node.clone();
+    let kept = [h.leaf, h.node, h.paired, h.update, h.index, h.path, h.width];
+    for column in kept {
+        air.add_transition(more.clone() * (next(column) - current(column)));
+    }
+
+    // A file's or leaf's first block starts the state, and each next one is
+    // added to the output
+    air.add_boundary_constant(h.chained, BoundaryRow::First, 0);
+    air.add_transition(next(h.chained) - chain.clone() - climb.clone());
+    air.add_transition(chain.clone() * (one() - last.clone()));
+    air.add_transition(begin.clone() * (one() - start.clone() - chained));
+    air.add_transition(start.clone() * (one() - begin.clone()));
+    air.add_transition(start.clone() * node.clone());
+    for i in 0..SPONGE_RATE {
+        air.add_transition(start.clone() * (current(h.state[i]) - current(h.block[i])));
+        air.add_transition(
+            chain.clone() * (next(h.state[i]) - current(h.output[i]) - next(h.block[i])),
+        );
+    }
+    air.add_transition(start.clone() * (current(h.state[SPONGE_RATE]) - current(h.left)));
+    for &capacity in &h.state[SPONGE_RATE + 1..] {
+        air.add_transition(start.clone() * current(capacity));
+    }
+    for (j, &capacity) in h.state.iter().enumerate().skip(SPONGE_RATE) {
+        air.add_transition(chain.clone() * (next(capacity) - mixed(j)));
+    }
+    air.add_transition(chain.clone() * node.clone());
+    air.add_transition(chain.clone() * next(h.node));
+    for column in kept.into_iter().filter(|&column| column != h.node) {
+        air.add_transition(chain.clone() * (next(column) - current(column)));
+    }
+    let continued = more.clone() + chain.clone() + climb.clone();
+    air.add_transition(continued * (next(h.key) - current(h.key)));
+    air.add_transition(more * (next(h.left) - current(h.left)));
+    air.add_transition(
+        chain.clone() * (next(h.left) - current(h.left) + AirExpr::constant(SPONGE_RATE as u32)),
+    );
+
+    // A leaf's first block starts with its key, which starts the path, and
+    // it holds one key
+    let leaf_start = leaf.clone() * start.clone();
+    let key = h.block[..4]
+        .iter()
+        .enumerate()
+        .fold(AirExpr::constant(0), |acc, (i, &byte)| {
+            acc + current(byte) * power_of_two(8 * i as u32)
+        });
+    air.add_transition(leaf.clone() * (current(h.path) - current(h.index)));
+    air.add_transition(leaf.clone() * (current(h.width) - one()));
+    let size = AirExpr::constant(4 + KV_VALUE_SIZE as u32);
+    air.add_transition(leaf_start.clone() * (current(h.left) - size));
+    air.add_transition(leaf_start.clone() * (current(h.index) - key));
+
+    // The node above takes the output and the sibling in its block, side
+    // by side as the bit is 0 or 1, and holds twice the keys and the path
+    // less its lowest bit
+    air.add_transition(climb.clone() * (one() - last.clone()));
+    air.add_transition(climb.clone() * chain.clone());
+    air.add_transition(climb.clone() * (one() - leaf.clone() - node.clone()));
+    air.add_transition(climb.clone() * (one() - next(h.node)));
+    for column in [h.paired, h.update, h.index] {
+        air.add_transition(climb.clone() * (next(column) - current(column)));
+    }
+    air.add_transition(
+        climb.clone() * (current(h.path) - next(h.path) * AirExpr::constant(2) - next(h.bit)),
+    );
+    air.add_transition(climb.clone() * (next(h.width) - current(h.width) * AirExpr::constant(2)));
+    for (i, &output) in h.output.iter().enumerate() {
+        let (output, sibling) = (current(output), next(h.block[i]));
+        let swap = next(h.bit) * (sibling.clone() - output.clone());
+        air.add_transition(climb.clone() * (next(h.state[i]) - output.clone() - swap.clone()));
+        air.add_transition(climb.clone() * (next(h.state[DIGEST_SIZE + i]) - sibling + swap));
+    }
+
+    // The bytes, size and hash of the file, keyed by its index, and the
+    // bytes of a leaf's value, keyed by the access
+    let file = current(h.key) * AirExpr::Constant(BabyBear::from_u64(1 << 24).inverse().unwrap());
+    for (i, &reads) in h.reads.iter().enumerate() {
+        let keyed = vec![
+            current(h.key) + current(h.left) - AirExpr::constant(i as u32),
+            current(h.block[i]),
+        ];
+        air.add_transition(current(reads) * (one() - begin.clone()));
+        air.add_transition(current(reads) * node.clone());
+        air.add_transition(current(reads) * current(h.paired));
+        air.add_lookup(
+            LookupTable::FileContent,
+            keyed.clone(),
+            -current(reads) * file_rows.clone(),
+        );
+        air.add_lookup(LookupTable::KvValue, keyed, -current(reads) * leaf.clone());
+    }
+    for &reads in &h.reads[..4] {
+        air.add_transition(leaf_start.clone() * current(reads));
+    }
+    air.add_transition(current(h.opens) * (one() - start));
+    air.add_transition(current(h.opens) * leaf.clone());
+    air.add_lookup(
+        LookupTable::FileSize,
+        vec![file.clone(), current(h.left)],
+        -current(h.opens),
+    );
+    air.add_lookup(
+        LookupTable::File,
+        std::iter::once(file)
+            .chain(h.output.iter().map(|&column| current(column)))
+            .collect(),
+        (last.clone() - chain.clone()) * file_rows,
+    );
+
+    // A path ends with nothing left of it, on the root of the keys it
+    // covers; KV_SET's paths share the siblings
+    let ends = (last - chain - climb) * (leaf + node.clone());
+    air.add_transition(ends.clone() * current(h.path));
+    air.add_lookup(
+        LookupTable::KvRoot,
+        [h.key, h.update, h.index, h.width]
+            .into_iter()
+            .chain(h.output.iter().copied())
+            .map(current)
+            .collect(),
+        -ends,
+    );
+    air.add_lookup(
+        LookupTable::KvSibling,
+        [h.key, h.width]
+            .into_iter()
+            .chain(h.block.iter().copied())
+            .map(current)
+            .collect(),
+        node * begin * (current(h.paired) - current(h.update)),
+    );
+    let pinned = [h.opens, h.last, h.chain, h.start, h.leaf, h.node, h.climb];
+    for &column in h.reads.iter().chain(&pinned) {
+        air.add_boundary_constant(column, BoundaryRow::Last, 0);
+    }
 }
@@ -0,0 +1,217 @@
+//! The quartic extension `BabyBear[X] / (X^4 - 11)`.
+//!
+//! BabyBear alone has about 2^31 elements, so a challenge drawn from it is
+//! guessed with probability 2^-31. Challenges, out-of-domain evaluations and
+//! FRI folding use this extension instead, which has about 2^124.
+
+use super::{BabyBear, Field};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::iter::{Product, Sum};
+use std::ops::{Add, AddAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+/// A field containing `F` as a subfield, with elements written as
+/// `DEGREE` coordinates over it.
+pub trait ExtensionField<F: Field>: Field + From<F> + Mul<F, Output = Self> {
+    const DEGREE: usize;
+
+    /// `values[0] + values[1] * X + ...`; missing coordinates are zero.
+    fn from_base_slice(values: &[F]) -> Self;
+
+    fn as_base_slice(&self) -> &[F];
+}
+
+impl ExtensionField<BabyBear> for BabyBear {
+    const DEGREE: usize = 1;
+
+    fn from_base_slice(values: &[BabyBear]) -> Self {
+        values.first().copied().unwrap_or_default()
+    }
+
+    fn as_base_slice(&self) -> &[BabyBear] {
+        std::slice::from_ref(self)
+    }
+}
+
+/// `X^4 - W` is irreducible over BabyBear.
+const W: BabyBear = BabyBear::new(11);
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BabyBearExt4 {
+    value: [BabyBear; 4],
+}
+
+impl BabyBearExt4 {
+    pub const fn new(value: [BabyBear; 4]) -> Self {
+        Self { value }
+    }
+
+    /// `sum(coordinates[i] * X^i)`, for polynomials over the extension that
+    /// are committed coordinate by coordinate and evaluated at an extension
+    /// point.
+    pub fn from_coordinates(coordinates: &[Self]) -> Self {
+        coordinates
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| {
+                let mut basis = [BabyBear::ZERO; 4];
+                basis[i] = BabyBear::ONE;
+                Self::new(basis) * value
+            })
+            .sum()
+    }
+}
+
+impl Field for BabyBearExt4 {
+    const ZERO: Self = Self::new([BabyBear::ZERO; 4]);
+    const ONE: Self = Self::new([
+        BabyBear::ONE,
+        BabyBear::ZERO,
+        BabyBear::ZERO,
+        BabyBear::ZERO,
+    ]);
+
+    /// Writes `a = e(X^2) + X * o(X^2)`. Then `a * (e - X * o) = e^2 - X^2 o^2`
+    /// lies in the quadratic subfield `BabyBear[Y] / (Y^2 - W)`, where it
+    /// is inverted with its norm.
+    fn inverse(&self) -> Option<Self> {
+        if self.is_zero() {
+            return None;
+        }
+        let [a0, a1, a2, a3] = self.value;
+        // c = e^2 - Y * o^2 with e = a0 + a2 Y, o = a1 + a3 Y
+        let c0 = a0.square() + W * a2.square() - (a1 * a3).double() * W;
+        let c1 = (a0 * a2).double() - a1.square() - W * a3.square();
+        let norm_inv = (c0.square() - W * c1.square()).inverse()?;
+        let (d0, d1) = (c0 * norm_inv, -c1 * norm_inv);
+
+        // (e - X o) * (d0 + d1 Y)
+        let conjugate = Self::new([a0, -a1, a2, -a3]);
+        Some(conjugate * Self::new([d0, BabyBear::ZERO, d1, BabyBear::ZERO]))
+    }
+
+    fn from_u64(value: u64) -> Self {
+        BabyBear::from_u64(value).into()
+    }
+}
+
+impl ExtensionField<BabyBear> for BabyBearExt4 {
+    const DEGREE: usize = 4;
+
+    fn from_base_slice(values: &[BabyBear]) -> Self {
+        let mut value = [BabyBear::ZERO; 4];
+        for (coordinate, &v) in value.iter_mut().zip(values) {
+            *coordinate = v;
+        }
+        Self::new(value)
+    }
+
+    fn as_base_slice(&self) -> &[BabyBear] {
+        &self.value
+    }
+}
+
+impl From<BabyBear> for BabyBearExt4 {
+    fn from(value: BabyBear) -> Self {
+        Self::new([value, BabyBear::ZERO, BabyBear::ZERO, BabyBear::ZERO])
+    }
+}
+
+impl fmt::Debug for BabyBearExt4 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.value, f)
+    }
+}
+
+impl fmt::Display for BabyBearExt4 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a0, a1, a2, a3] = self.value;
+        write!(f, "{a0} + {a1}X + {a2}X^2 + {a3}X^3")
+    }
+}
+
+impl Add for BabyBearExt4 {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        let mut value = self.value;
+        for (a, b) in value.iter_mut().zip(rhs.value) {
+            *a += b;
+        }
+        Self::new(value)
+    }
+}
+
+impl Sub for BabyBearExt4 {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        let mut value = self.value;
+        for (a, b) in value.iter_mut().zip(rhs.value) {
+            *a -= b;
+        }
+        Self::new(value)
+    }
+}
+
+impl Mul for BabyBearExt4 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        let [a0, a1, a2, a3] = self.value;
+        let [b0, b1, b2, b3] = rhs.value;
+        // Schoolbook product with X^4 = W
+        Self::new([
+            a0 * b0 + W * (a1 * b3 + a2 * b2 + a3 * b1),
+            a0 * b1 + a1 * b0 + W * (a2 * b3 + a3 * b2),
+            a0 * b2 + a1 * b1 + a2 * b0 + W * (a3 * b3),
+            a0 * b3 + a1 * b2 + a2 * b1 + a3 * b0,
+        ])
+    }
+}
+
+impl Mul<BabyBear> for BabyBearExt4 {
+    type Output = Self;
+
+    fn mul(self, rhs: BabyBear) -> Self {
+        Self::new(self.value.map(|v| v * rhs))
+    }
+}
+
+impl Neg for BabyBearExt4 {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(self.value.map(|v| -v))
+    }
+}
+
+impl AddAssign for BabyBearExt4 {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for BabyBearExt4 {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl MulAssign for BabyBearExt4 {
+    fn mul_assign(&mut self, rhs: Self) {
+        *self = *self * rhs;
+    }
+}
+
+impl Sum for BabyBearExt4 {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::ZERO, |acc, x| acc + x)
+    }
+}
+
+impl Product for BabyBearExt4 {
+    fn product<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::ONE, |acc, x| acc * x)
+    }
+}
@@ -0,0 +1,317 @@
+//! Finite field arithmetic for the proof system.
+//!
+//! [`BabyBear`] is the prime field of order `p = 15 * 2^27 + 1`. Elements are
+//! kept in Montgomery form, so a multiplication is one 64-bit product and a
+//! reduction without division. Its multiplicative group has a subgroup of
+//! order `2^27`, which gives the power-of-two evaluation domains NTTs and FRI
+//! need.
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::iter::{Product, Sum};
+use std::ops::{Add, AddAssign, Div, Mul, MulAssign, Neg, Sub, SubAssign};
+
+/// Arithmetic shared by the base field and its extensions.
+pub trait Field:
+    Copy
+    + Default
+    + Eq
+    + fmt::Debug
+    + fmt::Display
+    + Send
+    + Sync
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+    + SubAssign
+    + MulAssign
+    + Sum
+    + Product
+    + 'static
+{
+    const ZERO: Self;
+    const ONE: Self;
+
+    /// The multiplicative inverse, or `None` for zero.
+    fn inverse(&self) -> Option<Self>;
+
+    /// The integer `value`, reduced into the field.
+    fn from_u64(value: u64) -> Self;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::ZERO
+    }
+
+    fn square(&self) -> Self {
+        *self * *self
+    }
+
+    fn double(&self) -> Self {
+        *self + *self
+    }
+
+    fn pow(&self, mut exponent: u64) -> Self {
+        let mut base = *self;
+        let mut result = Self::ONE;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result *= base;
+            }
+            base = base.square();
+            exponent >>= 1;
+        }
+        result
+    }
+
+    /// `1, self, self^2, ...`
+    fn powers(&self) -> impl Iterator<Item = Self> {
+        let step = *self;
+        std::iter::successors(Some(Self::ONE), move |power| Some(*power * step))
+    }
+}
+
+/// A field with multiplicative subgroups of every order `2^k` up to
+/// `2^TWO_ADICITY`.
+pub trait TwoAdicField: Field {
+    const TWO_ADICITY: u32;
+
+    /// A generator of the whole multiplicative group, used to shift
+    /// evaluation domains off the subgroups.
+    fn generator() -> Self;
+
+    /// A primitive `2^bits`-th root of unity.
+    fn two_adic_generator(bits: u32) -> Self;
+}
+
+/// Inverts every element of `values` with a single field inversion. Zeros
+/// are left as zero.
+pub fn batch_inverse<F: Field>(values: &[F]) -> Vec<F> {
+    let mut prefix = Vec::with_capacity(values.len());
+    let mut product = F::ONE;
+    for value in values {
+        prefix.push(product);
+        if !value.is_zero() {
+            product *= *value;
+        }
+    }
+
+    let mut inverse = product.inverse().unwrap_or(F::ZERO);
+    let mut result = vec![F::ZERO; values.len()];
+    for (i, value) in values.iter().enumerate().rev() {
+        if !value.is_zero() {
+            result[i] = prefix[i] * inverse;
+            inverse *= *value;
+        }
+    }
+    result
+}
+
+/// The BabyBear modulus `15 * 2^27 + 1`.
+pub const BABY_BEAR_MODULUS: u32 = 0x7800_0001;
+
+/// `p^-1 mod 2^32` for the Montgomery reduction.
+const MONTY_MU: u32 = 0x8800_0001;
+/// `2^64 mod p`, to move values into Montgomery form.
+const MONTY_R2: u64 = ((1u128 << 64) % BABY_BEAR_MODULUS as u128) as u64;
+
+/// An element of the BabyBear field.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct BabyBear {
+    /// `value * 2^32 mod p`
+    monty: u32,
+}
+
+/// Reduces `x < p * 2^32` to `x * 2^-32 mod p`.
+#[inline]
+const fn monty_reduce(x: u64) -> u32 {
+    let t = (x as u32).wrapping_mul(MONTY_MU) as u64;
+    let (difference, borrow) = x.overflowing_sub(t * BABY_BEAR_MODULUS as u64);
+    let result = (difference >> 32) as u32;
+    if borrow {
+        result.wrapping_add(BABY_BEAR_MODULUS)
+    } else {
+        result
+    }
+}
+
+impl BabyBear {
+    /// `value` reduced modulo p.
+    pub const fn new(value: u32) -> Self {
+        Self::from_canonical_u64((value % BABY_BEAR_MODULUS) as u64)
+    }
+
+    const fn from_canonical_u64(value: u64) -> Self {
+        Self {
+            monty: monty_reduce(value * MONTY_R2),
+        }
+    }
+
+    /// The canonical representative in `0..p`.
+    pub fn as_u32(&self) -> u32 {
+        monty_reduce(self.monty as u64)
+    }
+}
+
+impl Field for BabyBear {
+    const ZERO: Self = Self::new(0);
+    const ONE: Self = Self::new(1);
+
+    fn inverse(&self) -> Option<Self> {
+        if self.is_zero() {
+            return None;
+        }
+        Some(self.pow(BABY_BEAR_MODULUS as u64 - 2))
+    }
+
+    fn from_u64(value: u64) -> Self {
+        Self::from_canonical_u64(value % BABY_BEAR_MODULUS as u64)
+    }
+}
+
+impl TwoAdicField for BabyBear {
+    const TWO_ADICITY: u32 = 27;
+
+    fn generator() -> Self {
+        Self::new(31)
+    }
+
+    fn two_adic_generator(bits: u32) -> Self {
+        assert!(bits <= Self::TWO_ADICITY, "no subgroup of order 2^{bits}");
+        Self::generator().pow(((BABY_BEAR_MODULUS - 1) >> bits) as u64)
+    }
+}
+
+impl From<u32> for BabyBear {
+    fn from(value: u32) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<BabyBear> for u32 {
+    fn from(value: BabyBear) -> Self {
+        value.as_u32()
+    }
+}
+
+/// Serialized as the canonical `u32`; anything else is rejected.
+impl Serialize for BabyBear {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.as_u32())
+    }
+}
+
+impl<'de> Deserialize<'de> for BabyBear {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = u32::deserialize(deserializer)?;
+        if value >= BABY_BEAR_MODULUS {
+            return Err(de::Error::custom("Field element out of range"));
+        }
+        Ok(Self::new(value))
+    }
+}
+
+impl fmt::Debug for BabyBear {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.as_u32(), f)
+    }
+}
+
+impl fmt::Display for BabyBear {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.as_u32(), f)
+    }
+}
+
+impl Add for BabyBear {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, rhs: Self) -> Self {
+        // Both are below p < 2^31, so the sum cannot overflow
+        let sum = self.monty + rhs.monty;
+        Self {
+            monty: if sum >= BABY_BEAR_MODULUS {
+                sum - BABY_BEAR_MODULUS
+            } else {
+                sum
+            },
+        }
+    }
+}
+
+impl Sub for BabyBear {
+    type Output = Self;
+
+    #[inline]
+    fn sub(self, rhs: Self) -> Self {
+        let (difference, borrow) = self.monty.overflowing_sub(rhs.monty);
+        Self {
+            monty: if borrow {
+                difference.wrapping_add(BABY_BEAR_MODULUS)
+            } else {
+                difference
+            },
+        }
+    }
+}
+
+impl Mul for BabyBear {
+    type Output = Self;
+
+    #[inline]
+    fn mul(self, rhs: Self) -> Self {
+        Self {
+            monty: monty_reduce(self.monty as u64 * rhs.monty as u64),
+        }
+    }
+}
+
+impl Neg for BabyBear {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::ZERO - self
+    }
+}
+
+impl Div for BabyBear {
+    type Output = Self;
+
+    /// Panics on division by zero.
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn div(self, rhs: Self) -> Self {
+        self * rhs.inverse().expect("division by zero")
+    }
+}
+
+impl AddAssign for BabyBear {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for BabyBear {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl MulAssign for BabyBear {
+    fn mul_assign(&mut self, rhs: Self) {
+        *self = *self * rhs;
+    }
+}
+
+impl Sum for BabyBear {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::ZERO, |acc, x| acc + x)
+    }
+}
+
+impl Product for BabyBear {
+    fn product<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::ONE, |acc, x| acc * x)
+    }
+}
@@ -0,0 +1,272 @@
+//! FRI low-degree test for values in the BabyBear extension.
+//!
+//! Each round pairs the values at `x` and `-x` of an evaluation domain
+//! `s * <ω_n>` and folds them with a random `β` into the values of
+//!
+//! ```text
+//! f'(x^2) = (f(x) + f(-x)) / 2 + β * (f(x) - f(-x)) / (2x)
+//! ```
+//!
+//! on `s^2 * <ω_{n/2}>`, halving the degree. Domains are in BabyBear, while
+//! values and `β` are in [`BabyBearExt4`]. Folded layers are committed
+//! pairwise in Merkle trees, the last layer is sent in full, and queries
+//! check that every fold along a random path was computed honestly.
+
+use super::{coset_intt, BabyBear, BabyBearExt4, ExtensionField, Field, Transcript, TwoAdicField};
+use crate::crypto::{hash_bytes, HashValue, MerkleProof, MerkleTree};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FriProof {
+    /// Roots of the folded layers, except the last.
+    pub commitments: Vec<HashValue>,
+    pub final_layer: Vec<BabyBearExt4>,
+    pub query_proofs: Vec<FriQueryProof>,
+}
+
+/// The pairs a query opens in each committed layer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FriQueryProof {
+    pub layers: Vec<PairOpening>,
+}
+
+/// `f(x)` and `f(-x)` with their Merkle path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairOpening {
+    pub values: [BabyBearExt4; 2],
+    pub merkle_path: Vec<HashValue>,
+}
+
+/// The bytes committed for a row of field elements.
+pub fn field_leaf<F: ExtensionField<BabyBear>>(values: &[F]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| value.as_base_slice())
+        .flat_map(|value| value.as_u32().to_le_bytes())
+        .collect()
+}
+
+/// Whether `path` opens `values` at `index` of the tree with root
+/// `commitment`.
+pub fn verify_opening<F: ExtensionField<BabyBear>>(
+    commitment: &HashValue,
+    index: usize,
+    values: &[F],
+    path: &[HashValue],
+) -> bool {
+    let proof = MerkleProof {
+        leaf: hash_bytes(&field_leaf(values)),
+        proof: path.to_vec(),
+        index,
+    };
+    proof.root() == *commitment
+}
+
+/// Merkle paths of a tree from [`commit_pairs`] or a row commitment.
+pub fn merkle_path(tree: &MerkleTree, index: usize) -> Vec<HashValue> {
+    tree.generate_proof(index)
+        .map(|proof| proof.proof)
+        .unwrap_or_default()
+}
+
+fn commit_pairs(layer: &[BabyBearExt4]) -> MerkleTree {
+    let half = layer.len() / 2;
+    let leaves: Vec<Vec<u8>> = (0..half)
+        .map(|i| field_leaf(&[layer[i], layer[i + half]]))
+        .collect();
+    MerkleTree::new(leaves.iter().map(Vec::as_slice).collect())
+}
+
+/// `(a + b) / 2 + β (a - b) / (2x)` for the pair `a = f(x)`, `b = f(-x)`.
+fn fold_pair(
+    a: BabyBearExt4,
+    b: BabyBearExt4,
+    beta: BabyBearExt4,
+    x_inv: BabyBear,
+) -> BabyBearExt4 {
+    let half = BabyBear::from(2).inverse().unwrap();
+    ((a + b) + beta * (a - b) * x_inv) * half
+}
+
+fn fold(layer: &[BabyBearExt4], beta: BabyBearExt4, shift: BabyBear) -> Vec<BabyBearExt4> {
+    let half = layer.len() / 2;
+    let root_inv = BabyBear::two_adic_generator(layer.len().trailing_zeros())
+        .inverse()
+        .unwrap();
+    let shift_inv = shift.inverse().unwrap();
+    (0..half)
+        .zip(root_inv.powers())
+        .map(|(i, power)| fold_pair(layer[i], layer[i + half], beta, shift_inv * power))
+        .collect()
+}
+
+/// Soundness parameters shared by prover and verifier.
+#[derive(Debug, Clone, Copy)]
+pub struct FriConfig {
+    /// Ratio of the evaluation domain to the degree bound.
+    pub blowup: usize,
+    pub num_queries: usize,
+}
+
+impl FriConfig {
+    /// Layers are folded down to this many values.
+    fn final_layer_len(&self) -> usize {
+        2 * self.blowup
+    }
+
+    /// Proves that `evals` on `shift * <ω_n>` have degree below
+    /// `n / blowup`. Returns the proof and the queried pair indices of the
+    /// first layer, which the caller opens itself.
+    pub fn prove(
+        &self,
+        evals: Vec<BabyBearExt4>,
+        mut shift: BabyBear,
+        transcript: &mut Transcript,
+    ) -> (FriProof, Vec<usize>) {
+        let first_half = evals.len() / 2;
+        let mut layer = fold(&evals, transcript.challenge(), shift);
+        shift = shift.square();
+
+        let mut trees = Vec::new();
+        while layer.len() > self.final_layer_len() {
+            let tree = commit_pairs(&layer);
+            transcript.absorb_bytes(tree.root());
+            let next = fold(&layer, transcript.challenge(), shift);
+            shift = shift.square();
+            trees.push((tree, layer));
+            layer = next;
+        }
+        transcript.absorb_field(&layer);
+
+        let indices: Vec<usize> = (0..self.num_queries)
+            .map(|_| transcript.challenge_index(first_half))
+            .collect();
+        let query_proofs = indices
+            .iter()
+            .map(|&index| FriQueryProof {
+                layers: trees
+                    .iter()
+                    .map(|(tree, values)| {
+                        let half = values.len() / 2;
+                        let pair = index % half;
+                        PairOpening {
+                            values: [values[pair], values[pair + half]],
+                            merkle_path: merkle_path(tree, pair),
+                        }
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        let proof = FriProof {
+            commitments: trees.iter().map(|(tree, _)| *tree.root()).collect(),
+            final_layer: layer,
+            query_proofs,
+        };
+        (proof, indices)
+    }
+
+    /// Checks `proof` for a first layer of `2^log_size` values on
+    /// `shift * <ω>`. `open(query, index)` must return the authenticated
+    /// first-layer pair at `index` for the `query`-th query.
+    pub fn verify(
+        &self,
+        proof: &FriProof,
+        log_size: u32,
+        mut shift: BabyBear,
+        transcript: &mut Transcript,
+        mut open: impl FnMut(usize, usize) -> Result<[BabyBearExt4; 2], &'static str>,
+    ) -> Result<(), &'static str> {
+        if proof.query_proofs.len() != self.num_queries {
+            return Err("Wrong number of FRI queries");
+        }
+        if log_size > BabyBear::TWO_ADICITY {
+            return Err("FRI domain too large");
+        }
+        let size = 1usize << log_size;
+        if size < 2 * self.blowup {
+            return Err("FRI domain too small");
+        }
+        let num_layers = proof.commitments.len();
+        if num_layers >= log_size as usize {
+            return Err("Wrong number of FRI layers");
+        }
+        let final_len = size >> (num_layers + 1);
+        if final_len > self.final_layer_len()
+            || (num_layers > 0 && final_len * 2 <= self.final_layer_len())
+            || proof.final_layer.len() != final_len
+        {
+            return Err("Wrong number of FRI layers");
+        }
+
+        // Replay the transcript for the folding challenges and queries
+        let mut betas = vec![transcript.challenge()];
+        for commitment in &proof.commitments {
+            transcript.absorb_bytes(commitment);
+            betas.push(transcript.challenge());
+        }
+        transcript.absorb_field(&proof.final_layer);
+
+        let mut shifts = Vec::with_capacity(num_layers + 1);
+        for _ in 0..=num_layers {
+            shifts.push(shift);
+            shift = shift.square();
+        }
+
+        for (query, query_proof) in proof.query_proofs.iter().enumerate() {
+            if query_proof.layers.len() != num_layers {
+                return Err("Wrong number of FRI layers");
+            }
+            let index = transcript.challenge_index(size / 2);
+            let mut pair = open(query, index)?;
+            let mut layer_size = size;
+
+            for (layer, &beta) in betas.iter().enumerate() {
+                let half = layer_size / 2;
+                let pair_index = index % half;
+                let x = shifts[layer]
+                    * BabyBear::two_adic_generator(layer_size.trailing_zeros())
+                        .pow(pair_index as u64);
+                let folded = fold_pair(pair[0], pair[1], beta, x.inverse().unwrap());
+
+                // The folded value sits at `pair_index` of the next layer
+                let next_half = half / 2;
+                let expected = if let Some(opening) = query_proof.layers.get(layer) {
+                    let next_pair = pair_index % next_half;
+                    if !verify_opening(
+                        &proof.commitments[layer],
+                        next_pair,
+                        &opening.values,
+                        &opening.merkle_path,
+                    ) {
+                        return Err("Invalid FRI layer opening");
+                    }
+                    pair = opening.values;
+                    opening.values[(pair_index >= next_half) as usize]
+                } else {
+                    proof.final_layer[pair_index]
+                };
+                if folded != expected {
+                    return Err("FRI folding mismatch");
+                }
+                layer_size = half;
+            }
+        }
+
+        // The last layer must be a polynomial of degree below its bound,
+        // which holds for each of its coordinates over BabyBear
+        let degree_bound = final_len / self.blowup;
+        for coordinate in 0..BabyBearExt4::DEGREE {
+            let mut coeffs: Vec<BabyBear> = proof
+                .final_layer
+                .iter()
+                .map(|value| value.as_base_slice()[coordinate])
+                .collect();
+            coset_intt(&mut coeffs, shifts[num_layers]);
+            if coeffs[degree_bound..].iter().any(|coeff| !coeff.is_zero()) {
+                return Err("FRI final layer degree too high");
+            }
+        }
+        Ok(())
+    }
+}
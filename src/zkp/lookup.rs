@@ -0,0 +1,360 @@
+//! LogUp lookups into fixed tables.
+//!
+//! A [`Lookup`] claims that some values of every row are an entry of a
+//! table. With challenges `gamma` and `delta`, values are fingerprinted as
+//! `table + delta * v_0 + delta^2 * v_1 + ...`, and the claims hold when
+//!
+//! ```text
+//! sum over rows and lookups of m / (gamma - fingerprint(values))
+//!     = sum over entries of count / (gamma - fingerprint(entry))
+//! ```
+//!
+//! where `m` is the lookup's multiplicity on the row and `count` how often
+//! the entry is looked up. The tables are fixed and known to the verifier,
+//! so instead of committing table columns the prover sends the count of
+//! every entry it uses, and the verifier sums the right-hand side itself.
+//! The left-hand side is an auxiliary trace committed once the challenges
+//! are drawn: a column `m / (gamma - fingerprint(values))` per lookup, and
+//! their running sum.
+//!
+//! Two more kinds of table have no fixed entries. A bus has an empty table
+//! side, so whatever one lookup puts on it another must take back with a
+//! negative multiplicity: the memory log in execution order and sorted by
+//! address are a permutation of each other this way, and loads and stores
+//! hand their accesses to the log. The entries of the
+//! initial and final memory tables are public, a [`MemoryBoundary`] per
+//! word accessed, and the verifier sums them like fixed entries. So are
+//! those of the program table, the instructions of the program image that
+//! rows fetch, which the verifier decodes from the initial memory at the
+//! pcs the prover counts fetches from, and those of the input, journal,
+//! syscall and public file tables, which it builds from the public values
+//! at the keys the prover counts.
+
+use super::{
+    round_constants, AirExpr, BabyBear, BabyBearExt4, ExtensionField, Field, SPONGE_ROUNDS,
+};
+use crate::vm::LinuxSyscalls;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A fixed table the AIR can look values up in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum LookupTable {
+    /// `0..2^8`
+    Range8,
+    /// `0..2^16`
+    Range16,
+    /// `(a, b, a & b)` for bytes `a` and `b`
+    And8,
+    /// `(a, b, a | b)` for bytes `a` and `b`
+    Or8,
+    /// `(a, b, a ^ b)` for bytes `a` and `b`
+    Xor8,
+    /// `(word, timestamp, value before, value after)` of memory accesses,
+    /// with values as byte limbs; a bus
+    Memory,
+    /// The same for the accesses of loads and stores, which their rows put
+    /// on it and the memory log takes back; a bus
+    Access,
+    /// `(word, value)` of every word accessed, before execution
+    InitialMemory,
+    /// `(word, value)` of every word accessed, after execution
+    FinalMemory,
+    /// `(pc, opcode, rd, rs1, rs2, imm, size, gas price)` of the
+    /// instructions in the initial memory, decoded
+    Program,
+    /// `(bytes left, byte)` of every byte of the input
+    Input,
+    /// `(bytes left, byte)` of every byte of the journal
+    Journal,
+    /// `(syscall, gas price)` of the syscalls the AIR constrains
+    Syscall,
+    /// `(timestamp, word, first byte, length, bytes left, kind, end)` of
+    /// the transfers of READ_INPUT, WRITE_JOURNAL, OPEN, READ, KV_GET,
+    /// KV_SET and Linux `read` and `fstat`, which their rows put on it and
+    /// the memory log takes back; a bus
+    Transfer,
+    /// `(bytes left, byte)` of every byte of the `struct stat` Linux
+    /// `fstat` writes for the standard streams
+    Stat,
+    /// `(round, first, last, constants)` of every round of the sponge
+    /// permutation
+    RoundConstants,
+    /// `(file * 2^24 + bytes left, byte)` of every byte of the path of a
+    /// public file, and `(file * 2^24, length)` of the path
+    FilePath,
+    /// `(file, digest)` of every public file
+    File,
+    /// `(file, size)` of the public files OPEN opens, which the rows
+    /// hashing them put on it; a bus
+    FileSize,
+    /// `(file * 2^24 + bytes left, byte)` of the bytes READ copies, which
+    /// the rows hashing their file put on it; a bus
+    FileContent,
+    /// `(timestamp * 64 + bytes left, byte)` of the values KV_GET and
+    /// KV_SET copy, which the rows hashing their leaf put on it; a bus
+    KvValue,
+    /// `(timestamp * 64, new, key, keys, root)` of the roots KV_GET and
+    /// KV_SET check and KV_SET moves to, which the rows hashing their path
+    /// put on it; a bus
+    KvRoot,
+    /// `(timestamp * 64, width, sibling)` of the siblings on the path
+    /// KV_SET updates, which the rows checking its old path put on it and
+    /// those hashing the new one take back; a bus
+    KvSibling,
+}
+
+/// The tables whose entries come from the public values.
+pub const PUBLIC_TABLES: [LookupTable; 6] = [
+    LookupTable::Program,
+    LookupTable::Input,
+    LookupTable::Journal,
+    LookupTable::Syscall,
+    LookupTable::FilePath,
+    LookupTable::File,
+];
+
+impl LookupTable {
+    pub fn is_fixed(self) -> bool {
+        self.size() > 0
+    }
+
+    pub fn is_bus(self) -> bool {
+        matches!(
+            self,
+            Self::Memory
+                | Self::Access
+                | Self::Transfer
+                | Self::FileSize
+                | Self::FileContent
+                | Self::KvValue
+                | Self::KvRoot
+                | Self::KvSibling
+        )
+    }
+
+    /// Whether the entries come from the public values, keyed by their
+    /// first value.
+    pub fn is_public(self) -> bool {
+        PUBLIC_TABLES.contains(&self)
+    }
+
+    /// Number of fixed entries.
+    pub fn size(self) -> usize {
+        match self {
+            Self::Range8 => 1 << 8,
+            Self::Range16 | Self::And8 | Self::Or8 | Self::Xor8 => 1 << 16,
+            Self::Stat => LinuxSyscalls::std_stat().len(),
+            Self::RoundConstants => SPONGE_ROUNDS,
+            Self::Memory
+            | Self::Access
+            | Self::InitialMemory
+            | Self::FinalMemory
+            | Self::Program
+            | Self::Input
+            | Self::Journal
+            | Self::Syscall
+            | Self::Transfer
+            | Self::FilePath
+            | Self::File
+            | Self::FileSize
+            | Self::FileContent
+            | Self::KvValue
+            | Self::KvRoot
+            | Self::KvSibling => 0,
+        }
+    }
+
+    /// Values in an entry.
+    pub fn arity(self) -> usize {
+        match self {
+            Self::Range8 | Self::Range16 => 1,
+            Self::And8 | Self::Or8 | Self::Xor8 => 3,
+            Self::Memory | Self::Access => 10,
+            Self::InitialMemory | Self::FinalMemory => 5,
+            Self::Program => 8,
+            Self::Input
+            | Self::Journal
+            | Self::Syscall
+            | Self::Stat
+            | Self::FilePath
+            | Self::FileSize
+            | Self::FileContent
+            | Self::KvValue => 2,
+            Self::Transfer => 7,
+            Self::File => 9,
+            Self::KvSibling => 10,
+            Self::KvRoot => 12,
+            Self::RoundConstants => 19,
+        }
+    }
+
+    /// Fixed entry number `index`.
+    pub fn entry(self, index: usize) -> Vec<u32> {
+        let (a, b) = (index as u32 & 0xff, index as u32 >> 8);
+        match self {
+            Self::Range8 | Self::Range16 => vec![index as u32],
+            Self::And8 => vec![a, b, a & b],
+            Self::Or8 => vec![a, b, a | b],
+            Self::Xor8 => vec![a, b, a ^ b],
+            Self::Stat => {
+                let stat = LinuxSyscalls::std_stat();
+                vec![index as u32 + 1, stat[stat.len() - 1 - index].into()]
+            }
+            Self::RoundConstants => {
+                let flags = [
+                    index as u32,
+                    (index == 0).into(),
+                    (index == SPONGE_ROUNDS - 1).into(),
+                ];
+                let constants = round_constants()[index].iter().map(BabyBear::as_u32);
+                flags.into_iter().chain(constants).collect()
+            }
+            Self::Memory
+            | Self::Access
+            | Self::InitialMemory
+            | Self::FinalMemory
+            | Self::Program
+            | Self::Input
+            | Self::Journal
+            | Self::Syscall
+            | Self::Transfer
+            | Self::FilePath
+            | Self::File
+            | Self::FileSize
+            | Self::FileContent
+            | Self::KvValue
+            | Self::KvRoot
+            | Self::KvSibling => Vec::new(),
+        }
+    }
+
+    /// The index of the entry holding `values`, if there is one.
+    pub fn index(self, values: &[BabyBear]) -> Option<usize> {
+        let values: Vec<u32> = values.iter().map(BabyBear::as_u32).collect();
+        let index = match values[..] {
+            [round, ..] if self == Self::RoundConstants => round as usize,
+            [value] => value as usize,
+            [left, _] => (left as usize).wrapping_sub(1),
+            [a, b, _] => a as usize + ((b as usize) << 8),
+            _ => return None,
+        };
+        (values.len() == self.arity() && index < self.size() && self.entry(index) == values)
+            .then_some(index)
+    }
+}
+
+/// `values` of every row are an entry of `table`, looked up `multiplicity`
+/// times.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lookup {
+    pub table: LookupTable,
+    pub values: Vec<AirExpr>,
+    pub multiplicity: AirExpr,
+}
+
+impl Lookup {
+    /// Degree of `(gamma - fingerprint(values)) * helper - multiplicity`.
+    pub fn degree(&self) -> usize {
+        let values = self.values.iter().map(AirExpr::degree).max().unwrap_or(0);
+        (values + 1).max(self.multiplicity.degree())
+    }
+}
+
+/// How often an entry of a table is looked up. Entries of the public
+/// tables are numbered by their first value: the pc, the bytes left, the
+/// syscall or the file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Multiplicity {
+    pub table: LookupTable,
+    pub entry: u32,
+    pub count: BabyBear,
+}
+
+/// A word of memory the trace accesses, with its value before the first
+/// access and after the last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryBoundary {
+    pub addr: u32,
+    pub initial_value: u32,
+    pub final_value: u32,
+}
+
+impl MemoryBoundary {
+    /// The entries of the initial and final memory tables for the word.
+    pub fn entries(&self) -> [(LookupTable, Vec<BabyBear>); 2] {
+        let entry = |value: u32| {
+            let limbs = value.to_le_bytes().map(u32::from);
+            [self.addr / 4]
+                .into_iter()
+                .chain(limbs)
+                .map(BabyBear::from)
+                .collect()
+        };
+        [
+            (LookupTable::InitialMemory, entry(self.initial_value)),
+            (LookupTable::FinalMemory, entry(self.final_value)),
+        ]
+    }
+}
+
+/// Challenges of the lookup argument, drawn once the trace and the
+/// multiplicities are committed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LookupChallenges {
+    pub gamma: BabyBearExt4,
+    pub delta: BabyBearExt4,
+}
+
+impl LookupChallenges {
+    /// `table + delta * values[0] + delta^2 * values[1] + ...`
+    pub fn fingerprint<F>(&self, table: LookupTable, values: &[F]) -> BabyBearExt4
+    where
+        F: ExtensionField<BabyBear>,
+        BabyBearExt4: From<F>,
+    {
+        let tag = <BabyBearExt4 as From<BabyBear>>::from((table as u32).into());
+        values
+            .iter()
+            .zip(self.delta.powers().skip(1))
+            .fold(tag, |acc, (&value, power)| {
+                acc + BabyBearExt4::from(value) * power
+            })
+    }
+
+    /// `sum(count / (gamma - fingerprint(entry)))`, with the entries of the
+    /// public tables from `public` by table and key, or `None` if an entry
+    /// is outside its table or a pole.
+    pub fn table_sum(
+        &self,
+        multiplicities: &[Multiplicity],
+        public: &BTreeMap<(LookupTable, u32), Vec<BabyBear>>,
+    ) -> Option<BabyBearExt4> {
+        multiplicities
+            .iter()
+            .map(|multiplicity| {
+                let table = multiplicity.table;
+                let index = multiplicity.entry as usize;
+                let entry: Vec<BabyBear> = match table {
+                    _ if table.is_public() => public.get(&(table, multiplicity.entry))?.clone(),
+                    _ if index < table.size() => {
+                        table.entry(index).into_iter().map(BabyBear::from).collect()
+                    }
+                    _ => return None,
+                };
+                let inverse = (self.gamma - self.fingerprint(table, &entry)).inverse()?;
+                Some(inverse * multiplicity.count)
+            })
+            .sum()
+    }
+
+    /// `sum(1 / (gamma - fingerprint(entry)))` over the initial and final
+    /// memory entries of `boundary`, or `None` on a pole.
+    pub fn memory_sum(&self, boundary: &[MemoryBoundary]) -> Option<BabyBearExt4> {
+        boundary
+            .iter()
+            .flat_map(MemoryBoundary::entries)
+            .map(|(table, entry)| (self.gamma - self.fingerprint(table, &entry)).inverse())
+            .sum()
+    }
+}
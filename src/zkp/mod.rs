@@ -1,9 +1,27 @@
+pub mod air;
 pub mod constraints;
+pub mod extension;
+pub mod field;
+pub mod fri;
+pub mod lookup;
+pub mod poly;
 pub mod prover;
+pub mod sponge;
 pub mod stark;
+pub mod trace_table;
+pub mod transcript;
 pub mod verifier;
 
+pub use air::*;
 pub use constraints::*;
+pub use extension::*;
+pub use field::*;
+pub use fri::*;
+pub use lookup::*;
+pub use poly::*;
 pub use prover::*;
+pub use sponge::*;
 pub use stark::*;
+pub use trace_table::*;
+pub use transcript::*;
 pub use verifier::*;
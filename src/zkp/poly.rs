@@ -0,0 +1,92 @@
+//! Polynomials over two-adic fields: radix-2 NTTs between coefficients and
+//! evaluations on `<ω_n>`, and low-degree extension onto a coset.
+//!
+//! Evaluations are in natural order, so `values[i]` is the value at `ω^i`
+//! (times the coset shift) and `values[i + n/2]` the value at its negation.
+
+use super::{ExtensionField, Field, TwoAdicField};
+
+fn log2_exact(n: usize) -> u32 {
+    assert!(n.is_power_of_two(), "length {n} is not a power of two");
+    n.trailing_zeros()
+}
+
+fn bit_reverse<T>(values: &mut [T]) {
+    let bits = log2_exact(values.len());
+    if bits == 0 {
+        return;
+    }
+    for i in 0..values.len() {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if i < j {
+            values.swap(i, j);
+        }
+    }
+}
+
+/// Evaluates the polynomial with `coeffs` on `<ω_n>`, `n = coeffs.len()`.
+pub fn ntt<F: TwoAdicField>(coeffs: &mut [F]) {
+    let n = coeffs.len();
+    let bits = log2_exact(n);
+    bit_reverse(coeffs);
+    for level in 1..=bits {
+        let half = 1 << (level - 1);
+        let root = F::two_adic_generator(level);
+        let twiddles: Vec<F> = root.powers().take(half).collect();
+        for chunk in coeffs.chunks_mut(2 * half) {
+            let (low, high) = chunk.split_at_mut(half);
+            for ((a, b), &twiddle) in low.iter_mut().zip(high).zip(&twiddles) {
+                let t = *b * twiddle;
+                *b = *a - t;
+                *a += t;
+            }
+        }
+    }
+}
+
+/// Inverse of [`ntt`]: interpolates evaluations on `<ω_n>`.
+pub fn intt<F: TwoAdicField>(values: &mut [F]) {
+    let n = values.len();
+    ntt(values);
+    // Evaluating at ω^-i is evaluating at ω^(n-i)
+    values[1..].reverse();
+    let n_inv = F::from_u64(n as u64).inverse().expect("n is nonzero");
+    for value in values.iter_mut() {
+        *value *= n_inv;
+    }
+}
+
+/// Evaluates the polynomial with `coeffs` on the coset `shift * <ω_n>`.
+pub fn coset_ntt<F: TwoAdicField>(coeffs: &mut [F], shift: F) {
+    for (coeff, power) in coeffs.iter_mut().zip(shift.powers()) {
+        *coeff *= power;
+    }
+    ntt(coeffs);
+}
+
+/// Coefficients of the polynomial taking `values` on `shift * <ω_n>`.
+pub fn coset_intt<F: TwoAdicField>(values: &mut [F], shift: F) {
+    intt(values);
+    let shift_inv = shift.inverse().expect("coset shift is nonzero");
+    for (coeff, power) in values.iter_mut().zip(shift_inv.powers()) {
+        *coeff *= power;
+    }
+}
+
+/// Extends `evals` on `<ω_n>` to the coset `shift * <ω_{n * blowup}>`.
+pub fn coset_lde<F: TwoAdicField>(evals: &[F], blowup: usize, shift: F) -> Vec<F> {
+    let mut coeffs = evals.to_vec();
+    intt(&mut coeffs);
+    coeffs.resize(evals.len() * blowup, F::ZERO);
+    coset_ntt(&mut coeffs, shift);
+    coeffs
+}
+
+/// Evaluates the polynomial with `coeffs` over `F` at a point of an
+/// extension of `F`.
+pub fn evaluate_polynomial<F: Field, E: ExtensionField<F>>(coeffs: &[F], point: E) -> E {
+    coeffs
+        .iter()
+        .rev()
+        .fold(E::ZERO, |acc, &coeff| acc * point + E::from(coeff))
+}
@@ -1,10 +1,18 @@
-use crate::vm::{Instruction, VmState};
+use super::{CONSTRAINED_OPCODES, CONSTRAINED_SYSCALLS};
+use crate::vm::{
+    ExitStatus, FileCommitment, Instruction, IsaProfile, KvStore, Opcode, ReplayLog,
+    StateTransition, VmState,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Clone)]
 pub struct ExecutionTrace {
     pub steps: Vec<TraceStep>,
     pub initial_state: VmState,
     pub final_state: VmState,
+    pub exit_status: ExitStatus,
+    pub gas_used: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -12,8 +20,12 @@ pub struct TraceStep {
     pub step_index: usize,
     pub pc_before: u32,
     pub pc_after: u32,
+    /// Low 32-bit limbs of the registers.
     pub registers_before: [u32; 32],
     pub registers_after: [u32; 32],
+    /// High 32-bit limbs of the registers; always zero on RV32 profiles.
+    pub registers_before_hi: [u32; 32],
+    pub registers_after_hi: [u32; 32],
     pub instruction: Instruction,
     pub memory_accesses: Vec<MemoryAccess>,
     pub intermediate_values: Vec<u32>,
@@ -25,6 +37,9 @@ pub struct MemoryAccess {
     pub value_before: u32,
     pub value_after: u32,
     pub is_write: bool,
+    /// Position of the access in execution order, from 1; 0 stands for
+    /// the memory before execution.
+    pub timestamp: u32,
 }
 
 #[derive(Debug, Clone)]
@@ -38,25 +53,63 @@ pub struct WitnessData {
 pub struct Proof {
     pub trace_commitment: Vec<u8>,
     pub witness: Vec<u8>,
+    /// Gas used by the run, which the AIR adds up from the price of every
+    /// instruction it executes.
+    pub gas_used: u64,
+    /// ISA the proven program was executed under.
+    pub isa_profile: IsaProfile,
 }
 
-pub trait ProofSystem {
-    type Error: std::fmt::Debug;
-
-    fn generate_proof(&self, trace: &ExecutionTrace) -> Result<Proof, Self::Error>;
+/// What a proof claims about the run, for the verifier to check it against.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicValues {
+    /// The pc the run starts at and the pc it stops at.
+    pub initial_pc: u32,
+    pub final_pc: u32,
+    /// The registers the run starts with.
+    pub initial_registers: [u64; 32],
+    /// Every nonzero word of memory before execution, by address: the
+    /// program image and the initial stack.
+    pub initial_memory: BTreeMap<u32, u32>,
+    /// Gas price of each instruction the AIR constrains.
+    pub gas_prices: BTreeMap<Opcode, u64>,
+    /// Program arguments the run started with, as the initial stack at `sp`
+    /// holds them; empty if `sp` does not point at one.
+    pub argv: Vec<String>,
+    /// The input left for the guest to read, and the journal it wrote.
+    pub input: Vec<u8>,
+    pub journal: Vec<u8>,
+    /// How the run stopped. Proofs tell a guest that halted or panicked
+    /// from one still running, but not the step limit from the gas limit.
+    pub exit_status: ExitStatus,
+    /// Gas price of each syscall the AIR constrains.
+    pub syscall_prices: BTreeMap<u32, u64>,
+    /// The program break the run starts with, and the range BRK moves it in.
+    pub brk: u32,
+    pub heap_start: u32,
+    pub heap_end: u32,
+    /// Hash of every public file of the virtual filesystem, ordered by
+    /// path; a file's index is its place here.
+    pub files: Vec<FileCommitment>,
+    /// The key-value store roots the run goes from and to, and its number
+    /// of keys; `None` and 0 without a store, when KV_GET and KV_SET
+    /// cannot be proven.
+    pub state_transition: Option<StateTransition>,
+    pub kv_keys: u32,
 }
 
-pub struct Prover<P: ProofSystem> {
-    proof_system: P,
+fn low_limbs(registers: &[u64; 32]) -> [u32; 32] {
+    registers.map(|value| value as u32)
 }
 
-impl<P: ProofSystem> Prover<P> {
-    pub fn new(proof_system: P) -> Self {
-        Self { proof_system }
-    }
+fn high_limbs(registers: &[u64; 32]) -> [u32; 32] {
+    registers.map(|value| (value >> 32) as u32)
+}
 
-    pub fn generate_execution_trace(
-        &self,
+impl ExecutionTrace {
+    /// Runs `vm_state` for up to `max_steps` instructions and records every
+    /// step.
+    pub fn record(
         vm_state: &mut VmState,
         max_steps: usize,
     ) -> Result<ExecutionTrace, &'static str> {
@@ -65,26 +118,31 @@ impl<P: ProofSystem> Prover<P> {
         let final_state = vm_state.clone();
 
         let mut trace_steps = Vec::new();
+        let mut timestamp = 0;
         for (index, exec_step) in execution_steps.iter().enumerate() {
             let mut memory_accesses = Vec::new();
 
             // Convert memory reads
             for (addr, value) in &exec_step.memory_reads {
+                timestamp += 1;
                 memory_accesses.push(MemoryAccess {
                     addr: *addr,
                     value_before: *value,
                     value_after: *value,
                     is_write: false,
+                    timestamp,
                 });
             }
 
             // Convert memory writes
             for (addr, old_value, new_value) in &exec_step.memory_writes {
+                timestamp += 1;
                 memory_accesses.push(MemoryAccess {
                     addr: *addr,
                     value_before: *old_value,
                     value_after: *new_value,
                     is_write: true,
+                    timestamp,
                 });
             }
 
@@ -92,21 +150,140 @@ impl<P: ProofSystem> Prover<P> {
                 step_index: index,
                 pc_before: exec_step.pc_before,
                 pc_after: exec_step.pc_after,
-                registers_before: exec_step.registers_before,
-                registers_after: exec_step.registers_after,
+                registers_before: low_limbs(&exec_step.registers_before),
+                registers_after: low_limbs(&exec_step.registers_after),
+                registers_before_hi: high_limbs(&exec_step.registers_before),
+                registers_after_hi: high_limbs(&exec_step.registers_after),
                 instruction: exec_step.instruction,
                 memory_accesses,
                 intermediate_values: exec_step.intermediate_values.clone(),
             });
         }
 
+        let exit_status = final_state.exit_status.unwrap_or(ExitStatus::StepLimit);
+        let gas_used = final_state.gas.used - initial_state.gas.used;
+
         Ok(ExecutionTrace {
             steps: trace_steps,
             initial_state,
             final_state,
+            exit_status,
+            gas_used,
         })
     }
 
+    /// The input left for the guest when the trace starts.
+    pub fn input(&self) -> &[u8] {
+        let input = &self.initial_state.input;
+        &input[self.initial_state.input_cursor.min(input.len())..]
+    }
+
+    /// The journal the guest wrote during the trace.
+    pub fn journal(&self) -> &[u8] {
+        let journal = &self.final_state.journal;
+        &journal[self.initial_state.journal.len().min(journal.len())..]
+    }
+
+    /// The public values a proof of this trace is verified against.
+    pub fn public_values(&self) -> PublicValues {
+        let initial_pc = self
+            .steps
+            .first()
+            .map_or(self.initial_state.pc, |step| step.pc_before);
+        let initial_registers = self
+            .steps
+            .first()
+            .map_or(self.initial_state.registers, |step| {
+                std::array::from_fn(|i| {
+                    step.registers_before[i] as u64 | (step.registers_before_hi[i] as u64) << 32
+                })
+            });
+        PublicValues {
+            initial_pc,
+            initial_registers,
+            final_pc: self.steps.last().map_or(initial_pc, |step| step.pc_after),
+            initial_memory: self.initial_state.memory.words(),
+            gas_prices: CONSTRAINED_OPCODES
+                .iter()
+                .map(|&opcode| (opcode, self.initial_state.gas.opcode_price(opcode)))
+                .collect(),
+            argv: self.initial_state.argv.clone(),
+            input: self.input().to_vec(),
+            journal: self.journal().to_vec(),
+            exit_status: self.exit_status,
+            syscall_prices: CONSTRAINED_SYSCALLS
+                .iter()
+                .map(|&syscall| (syscall, self.initial_state.gas.syscall_price(syscall)))
+                .collect(),
+            brk: self.initial_state.brk,
+            heap_start: self.initial_state.heap_start,
+            heap_end: self.initial_state.memory_map.heap_end,
+            files: self.initial_state.files.commitments(),
+            state_transition: self.state_transition(),
+            kv_keys: self
+                .initial_state
+                .kv_store
+                .as_ref()
+                .map_or(0, KvStore::capacity),
+        }
+    }
+
+    /// The key-value store roots the run went from and to.
+    pub fn state_transition(&self) -> Option<StateTransition> {
+        self.initial_state
+            .state_root
+            .zip(self.final_state.state_root)
+            .map(|(old_root, new_root)| StateTransition { old_root, new_root })
+    }
+
+    /// Re-executes from `initial_state`, taking syscall effects from `log`
+    /// instead of the host, and records every step.
+    pub fn replay(
+        initial_state: &VmState,
+        log: ReplayLog,
+        max_steps: usize,
+    ) -> Result<ExecutionTrace, &'static str> {
+        let mut vm_state = initial_state.clone().with_replay(log);
+        let trace = Self::record(&mut vm_state, max_steps)?;
+        vm_state.check_replay_finished()?;
+        Ok(trace)
+    }
+}
+
+pub trait ProofSystem {
+    type Error: std::fmt::Debug;
+
+    fn generate_proof(&self, trace: &ExecutionTrace) -> Result<Proof, Self::Error>;
+}
+
+pub struct Prover<P: ProofSystem> {
+    proof_system: P,
+}
+
+impl<P: ProofSystem> Prover<P> {
+    pub fn new(proof_system: P) -> Self {
+        Self { proof_system }
+    }
+
+    pub fn generate_execution_trace(
+        &self,
+        vm_state: &mut VmState,
+        max_steps: usize,
+    ) -> Result<ExecutionTrace, &'static str> {
+        ExecutionTrace::record(vm_state, max_steps)
+    }
+
+    /// Regenerates a trace from a replay log without calling syscall
+    /// handlers; see [`ExecutionTrace::replay`].
+    pub fn replay_execution_trace(
+        &self,
+        initial_state: &VmState,
+        log: ReplayLog,
+        max_steps: usize,
+    ) -> Result<ExecutionTrace, &'static str> {
+        ExecutionTrace::replay(initial_state, log, max_steps)
+    }
+
     pub fn generate_witness(
         &self,
         trace: &ExecutionTrace,
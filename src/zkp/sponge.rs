@@ -0,0 +1,175 @@
+//! A hash over BabyBear that the AIR can check a round per row.
+//!
+//! The permutation is Poseidon-like: a state of [`SPONGE_WIDTH`] elements
+//! goes through [`SPONGE_ROUNDS`] full rounds, each adding the round's
+//! constants, raising every element to the 7th power and mixing them with
+//! the Poseidon2 external matrix. The parameters are fixed here rather than
+//! taken from a vetted instance.
+//!
+//! [`sponge_hash`] absorbs bytes one per element, [`SPONGE_RATE`] at a
+//! time, into a state whose capacity starts with the length, and squeezes
+//! the first [`DIGEST_SIZE`] elements. A digest is a [`HashValue`] as the
+//! little-endian bytes of its elements. [`sponge_compress`] joins two
+//! digests by permuting them side by side, and [`SpongeHasher`] builds
+//! Merkle trees from the two.
+
+use super::{BabyBear, Field};
+use crate::crypto::{HashValue, MerkleHasher};
+use std::sync::OnceLock;
+
+pub const SPONGE_WIDTH: usize = 16;
+/// Elements a block absorbs.
+pub const SPONGE_RATE: usize = 8;
+pub const SPONGE_ROUNDS: usize = 21;
+pub const DIGEST_SIZE: usize = 8;
+
+pub type SpongeState = [BabyBear; SPONGE_WIDTH];
+pub type Digest = [BabyBear; DIGEST_SIZE];
+
+/// The constants each round adds, drawn from SplitMix64 seeded with the
+/// width and round count.
+pub fn round_constants() -> &'static [SpongeState; SPONGE_ROUNDS] {
+    static CONSTANTS: OnceLock<[SpongeState; SPONGE_ROUNDS]> = OnceLock::new();
+    CONSTANTS.get_or_init(|| {
+        let mut seed = ((SPONGE_WIDTH as u64) << 32) | SPONGE_ROUNDS as u64;
+        let mut draw = || {
+            seed = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = seed;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            BabyBear::from_u64(z ^ (z >> 31))
+        };
+        std::array::from_fn(|_| std::array::from_fn(|_| draw()))
+    })
+}
+
+/// The 4x4 block of the mixing matrix.
+const M4: [[u32; 4]; 4] = [[5, 7, 1, 3], [4, 6, 1, 1], [1, 3, 5, 7], [1, 1, 4, 6]];
+
+/// Entry `(i, j)` of the mixing matrix: [`M4`] off the diagonal, and twice
+/// it on it.
+pub fn mix_coefficient(i: usize, j: usize) -> u32 {
+    let entry = M4[i % 4][j % 4];
+    if i / 4 == j / 4 {
+        2 * entry
+    } else {
+        entry
+    }
+}
+
+/// `state + constants`, raised to the 7th power and mixed, for round
+/// `round`.
+pub fn sponge_round(state: &SpongeState, round: usize) -> SpongeState {
+    let constants = &round_constants()[round];
+    let powered: SpongeState = std::array::from_fn(|j| (state[j] + constants[j]).pow(7));
+    // Each block of four mixed by M4, then added to the sum over blocks
+    let blocks: [[BabyBear; 4]; SPONGE_WIDTH / 4] = std::array::from_fn(|b| {
+        std::array::from_fn(|r| {
+            (0..4)
+                .map(|k| BabyBear::from(M4[r][k]) * powered[4 * b + k])
+                .sum()
+        })
+    });
+    std::array::from_fn(|i| {
+        let total: BabyBear = blocks.iter().map(|block| block[i % 4]).sum();
+        total + blocks[i / 4][i % 4]
+    })
+}
+
+pub fn permute(state: &mut SpongeState) {
+    for round in 0..SPONGE_ROUNDS {
+        *state = sponge_round(state, round);
+    }
+}
+
+/// The blocks `bytes` are absorbed in, at least one, zero-padded.
+pub fn sponge_blocks(bytes: &[u8]) -> Vec<[BabyBear; SPONGE_RATE]> {
+    let blocks = bytes.len().div_ceil(SPONGE_RATE).max(1);
+    (0..blocks)
+        .map(|b| {
+            std::array::from_fn(|i| {
+                let byte = bytes.get(b * SPONGE_RATE + i).copied().unwrap_or(0);
+                BabyBear::from(u32::from(byte))
+            })
+        })
+        .collect()
+}
+
+/// The state the first block of `bytes` is absorbed into.
+pub fn sponge_initial_state(len: usize) -> SpongeState {
+    let mut state = [BabyBear::ZERO; SPONGE_WIDTH];
+    state[SPONGE_RATE] = BabyBear::from_u64(len as u64);
+    state
+}
+
+pub fn sponge_digest(bytes: &[u8]) -> Digest {
+    let mut state = sponge_initial_state(bytes.len());
+    for block in sponge_blocks(bytes) {
+        for (element, value) in state.iter_mut().zip(block) {
+            *element += value;
+        }
+        permute(&mut state);
+    }
+    std::array::from_fn(|i| state[i])
+}
+
+pub fn sponge_hash(bytes: &[u8]) -> HashValue {
+    digest_bytes(&sponge_digest(bytes))
+}
+
+/// The first [`DIGEST_SIZE`] elements of the permutation of `left` and
+/// `right`, side by side.
+pub fn sponge_compress(left: &Digest, right: &Digest) -> Digest {
+    let mut state: SpongeState = std::array::from_fn(|i| {
+        if i < DIGEST_SIZE {
+            left[i]
+        } else {
+            right[i - DIGEST_SIZE]
+        }
+    });
+    permute(&mut state);
+    std::array::from_fn(|i| state[i])
+}
+
+/// The hash value of `digest`.
+pub fn digest_bytes(digest: &Digest) -> HashValue {
+    let mut hash = [0; 32];
+    for (chunk, element) in hash.as_chunks_mut::<4>().0.iter_mut().zip(digest) {
+        *chunk = element.as_u32().to_le_bytes();
+    }
+    hash
+}
+
+/// The digest `hash` holds, if its elements are canonical.
+pub fn digest_of(hash: &HashValue) -> Option<Digest> {
+    let elements: Vec<BabyBear> = hash
+        .as_chunks::<4>()
+        .0
+        .iter()
+        .map(|&chunk| u32::from_le_bytes(chunk))
+        .map(|value| (value < super::BABY_BEAR_MODULUS).then(|| BabyBear::from(value)))
+        .collect::<Option<_>>()?;
+    elements.try_into().ok()
+}
+
+/// Merkle hashing over the sponge: leaves by [`sponge_hash`], nodes by
+/// [`sponge_compress`], so the AIR can check a path.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpongeHasher;
+
+impl MerkleHasher for SpongeHasher {
+    fn hash_leaf(&self, data: &[u8]) -> HashValue {
+        sponge_hash(data)
+    }
+
+    /// Bytes that are not a digest are read modulo the field.
+    fn hash_node(&self, left: &HashValue, right: &HashValue) -> HashValue {
+        let elements = |hash: &HashValue| -> Digest {
+            std::array::from_fn(|i| {
+                let bytes = hash[4 * i..4 * i + 4].try_into().unwrap();
+                BabyBear::from(u32::from_le_bytes(bytes))
+            })
+        };
+        digest_bytes(&sponge_compress(&elements(left), &elements(right)))
+    }
+}
@@ -1,27 +1,277 @@
-use super::{ConstraintSystem, ExecutionTrace, Proof, ProofSystem};
+use super::{
+    batch_inverse, coset_intt, coset_ntt, evaluate_polynomial, field_leaf, intt, merkle_path, Air,
+    BabyBear, BabyBearExt4, BoundaryRow, ConstraintSystem, ExecutionTrace, ExtensionField, Field,
+    FriConfig, FriProof, LookupChallenges, LookupTable, MemoryBoundary, Multiplicity, Proof,
+    ProofSystem, Transcript, TwoAdicField,
+};
 use crate::crypto::{HashValue, MerkleTree};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StarkProof {
+    /// Root of the Merkle tree over the rows of the extended trace.
     pub trace_commitment: HashValue,
-    pub constraint_evaluations: Vec<u32>,
-    pub merkle_proof: Vec<HashValue>,
+    /// How often each table entry is looked up, and each pc fetched from.
+    pub lookup_multiplicities: Vec<Multiplicity>,
+    /// The entries of the initial and final memory tables, by address.
+    pub memory_boundary: Vec<MemoryBoundary>,
+    /// Root of the Merkle tree over the extended auxiliary trace, committed
+    /// after the lookup challenges. Its extension-field columns are
+    /// committed coordinate by coordinate.
+    pub aux_commitment: HashValue,
+    /// Root of the Merkle tree over the extended quotient chunks.
+    pub quotient_commitment: HashValue,
+    /// Rows of the trace, padded to a power of two.
+    pub trace_length: usize,
+    pub expansion_factor: u32,
+    /// Values the AIR's boundary constraints pin the trace to.
+    pub public_inputs: Vec<BabyBear>,
+    /// Every trace column evaluated at the out-of-domain point `z`, and at
+    /// `z * g` for the next row.
+    pub trace_ood_values: Vec<BabyBearExt4>,
+    pub trace_ood_next_values: Vec<BabyBearExt4>,
+    /// Every auxiliary coordinate column evaluated at `z` and `z * g`.
+    pub aux_ood_values: Vec<BabyBearExt4>,
+    pub aux_ood_next_values: Vec<BabyBearExt4>,
+    /// Every quotient chunk column evaluated at `z`.
+    pub quotient_ood_values: Vec<BabyBearExt4>,
+    /// The extended trace, auxiliary and quotient rows at `x` and `-x` for
+    /// each FRI query.
+    pub trace_openings: Vec<[RowOpening; 2]>,
+    pub aux_openings: Vec<[RowOpening; 2]>,
+    pub quotient_openings: Vec<[RowOpening; 2]>,
+    /// Gas used by the run, which the last public input pins the AIR's
+    /// running total to.
+    pub gas_used: u64,
+    pub isa_profile: u32,
     pub fri_proof: FriProof,
 }
 
-#[derive(Debug, Clone)]
-pub struct FriProof {
-    pub commitments: Vec<HashValue>,
-    pub final_polynomial: Vec<u32>,
-    pub query_proofs: Vec<QueryProof>,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RowOpening {
+    pub values: Vec<BabyBear>,
+    pub merkle_path: Vec<HashValue>,
 }
 
-#[derive(Debug, Clone)]
-pub struct QueryProof {
-    pub index: usize,
-    pub value: u32,
-    pub merkle_path: Vec<HashValue>,
+impl StarkProof {
+    /// The transcript after the public values, the trace commitment, the
+    /// lookup multiplicities and the memory boundary.
+    pub fn transcript(&self) -> Transcript {
+        let mut transcript = Transcript::new(b"zkvm-stark");
+        transcript.absorb_u64(self.gas_used);
+        transcript.absorb_u64(self.isa_profile as u64);
+        transcript.absorb_u64(self.trace_length as u64);
+        transcript.absorb_u64(self.expansion_factor as u64);
+        transcript.absorb_field(&self.public_inputs);
+        transcript.absorb_bytes(&self.trace_commitment);
+        transcript.absorb_u64(self.lookup_multiplicities.len() as u64);
+        for multiplicity in &self.lookup_multiplicities {
+            transcript.absorb_u64(multiplicity.table as u64);
+            transcript.absorb_u64(multiplicity.entry as u64);
+            transcript.absorb_field(&[multiplicity.count]);
+        }
+        transcript.absorb_u64(self.memory_boundary.len() as u64);
+        for word in &self.memory_boundary {
+            transcript.absorb_u64(word.addr as u64);
+            transcript.absorb_u64(word.initial_value as u64);
+            transcript.absorb_u64(word.final_value as u64);
+        }
+        transcript
+    }
+
+    /// The table side of the lookups, with the public tables' entries from
+    /// `public`, or `None` if it has a pole.
+    pub fn lookup_sum(
+        &self,
+        challenges: &LookupChallenges,
+        public: &BTreeMap<(LookupTable, u32), Vec<BabyBear>>,
+    ) -> Option<BabyBearExt4> {
+        Some(
+            challenges.table_sum(&self.lookup_multiplicities, public)?
+                + challenges.memory_sum(&self.memory_boundary)?,
+        )
+    }
+
+    pub fn lookup_challenges(transcript: &mut Transcript) -> LookupChallenges {
+        LookupChallenges {
+            gamma: transcript.challenge(),
+            delta: transcript.challenge(),
+        }
+    }
+
+    pub fn absorb_ood_values(&self, transcript: &mut Transcript) {
+        transcript.absorb_field(&self.trace_ood_values);
+        transcript.absorb_field(&self.trace_ood_next_values);
+        transcript.absorb_field(&self.aux_ood_values);
+        transcript.absorb_field(&self.aux_ood_next_values);
+        transcript.absorb_field(&self.quotient_ood_values);
+    }
+
+    /// The DEEP quotient at a point `x` of the evaluation domain, from the
+    /// trace, auxiliary and quotient rows at `x`:
+    ///
+    /// ```text
+    /// sum(alpha^i (T_i(x) - T_i(z)) / (x - z)) + sum(alpha^j (T_j(x) - T_j(zg)) / (x - zg))
+    ///     + sum(alpha^k (Q_k(x) - Q_k(z)) / (x - z))
+    /// ```
+    ///
+    /// with the auxiliary columns among the `T_i`. It is a polynomial only
+    /// if the claimed out-of-domain values are right, so FRI on it checks
+    /// them together with the degree of every column.
+    pub fn deep_quotient(
+        &self,
+        trace_row: &[BabyBear],
+        aux_row: &[BabyBear],
+        quotient_row: &[BabyBear],
+        alpha: BabyBearExt4,
+        x_minus_z_inv: BabyBearExt4,
+        x_minus_zg_inv: BabyBearExt4,
+    ) -> BabyBearExt4 {
+        let mut powers = alpha.powers();
+        let mut combine = |row: &[BabyBear], ood_values: &[BabyBearExt4]| -> BabyBearExt4 {
+            row.iter()
+                .zip(ood_values)
+                .zip(&mut powers)
+                .map(|((&value, &ood), power)| (BabyBearExt4::from(value) - ood) * power)
+                .sum()
+        };
+        let at_z = combine(trace_row, &self.trace_ood_values)
+            + combine(aux_row, &self.aux_ood_values)
+            + combine(quotient_row, &self.quotient_ood_values);
+        let at_zg = combine(trace_row, &self.trace_ood_next_values)
+            + combine(aux_row, &self.aux_ood_next_values);
+        at_z * x_minus_z_inv + at_zg * x_minus_zg_inv
+    }
+}
+
+/// Trace polynomials are extended onto this coset of the evaluation
+/// domain, so they are never evaluated on the trace domain itself.
+pub fn domain_shift() -> BabyBear {
+    BabyBear::generator()
+}
+
+/// Number of chunks of degree below the trace length the quotient of `air`
+/// is split into; each is committed as `BabyBearExt4::DEGREE` base columns.
+pub fn quotient_chunks(air: &Air) -> usize {
+    air.max_degree().saturating_sub(1).max(1)
+}
+
+/// A row of the main and auxiliary traces, with the row after it.
+pub struct RowPair<'a, F> {
+    pub current: &'a [F],
+    pub next: &'a [F],
+    pub aux_current: &'a [BabyBearExt4],
+    pub aux_next: &'a [BabyBearExt4],
+}
+
+/// What the constraints are checked against besides the trace.
+pub struct ConstraintInputs<'a> {
+    pub public_inputs: &'a [BabyBear],
+    pub challenges: LookupChallenges,
+    /// The table side of the lookups, which the running sum must reach.
+    pub lookup_sum: BabyBearExt4,
+    /// Combines the constraints.
+    pub beta: BabyBearExt4,
+}
+
+/// Inverses of the vanishing polynomials at a point `x`.
+pub struct VanishingFactors {
+    /// `1 / (x^n - 1)`, vanishing on every row.
+    pub every_row: BabyBearExt4,
+    /// `(x - g^(n-1)) / (x^n - 1)`, vanishing on every row but the last.
+    pub transition: BabyBearExt4,
+    /// `1 / (x - 1)` and `1 / (x - g^(n-1))`.
+    pub first_row: BabyBearExt4,
+    pub last_row: BabyBearExt4,
+}
+
+/// The constraints of `air` at a point `x`, combined with powers of `beta`
+/// and divided by their vanishing polynomials.
+pub fn constraint_quotient<F>(
+    air: &Air,
+    rows: &RowPair<F>,
+    inputs: &ConstraintInputs,
+    factors: &VanishingFactors,
+) -> BabyBearExt4
+where
+    F: ExtensionField<BabyBear>,
+    BabyBearExt4: From<F>,
+{
+    let (current, next) = (rows.current, rows.next);
+    let mut powers = inputs.beta.powers();
+    let transitions: BabyBearExt4 = air
+        .transitions
+        .iter()
+        .zip(&mut powers)
+        .map(|(constraint, power)| BabyBearExt4::from(constraint.evaluate(current, next)) * power)
+        .sum();
+    let boundaries: BabyBearExt4 = air
+        .boundaries
+        .iter()
+        .zip(&mut powers)
+        .map(|(boundary, power)| {
+            let value = current[boundary.column] - F::from(boundary.value(inputs.public_inputs));
+            let factor = match boundary.row {
+                BoundaryRow::First => factors.first_row,
+                BoundaryRow::Last => factors.last_row,
+            };
+            BabyBearExt4::from(value) * factor * power
+        })
+        .sum();
+    let quotient = transitions * factors.transition + boundaries;
+    if air.lookups.is_empty() {
+        return quotient;
+    }
+
+    // Every helper column is its lookup's term
+    let challenges = &inputs.challenges;
+    let helpers: BabyBearExt4 = air
+        .lookups
+        .iter()
+        .zip(rows.aux_current)
+        .zip(&mut powers)
+        .map(|((lookup, &helper), power)| {
+            let values: Vec<F> = lookup
+                .values
+                .iter()
+                .map(|value| value.evaluate(current, next))
+                .collect();
+            let denominator = challenges.gamma - challenges.fingerprint(lookup.table, &values);
+            let multiplicity = BabyBearExt4::from(lookup.multiplicity.evaluate(current, next));
+            (helper * denominator - multiplicity) * power
+        })
+        .sum();
+
+    // The last auxiliary column sums them row by row, up to the table side
+    let sum = air.lookups.len();
+    let row_sum = |row: &[BabyBearExt4]| row[..sum].iter().copied().sum::<BabyBearExt4>();
+    let (aux_current, aux_next) = (rows.aux_current, rows.aux_next);
+    let running = aux_next[sum] - aux_current[sum] - row_sum(aux_next);
+    let first = aux_current[sum] - row_sum(aux_current);
+    let last = aux_current[sum] - inputs.lookup_sum;
+    let mut term = |value: BabyBearExt4| value * powers.next().unwrap_or_default();
+    quotient
+        + helpers * factors.every_row
+        + term(running) * factors.transition
+        + term(first) * factors.first_row
+        + term(last) * factors.last_row
+}
+
+/// Splits extension-field columns into base-field columns, coordinate by
+/// coordinate.
+fn coordinate_columns(rows: &[Vec<BabyBearExt4>]) -> Vec<Vec<BabyBear>> {
+    let width = rows.first().map_or(0, Vec::len) * BabyBearExt4::DEGREE;
+    (0..width)
+        .map(|column| {
+            rows.iter()
+                .map(|row| {
+                    let value = row[column / BabyBearExt4::DEGREE];
+                    value.as_base_slice()[column % BabyBearExt4::DEGREE]
+                })
+                .collect()
+        })
+        .collect()
 }
 
 pub struct StarkProver {
@@ -46,108 +296,116 @@ impl StarkProver {
         }
     }
 
-    fn interpolate_trace(&self, trace: &ExecutionTrace) -> Vec<Vec<u32>> {
-        let mut columns = Vec::new();
-
-        // Create columns for each piece of state
-        let mut pc_column = Vec::new();
-        let mut reg_columns = vec![Vec::new(); 32];
-
-        for step in &trace.steps {
-            pc_column.push(step.pc_before);
-            for (i, reg_col) in reg_columns.iter_mut().enumerate().take(32) {
-                reg_col.push(step.registers_before[i]);
-            }
+    /// Each query contributes `log2(expansion_factor)` bits of soundness.
+    pub fn fri_config(&self) -> FriConfig {
+        let bits_per_query = self.expansion_factor.trailing_zeros().max(1);
+        FriConfig {
+            blowup: self.expansion_factor as usize,
+            num_queries: self.security_level.div_ceil(bits_per_query) as usize,
         }
-
-        columns.push(pc_column);
-        columns.extend(reg_columns);
-
-        columns
     }
 
-    fn evaluate_constraints(&self, trace: &ExecutionTrace) -> Vec<u32> {
-        let mut constraint_system = ConstraintSystem::new();
-        constraint_system.generate_constraints_for_trace(trace);
-
-        // Create witness from trace
-        let mut witness = HashMap::new();
-        for (step_idx, step) in trace.steps.iter().enumerate() {
-            witness.insert(format!("pc_before_{step_idx}"), step.pc_before);
-            witness.insert(format!("pc_after_{step_idx}"), step.pc_after);
-
-            for i in 0..32 {
-                witness.insert(
-                    format!("reg_{i}_before_{step_idx}"),
-                    step.registers_before[i],
+    /// Evaluates every column polynomial on the coset `domain_shift() * <ω>`
+    /// of `expansion_factor` times the trace length and returns the rows.
+    fn low_degree_extend(&self, coeffs: &[Vec<BabyBear>]) -> Vec<Vec<BabyBear>> {
+        let extended: Vec<Vec<BabyBear>> = coeffs
+            .iter()
+            .map(|column| {
+                let mut values = column.clone();
+                values.resize(
+                    column.len() * self.expansion_factor as usize,
+                    BabyBear::ZERO,
                 );
-                witness.insert(format!("reg_{i}_after_{step_idx}"), step.registers_after[i]);
-            }
-        }
-
-        // Evaluate all constraints
-        let mut evaluations = Vec::new();
-        for constraint in &constraint_system.constraints {
-            let satisfied = constraint_system.verify_constraint(constraint, &witness);
-            evaluations.push(if satisfied { 0 } else { 1 });
-        }
-
-        evaluations
+                coset_ntt(&mut values, domain_shift());
+                values
+            })
+            .collect();
+        let size = extended.first().map_or(0, Vec::len);
+        (0..size)
+            .map(|row| extended.iter().map(|column| column[row]).collect())
+            .collect()
     }
 
-    fn commit_to_columns(&self, columns: &[Vec<u32>]) -> (HashValue, MerkleTree) {
-        let mut all_bytes = Vec::new();
-
-        // Serialize columns into byte data
-        for col in columns {
-            for &value in col {
-                let bytes = value.to_le_bytes();
-                all_bytes.extend_from_slice(&bytes);
-            }
-        }
-
-        // Create data slices for Merkle tree
-        let data_to_commit: Vec<&[u8]> = all_bytes.chunks(4).collect();
-
-        let tree = MerkleTree::new(data_to_commit);
-        (*tree.root(), tree)
+    fn commit_to_rows(&self, rows: &[Vec<BabyBear>]) -> MerkleTree {
+        let leaves: Vec<Vec<u8>> = rows.iter().map(|row| field_leaf(row)).collect();
+        MerkleTree::new(leaves.iter().map(Vec::as_slice).collect())
     }
 
-    fn fri_commit(&self, polynomial: &[u32]) -> FriProof {
-        // Simplified FRI protocol
-        let mut commitments = Vec::new();
-        let mut current_poly = polynomial.to_vec();
-
-        // Commit to initial polynomial
-        let (commitment, _) = self.commit_to_columns(&[current_poly.clone()]);
-        commitments.push(commitment);
-
-        // Fold polynomial multiple times
-        while current_poly.len() > 4 {
-            let mut next_poly = Vec::new();
-            for i in 0..current_poly.len() / 2 {
-                // Simple folding: average adjacent coefficients
-                let folded = (current_poly[2 * i] + current_poly[2 * i + 1]) / 2;
-                next_poly.push(folded);
+    /// Coefficients of the quotient's chunks as base-field columns, from
+    /// the extended main and auxiliary traces.
+    fn quotient_columns(
+        &self,
+        air: &Air,
+        rows: &[Vec<BabyBear>],
+        aux_rows: &[Vec<BabyBear>],
+        trace_length: usize,
+        inputs: &ConstraintInputs,
+    ) -> Vec<Vec<BabyBear>> {
+        let size = rows.len();
+        let blowup = self.expansion_factor as usize;
+        let last = BabyBear::two_adic_generator(trace_length.trailing_zeros())
+            .inverse()
+            .unwrap();
+        let points: Vec<BabyBear> = BabyBear::two_adic_generator(size.trailing_zeros())
+            .powers()
+            .take(size)
+            .map(|power| domain_shift() * power)
+            .collect();
+
+        let inverses = |f: &dyn Fn(BabyBear) -> BabyBear| {
+            batch_inverse(&points.iter().map(|&x| f(x)).collect::<Vec<_>>())
+        };
+        let vanishing = inverses(&|x| x.pow(trace_length as u64) - BabyBear::ONE);
+        let first_row = inverses(&|x| x - BabyBear::ONE);
+        let last_row = inverses(&|x| x - last);
+        let aux_rows: Vec<Vec<BabyBearExt4>> = aux_rows
+            .iter()
+            .map(|row| {
+                row.chunks(BabyBearExt4::DEGREE)
+                    .map(BabyBearExt4::from_base_slice)
+                    .collect()
+            })
+            .collect();
+
+        // The next row of the trace domain is `blowup` points further on
+        let quotient: Vec<BabyBearExt4> = (0..size)
+            .map(|i| {
+                let next = (i + blowup) % size;
+                let rows = RowPair {
+                    current: &rows[i],
+                    next: &rows[next],
+                    aux_current: &aux_rows[i],
+                    aux_next: &aux_rows[next],
+                };
+                let factors = VanishingFactors {
+                    every_row: vanishing[i].into(),
+                    transition: ((points[i] - last) * vanishing[i]).into(),
+                    first_row: first_row[i].into(),
+                    last_row: last_row[i].into(),
+                };
+                constraint_quotient(air, &rows, inputs, &factors)
+            })
+            .collect();
+
+        // Interpolate each coordinate and cut it into chunks of
+        // `trace_length` coefficients
+        let coordinates: Vec<Vec<BabyBear>> = (0..BabyBearExt4::DEGREE)
+            .map(|coordinate| {
+                let mut values: Vec<BabyBear> = quotient
+                    .iter()
+                    .map(|value| value.as_base_slice()[coordinate])
+                    .collect();
+                coset_intt(&mut values, domain_shift());
+                values
+            })
+            .collect();
+        let mut columns = Vec::new();
+        for chunk in 0..quotient_chunks(air) {
+            for coeffs in &coordinates {
+                columns.push(coeffs[chunk * trace_length..(chunk + 1) * trace_length].to_vec());
             }
-
-            let (commitment, _) = self.commit_to_columns(&[next_poly.clone()]);
-            commitments.push(commitment);
-            current_poly = next_poly;
-        }
-
-        // Generate query proofs (simplified)
-        let query_proofs = vec![QueryProof {
-            index: 0,
-            value: current_poly[0],
-            merkle_path: vec![],
-        }];
-
-        FriProof {
-            commitments,
-            final_polynomial: current_poly,
-            query_proofs,
         }
+        columns
     }
 }
 
@@ -155,71 +413,159 @@ impl ProofSystem for StarkProver {
     type Error = &'static str;
 
     fn generate_proof(&self, trace: &ExecutionTrace) -> Result<Proof, Self::Error> {
+        if !self.expansion_factor.is_power_of_two() || self.expansion_factor < 2 {
+            return Err("Expansion factor must be a power of two of at least 2");
+        }
+        let profile = trace.initial_state.profile;
+        let constraint_system = ConstraintSystem::new().with_profile(profile);
+        let air = &constraint_system.air;
+        if air.max_degree() > self.expansion_factor as usize {
+            return Err("Constraint degree exceeds the expansion factor");
+        }
+
         // Step 1: Interpolate the execution trace into polynomials
-        let trace_columns = self.interpolate_trace(trace);
-
-        // Step 2: Commit to the trace
-        let (trace_commitment, _merkle_tree) = self.commit_to_columns(&trace_columns);
-
-        // Step 3: Evaluate constraint polynomials
-        let constraint_evaluations = self.evaluate_constraints(trace);
-
-        // Step 4: Create constraint polynomial and commit via FRI
-        let fri_proof = self.fri_commit(&constraint_evaluations);
-
-        // Convert to serializable format
-        let serializable_proof = SerializableStarkProof {
-            trace_commitment,
-            constraint_evaluations,
-            merkle_proof: vec![], // Simplified for now
-            fri_proof: SerializableFriProof {
-                commitments: fri_proof.commitments,
-                final_polynomial: fri_proof.final_polynomial,
-                query_proofs: fri_proof
-                    .query_proofs
-                    .into_iter()
-                    .map(|q| SerializableQueryProof {
-                        index: q.index,
-                        value: q.value,
-                        merkle_path: q.merkle_path,
-                    })
-                    .collect(),
+        let table = constraint_system.generate_trace(trace)?;
+        let public_inputs = constraint_system.public_inputs(&table);
+        let trace_length = table.len();
+        let mut trace_coeffs: Vec<Vec<BabyBear>> = (0..table.width())
+            .map(|column| table.column(column))
+            .collect();
+        for column in &mut trace_coeffs {
+            intt(column);
+        }
+        let rows = self.low_degree_extend(&trace_coeffs);
+
+        // Step 2: Commit to the extended trace and the lookup multiplicities
+        let tree = self.commit_to_rows(&rows);
+        let mut lookup_multiplicities = air.multiplicities(&table.rows)?;
+        lookup_multiplicities.extend(constraint_system.public_multiplicities(&table));
+        let public =
+            constraint_system.public_entries(&trace.public_values(), &lookup_multiplicities);
+
+        let mut proof = StarkProof {
+            trace_commitment: *tree.root(),
+            lookup_multiplicities,
+            memory_boundary: constraint_system.memory_boundary(&table),
+            aux_commitment: [0; 32],
+            quotient_commitment: [0; 32],
+            trace_length,
+            expansion_factor: self.expansion_factor,
+            public_inputs,
+            trace_ood_values: Vec::new(),
+            trace_ood_next_values: Vec::new(),
+            aux_ood_values: Vec::new(),
+            aux_ood_next_values: Vec::new(),
+            quotient_ood_values: Vec::new(),
+            trace_openings: Vec::new(),
+            aux_openings: Vec::new(),
+            quotient_openings: Vec::new(),
+            gas_used: trace.gas_used,
+            isa_profile: profile.id(),
+            fri_proof: FriProof {
+                commitments: Vec::new(),
+                final_layer: Vec::new(),
+                query_proofs: Vec::new(),
             },
         };
 
+        // Step 3: Commit to the lookups' auxiliary trace
+        let mut transcript = proof.transcript();
+        let challenges = StarkProof::lookup_challenges(&mut transcript);
+        let lookup_sum = proof
+            .lookup_sum(&challenges, &public)
+            .ok_or("Lookup challenge hits a table entry")?;
+        let mut aux_coeffs = coordinate_columns(&air.lookup_trace(&table.rows, &challenges));
+        for column in &mut aux_coeffs {
+            intt(column);
+        }
+        let aux_rows = self.low_degree_extend(&aux_coeffs);
+        let aux_tree = self.commit_to_rows(&aux_rows);
+        proof.aux_commitment = *aux_tree.root();
+        transcript.absorb_bytes(&proof.aux_commitment);
+
+        // Step 4: Divide the combined constraints by their vanishing
+        // polynomials and commit to the quotient
+        let inputs = ConstraintInputs {
+            public_inputs: &proof.public_inputs,
+            challenges,
+            lookup_sum,
+            beta: transcript.challenge(),
+        };
+        let quotient_coeffs = self.quotient_columns(air, &rows, &aux_rows, trace_length, &inputs);
+        let quotient_rows = self.low_degree_extend(&quotient_coeffs);
+        let quotient_tree = self.commit_to_rows(&quotient_rows);
+        proof.quotient_commitment = *quotient_tree.root();
+        transcript.absorb_bytes(&proof.quotient_commitment);
+
+        // Step 5: Open everything at a random point outside the domain
+        let z = transcript.challenge();
+        let zg = z * BabyBear::two_adic_generator(trace_length.trailing_zeros());
+        let evaluate = |columns: &[Vec<BabyBear>], point| {
+            columns
+                .iter()
+                .map(|column| evaluate_polynomial(column, point))
+                .collect()
+        };
+        proof.trace_ood_values = evaluate(&trace_coeffs, z);
+        proof.trace_ood_next_values = evaluate(&trace_coeffs, zg);
+        proof.aux_ood_values = evaluate(&aux_coeffs, z);
+        proof.aux_ood_next_values = evaluate(&aux_coeffs, zg);
+        proof.quotient_ood_values = evaluate(&quotient_coeffs, z);
+        proof.absorb_ood_values(&mut transcript);
+
+        // Step 6: Prove the DEEP quotient low-degree
+        let alpha = transcript.challenge();
+        let points: Vec<BabyBearExt4> = BabyBear::two_adic_generator(rows.len().trailing_zeros())
+            .powers()
+            .take(rows.len())
+            .map(|power| BabyBearExt4::from(domain_shift() * power))
+            .collect();
+        let z_inverses = batch_inverse(&points.iter().map(|&x| x - z).collect::<Vec<_>>());
+        let zg_inverses = batch_inverse(&points.iter().map(|&x| x - zg).collect::<Vec<_>>());
+        let combined = (0..rows.len())
+            .map(|i| {
+                proof.deep_quotient(
+                    &rows[i],
+                    &aux_rows[i],
+                    &quotient_rows[i],
+                    alpha,
+                    z_inverses[i],
+                    zg_inverses[i],
+                )
+            })
+            .collect();
+        let (fri_proof, indices) =
+            self.fri_config()
+                .prove(combined, domain_shift(), &mut transcript);
+
+        let half = rows.len() / 2;
+        let open = |rows: &[Vec<BabyBear>], tree: &MerkleTree, index: usize| RowOpening {
+            values: rows[index].clone(),
+            merkle_path: merkle_path(tree, index),
+        };
+        for index in indices {
+            proof
+                .trace_openings
+                .push([open(&rows, &tree, index), open(&rows, &tree, index + half)]);
+            proof.aux_openings.push([
+                open(&aux_rows, &aux_tree, index),
+                open(&aux_rows, &aux_tree, index + half),
+            ]);
+            proof.quotient_openings.push([
+                open(&quotient_rows, &quotient_tree, index),
+                open(&quotient_rows, &quotient_tree, index + half),
+            ]);
+        }
+        proof.fri_proof = fri_proof;
+
         // Convert to generic proof format
-        let proof_bytes =
-            bincode::serialize(&serializable_proof).map_err(|_| "Serialization failed")?;
+        let proof_bytes = bincode::serialize(&proof).map_err(|_| "Serialization failed")?;
 
         Ok(Proof {
-            trace_commitment: trace_commitment.to_vec(),
+            trace_commitment: proof.trace_commitment.to_vec(),
             witness: proof_bytes,
+            gas_used: trace.gas_used,
+            isa_profile: profile,
         })
     }
 }
-
-// We need to add serde support for serialization
-use serde::{Deserialize, Serialize};
-
-// Add derive macros to our structs
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SerializableStarkProof {
-    pub trace_commitment: [u8; 32],
-    pub constraint_evaluations: Vec<u32>,
-    pub merkle_proof: Vec<[u8; 32]>,
-    pub fri_proof: SerializableFriProof,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SerializableFriProof {
-    pub commitments: Vec<[u8; 32]>,
-    pub final_polynomial: Vec<u32>,
-    pub query_proofs: Vec<SerializableQueryProof>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SerializableQueryProof {
-    pub index: usize,
-    pub value: u32,
-    pub merkle_path: Vec<[u8; 32]>,
-}
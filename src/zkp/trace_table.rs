@@ -0,0 +1,2872 @@
+//! The execution trace as a matrix over BabyBear.
+//!
+//! [`TraceLayout`] fixes what every column of the matrix holds for an ISA
+//! profile, and [`TraceTable`] fills it from an [`ExecutionTrace`]. Row `i`
+//! holds the state before step `i` and everything the step read, computed
+//! and wrote; after the last step come rows with the final state that
+//! execute nothing. The memory columns are a log of every access in
+//! execution order, one per row, independent of the step on the same row,
+//! and the same log sorted by address and then timestamp. Rows of loads
+//! and stores hold their access again, to tie it to the step.
+//!
+//! Register values are split into byte limbs: products of two limbs summed
+//! over a whole word stay far below the BabyBear modulus, so limb equations
+//! that hold in the field hold over the integers.
+
+use super::{
+    digest_of, round_constants, sponge_blocks, sponge_initial_state, sponge_round, BabyBear,
+    Digest, ExecutionTrace, Field, MemoryAccess, SpongeState, TraceStep, BABY_BEAR_MODULUS,
+    DIGEST_SIZE, SPONGE_RATE, SPONGE_ROUNDS, SPONGE_WIDTH,
+};
+use crate::crypto::HashValue;
+use crate::vm::{
+    alu, alu64, errno, kv_leaf, linux_nr, seek, syscall_register, ExitStatus, Instruction,
+    IsaProfile, KvStore, KvValue, LinuxSyscalls, Opcode, FIRST_FILE_FD, KV_VALUE_SIZE,
+};
+use zkvm_guest::syscall::{fd, nr};
+
+/// Intermediate values a step can record; a double-precision fused
+/// multiply-add records the most.
+pub const INTERMEDIATE_COLUMNS: usize = 9;
+
+/// Bits in a limb of a register value.
+pub const LIMB_BITS: u32 = 8;
+
+const LIMB: u64 = 1 << LIMB_BITS;
+
+/// The opcodes with a selector column, whose results the AIR checks. A
+/// trace with any other instruction cannot be proven.
+pub const CONSTRAINED_OPCODES: [Opcode; 63] = [
+    Opcode::Add,
+    Opcode::Sub,
+    Opcode::Mul,
+    Opcode::Mulh,
+    Opcode::Mulhsu,
+    Opcode::Mulhu,
+    Opcode::Div,
+    Opcode::Divu,
+    Opcode::Rem,
+    Opcode::Remu,
+    Opcode::Slt,
+    Opcode::Sltu,
+    Opcode::And,
+    Opcode::Or,
+    Opcode::Xor,
+    Opcode::Branch,
+    Opcode::Bne,
+    Opcode::Blt,
+    Opcode::Bge,
+    Opcode::Bltu,
+    Opcode::Bgeu,
+    Opcode::Jump,
+    Opcode::Jalr,
+    Opcode::Addi,
+    Opcode::Slti,
+    Opcode::Sltiu,
+    Opcode::Xori,
+    Opcode::Ori,
+    Opcode::Andi,
+    Opcode::Sll,
+    Opcode::Srl,
+    Opcode::Sra,
+    Opcode::Slli,
+    Opcode::Srli,
+    Opcode::Srai,
+    Opcode::Lui,
+    Opcode::Auipc,
+    Opcode::Load,
+    Opcode::Store,
+    Opcode::Lb,
+    Opcode::Lh,
+    Opcode::Lbu,
+    Opcode::Lhu,
+    Opcode::Sb,
+    Opcode::Sh,
+    Opcode::Ld,
+    Opcode::Lwu,
+    Opcode::Sd,
+    Opcode::Addiw,
+    Opcode::Slliw,
+    Opcode::Srliw,
+    Opcode::Sraiw,
+    Opcode::Addw,
+    Opcode::Subw,
+    Opcode::Sllw,
+    Opcode::Srlw,
+    Opcode::Sraw,
+    Opcode::Mulw,
+    Opcode::Divw,
+    Opcode::Divuw,
+    Opcode::Remw,
+    Opcode::Remuw,
+    Opcode::Ecall,
+];
+
+/// The syscalls with a selector column, whose results and effects the AIR
+/// checks. A trace with an `ecall` making any other syscall cannot be
+/// proven. The Linux ones are those of [`LinuxSyscalls`] that act like the
+/// built-in ones; `read` only reads the input, and `fstat` only describes
+/// the standard streams.
+///
+/// [`LinuxSyscalls`]: crate::vm::LinuxSyscalls
+pub const CONSTRAINED_SYSCALLS: [u32; 20] = [
+    nr::HALT,
+    nr::READ_INPUT,
+    nr::WRITE_JOURNAL,
+    nr::PANIC,
+    nr::WRITE,
+    nr::BRK,
+    linux_nr::READ,
+    linux_nr::WRITE,
+    linux_nr::EXIT,
+    linux_nr::EXIT_GROUP,
+    linux_nr::BRK,
+    linux_nr::CLOSE,
+    linux_nr::FSTAT,
+    nr::OPEN,
+    nr::READ,
+    nr::SEEK,
+    nr::CLOSE,
+    linux_nr::LSEEK,
+    nr::KV_GET,
+    nr::KV_SET,
+];
+
+/// Files a proven run can have open at once, on descriptors 3 and up.
+pub const FILE_SLOTS: usize = 4;
+
+/// Columns of the memory access log, in execution order.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryColumns {
+    /// Set on rows holding an access.
+    pub used: usize,
+    /// Address of the accessed word, divided by four.
+    pub word: usize,
+    /// Byte limbs, low limb first.
+    pub value_before: Vec<usize>,
+    pub value_after: Vec<usize>,
+    pub is_write: usize,
+    /// The access's `timestamp`; one more than the last one on unused rows.
+    pub timestamp: usize,
+}
+
+/// Columns of the word a load or store accesses, which its row puts on the
+/// access bus for the memory log to take back. Every access in the log is
+/// one of these.
+///
+/// The address is `rs1 + imm`, added in 16-bit halves. LD and SD also
+/// access the word after it.
+#[derive(Debug, Clone, Default)]
+pub struct LoadStoreColumns {
+    /// Bits 3 to 15 of the address, bit 2, and bits 16 to 31.
+    pub word_low: usize,
+    pub word_odd: usize,
+    pub word_high: usize,
+    /// Carry into the high half, and out of it, each -1, 0 or 1.
+    pub carry: usize,
+    pub wrap: usize,
+    /// One-hot selector of the byte of the word the address points at.
+    pub byte_is: Vec<usize>,
+    /// The word before and after the access. Loads leave it unchanged.
+    pub value_before: Vec<usize>,
+    pub value_after: Vec<usize>,
+    /// The second word LD and SD access, before and after.
+    pub high_before: Vec<usize>,
+    pub high_after: Vec<usize>,
+    /// The low 7 bits of the top byte a signed load reads, which it
+    /// sign-extends from the bit above.
+    pub value_top: usize,
+    /// The timestamp of the step's access, counting the loads and stores
+    /// before it from 1.
+    pub timestamp: usize,
+}
+
+/// Columns of the memory access log sorted by word, then timestamp.
+#[derive(Debug, Clone, Default)]
+pub struct SortedMemoryColumns {
+    pub used: usize,
+    pub word: usize,
+    pub timestamp: usize,
+    pub value_before: Vec<usize>,
+    pub value_after: Vec<usize>,
+    /// Set on the first access to a word, and on unused rows.
+    pub first: usize,
+    /// Set when the next row accesses the same word.
+    pub same_word: usize,
+    /// Set when the next row accesses a later word.
+    pub new_word: usize,
+    /// Bits 16 and up of the gap to the next row's timestamp or word, less
+    /// one.
+    pub gap_high: usize,
+}
+
+/// Columns comparing rs1 with rs2.
+///
+/// With `A` and `B` the operands, their sign bits flipped for signed
+/// comparisons, `D = A - B + less * 2^XLEN` lies in `[0, 2^XLEN)`.
+/// Subtracting limb by limb, `borrows` are the borrows between limbs.
+#[derive(Debug, Clone, Default)]
+pub struct ComparisonColumns {
+    pub less: usize,
+    pub equal: usize,
+    /// Inverse of the sum of the limbs of `D`, or 0.
+    pub inverse: usize,
+    /// `rs2_sign - rs1_sign` on signed comparisons, 0 otherwise.
+    pub bias: usize,
+    pub borrows: Vec<usize>,
+    /// Limbs of `D`.
+    pub difference: Vec<usize>,
+}
+
+/// Columns checking `dividend = quotient * divisor + remainder`.
+///
+/// The quotient is the product's multiplicand. Negative operands of signed
+/// divisions are also held as magnitudes, with the carries of negating
+/// them, and `gap = |divisor| - |remainder| - 1` shows the remainder is
+/// smaller than the divisor.
+#[derive(Debug, Clone, Default)]
+pub struct DivisionColumns {
+    pub remainder: Vec<usize>,
+    pub remainder_sign: usize,
+    pub remainder_top: usize,
+    /// Set on signed divisions with a negative dividend, divisor or
+    /// remainder.
+    pub negative_dividend: usize,
+    pub negative_divisor: usize,
+    pub negative_remainder: usize,
+    /// Carries of `quotient * divisor + remainder`, over twice the width.
+    pub carries: Vec<usize>,
+    pub remainder_abs: Vec<usize>,
+    pub remainder_abs_carries: Vec<usize>,
+    pub divisor_abs: Vec<usize>,
+    pub divisor_abs_carries: Vec<usize>,
+    pub gap: Vec<usize>,
+    pub gap_borrows: Vec<usize>,
+    pub divisor_zero: usize,
+    /// Inverse of the sum of the limbs of the divisor, or 0.
+    pub divisor_inverse: usize,
+    /// Set on divisions by zero.
+    pub by_zero: usize,
+    /// Set on signed divisions of the most negative value by -1.
+    pub overflow: usize,
+}
+
+/// Columns of shifts, which multiply rs1 by `2^exponent`: `shamt` for
+/// left shifts and `XLEN - shamt` for right shifts.
+///
+/// The exponent is `8 * j + bits`, with `limb_is` selecting limb `j` of
+/// the factor and `power = 2^bits` its value.
+#[derive(Debug, Clone, Default)]
+pub struct ShiftColumns {
+    /// Bits 0 to 4 of rs2, bit 5, and the bits of its low limb above them.
+    /// `shamt` is `amount`, plus bit 5 on RV64 shifts that are not word
+    /// shifts.
+    pub amount: usize,
+    pub bit5: usize,
+    pub high: usize,
+    /// Set when `shamt` is 0.
+    pub zero: usize,
+    /// Inverse of `shamt`, or 0.
+    pub inverse: usize,
+    pub bits: Vec<usize>,
+    pub power: usize,
+    pub limb_is: Vec<usize>,
+}
+
+/// Columns of the RV64 word instructions, which compute on the low words
+/// of their operands, sign- or zero-extended to 64 bits, and write the low
+/// word of the result, sign-extended.
+#[derive(Debug, Clone, Default)]
+pub struct WordColumns {
+    /// Bits 24 to 30 of the low words of rs1 and rs2, below the sign bits
+    /// sign-extension copies to their high limbs.
+    pub rs1_top: usize,
+    pub rs2_top: usize,
+    /// Bit 31 of the result, which rd is sign-extended from, and bits 24 to
+    /// 30.
+    pub result_sign: usize,
+    pub result_top: usize,
+}
+
+/// Columns of the syscalls ECALL makes, and of the state they keep from
+/// step to step.
+///
+/// ECALL compares with the comparison columns what its syscall needs
+/// compared: READ_INPUT `a1` with the input left, WRITE `a2` with `2^31`
+/// and BRK `a0` with `heap_start`.
+#[derive(Debug, Clone, Default)]
+pub struct SyscallColumns {
+    pub is_halt: usize,
+    pub is_read_input: usize,
+    pub is_write_journal: usize,
+    pub is_panic: usize,
+    pub is_write: usize,
+    pub is_brk: usize,
+    pub is_linux_read: usize,
+    pub is_linux_write: usize,
+    pub is_linux_exit: usize,
+    pub is_linux_exit_group: usize,
+    pub is_linux_brk: usize,
+    pub is_linux_close: usize,
+    pub is_linux_fstat: usize,
+    pub is_open: usize,
+    pub is_read: usize,
+    pub is_seek: usize,
+    pub is_close: usize,
+    pub is_linux_lseek: usize,
+    pub is_kv_get: usize,
+    pub is_kv_set: usize,
+    /// The price of the syscall, charged with the ECALL's own.
+    pub price: usize,
+    /// The bytes READ_INPUT, READ or KV_GET copies to memory,
+    /// WRITE_JOURNAL appends, OPEN reads the path from, KV_SET stores or
+    /// `fstat` writes.
+    pub length: usize,
+    /// Set when `length` is nonzero, on WRITE when `a0` is neither stdout
+    /// nor stderr, or on SEEK when `a2` is no `whence`, with the inverse
+    /// showing it.
+    pub nonzero: usize,
+    pub inverse: usize,
+    /// Bytes of the input and of the journal not read or written yet.
+    pub input_remaining: usize,
+    pub journal_remaining: usize,
+    /// 0 while the guest runs, 1 once it halted and 2 once it panicked.
+    pub exit_status: usize,
+    /// The code HALT exits with, in 16-bit halves, the same on every row.
+    pub exit_code_low: usize,
+    pub exit_code_high: usize,
+    /// The program break and the range BRK moves it in, in 16-bit halves.
+    pub brk_low: usize,
+    pub brk_high: usize,
+    pub heap_start_low: usize,
+    pub heap_start_high: usize,
+    pub heap_end_low: usize,
+    pub heap_end_high: usize,
+    /// `heap_end - a0` in halves, with the borrow between them and the one
+    /// out of the top, which is set when `a0` is above `heap_end`.
+    pub gap_low: usize,
+    pub gap_high: usize,
+    pub gap_borrow: usize,
+    pub above: usize,
+}
+
+impl SyscallColumns {
+    /// The syscall selector columns with the syscall each one selects.
+    pub fn flags(&self) -> [(usize, u32); CONSTRAINED_SYSCALLS.len()] {
+        let flags = [
+            self.is_halt,
+            self.is_read_input,
+            self.is_write_journal,
+            self.is_panic,
+            self.is_write,
+            self.is_brk,
+            self.is_linux_read,
+            self.is_linux_write,
+            self.is_linux_exit,
+            self.is_linux_exit_group,
+            self.is_linux_brk,
+            self.is_linux_close,
+            self.is_linux_fstat,
+            self.is_open,
+            self.is_read,
+            self.is_seek,
+            self.is_close,
+            self.is_linux_lseek,
+            self.is_kv_get,
+            self.is_kv_set,
+        ];
+        std::array::from_fn(|i| (flags[i], CONSTRAINED_SYSCALLS[i]))
+    }
+}
+
+/// Columns of memory log rows that READ_INPUT writes input bytes to,
+/// WRITE_JOURNAL reads journal bytes from, OPEN reads a path from, READ
+/// writes file bytes to, KV_GET writes a value to, KV_SET reads one from,
+/// or `fstat` writes its `struct stat` to. A transfer takes consecutive
+/// rows, one per word, from the bytes `start` to `start + count` of each.
+///
+/// The streams are tables keyed by the bytes left from a byte on, plus
+/// `file * 2^24` for paths and file contents and `timestamp * 64` for
+/// values, which is `position` for the first byte of a row.
+#[derive(Debug, Clone, Default)]
+pub struct TransferColumns {
+    /// Set on the first row of a transfer, and on the rows after it.
+    pub first: usize,
+    pub rest: usize,
+    /// Set on rows of input, journal, `struct stat`, path, file, KV_GET
+    /// and KV_SET transfers.
+    pub input: usize,
+    pub journal: usize,
+    pub stat: usize,
+    pub path: usize,
+    pub file: usize,
+    pub kv_get: usize,
+    pub kv_set: usize,
+    /// One-hot selectors of the first byte and of the number of bytes,
+    /// less one.
+    pub start_is: Vec<usize>,
+    pub count_is: Vec<usize>,
+    pub position: usize,
+    /// Bytes of the transfer from the row's first byte on.
+    pub remaining: usize,
+    /// The timestamp after the transfer's last access.
+    pub end: usize,
+}
+
+/// Columns of the descriptor slots and of the syscalls acting on them.
+/// Slot `k` holds descriptor `3 + k`: 0 when it is free, or one more than
+/// the index of the public file open on it, with the file's size and the
+/// descriptor's offset, which stays below `2^29`.
+#[derive(Debug, Clone, Default)]
+pub struct FileColumns {
+    pub slot_file: Vec<usize>,
+    pub slot_size: Vec<usize>,
+    pub slot_offset: Vec<usize>,
+    /// Inverses of `slot_file`, showing the slots below the one OPEN takes
+    /// are in use.
+    pub slot_inverse: Vec<usize>,
+    /// One-hot selector of the slot of `a0`, or of the one OPEN takes,
+    /// and what that slot holds.
+    pub slot_is: Vec<usize>,
+    pub selected_file: usize,
+    pub selected_size: usize,
+    pub selected_offset: usize,
+    /// Set when the selected slot is in use, with the inverse of its file
+    /// showing it.
+    pub descriptor_open: usize,
+    pub descriptor_inverse: usize,
+    /// `(a0 - 3) * ... * (a0 - 2 - FILE_SLOTS)`, and its inverse showing
+    /// `a0` has no slot.
+    pub fd_tested: usize,
+    pub fd_inverse: usize,
+    /// The index of the file OPEN opens or READ reads, and the size OPEN
+    /// finds it has.
+    pub file: usize,
+    pub size: usize,
+    /// What the selected slot holds after the step.
+    pub file_after: usize,
+    pub size_after: usize,
+    pub offset_after: usize,
+    /// Set when CLOSE closes the selected slot.
+    pub closing: usize,
+    /// Set when READ's offset is past the end, with the distance between
+    /// them, less one if so, in a low half and 13 high bits.
+    pub above: usize,
+    pub distance_low: usize,
+    pub distance_high: usize,
+    /// SEEK's one-hot `whence`, and `a2 * (a2 - 1) * (a2 - 2)`.
+    pub whence_is: Vec<usize>,
+    pub whence_tested: usize,
+    /// The offset SEEK moves from, in a low half and 13 high bits.
+    pub base_low: usize,
+    pub base_high: usize,
+    /// Sign of `a1`, and the rest of its high half.
+    pub offset_sign: usize,
+    pub offset_rest: usize,
+    /// `base + a1` in 32 bits: the low half with its carry, and the high
+    /// half as its top bit, the two bits below it and 13 more, with the
+    /// carry out of the top.
+    pub sum_low: usize,
+    pub sum_carry: usize,
+    pub sum_top: usize,
+    pub sum_mid: usize,
+    pub sum_rest: usize,
+    pub wrap: usize,
+    /// Set when the sum is a valid offset, and when SEEK moves to it.
+    pub in_bounds: usize,
+    pub accepted: usize,
+}
+
+/// Columns of the key-value store and of KV_GET and KV_SET.
+#[derive(Debug, Clone, Default)]
+pub struct KvColumns {
+    /// The root the run has reached, which only KV_SET moves.
+    pub root: Vec<usize>,
+    /// Number of keys of the store, 0 without one.
+    pub keys: usize,
+    /// The ECALL's timestamp, in a low half and 8 high bits, so that
+    /// `timestamp * 64` keys the access in the field.
+    pub timestamp_low: usize,
+    pub timestamp_high: usize,
+}
+
+/// Columns of the sponge rows, which hash the public files the run opens
+/// and the Merkle paths of the key-value store it accesses, one round of
+/// the permutation per row. A file's or leaf's blocks are hashed on
+/// consecutive permutations, and each puts the bytes it absorbs on the
+/// file content or value bus, keyed as READ or the value's transfer looks
+/// them up. A path goes on from its leaf a node per permutation.
+#[derive(Debug, Clone, Default)]
+pub struct HashColumns {
+    /// Set on rows holding a round, on those holding the first and last
+    /// round of a permutation, and the round.
+    pub active: usize,
+    pub begin: usize,
+    pub last: usize,
+    pub round: usize,
+    pub constants: Vec<usize>,
+    /// The state before the round, and the cubes of the state plus the
+    /// constants.
+    pub state: Vec<usize>,
+    pub cube: Vec<usize>,
+    /// The first elements of the state after the last round.
+    pub output: Vec<usize>,
+    /// The block the first round absorbs.
+    pub block: Vec<usize>,
+    /// Set on the first round of a file's hash, on the last round of a
+    /// permutation followed by the file's next block, and on the first
+    /// round of that next block.
+    pub start: usize,
+    pub chain: usize,
+    pub chained: usize,
+    /// `file * 2^24`, and the bytes of the file left from the block on.
+    pub key: usize,
+    pub left: usize,
+    /// How often READ copies each byte of the block, and OPEN opens the
+    /// file.
+    pub reads: Vec<usize>,
+    pub opens: usize,
+    /// Set on the permutations of a leaf and of the nodes above it; the
+    /// others hash a file.
+    pub leaf: usize,
+    pub node: usize,
+    /// Set on the last round of a permutation followed by the node above.
+    pub climb: usize,
+    /// On a node, set when the path comes from its right child, whose
+    /// sibling is then in `block`.
+    pub bit: usize,
+    /// The key of the leaf, what is left of it above the node, and the
+    /// number of leaves below it.
+    pub index: usize,
+    pub path: usize,
+    pub width: usize,
+    /// Set on the path KV_SET updates from, and on the one it updates to.
+    pub paired: usize,
+    pub update: usize,
+}
+
+/// A column whose value is below `2^bits`, shown by lookups into a range
+/// table.
+#[derive(Debug, Clone, Copy)]
+pub struct RangeCheck {
+    pub column: usize,
+    pub bits: u32,
+}
+
+/// Indices and names of the trace columns.
+#[derive(Debug, Clone, Default)]
+pub struct TraceLayout {
+    pub names: Vec<String>,
+    pub profile: IsaProfile,
+    pub pc: usize,
+    pub next_pc: usize,
+    /// Length of the instruction, 0 on padding rows.
+    pub size: usize,
+    /// The decoded instruction.
+    pub opcode: usize,
+    /// The integer register the step writes, 0 if none.
+    pub rd: usize,
+    pub rs1: usize,
+    pub rs2: usize,
+    pub imm: usize,
+    pub is_add: usize,
+    pub is_sub: usize,
+    pub is_mul: usize,
+    pub is_mulh: usize,
+    pub is_mulhsu: usize,
+    pub is_mulhu: usize,
+    pub is_div: usize,
+    pub is_divu: usize,
+    pub is_rem: usize,
+    pub is_remu: usize,
+    pub is_slt: usize,
+    pub is_sltu: usize,
+    pub is_and: usize,
+    pub is_or: usize,
+    pub is_xor: usize,
+    pub is_beq: usize,
+    pub is_bne: usize,
+    pub is_blt: usize,
+    pub is_bge: usize,
+    pub is_bltu: usize,
+    pub is_bgeu: usize,
+    pub is_jal: usize,
+    pub is_jalr: usize,
+    pub is_addi: usize,
+    pub is_slti: usize,
+    pub is_sltiu: usize,
+    pub is_xori: usize,
+    pub is_ori: usize,
+    pub is_andi: usize,
+    pub is_sll: usize,
+    pub is_srl: usize,
+    pub is_sra: usize,
+    pub is_slli: usize,
+    pub is_srli: usize,
+    pub is_srai: usize,
+    pub is_lui: usize,
+    pub is_auipc: usize,
+    pub is_lw: usize,
+    pub is_sw: usize,
+    pub is_lb: usize,
+    pub is_lh: usize,
+    pub is_lbu: usize,
+    pub is_lhu: usize,
+    pub is_sb: usize,
+    pub is_sh: usize,
+    pub is_ld: usize,
+    pub is_lwu: usize,
+    pub is_sd: usize,
+    pub is_addiw: usize,
+    pub is_slliw: usize,
+    pub is_srliw: usize,
+    pub is_sraiw: usize,
+    pub is_addw: usize,
+    pub is_subw: usize,
+    pub is_sllw: usize,
+    pub is_srlw: usize,
+    pub is_sraw: usize,
+    pub is_mulw: usize,
+    pub is_divw: usize,
+    pub is_divuw: usize,
+    pub is_remw: usize,
+    pub is_remuw: usize,
+    pub is_ecall: usize,
+    /// Set on the rows after the last step, which execute nothing.
+    pub is_padding: usize,
+    /// Gas used before the step, and the price of its instruction.
+    pub gas: usize,
+    pub gas_price: usize,
+    /// Operands, low limb first. rs2 is the immediate on the immediate
+    /// forms, rs1 the pc on AUIPC and rs2 the loaded value on loads.
+    pub rs1_value: Vec<usize>,
+    pub rs2_value: Vec<usize>,
+    /// What the step computes for rd, written unless rd is x0.
+    pub result: Vec<usize>,
+    /// The value of rd after the step.
+    pub rd_value: Vec<usize>,
+    /// Sign bits of the operands, and their top limbs without it.
+    pub rs1_sign: usize,
+    pub rs2_sign: usize,
+    pub rs1_top: usize,
+    pub rs2_top: usize,
+    /// Carries of ADD, or borrows of SUB, out of each limb.
+    pub carries: Vec<usize>,
+    /// rs1 on multiplications, the quotient on divisions.
+    pub multiplicand: Vec<usize>,
+    pub multiplicand_sign: usize,
+    pub multiplicand_top: usize,
+    /// Limbs of the double-width product of the multiplicand and rs2,
+    /// sign-extended as MULH* and signed division require, and the carries
+    /// out of each limb.
+    pub product: Vec<usize>,
+    pub product_carries: Vec<usize>,
+    /// The second factor of the product: rs2, or a power of two on shifts.
+    pub factor: Vec<usize>,
+    /// Set when the product sign-extends the multiplicand or rs2.
+    pub multiplicand_extended: usize,
+    pub rs2_extended: usize,
+    pub division: DivisionColumns,
+    pub comparison: ComparisonColumns,
+    pub shift: ShiftColumns,
+    pub word: WordColumns,
+    pub syscall: SyscallColumns,
+    /// Set on branches that are taken.
+    pub taken: usize,
+    /// The bit JALR clears from its target.
+    pub target_lsb: usize,
+    /// Top limb of the low word of a return address, or of the pc AUIPC
+    /// reads, which is kept below 2^30 so that the word is unique in the
+    /// field.
+    pub link_top: usize,
+    /// The step's `intermediate_values`, zero-padded.
+    pub intermediate: Vec<usize>,
+    pub load_store: LoadStoreColumns,
+    pub memory: MemoryColumns,
+    pub transfer: TransferColumns,
+    pub sorted_memory: SortedMemoryColumns,
+    pub files: FileColumns,
+    pub kv: KvColumns,
+    pub hash: HashColumns,
+    /// The limbs of every register, low limb first.
+    pub registers: Vec<Vec<usize>>,
+    /// One-hot selectors of the rs1, rs2 and rd register indices.
+    pub rs1_is: Vec<usize>,
+    pub rs2_is: Vec<usize>,
+    pub rd_is: Vec<usize>,
+    pub range_checks: Vec<RangeCheck>,
+}
+
+impl TraceLayout {
+    pub fn new(profile: IsaProfile) -> Self {
+        let mut layout = Self {
+            profile,
+            ..Self::default()
+        };
+        layout.pc = layout.add("pc");
+        layout.next_pc = layout.add("next_pc");
+        layout.size = layout.add("size");
+        layout.opcode = layout.add("opcode");
+        layout.rd = layout.add("rd");
+        layout.rs1 = layout.add("rs1");
+        layout.rs2 = layout.add("rs2");
+        layout.imm = layout.add("imm");
+        layout.is_add = layout.add("is_add");
+        layout.is_sub = layout.add("is_sub");
+        layout.is_mul = layout.add("is_mul");
+        layout.is_mulh = layout.add("is_mulh");
+        layout.is_mulhsu = layout.add("is_mulhsu");
+        layout.is_mulhu = layout.add("is_mulhu");
+        layout.is_div = layout.add("is_div");
+        layout.is_divu = layout.add("is_divu");
+        layout.is_rem = layout.add("is_rem");
+        layout.is_remu = layout.add("is_remu");
+        layout.is_slt = layout.add("is_slt");
+        layout.is_sltu = layout.add("is_sltu");
+        layout.is_and = layout.add("is_and");
+        layout.is_or = layout.add("is_or");
+        layout.is_xor = layout.add("is_xor");
+        layout.is_beq = layout.add("is_beq");
+        layout.is_bne = layout.add("is_bne");
+        layout.is_blt = layout.add("is_blt");
+        layout.is_bge = layout.add("is_bge");
+        layout.is_bltu = layout.add("is_bltu");
+        layout.is_bgeu = layout.add("is_bgeu");
+        layout.is_jal = layout.add("is_jal");
+        layout.is_jalr = layout.add("is_jalr");
+        layout.is_addi = layout.add("is_addi");
+        layout.is_slti = layout.add("is_slti");
+        layout.is_sltiu = layout.add("is_sltiu");
+        layout.is_xori = layout.add("is_xori");
+        layout.is_ori = layout.add("is_ori");
+        layout.is_andi = layout.add("is_andi");
+        layout.is_sll = layout.add("is_sll");
+        layout.is_srl = layout.add("is_srl");
+        layout.is_sra = layout.add("is_sra");
+        layout.is_slli = layout.add("is_slli");
+        layout.is_srli = layout.add("is_srli");
+        layout.is_srai = layout.add("is_srai");
+        layout.is_lui = layout.add("is_lui");
+        layout.is_auipc = layout.add("is_auipc");
+        layout.is_lw = layout.add("is_lw");
+        layout.is_sw = layout.add("is_sw");
+        layout.is_lb = layout.add("is_lb");
+        layout.is_lh = layout.add("is_lh");
+        layout.is_lbu = layout.add("is_lbu");
+        layout.is_lhu = layout.add("is_lhu");
+        layout.is_sb = layout.add("is_sb");
+        layout.is_sh = layout.add("is_sh");
+        layout.is_ld = layout.add("is_ld");
+        layout.is_lwu = layout.add("is_lwu");
+        layout.is_sd = layout.add("is_sd");
+        layout.is_addiw = layout.add("is_addiw");
+        layout.is_slliw = layout.add("is_slliw");
+        layout.is_srliw = layout.add("is_srliw");
+        layout.is_sraiw = layout.add("is_sraiw");
+        layout.is_addw = layout.add("is_addw");
+        layout.is_subw = layout.add("is_subw");
+        layout.is_sllw = layout.add("is_sllw");
+        layout.is_srlw = layout.add("is_srlw");
+        layout.is_sraw = layout.add("is_sraw");
+        layout.is_mulw = layout.add("is_mulw");
+        layout.is_divw = layout.add("is_divw");
+        layout.is_divuw = layout.add("is_divuw");
+        layout.is_remw = layout.add("is_remw");
+        layout.is_remuw = layout.add("is_remuw");
+        layout.is_ecall = layout.add("is_ecall");
+        layout.is_padding = layout.add("is_padding");
+        layout.gas = layout.add("gas");
+        layout.gas_price = layout.add("gas_price");
+
+        // Columns with the number of bits they are checked to fit in
+        let mut ranged = Vec::new();
+        let limbs = (profile.xlen() / LIMB_BITS) as usize;
+        layout.rs1_value = layout.add_many("rs1_value", limbs);
+        layout.rs2_value = layout.add_many("rs2_value", limbs);
+        layout.result = layout.add_many("result", limbs);
+        layout.rd_value = layout.add_many("rd_value", limbs);
+        for &column in [&layout.rs1_value, &layout.rs2_value, &layout.result]
+            .into_iter()
+            .flatten()
+        {
+            ranged.push((column, LIMB_BITS));
+        }
+        layout.rs1_sign = layout.add("rs1_sign");
+        layout.rs2_sign = layout.add("rs2_sign");
+        layout.rs1_top = layout.add("rs1_top");
+        layout.rs2_top = layout.add("rs2_top");
+        ranged.push((layout.rs1_top, LIMB_BITS - 1));
+        ranged.push((layout.rs2_top, LIMB_BITS - 1));
+        layout.carries = layout.add_many("carry", limbs);
+        layout.multiplicand = layout.add_many("multiplicand", limbs);
+        layout.multiplicand_sign = layout.add("multiplicand_sign");
+        layout.multiplicand_top = layout.add("multiplicand_top");
+        for &column in &layout.multiplicand {
+            ranged.push((column, LIMB_BITS));
+        }
+        ranged.push((layout.multiplicand_top, LIMB_BITS - 1));
+
+        // A limb of the product sums at most `2 * limbs` products of two
+        // limbs, so its carry is below `2 * limbs * 2^LIMB_BITS`
+        layout.product = layout.add_many("product", 2 * limbs);
+        layout.product_carries = layout.add_many("product_carry", 2 * limbs);
+        let carry_bits = LIMB_BITS + (2 * limbs).trailing_zeros();
+        for i in 0..2 * limbs {
+            ranged.push((layout.product[i], LIMB_BITS));
+            ranged.push((layout.product_carries[i], carry_bits));
+        }
+        layout.factor = layout.add_many("factor", limbs);
+        layout.multiplicand_extended = layout.add("multiplicand_extended");
+        layout.rs2_extended = layout.add("rs2_extended");
+
+        layout.division = DivisionColumns {
+            remainder: layout.add_many("div_remainder", limbs),
+            remainder_sign: layout.add("div_remainder_sign"),
+            remainder_top: layout.add("div_remainder_top"),
+            negative_dividend: layout.add("div_negative_dividend"),
+            negative_divisor: layout.add("div_negative_divisor"),
+            negative_remainder: layout.add("div_negative_remainder"),
+            carries: layout.add_many("div_carry", 2 * limbs),
+            remainder_abs: layout.add_many("div_remainder_abs", limbs),
+            remainder_abs_carries: layout.add_many("div_remainder_abs_carry", limbs),
+            divisor_abs: layout.add_many("div_divisor_abs", limbs),
+            divisor_abs_carries: layout.add_many("div_divisor_abs_carry", limbs),
+            gap: layout.add_many("div_gap", limbs),
+            gap_borrows: layout.add_many("div_gap_borrow", limbs),
+            divisor_zero: layout.add("div_divisor_zero"),
+            divisor_inverse: layout.add("div_divisor_inverse"),
+            by_zero: layout.add("div_by_zero"),
+            overflow: layout.add("div_overflow"),
+        };
+        let d = &layout.division;
+        for &column in [&d.remainder, &d.remainder_abs, &d.divisor_abs, &d.gap]
+            .into_iter()
+            .flatten()
+        {
+            ranged.push((column, LIMB_BITS));
+        }
+        ranged.push((d.remainder_top, LIMB_BITS - 1));
+
+        layout.comparison = ComparisonColumns {
+            less: layout.add("cmp_less"),
+            equal: layout.add("cmp_equal"),
+            inverse: layout.add("cmp_inverse"),
+            bias: layout.add("cmp_bias"),
+            borrows: layout.add_many("cmp_borrow", limbs - 1),
+            difference: layout.add_many("cmp_difference", limbs),
+        };
+        for &column in &layout.comparison.difference {
+            ranged.push((column, LIMB_BITS));
+        }
+
+        layout.shift = ShiftColumns {
+            amount: layout.add("shift_amount"),
+            bit5: layout.add("shift_bit5"),
+            high: layout.add("shift_high"),
+            zero: layout.add("shift_zero"),
+            inverse: layout.add("shift_inverse"),
+            bits: layout.add_many("shift_bit", 3),
+            power: layout.add("shift_power"),
+            limb_is: layout.add_many("shift_limb_is", limbs),
+        };
+        ranged.push((layout.shift.amount, 5));
+        ranged.push((layout.shift.high, LIMB_BITS - 6));
+        layout.word = WordColumns {
+            rs1_top: layout.add("word_rs1_top"),
+            rs2_top: layout.add("word_rs2_top"),
+            result_sign: layout.add("word_result_sign"),
+            result_top: layout.add("word_result_top"),
+        };
+        // Only RV64 has word instructions
+        if profile.xlen() == 64 {
+            let w = &layout.word;
+            for column in [w.rs1_top, w.rs2_top, w.result_top] {
+                ranged.push((column, LIMB_BITS - 1));
+            }
+        }
+        layout.syscall = SyscallColumns {
+            is_halt: layout.add("sys_is_halt"),
+            is_read_input: layout.add("sys_is_read_input"),
+            is_write_journal: layout.add("sys_is_write_journal"),
+            is_panic: layout.add("sys_is_panic"),
+            is_write: layout.add("sys_is_write"),
+            is_brk: layout.add("sys_is_brk"),
+            is_linux_read: layout.add("sys_is_linux_read"),
+            is_linux_write: layout.add("sys_is_linux_write"),
+            is_linux_exit: layout.add("sys_is_linux_exit"),
+            is_linux_exit_group: layout.add("sys_is_linux_exit_group"),
+            is_linux_brk: layout.add("sys_is_linux_brk"),
+            is_linux_close: layout.add("sys_is_linux_close"),
+            is_linux_fstat: layout.add("sys_is_linux_fstat"),
+            is_open: layout.add("sys_is_open"),
+            is_read: layout.add("sys_is_read"),
+            is_seek: layout.add("sys_is_seek"),
+            is_close: layout.add("sys_is_close"),
+            is_linux_lseek: layout.add("sys_is_linux_lseek"),
+            is_kv_get: layout.add("sys_is_kv_get"),
+            is_kv_set: layout.add("sys_is_kv_set"),
+            price: layout.add("sys_price"),
+            length: layout.add("sys_length"),
+            nonzero: layout.add("sys_nonzero"),
+            inverse: layout.add("sys_inverse"),
+            input_remaining: layout.add("sys_input_remaining"),
+            journal_remaining: layout.add("sys_journal_remaining"),
+            exit_status: layout.add("sys_exit_status"),
+            exit_code_low: layout.add("sys_exit_code_low"),
+            exit_code_high: layout.add("sys_exit_code_high"),
+            brk_low: layout.add("sys_brk_low"),
+            brk_high: layout.add("sys_brk_high"),
+            heap_start_low: layout.add("sys_heap_start_low"),
+            heap_start_high: layout.add("sys_heap_start_high"),
+            heap_end_low: layout.add("sys_heap_end_low"),
+            heap_end_high: layout.add("sys_heap_end_high"),
+            gap_low: layout.add("sys_gap_low"),
+            gap_high: layout.add("sys_gap_high"),
+            gap_borrow: layout.add("sys_gap_borrow"),
+            above: layout.add("sys_above"),
+        };
+        ranged.push((layout.syscall.gap_low, 16));
+        ranged.push((layout.syscall.gap_high, 16));
+        layout.files = FileColumns {
+            slot_file: layout.add_many("file_slot_file", FILE_SLOTS),
+            slot_size: layout.add_many("file_slot_size", FILE_SLOTS),
+            slot_offset: layout.add_many("file_slot_offset", FILE_SLOTS),
+            slot_inverse: layout.add_many("file_slot_inverse", FILE_SLOTS),
+            slot_is: layout.add_many("file_slot_is", FILE_SLOTS),
+            selected_file: layout.add("file_selected_file"),
+            selected_size: layout.add("file_selected_size"),
+            selected_offset: layout.add("file_selected_offset"),
+            descriptor_open: layout.add("file_descriptor_open"),
+            descriptor_inverse: layout.add("file_descriptor_inverse"),
+            fd_tested: layout.add("file_fd_tested"),
+            fd_inverse: layout.add("file_fd_inverse"),
+            file: layout.add("file_file"),
+            size: layout.add("file_size"),
+            file_after: layout.add("file_file_after"),
+            size_after: layout.add("file_size_after"),
+            offset_after: layout.add("file_offset_after"),
+            closing: layout.add("file_closing"),
+            above: layout.add("file_above"),
+            distance_low: layout.add("file_distance_low"),
+            distance_high: layout.add("file_distance_high"),
+            whence_is: layout.add_many("file_whence_is", 3),
+            whence_tested: layout.add("file_whence_tested"),
+            base_low: layout.add("file_base_low"),
+            base_high: layout.add("file_base_high"),
+            offset_sign: layout.add("file_offset_sign"),
+            offset_rest: layout.add("file_offset_rest"),
+            sum_low: layout.add("file_sum_low"),
+            sum_carry: layout.add("file_sum_carry"),
+            sum_top: layout.add("file_sum_top"),
+            sum_mid: layout.add("file_sum_mid"),
+            sum_rest: layout.add("file_sum_rest"),
+            wrap: layout.add("file_wrap"),
+            in_bounds: layout.add("file_in_bounds"),
+            accepted: layout.add("file_accepted"),
+        };
+        let f = &layout.files;
+        for (column, bits) in [
+            (f.distance_low, 16),
+            (f.distance_high, 13),
+            (f.base_low, 16),
+            (f.base_high, 13),
+            (f.offset_rest, 15),
+            (f.sum_low, 16),
+            (f.sum_mid, 2),
+            (f.sum_rest, 13),
+        ] {
+            ranged.push((column, bits));
+        }
+        layout.kv = KvColumns {
+            root: layout.add_many("kv_root", DIGEST_SIZE),
+            keys: layout.add("kv_keys"),
+            timestamp_low: layout.add("kv_timestamp_low"),
+            timestamp_high: layout.add("kv_timestamp_high"),
+        };
+        ranged.push((layout.kv.timestamp_low, 16));
+        ranged.push((layout.kv.timestamp_high, 8));
+        layout.hash = HashColumns {
+            active: layout.add("hash_active"),
+            begin: layout.add("hash_begin"),
+            last: layout.add("hash_last"),
+            round: layout.add("hash_round"),
+            constants: layout.add_many("hash_constant", SPONGE_WIDTH),
+            state: layout.add_many("hash_state", SPONGE_WIDTH),
+            cube: layout.add_many("hash_cube", SPONGE_WIDTH),
+            output: layout.add_many("hash_output", DIGEST_SIZE),
+            block: layout.add_many("hash_block", SPONGE_RATE),
+            start: layout.add("hash_start"),
+            chain: layout.add("hash_chain"),
+            chained: layout.add("hash_chained"),
+            key: layout.add("hash_key"),
+            left: layout.add("hash_left"),
+            reads: layout.add_many("hash_reads", SPONGE_RATE),
+            opens: layout.add("hash_opens"),
+            leaf: layout.add("hash_leaf"),
+            node: layout.add("hash_node"),
+            climb: layout.add("hash_climb"),
+            bit: layout.add("hash_bit"),
+            index: layout.add("hash_index"),
+            path: layout.add("hash_path"),
+            width: layout.add("hash_width"),
+            paired: layout.add("hash_paired"),
+            update: layout.add("hash_update"),
+        };
+        layout.taken = layout.add("taken");
+        layout.target_lsb = layout.add("target_lsb");
+        layout.link_top = layout.add("link_top");
+        ranged.push((layout.link_top, LIMB_BITS - 2));
+        layout.intermediate = layout.add_many("intermediate", INTERMEDIATE_COLUMNS);
+        layout.load_store = LoadStoreColumns {
+            word_low: layout.add("ls_word_low"),
+            word_odd: layout.add("ls_word_odd"),
+            word_high: layout.add("ls_word_high"),
+            carry: layout.add("ls_carry"),
+            wrap: layout.add("ls_wrap"),
+            byte_is: layout.add_many("ls_byte_is", 4),
+            value_before: layout.add_many("ls_value_before", 4),
+            value_after: layout.add_many("ls_value_after", 4),
+            high_before: layout.add_many("ls_high_before", 4),
+            high_after: layout.add_many("ls_high_after", 4),
+            value_top: layout.add("ls_value_top"),
+            timestamp: layout.add("ls_timestamp"),
+        };
+        let ls = &layout.load_store;
+        ranged.push((ls.word_low, 13));
+        ranged.push((ls.word_high, 16));
+        ranged.push((ls.value_top, LIMB_BITS - 1));
+        layout.memory = MemoryColumns {
+            used: layout.add("mem_used"),
+            word: layout.add("mem_word"),
+            value_before: layout.add_many("mem_value_before", 4),
+            value_after: layout.add_many("mem_value_after", 4),
+            is_write: layout.add("mem_is_write"),
+            timestamp: layout.add("mem_timestamp"),
+        };
+        layout.transfer = TransferColumns {
+            first: layout.add("xfer_first"),
+            rest: layout.add("xfer_rest"),
+            input: layout.add("xfer_input"),
+            journal: layout.add("xfer_journal"),
+            stat: layout.add("xfer_stat"),
+            path: layout.add("xfer_path"),
+            file: layout.add("xfer_file"),
+            kv_get: layout.add("xfer_kv_get"),
+            kv_set: layout.add("xfer_kv_set"),
+            start_is: layout.add_many("xfer_start_is", 4),
+            count_is: layout.add_many("xfer_count_is", 4),
+            position: layout.add("xfer_position"),
+            remaining: layout.add("xfer_remaining"),
+            end: layout.add("xfer_end"),
+        };
+        layout.sorted_memory = SortedMemoryColumns {
+            used: layout.add("sorted_used"),
+            word: layout.add("sorted_word"),
+            timestamp: layout.add("sorted_timestamp"),
+            value_before: layout.add_many("sorted_value_before", 4),
+            value_after: layout.add_many("sorted_value_after", 4),
+            first: layout.add("sorted_first"),
+            same_word: layout.add("sorted_same_word"),
+            new_word: layout.add("sorted_new_word"),
+            gap_high: layout.add("sorted_gap_high"),
+        };
+        let s = &layout.sorted_memory;
+        for &column in s.value_before.iter().chain(&s.value_after) {
+            ranged.push((column, LIMB_BITS));
+        }
+        ranged.push((s.gap_high, 14));
+
+        let num_registers = profile.num_registers();
+        layout.registers = (0..num_registers)
+            .map(|i| layout.add_many(&format!("reg_{i}"), limbs))
+            .collect();
+        layout.rs1_is = layout.add_many("rs1_is", num_registers);
+        layout.rs2_is = layout.add_many("rs2_is", num_registers);
+        layout.rd_is = layout.add_many("rd_is", num_registers);
+
+        layout.range_checks = ranged
+            .into_iter()
+            .map(|(column, bits)| RangeCheck { column, bits })
+            .collect();
+        layout
+    }
+
+    fn add(&mut self, name: &str) -> usize {
+        self.names.push(name.to_string());
+        self.names.len() - 1
+    }
+
+    fn add_many(&mut self, prefix: &str, count: usize) -> Vec<usize> {
+        (0..count)
+            .map(|i| self.add(&format!("{prefix}_{i}")))
+            .collect()
+    }
+
+    pub fn width(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|column| column == name)
+    }
+
+    /// The opcode selector columns with the opcode each one selects.
+    pub fn opcode_flags(&self) -> [(usize, Opcode); CONSTRAINED_OPCODES.len()] {
+        let flags = [
+            self.is_add,
+            self.is_sub,
+            self.is_mul,
+            self.is_mulh,
+            self.is_mulhsu,
+            self.is_mulhu,
+            self.is_div,
+            self.is_divu,
+            self.is_rem,
+            self.is_remu,
+            self.is_slt,
+            self.is_sltu,
+            self.is_and,
+            self.is_or,
+            self.is_xor,
+            self.is_beq,
+            self.is_bne,
+            self.is_blt,
+            self.is_bge,
+            self.is_bltu,
+            self.is_bgeu,
+            self.is_jal,
+            self.is_jalr,
+            self.is_addi,
+            self.is_slti,
+            self.is_sltiu,
+            self.is_xori,
+            self.is_ori,
+            self.is_andi,
+            self.is_sll,
+            self.is_srl,
+            self.is_sra,
+            self.is_slli,
+            self.is_srli,
+            self.is_srai,
+            self.is_lui,
+            self.is_auipc,
+            self.is_lw,
+            self.is_sw,
+            self.is_lb,
+            self.is_lh,
+            self.is_lbu,
+            self.is_lhu,
+            self.is_sb,
+            self.is_sh,
+            self.is_ld,
+            self.is_lwu,
+            self.is_sd,
+            self.is_addiw,
+            self.is_slliw,
+            self.is_srliw,
+            self.is_sraiw,
+            self.is_addw,
+            self.is_subw,
+            self.is_sllw,
+            self.is_srlw,
+            self.is_sraw,
+            self.is_mulw,
+            self.is_divw,
+            self.is_divuw,
+            self.is_remw,
+            self.is_remuw,
+            self.is_ecall,
+        ];
+        std::array::from_fn(|i| (flags[i], CONSTRAINED_OPCODES[i]))
+    }
+
+    /// The columns of the pc, the decoded instruction and its gas price, as
+    /// the program table holds them.
+    pub fn instruction_columns(&self) -> [usize; 8] {
+        [
+            self.pc,
+            self.opcode,
+            self.rd,
+            self.rs1,
+            self.rs2,
+            self.imm,
+            self.size,
+            self.gas_price,
+        ]
+    }
+
+    /// The values of [`Self::instruction_columns`] on a row executing
+    /// `instruction` at `pc` for `gas_price`.
+    pub fn instruction_entry(pc: u32, instruction: &Instruction, gas_price: u32) -> Vec<BabyBear> {
+        let rd = instruction.int_destination().unwrap_or(0);
+        vec![
+            pc.into(),
+            (instruction.opcode as u32).into(),
+            (rd as u32).into(),
+            (instruction.rs1 as u32).into(),
+            (instruction.rs2 as u32).into(),
+            signed(instruction.imm as i64),
+            (instruction.size as u32).into(),
+            gas_price.into(),
+        ]
+    }
+
+    /// Splits a register value into its limbs.
+    pub fn limbs(&self, value: u64) -> Vec<u64> {
+        (0..self.rs1_value.len())
+            .map(|limb| value >> (LIMB_BITS as usize * limb) & (LIMB - 1))
+            .collect()
+    }
+}
+
+/// A trace laid out by a [`TraceLayout`], padded to a power of two.
+#[derive(Debug, Clone)]
+pub struct TraceTable {
+    pub layout: TraceLayout,
+    pub rows: Vec<Vec<BabyBear>>,
+}
+
+/// Negative values are mapped to `p - |value|`.
+fn signed(value: i64) -> BabyBear {
+    if value < 0 {
+        -BabyBear::from_u64(value.unsigned_abs())
+    } else {
+        BabyBear::from_u64(value as u64)
+    }
+}
+
+fn join_limbs(low: &[u32; 32], high: &[u32; 32]) -> [u64; 32] {
+    std::array::from_fn(|i| low[i] as u64 | (high[i] as u64) << 32)
+}
+
+/// What a load reads from the doubleword `value` at byte `offset`,
+/// sign-extended to 64 bits by the signed loads.
+fn loaded_value(opcode: Opcode, value: u64, offset: u32) -> u64 {
+    let shifted = value >> (8 * offset);
+    match opcode {
+        Opcode::Lb => shifted as i8 as i64 as u64,
+        Opcode::Lbu => shifted as u8 as u64,
+        Opcode::Lh => shifted as i16 as i64 as u64,
+        Opcode::Lhu => shifted as u16 as u64,
+        Opcode::Lwu => value as u32 as u64,
+        Opcode::Ld => value,
+        _ => value as i32 as i64 as u64,
+    }
+}
+
+/// The instruction a word instruction computes as, on the extended low
+/// words of its operands; other opcodes compute as themselves.
+fn base_opcode(opcode: Opcode) -> Opcode {
+    use Opcode::*;
+    match opcode {
+        Addw | Addiw => Add,
+        Subw => Sub,
+        Sllw | Slliw => Sll,
+        Srlw | Srliw => Srl,
+        Sraw | Sraiw => Sra,
+        Mulw => Mul,
+        Divw => Div,
+        Divuw => Divu,
+        Remw => Rem,
+        Remuw => Remu,
+        op => op,
+    }
+}
+
+/// Splits a memory word into its byte limbs.
+fn word_limbs(value: u32) -> [u64; 4] {
+    value.to_le_bytes().map(u64::from)
+}
+
+fn set_limbs(row: &mut [BabyBear], columns: &[usize], limbs: &[u64]) {
+    for (&column, &limb) in columns.iter().zip(limbs) {
+        row[column] = BabyBear::from_u64(limb);
+    }
+}
+
+/// The exit status and code the syscall columns end with for a run that
+/// stopped with `status`. A run that stopped before the guest did ends as
+/// it started, whether it ran out of steps or of gas.
+pub fn exit_values(status: ExitStatus) -> (u32, u32) {
+    match status {
+        ExitStatus::Halted(code) => (1, code),
+        ExitStatus::Panicked => (2, 0),
+        ExitStatus::StepLimit | ExitStatus::OutOfGas => (0, 0),
+    }
+}
+
+const UNCONSTRAINED_SYSCALL: &str = "Syscall the AIR does not constrain";
+
+/// What the syscalls keep from step to step, as the syscall columns hold
+/// it.
+#[derive(Debug, Clone, Copy)]
+struct SyscallState {
+    input_remaining: u32,
+    journal_remaining: u32,
+    exit_status: u32,
+    exit_code: u32,
+    brk: u32,
+    heap_start: u32,
+    heap_end: u32,
+    slots: [Option<Slot>; FILE_SLOTS],
+    kv_root: Digest,
+    kv_keys: u32,
+}
+
+/// A public file open on a descriptor slot.
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    file: u32,
+    size: u32,
+    offset: u32,
+}
+
+impl SyscallState {
+    fn assign(&self, l: &TraceLayout, row: &mut [BabyBear]) {
+        let sys = &l.syscall;
+        row[sys.input_remaining] = self.input_remaining.into();
+        row[sys.journal_remaining] = self.journal_remaining.into();
+        row[sys.exit_status] = self.exit_status.into();
+        for (value, low, high) in [
+            (self.exit_code, sys.exit_code_low, sys.exit_code_high),
+            (self.brk, sys.brk_low, sys.brk_high),
+            (self.heap_start, sys.heap_start_low, sys.heap_start_high),
+            (self.heap_end, sys.heap_end_low, sys.heap_end_high),
+        ] {
+            row[low] = (value & 0xffff).into();
+            row[high] = (value >> 16).into();
+        }
+        let f = &l.files;
+        for (k, slot) in self.slots.iter().enumerate() {
+            let [file, size, offset] = Self::slot_values(slot);
+            row[f.slot_file[k]] = file;
+            row[f.slot_size[k]] = size;
+            row[f.slot_offset[k]] = offset;
+            row[f.slot_inverse[k]] = file.inverse().unwrap_or(BabyBear::ZERO);
+        }
+        for (&column, &element) in l.kv.root.iter().zip(&self.kv_root) {
+            row[column] = element;
+        }
+        row[l.kv.keys] = self.kv_keys.into();
+    }
+
+    /// The file, size and offset columns of `slot`.
+    fn slot_values(slot: &Option<Slot>) -> [BabyBear; 3] {
+        slot.map_or([BabyBear::ZERO; 3], |slot| {
+            [slot.file + 1, slot.size, slot.offset].map(BabyBear::from)
+        })
+    }
+}
+
+/// The slot of descriptor `fd`, if it has one.
+fn slot_of(fd: u32) -> Option<usize> {
+    let slot = fd.checked_sub(FIRST_FILE_FD)? as usize;
+    (slot < FILE_SLOTS).then_some(slot)
+}
+
+/// Most public files a proven run can open, so that `file * 2^24` stays in
+/// the field.
+const MAX_FILES: usize = 64;
+
+/// The public files OPEN finds by path, by index, with how often the trace
+/// opens each and READ copies each of its bytes.
+struct PublicFiles<'a> {
+    paths: Vec<&'a str>,
+    contents: Vec<&'a [u8]>,
+    opens: Vec<u32>,
+    reads: Vec<Vec<u32>>,
+}
+
+/// Most timestamps a KV_GET or KV_SET can have, so that `timestamp * 64`
+/// stays in the field.
+const MAX_KV_TIMESTAMP: u32 = 1 << 24;
+
+/// The key-value store as the trace moves it, and the Merkle path of every
+/// access KV_GET and KV_SET make, in order.
+struct KvAccesses {
+    store: Option<KvStore>,
+    paths: Vec<KvPath>,
+}
+
+/// The path of an access from the leaf of `index` to the root, keyed by
+/// `timestamp * 64`, with the value it reads or the one KV_SET replaces
+/// and the one it stores.
+struct KvPath {
+    key: u32,
+    index: u32,
+    value: KvValue,
+    stored: Option<KvValue>,
+    siblings: Vec<Digest>,
+}
+
+impl KvPath {
+    /// The value the access copies to or from guest memory.
+    fn transferred(&self) -> &KvValue {
+        self.stored.as_ref().unwrap_or(&self.value)
+    }
+}
+
+/// The bytes a transfer copies to or from guest memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stream {
+    Input,
+    Journal,
+    Stat,
+    /// The path of a public file, and its contents.
+    Path(u32),
+    File(u32),
+    /// The value KV_GET reads and the one KV_SET stores, at a timestamp.
+    KvGet(u32),
+    KvSet(u32),
+}
+
+impl Stream {
+    /// Whether the transfer writes guest memory rather than reads it.
+    fn writes(self) -> bool {
+        !matches!(self, Self::Journal | Self::Path(_) | Self::KvSet(_))
+    }
+
+    /// What the stream's keys add to the bytes left.
+    fn key(self) -> u32 {
+        match self {
+            Self::Path(file) | Self::File(file) => file << 24,
+            Self::KvGet(timestamp) | Self::KvSet(timestamp) => timestamp << 6,
+            _ => 0,
+        }
+    }
+}
+
+/// Bytes READ_INPUT copies from the input to `addr`, WRITE_JOURNAL appends
+/// to the journal from it, OPEN reads a path from it, READ copies from a
+/// file to it, KV_GET and KV_SET copy a value to or from it, or `fstat`
+/// writes to it, with the bytes of the stream left before the first.
+#[derive(Debug, Clone, Copy)]
+struct Transfer {
+    stream: Stream,
+    addr: u32,
+    length: u32,
+    position: u32,
+}
+
+/// A memory log row of a transfer.
+#[derive(Debug, Clone, Copy)]
+struct TransferRow {
+    first: bool,
+    stream: Stream,
+    start: u32,
+    count: u32,
+    position: u32,
+    remaining: u32,
+    end: u32,
+}
+
+/// What the syscall of an ECALL compares and returns, as the built-in
+/// syscall computes it.
+#[derive(Debug, Clone, Copy)]
+struct SyscallStep {
+    /// Index of the syscall in [`CONSTRAINED_SYSCALLS`].
+    kind: usize,
+    operands: (u64, u64),
+    result: u32,
+    length: u32,
+    /// The value the `nonzero` column tests.
+    tested: BabyBear,
+    transfer: Option<Transfer>,
+    /// The descriptor slot the syscall acts on, and the file OPEN opens or
+    /// READ reads, with its size.
+    slot: Option<usize>,
+    file: u32,
+    size: u32,
+}
+
+/// The `len` bytes from `addr` on that `accesses` read.
+fn read_bytes(accesses: &[MemoryAccess], addr: u32, len: u32) -> Option<Vec<u8>> {
+    (0..len)
+        .map(|i| {
+            let byte = addr.checked_add(i)?;
+            let access = accesses
+                .iter()
+                .find(|access| !access.is_write && access.addr == byte & !3)?;
+            Some(access.value_before.to_le_bytes()[byte as usize % 4])
+        })
+        .collect()
+}
+
+impl SyscallStep {
+    /// Runs the syscall the registers select on `state`, with the memory
+    /// the step read in `accesses`, at `timestamp`.
+    fn execute(
+        l: &TraceLayout,
+        registers: &[u64; 32],
+        accesses: &[MemoryAccess],
+        timestamp: u32,
+        state: &mut SyscallState,
+        files: &mut PublicFiles,
+        kv: &mut KvAccesses,
+    ) -> Result<Self, &'static str> {
+        let number = registers[syscall_register(l.profile.base)] as u32;
+        let [a0, a1, a2] = [10, 11, 12].map(|register| registers[register] as u32);
+        let kind = CONSTRAINED_SYSCALLS
+            .iter()
+            .position(|&syscall| syscall == number)
+            .ok_or(UNCONSTRAINED_SYSCALL)?;
+        let mut step = Self {
+            kind,
+            operands: (0, 0),
+            result: 0,
+            length: 0,
+            tested: BabyBear::ZERO,
+            transfer: None,
+            slot: None,
+            file: 0,
+            size: 0,
+        };
+        let input = |step: &mut Self, state: &mut SyscallState, addr: u32, limit: u32| {
+            let length = limit.min(state.input_remaining);
+            step.operands = (limit as u64, state.input_remaining as u64);
+            step.result = length;
+            step.length = length;
+            step.transfer = Some(Transfer {
+                stream: Stream::Input,
+                addr,
+                length,
+                position: state.input_remaining,
+            });
+            state.input_remaining -= length;
+        };
+        // close and fstat tell the standard streams, below 3, from
+        // descriptors
+        let std_stream = a0 <= fd::STDERR;
+        let ebadf = errno::to_result(errno::EBADF);
+        let einval = errno::to_result(errno::EINVAL);
+        let slot = slot_of(a0);
+        let open = slot.and_then(|slot| state.slots[slot]);
+        match number {
+            nr::HALT | linux_nr::EXIT | linux_nr::EXIT_GROUP => {
+                step.result = a0;
+                state.exit_status = 1;
+            }
+            nr::READ_INPUT => input(&mut step, state, a0, a1),
+            linux_nr::READ if a0 == 0 => input(&mut step, state, a1, a2),
+            linux_nr::READ => return Err(UNCONSTRAINED_SYSCALL),
+            nr::WRITE_JOURNAL => {
+                if a1 > state.journal_remaining {
+                    return Err(UNCONSTRAINED_SYSCALL);
+                }
+                step.length = a1;
+                step.transfer = Some(Transfer {
+                    stream: Stream::Journal,
+                    addr: a0,
+                    length: a1,
+                    position: state.journal_remaining,
+                });
+                state.journal_remaining -= a1;
+            }
+            nr::PANIC => state.exit_status = 2,
+            nr::WRITE | linux_nr::WRITE => {
+                // Zero exactly when the low byte is 1 or 2 and the others 0
+                let [b0, b1, b2, b3] = a0.to_le_bytes().map(i64::from);
+                let tested = (b0 - 1) * (b0 - 2) + b1 + b2 + b3;
+                step.tested = BabyBear::from_u64(tested as u64);
+                step.operands = (a2 as u64, 1 << 31);
+                step.result = if a0 != fd::STDOUT && a0 != fd::STDERR {
+                    ebadf
+                } else if a2 > i32::MAX as u32 {
+                    einval
+                } else {
+                    a2
+                };
+            }
+            linux_nr::FSTAT => {
+                step.operands = (a0 as u64, 3);
+                step.slot = slot;
+                // The struct stat of a file is not constrained
+                if open.is_some() {
+                    return Err(UNCONSTRAINED_SYSCALL);
+                }
+                step.result = if std_stream { 0 } else { ebadf };
+                let stat = LinuxSyscalls::std_stat().len() as u32;
+                step.length = if std_stream { stat } else { 0 };
+                step.transfer = Some(Transfer {
+                    stream: Stream::Stat,
+                    addr: a1,
+                    length: step.length,
+                    position: stat,
+                });
+            }
+            nr::OPEN => {
+                // Only public files, on a free slot, can be opened
+                let path = read_bytes(accesses, a0, a1).ok_or(UNCONSTRAINED_SYSCALL)?;
+                let file = files
+                    .paths
+                    .iter()
+                    .position(|path_of| path_of.as_bytes() == path)
+                    .ok_or(UNCONSTRAINED_SYSCALL)?;
+                let slot = state
+                    .slots
+                    .iter()
+                    .position(Option::is_none)
+                    .ok_or(UNCONSTRAINED_SYSCALL)?;
+                let size = files.contents[file].len();
+                if file >= MAX_FILES || size + SPONGE_RATE >= 1 << 24 || a1 >= 1 << 24 {
+                    return Err("File too large for the trace");
+                }
+                let (file, size) = (file as u32, size as u32);
+                files.opens[file as usize] += 1;
+                state.slots[slot] = Some(Slot {
+                    file,
+                    size,
+                    offset: 0,
+                });
+                step.slot = Some(slot);
+                step.file = file;
+                step.size = size;
+                step.result = FIRST_FILE_FD + slot as u32;
+                step.length = a1;
+                step.transfer = Some(Transfer {
+                    stream: Stream::Path(file),
+                    addr: a0,
+                    length: a1,
+                    position: a1,
+                });
+            }
+            nr::READ => {
+                step.slot = slot;
+                let available = open.map_or(0, |open| open.size.saturating_sub(open.offset));
+                step.operands = (a2 as u64, available as u64);
+                step.result = ebadf;
+                if let (Some(slot), Some(open)) = (slot, open) {
+                    let length = a2.min(available);
+                    let start = open.offset as usize;
+                    let reads = files.reads[open.file as usize].iter_mut();
+                    for count in reads.skip(start).take(length as usize) {
+                        *count += 1;
+                    }
+                    if let Some(open) = &mut state.slots[slot] {
+                        open.offset += length;
+                    }
+                    step.file = open.file;
+                    step.result = length;
+                    step.length = length;
+                    step.transfer = Some(Transfer {
+                        stream: Stream::File(open.file),
+                        addr: a1,
+                        length,
+                        position: available,
+                    });
+                }
+            }
+            nr::SEEK | linux_nr::LSEEK => {
+                step.slot = slot;
+                let whence = BabyBear::from(a2);
+                step.tested = whence * (whence - BabyBear::ONE) * (whence - BabyBear::from(2));
+                step.result = ebadf;
+                if let (Some(slot), Some(open)) = (slot, open) {
+                    let base = match a2 {
+                        seek::SET => Some(0),
+                        seek::CUR => Some(open.offset),
+                        seek::END => Some(open.size),
+                        _ => None,
+                    };
+                    let offset = base.map(|base| base as i64 + a1 as i32 as i64);
+                    step.result = match offset {
+                        Some(offset) if (0..=i32::MAX as i64).contains(&offset) => {
+                            // Offsets stay below 2^29 in the trace
+                            if offset >= 1 << 29 {
+                                return Err(UNCONSTRAINED_SYSCALL);
+                            }
+                            if let Some(open) = &mut state.slots[slot] {
+                                open.offset = offset as u32;
+                            }
+                            offset as u32
+                        }
+                        _ => einval,
+                    };
+                }
+            }
+            nr::CLOSE | linux_nr::CLOSE => {
+                if number == linux_nr::CLOSE {
+                    step.operands = (a0 as u64, 3);
+                }
+                step.slot = slot;
+                step.result = match (open, slot) {
+                    _ if number == linux_nr::CLOSE && std_stream => 0,
+                    (Some(_), Some(slot)) => {
+                        state.slots[slot] = None;
+                        0
+                    }
+                    _ => ebadf,
+                };
+            }
+            nr::KV_GET | nr::KV_SET => {
+                // Keys at or above the capacity are rejected
+                let store = kv.store.as_mut().ok_or("No key-value store")?;
+                if timestamp >= MAX_KV_TIMESTAMP {
+                    return Err("Key-value access too late for the trace");
+                }
+                step.operands = (a0 as u64, state.kv_keys as u64);
+                step.result = einval;
+                if let Some((value, proof)) = store.prove(a0) {
+                    let siblings = proof.proof.iter().map(digest_of).collect::<Option<_>>();
+                    let mut path = KvPath {
+                        key: timestamp << 6,
+                        index: a0,
+                        value,
+                        stored: None,
+                        siblings: siblings.ok_or("Invalid state proof")?,
+                    };
+                    let stream = if number == nr::KV_SET {
+                        let bytes = read_bytes(accesses, a1, KV_VALUE_SIZE as u32)
+                            .ok_or(UNCONSTRAINED_SYSCALL)?;
+                        let stored = bytes.try_into().map_err(|_| UNCONSTRAINED_SYSCALL)?;
+                        store.set(a0, stored)?;
+                        state.kv_root = digest_of(&store.root()).ok_or("Invalid state proof")?;
+                        path.stored = Some(stored);
+                        Stream::KvSet(timestamp)
+                    } else {
+                        Stream::KvGet(timestamp)
+                    };
+                    kv.paths.push(path);
+                    step.result = 0;
+                    step.length = KV_VALUE_SIZE as u32;
+                    step.transfer = Some(Transfer {
+                        stream,
+                        addr: a1,
+                        length: step.length,
+                        position: step.length,
+                    });
+                }
+            }
+            _ => {
+                step.operands = (a0 as u64, state.heap_start as u64);
+                if (state.heap_start..=state.heap_end).contains(&a0) {
+                    state.brk = a0;
+                }
+                step.result = state.brk;
+            }
+        }
+        if step.transfer.is_some() {
+            step.tested = step.length.into();
+        }
+        // Empty transfers touch no memory
+        step.transfer = step.transfer.filter(|transfer| transfer.length > 0);
+        Ok(step)
+    }
+}
+
+impl TraceTable {
+    pub fn generate(layout: &TraceLayout, trace: &ExecutionTrace) -> Result<Self, &'static str> {
+        let l = layout;
+        let accesses = trace
+            .steps
+            .iter()
+            .map(|step| step.memory_accesses.len())
+            .sum::<usize>();
+        let mut rows = Vec::with_capacity(trace.steps.len() + 1);
+
+        // Each step starts where the previous one left off
+        for pair in trace.steps.windows(2) {
+            if pair[0].pc_after != pair[1].pc_before
+                || pair[0].registers_after != pair[1].registers_before
+                || pair[0].registers_after_hi != pair[1].registers_before_hi
+            {
+                return Err("Trace steps are not contiguous");
+            }
+        }
+
+        let (input, journal) = (trace.input(), trace.journal());
+        let stat = LinuxSyscalls::std_stat();
+        if input.len().max(journal.len()) >= 1 << 24 {
+            return Err("Input or journal too long for the trace");
+        }
+        let (exit_status, exit_code) = exit_values(trace.exit_status);
+        let initial = &trace.initial_state;
+        let kv_root = |root: Option<HashValue>| {
+            root.map_or(Some([BabyBear::ZERO; DIGEST_SIZE]), |root| digest_of(&root))
+                .ok_or("Invalid state proof")
+        };
+        if initial.kv_store.as_ref().map(KvStore::root) != initial.state_root {
+            return Err("Key-value store does not match the state root");
+        }
+        let mut kv = KvAccesses {
+            store: initial.kv_store.clone(),
+            paths: Vec::new(),
+        };
+        let mut state = SyscallState {
+            input_remaining: input.len() as u32,
+            journal_remaining: journal.len() as u32,
+            exit_status: 0,
+            exit_code,
+            brk: initial.brk,
+            heap_start: initial.heap_start,
+            heap_end: initial.memory_map.heap_end,
+            slots: [None; FILE_SLOTS],
+            kv_root: kv_root(initial.state_root)?,
+            kv_keys: kv.store.as_ref().map_or(0, KvStore::capacity),
+        };
+        if initial.files.has_open_files() {
+            return Err("Trace starts with open files");
+        }
+        let (paths, contents): (Vec<&str>, Vec<&[u8]>) = initial.files.public_files().unzip();
+        let mut files = PublicFiles {
+            opens: vec![0; paths.len()],
+            reads: contents.iter().map(|bytes| vec![0; bytes.len()]).collect(),
+            paths,
+            contents,
+        };
+
+        // The memory log numbers the accesses it holds in order, and leaves
+        // out those of syscalls that only read memory for the host
+        let mut log: Vec<(MemoryAccess, Option<TransferRow>)> = Vec::new();
+        let mut timestamp = 1;
+        let mut gas = 0;
+        let mut max_price = 0;
+        for step in &trace.steps {
+            // Instructions outside the profile cannot be proven
+            if !l.profile.allows(&step.instruction) {
+                return Err("Instruction outside the ISA profile");
+            }
+            if !CONSTRAINED_OPCODES.contains(&step.instruction.opcode) {
+                return Err("Instruction the AIR does not constrain");
+            }
+            if step.intermediate_values.len() > INTERMEDIATE_COLUMNS {
+                return Err("Too many intermediate values for the trace layout");
+            }
+            let instruction = &step.instruction;
+            let (rs1, rs2, rd) = (
+                instruction.rs1 as usize,
+                instruction.rs2 as usize,
+                instruction.int_destination().unwrap_or(0) as usize,
+            );
+            let opcode = instruction.opcode;
+            let registers_before = join_limbs(&step.registers_before, &step.registers_before_hi);
+            let registers_after = join_limbs(&step.registers_after, &step.registers_after_hi);
+            // The immediate forms read imm, sign-extended, in place of rs2
+            let mask = u64::MAX >> (64 - l.profile.xlen());
+            let imm = instruction.imm as i64 as u64 & mask;
+            let state_before = state;
+            let syscall = (opcode == Opcode::Ecall)
+                .then(|| {
+                    SyscallStep::execute(
+                        l,
+                        &registers_before,
+                        &step.memory_accesses,
+                        timestamp,
+                        &mut state,
+                        &mut files,
+                        &mut kv,
+                    )
+                })
+                .transpose()?;
+            let (rs1_value, rs2_value) = match opcode {
+                Opcode::Auipc => (step.pc_before as u64, imm),
+                // ECALL compares what its syscall needs to
+                Opcode::Ecall => syscall.map_or((0, 0), |syscall| syscall.operands),
+                Opcode::Addi
+                | Opcode::Slti
+                | Opcode::Sltiu
+                | Opcode::Xori
+                | Opcode::Ori
+                | Opcode::Andi
+                | Opcode::Slli
+                | Opcode::Srli
+                | Opcode::Srai
+                | Opcode::Lui => (registers_before[rs1], imm),
+                // Loads read the value they load, extended to XLEN
+                op if op.is_load() => {
+                    let mut words = step
+                        .memory_accesses
+                        .iter()
+                        .map(|access| access.value_before);
+                    let low = words.next().ok_or("Load without a memory access")?;
+                    let value = low as u64 | (words.next().unwrap_or(0) as u64) << 32;
+                    let addr = registers_before[rs1].wrapping_add(imm) as u32;
+                    let value = loaded_value(op, value, addr & 3);
+                    (registers_before[rs1], value & mask)
+                }
+                // Word instructions read the low words, zero-extended by the
+                // unsigned ones and sign-extended by the others
+                op if op.is_word_op() => {
+                    let extend = |value: u64, signed: bool| match signed {
+                        true => value as i32 as i64 as u64,
+                        false => value as u32 as u64,
+                    };
+                    let unsigned = matches!(op, Opcode::Divuw | Opcode::Remuw);
+                    let rs1_signed = !unsigned && !matches!(op, Opcode::Srlw | Opcode::Srliw);
+                    let rs2_value = match op.uses_immediate() {
+                        true => imm,
+                        false => extend(registers_before[rs2], !unsigned),
+                    };
+                    (extend(registers_before[rs1], rs1_signed), rs2_value)
+                }
+                _ => (registers_before[rs1], registers_before[rs2]),
+            };
+
+            let gas_price = initial.gas.opcode_price(opcode);
+            let syscall_price = syscall.map_or(0, |syscall| {
+                initial
+                    .gas
+                    .syscall_price(CONSTRAINED_SYSCALLS[syscall.kind])
+            });
+            let total_price = gas_price.saturating_add(syscall_price);
+            max_price = max_price.max(total_price);
+
+            let mut row = Self::state_row(l, step.pc_before, &registers_before);
+            row[l.next_pc] = step.pc_after.into();
+            row[l.gas] = BabyBear::from_u64(gas);
+            gas += total_price;
+            state_before.assign(l, &mut row);
+            let entry =
+                TraceLayout::instruction_entry(step.pc_before, instruction, gas_price as u32);
+            for (column, value) in l.instruction_columns().into_iter().zip(entry) {
+                row[column] = value;
+            }
+            row[l.is_padding] = BabyBear::ZERO;
+            for (flag, flag_opcode) in l.opcode_flags() {
+                row[flag] = BabyBear::from((opcode == flag_opcode) as u32);
+            }
+            // Divisions read their quotient and remainder from the step, and
+            // word divisions compute them on the extended operands
+            let base = base_opcode(opcode);
+            let (quotient, remainder) = match opcode {
+                Opcode::Div | Opcode::Divu | Opcode::Rem | Opcode::Remu => {
+                    let words = (l.profile.xlen() / 32) as usize;
+                    let value = |index: usize| {
+                        let values = step.intermediate_values.get(index * words..)?;
+                        let words = values.get(..words)?.iter().enumerate();
+                        Some(words.map(|(w, &word)| (word as u64) << (32 * w)).sum())
+                    };
+                    value(2)
+                        .zip(value(3))
+                        .ok_or("Missing quotient or remainder of a division")?
+                }
+                Opcode::Divw | Opcode::Divuw | Opcode::Remw | Opcode::Remuw => {
+                    let (quotient, remainder) = match base {
+                        Opcode::Div | Opcode::Rem => (Opcode::Div, Opcode::Rem),
+                        _ => (Opcode::Divu, Opcode::Remu),
+                    };
+                    alu64(quotient, rs1_value, rs2_value)
+                        .zip(alu64(remainder, rs1_value, rs2_value))
+                        .ok_or("Unsupported instruction")?
+                }
+                _ => (rs1_value, 0),
+            };
+            let factor = Self::assign_shift(l, &mut row, opcode, rs2_value);
+            Self::assign_arithmetic(l, &mut row, base, rs1_value, rs2_value, factor, quotient);
+            Self::assign_division(l, &mut row, base, rs1_value, rs2_value, remainder);
+            let signed_comparison = matches!(
+                opcode,
+                Opcode::Blt | Opcode::Bge | Opcode::Slt | Opcode::Slti
+            );
+            let (less, equal) =
+                Self::assign_comparison(l, &mut row, rs1_value, rs2_value, signed_comparison);
+
+            let link = step.pc_before as u64 + instruction.size as u64;
+            let result = match opcode {
+                Opcode::Jump | Opcode::Jalr => link,
+                Opcode::Div | Opcode::Divu => quotient,
+                Opcode::Rem | Opcode::Remu => remainder,
+                Opcode::Lui | Opcode::Auipc => rs1_value.wrapping_add(rs2_value) & mask,
+                Opcode::Ecall => syscall.map_or(0, |syscall| syscall.result as u64),
+                op if op.is_load() => rs2_value,
+                // Word instructions compute on the extended operands, and
+                // shift by the low five bits of rs2
+                op if op.is_word_op() => match base {
+                    Opcode::Div | Opcode::Divu => quotient,
+                    Opcode::Rem | Opcode::Remu => remainder,
+                    Opcode::Sll | Opcode::Srl | Opcode::Sra => {
+                        alu64(base, rs1_value, rs2_value & 0x1f).ok_or("Unsupported instruction")?
+                    }
+                    _ => alu64(base, rs1_value, rs2_value).ok_or("Unsupported instruction")?,
+                },
+                Opcode::Add
+                | Opcode::Sub
+                | Opcode::Mul
+                | Opcode::Mulh
+                | Opcode::Mulhsu
+                | Opcode::Mulhu
+                | Opcode::Slt
+                | Opcode::Sltu
+                | Opcode::And
+                | Opcode::Or
+                | Opcode::Xor
+                | Opcode::Addi
+                | Opcode::Slti
+                | Opcode::Sltiu
+                | Opcode::Xori
+                | Opcode::Ori
+                | Opcode::Andi
+                | Opcode::Sll
+                | Opcode::Srl
+                | Opcode::Sra
+                | Opcode::Slli
+                | Opcode::Srli
+                | Opcode::Srai => {
+                    let result = if l.profile.xlen() == 64 {
+                        alu64(opcode, rs1_value, rs2_value)
+                    } else {
+                        alu(opcode, rs1_value as u32, rs2_value as u32).map(u64::from)
+                    };
+                    result.ok_or("Unsupported instruction")?
+                }
+                // Branches and stores write no register
+                _ => 0,
+            };
+            // The AIR holds syscalls to the built-in ones
+            if syscall.is_some() && registers_after[rd] != result {
+                return Err(UNCONSTRAINED_SYSCALL);
+            }
+            set_limbs(&mut row, &l.result, &l.limbs(result));
+            set_limbs(&mut row, &l.rd_value, &l.limbs(registers_after[rd]));
+            if opcode.is_word_op() {
+                let w = &l.word;
+                row[w.rs1_top] = BabyBear::from_u64(rs1_value >> 24 & 0x7f);
+                row[w.rs2_top] = BabyBear::from_u64(rs2_value >> 24 & 0x7f);
+                row[w.result_sign] = BabyBear::from_u64(result >> 31 & 1);
+                row[w.result_top] = BabyBear::from_u64(result >> 24 & 0x7f);
+            }
+            let taken = match opcode {
+                Opcode::Branch => equal,
+                Opcode::Bne => !equal,
+                Opcode::Blt | Opcode::Bltu => less,
+                Opcode::Bge | Opcode::Bgeu => !less,
+                _ => false,
+            };
+            row[l.taken] = BabyBear::from(taken as u32);
+            if matches!(opcode, Opcode::Jump | Opcode::Jalr) {
+                row[l.link_top] = BabyBear::from_u64(l.limbs(link)[3]);
+            }
+            if opcode == Opcode::Auipc {
+                row[l.link_top] = BabyBear::from_u64(l.limbs(rs1_value)[3]);
+            }
+            let ls = &l.load_store;
+            row[ls.timestamp] = BabyBear::from(timestamp);
+            let next_timestamp = log.len() as u32 + 1;
+            if let Some(syscall) = syscall {
+                Self::assign_syscall(l, &mut row, &syscall, syscall_price, &registers_before);
+                Self::assign_files(
+                    l,
+                    &mut row,
+                    &syscall,
+                    &registers_before,
+                    &state_before,
+                    &state,
+                );
+                // Syscalls write guest memory only through their transfer
+                let mut writes = 0;
+                if let Some(transfer) = syscall.transfer {
+                    let stream = match transfer.stream {
+                        Stream::Input => input,
+                        Stream::Journal => journal,
+                        Stream::Stat => &stat,
+                        Stream::Path(file) => files.paths[file as usize].as_bytes(),
+                        Stream::File(file) => files.contents[file as usize],
+                        Stream::KvGet(_) | Stream::KvSet(_) => {
+                            let path = kv.paths.last().ok_or(UNCONSTRAINED_SYSCALL)?;
+                            path.transferred()
+                        }
+                    };
+                    let words = Self::transfer_rows(&transfer, stream, step, next_timestamp)?;
+                    if transfer.stream.writes() {
+                        writes = words.len();
+                    }
+                    timestamp += words.len() as u32;
+                    log.extend(words);
+                }
+                let accesses = step.memory_accesses.iter();
+                if accesses.filter(|access| access.is_write).count() != writes {
+                    return Err(UNCONSTRAINED_SYSCALL);
+                }
+            } else {
+                let accesses = step.memory_accesses.iter().zip(next_timestamp..);
+                log.extend(accesses.map(|(access, timestamp)| {
+                    let access = MemoryAccess {
+                        timestamp,
+                        ..access.clone()
+                    };
+                    (access, None)
+                }));
+            }
+            if opcode.is_load() || opcode.is_store() {
+                let dword = matches!(opcode, Opcode::Ld | Opcode::Sd);
+                timestamp += 1 + dword as u32;
+                let access = step
+                    .memory_accesses
+                    .first()
+                    .ok_or("Load or store without a memory access")?;
+                if dword {
+                    let high = step
+                        .memory_accesses
+                        .get(1)
+                        .ok_or("Doubleword access without a second word")?;
+                    set_limbs(&mut row, &ls.high_before, &word_limbs(high.value_before));
+                    set_limbs(&mut row, &ls.high_after, &word_limbs(high.value_after));
+                }
+                let low = (rs1_value & 0xffff) as i64 + instruction.imm as i64;
+                let carry = low.div_euclid(1 << 16);
+                let high = (rs1_value >> 16 & 0xffff) as i64 + carry;
+                let wrap = high.div_euclid(1 << 16);
+                let low = low.rem_euclid(1 << 16) as u64;
+                row[ls.word_low] = BabyBear::from_u64(low / 8);
+                row[ls.word_odd] = BabyBear::from_u64(low / 4 % 2);
+                row[ls.word_high] = BabyBear::from_u64(high.rem_euclid(1 << 16) as u64);
+                row[ls.carry] = signed(carry);
+                row[ls.wrap] = signed(wrap);
+                row[ls.byte_is[low as usize % 4]] = BabyBear::ONE;
+                set_limbs(&mut row, &ls.value_before, &word_limbs(access.value_before));
+                set_limbs(&mut row, &ls.value_after, &word_limbs(access.value_after));
+                let top = match opcode {
+                    Opcode::Lb => rs2_value,
+                    Opcode::Lh => rs2_value >> 8,
+                    _ => access.value_before as u64 >> 24,
+                };
+                row[ls.value_top] = BabyBear::from_u64(top & 0x7f);
+            }
+            if opcode == Opcode::Jalr {
+                let target = (rs1_value as u32).wrapping_add(instruction.imm as u32);
+                row[l.target_lsb] = BabyBear::from(target & 1);
+            }
+            for (&column, &value) in l.intermediate.iter().zip(&step.intermediate_values) {
+                row[column] = value.into();
+            }
+            for (selectors, index) in [(&l.rs1_is, rs1), (&l.rs2_is, rs2), (&l.rd_is, rd)] {
+                row[selectors[0]] = BabyBear::ZERO;
+                row[selectors[index]] = BabyBear::ONE;
+            }
+            rows.push(row);
+        }
+
+        // The sponge rows run alongside the steps, and the last row holds
+        // no access or round
+        let hashes = Self::hash_rows(l, &files, &kv.paths);
+        let len = (trace.steps.len().max(accesses).max(hashes.len()) + 1)
+            .next_power_of_two()
+            .max(2);
+        // Gas adds up in the field, so no trace may cost the modulus
+        if max_price.saturating_mul(len as u64) >= BABY_BEAR_MODULUS as u64 {
+            return Err("Gas price too large for the trace");
+        }
+
+        // The final state, repeated up to a power of two
+        let mut final_row = match trace.steps.last() {
+            Some(step) => Self::state_row(
+                l,
+                step.pc_after,
+                &join_limbs(&step.registers_after, &step.registers_after_hi),
+            ),
+            None => Self::state_row(l, trace.initial_state.pc, &trace.initial_state.registers),
+        };
+        final_row[l.load_store.timestamp] = BabyBear::from(timestamp);
+        final_row[l.gas] = BabyBear::from_u64(gas);
+        if state.exit_status != exit_status {
+            return Err("Exit status does not match the trace");
+        }
+        if state.kv_root != kv_root(trace.final_state.state_root)? {
+            return Err("State root does not match the trace");
+        }
+        state.assign(l, &mut final_row);
+        rows.resize(len, final_row);
+
+        if log.iter().any(|(access, _)| !access.addr.is_multiple_of(4)) {
+            return Err("Unaligned memory access in the trace");
+        }
+        let (m, t) = (&l.memory, &l.transfer);
+        let end = BabyBear::from(log.len() as u32 + 1);
+        for (i, row) in rows.iter_mut().enumerate() {
+            let Some((access, transfer)) = log.get(i) else {
+                row[m.timestamp] = end;
+                continue;
+            };
+            row[m.used] = BabyBear::ONE;
+            row[m.word] = BabyBear::from(access.addr / 4);
+            set_limbs(row, &m.value_before, &word_limbs(access.value_before));
+            set_limbs(row, &m.value_after, &word_limbs(access.value_after));
+            row[m.is_write] = BabyBear::from(access.is_write as u32);
+            row[m.timestamp] = access.timestamp.into();
+            let Some(transfer) = transfer else {
+                continue;
+            };
+            let chained = if transfer.first { t.first } else { t.rest };
+            let kind = match transfer.stream {
+                Stream::Input => t.input,
+                Stream::Journal => t.journal,
+                Stream::Stat => t.stat,
+                Stream::Path(_) => t.path,
+                Stream::File(_) => t.file,
+                Stream::KvGet(_) => t.kv_get,
+                Stream::KvSet(_) => t.kv_set,
+            };
+            row[chained] = BabyBear::ONE;
+            row[kind] = BabyBear::ONE;
+            row[t.start_is[transfer.start as usize]] = BabyBear::ONE;
+            row[t.count_is[transfer.count as usize - 1]] = BabyBear::ONE;
+            row[t.position] = transfer.position.into();
+            row[t.remaining] = transfer.remaining.into();
+            row[t.end] = transfer.end.into();
+        }
+
+        for (row, hash) in rows.iter_mut().zip(hashes) {
+            for (column, value) in hash {
+                row[column] = value;
+            }
+        }
+
+        // Each row of the sorted log also records how the next one follows
+        // it
+        let s = &l.sorted_memory;
+        let mut log: Vec<MemoryAccess> = log.into_iter().map(|(access, _)| access).collect();
+        log.sort_by_key(|access| (access.addr, access.timestamp));
+        for (i, access) in log.iter().enumerate() {
+            let row = &mut rows[i];
+            row[s.used] = BabyBear::ONE;
+            row[s.word] = BabyBear::from(access.addr / 4);
+            row[s.timestamp] = access.timestamp.into();
+            set_limbs(row, &s.value_before, &word_limbs(access.value_before));
+            set_limbs(row, &s.value_after, &word_limbs(access.value_after));
+            let first = i == 0 || log[i - 1].addr != access.addr;
+            row[s.first] = BabyBear::from(first as u32);
+            let Some(next) = log.get(i + 1) else {
+                continue;
+            };
+            let gap = if next.addr == access.addr {
+                row[s.same_word] = BabyBear::ONE;
+                next.timestamp - access.timestamp - 1
+            } else {
+                row[s.new_word] = BabyBear::ONE;
+                (next.addr - access.addr) / 4 - 1
+            };
+            row[s.gap_high] = BabyBear::from(gap >> 16);
+        }
+
+        Ok(Self {
+            layout: layout.clone(),
+            rows,
+        })
+    }
+
+    /// Fills the syscall columns of an ECALL row, and the address columns of
+    /// the load/store group for a transfer.
+    fn assign_syscall(
+        l: &TraceLayout,
+        row: &mut [BabyBear],
+        syscall: &SyscallStep,
+        price: u64,
+        registers: &[u64; 32],
+    ) {
+        let sys = &l.syscall;
+        let a0 = registers[10] as u32;
+        let number = CONSTRAINED_SYSCALLS[syscall.kind];
+        row[sys.flags()[syscall.kind].0] = BabyBear::ONE;
+        row[sys.price] = BabyBear::from_u64(price);
+        row[sys.length] = syscall.length.into();
+        row[sys.nonzero] = BabyBear::from(!syscall.tested.is_zero() as u32);
+        row[sys.inverse] = syscall.tested.inverse().unwrap_or(BabyBear::ZERO);
+        if number == nr::BRK || number == linux_nr::BRK {
+            // heap_end - a0, half by half
+            let heap_end = row[sys.heap_end_low].as_u32() | row[sys.heap_end_high].as_u32() << 16;
+            let (low, high) = (heap_end & 0xffff, heap_end >> 16);
+            let borrow = low < a0 & 0xffff;
+            let above = high < (a0 >> 16) + borrow as u32;
+            row[sys.gap_low] = (low.wrapping_sub(a0) & 0xffff).into();
+            row[sys.gap_high] = (heap_end.wrapping_sub(a0) >> 16).into();
+            row[sys.gap_borrow] = BabyBear::from(borrow as u32);
+            row[sys.above] = BabyBear::from(above as u32);
+        }
+        if number == nr::KV_GET || number == nr::KV_SET {
+            let timestamp = row[l.load_store.timestamp].as_u32();
+            row[l.kv.timestamp_low] = (timestamp & 0xffff).into();
+            row[l.kv.timestamp_high] = (timestamp >> 16).into();
+        }
+        if let Some(transfer) = syscall.transfer {
+            let (ls, addr) = (&l.load_store, transfer.addr);
+            row[ls.word_low] = ((addr & 0xffff) / 8).into();
+            row[ls.word_odd] = (addr / 4 % 2).into();
+            row[ls.word_high] = (addr >> 16).into();
+            row[ls.byte_is[addr as usize % 4]] = BabyBear::ONE;
+        }
+    }
+
+    /// Fills the file columns of an ECALL row from the descriptor slots
+    /// before and after the step.
+    fn assign_files(
+        l: &TraceLayout,
+        row: &mut [BabyBear],
+        syscall: &SyscallStep,
+        registers: &[u64; 32],
+        before: &SyscallState,
+        after: &SyscallState,
+    ) {
+        let f = &l.files;
+        let number = CONSTRAINED_SYSCALLS[syscall.kind];
+        let [a1, a2] = [11, 12].map(|register| registers[register] as u32);
+        let selected = syscall.slot.and_then(|slot| before.slots[slot]);
+        let [file, size, offset] = SyscallState::slot_values(&selected);
+        if let Some(slot) = syscall.slot {
+            row[f.slot_is[slot]] = BabyBear::ONE;
+            let [file, size, offset] = SyscallState::slot_values(&after.slots[slot]);
+            row[f.file_after] = file;
+            row[f.size_after] = size;
+            row[f.offset_after] = offset;
+        }
+        row[f.selected_file] = file;
+        row[f.selected_size] = size;
+        row[f.selected_offset] = offset;
+        row[f.descriptor_open] = BabyBear::from(selected.is_some() as u32);
+        row[f.descriptor_inverse] = file.inverse().unwrap_or(BabyBear::ZERO);
+        row[f.file] = syscall.file.into();
+        row[f.size] = syscall.size.into();
+        let closes = number == nr::CLOSE || number == linux_nr::CLOSE;
+        row[f.closing] = BabyBear::from((closes && selected.is_some()) as u32);
+        let (size, offset) = selected.map_or((0, 0), |open| (open.size, open.offset));
+        if number == nr::READ && selected.is_some() {
+            let above = offset > size;
+            let distance = if above {
+                offset - size - 1
+            } else {
+                size - offset
+            };
+            row[f.above] = BabyBear::from(above as u32);
+            row[f.distance_low] = (distance & 0xffff).into();
+            row[f.distance_high] = (distance >> 16).into();
+        }
+        if number == nr::SEEK || number == linux_nr::LSEEK {
+            let whence = BabyBear::from(a2);
+            row[f.whence_tested] = whence * (whence - BabyBear::ONE) * (whence - BabyBear::from(2));
+            let base = match a2 {
+                seek::CUR => offset,
+                seek::END => size,
+                _ => 0,
+            };
+            if a2 <= seek::END {
+                row[f.whence_is[a2 as usize]] = BabyBear::ONE;
+            }
+            // base + a1, half by half
+            let low = (base & 0xffff) + (a1 & 0xffff);
+            let high = (base >> 16) + (a1 >> 16) + (low >> 16);
+            let (sign, top, wrap) = (a1 >> 31, high >> 15 & 1, high >> 16);
+            let in_bounds = (sign == 0 && top == 0) || (sign == 1 && wrap == 1);
+            row[f.base_low] = (base & 0xffff).into();
+            row[f.base_high] = (base >> 16).into();
+            row[f.offset_sign] = sign.into();
+            row[f.offset_rest] = (a1 >> 16 & 0x7fff).into();
+            row[f.sum_low] = (low & 0xffff).into();
+            row[f.sum_carry] = (low >> 16).into();
+            row[f.sum_top] = top.into();
+            row[f.sum_mid] = (high >> 13 & 3).into();
+            row[f.sum_rest] = (high & 0x1fff).into();
+            row[f.wrap] = wrap.into();
+            row[f.in_bounds] = BabyBear::from(in_bounds as u32);
+            let accepted = selected.is_some() && a2 <= seek::END && in_bounds;
+            row[f.accepted] = BabyBear::from(accepted as u32);
+        }
+    }
+
+    /// The sponge rows hashing every public file the trace opens and the
+    /// Merkle path of every key-value access, as the columns each sets.
+    fn hash_rows(
+        l: &TraceLayout,
+        files: &PublicFiles,
+        paths: &[KvPath],
+    ) -> Vec<Vec<(usize, BabyBear)>> {
+        let h = &l.hash;
+        let mut rows = Vec::new();
+        for (file, &bytes) in files.contents.iter().enumerate() {
+            if files.opens[file] == 0 {
+                continue;
+            }
+            let blocks = sponge_blocks(bytes);
+            let mut state = sponge_initial_state(bytes.len());
+            for (b, block) in blocks.iter().enumerate() {
+                for (element, &value) in state.iter_mut().zip(block) {
+                    *element += value;
+                }
+                let left = (bytes.len() - b * SPONGE_RATE) as u32;
+                let common = [
+                    (h.key, BabyBear::from((file as u32) << 24)),
+                    (h.left, BabyBear::from(left)),
+                ];
+                let mut first = vec![(if b == 0 { h.start } else { h.chained }, BabyBear::ONE)];
+                first.extend(h.block.iter().copied().zip(block.iter().copied()));
+                let reads = &files.reads[file];
+                for (i, &column) in h.reads.iter().enumerate() {
+                    let count = reads.get(b * SPONGE_RATE + i).copied().unwrap_or(0);
+                    first.push((column, count.into()));
+                }
+                if b == 0 {
+                    first.push((h.opens, files.opens[file].into()));
+                }
+                let last = if b + 1 < blocks.len() {
+                    vec![(h.chain, BabyBear::ONE)]
+                } else {
+                    Vec::new()
+                };
+                rows.extend(Self::permutation_rows(
+                    h, &mut state, &common, &first, &last,
+                ));
+            }
+        }
+        for path in paths {
+            // KV_SET replaces the value along the path, the siblings kept
+            let values = match path.stored {
+                Some(stored) => vec![
+                    (path.value, Some(h.paired), false),
+                    (stored, Some(h.update), true),
+                ],
+                None => vec![(path.value, None, true)],
+            };
+            for (value, flag, reads) in values {
+                rows.extend(Self::path_rows(h, path, &value, flag, reads));
+            }
+        }
+        rows
+    }
+
+    /// The sponge rows of the path of `path` from the leaf holding `value`,
+    /// with `flag`, if any, set on each, and the bytes of `value` put on the
+    /// value bus if `reads`.
+    fn path_rows(
+        h: &HashColumns,
+        path: &KvPath,
+        value: &KvValue,
+        flag: Option<usize>,
+        reads: bool,
+    ) -> Vec<Vec<(usize, BabyBear)>> {
+        let mut rows = Vec::new();
+        let leaf = kv_leaf(path.index, value);
+        let blocks = sponge_blocks(&leaf);
+        let mut state = sponge_initial_state(leaf.len());
+        let mut keyed = vec![(h.key, path.key.into()), (h.index, path.index.into())];
+        keyed.extend(flag.map(|flag| (flag, BabyBear::ONE)));
+        for (b, block) in blocks.iter().enumerate() {
+            for (element, &value) in state.iter_mut().zip(block) {
+                *element += value;
+            }
+            let left = (leaf.len() - b * SPONGE_RATE) as u32;
+            let mut common = keyed.clone();
+            common.extend([
+                (h.leaf, BabyBear::ONE),
+                (h.left, left.into()),
+                (h.path, path.index.into()),
+                (h.width, BabyBear::ONE),
+            ]);
+            let mut first = vec![(if b == 0 { h.start } else { h.chained }, BabyBear::ONE)];
+            first.extend(h.block.iter().copied().zip(block.iter().copied()));
+            for (i, &column) in h.reads.iter().enumerate() {
+                // The key is not part of the value
+                let byte = b * SPONGE_RATE + i;
+                let read = reads && (4..leaf.len()).contains(&byte);
+                first.push((column, BabyBear::from(read as u32)));
+            }
+            let climb = if b + 1 < blocks.len() {
+                h.chain
+            } else {
+                h.climb
+            };
+            let last = [(climb, BabyBear::ONE)];
+            rows.extend(Self::permutation_rows(
+                h, &mut state, &common, &first, &last,
+            ));
+        }
+        let depth = path.siblings.len();
+        for (k, sibling) in path.siblings.iter().enumerate() {
+            let bit = path.index >> k & 1;
+            let output: Digest = std::array::from_fn(|i| state[i]);
+            let (left, right) = if bit == 0 {
+                (&output, sibling)
+            } else {
+                (sibling, &output)
+            };
+            state = std::array::from_fn(|i| {
+                if i < DIGEST_SIZE {
+                    left[i]
+                } else {
+                    right[i - DIGEST_SIZE]
+                }
+            });
+            let mut common = keyed.clone();
+            common.extend([
+                (h.node, BabyBear::ONE),
+                (h.bit, BabyBear::from(bit)),
+                (h.path, BabyBear::from(path.index >> (k + 1))),
+                (h.width, BabyBear::from(1 << (k + 1))),
+            ]);
+            let mut first = vec![(h.chained, BabyBear::ONE)];
+            first.extend(h.block.iter().copied().zip(sibling.iter().copied()));
+            let last = if k + 1 < depth {
+                vec![(h.climb, BabyBear::ONE)]
+            } else {
+                Vec::new()
+            };
+            rows.extend(Self::permutation_rows(
+                h, &mut state, &common, &first, &last,
+            ));
+        }
+        rows
+    }
+
+    /// The rows of one permutation of `state`, one per round, each setting
+    /// `common`, the first also `first` and the last also `last` and the
+    /// output. Leaves `state` permuted.
+    fn permutation_rows(
+        h: &HashColumns,
+        state: &mut SpongeState,
+        common: &[(usize, BabyBear)],
+        first: &[(usize, BabyBear)],
+        last: &[(usize, BabyBear)],
+    ) -> Vec<Vec<(usize, BabyBear)>> {
+        let mut rows = Vec::with_capacity(SPONGE_ROUNDS);
+        for (round, constants) in round_constants().iter().enumerate() {
+            let mut row = vec![
+                (h.active, BabyBear::ONE),
+                (h.round, BabyBear::from(round as u32)),
+            ];
+            row.extend_from_slice(common);
+            for j in 0..SPONGE_WIDTH {
+                let sum = state[j] + constants[j];
+                row.push((h.constants[j], constants[j]));
+                row.push((h.state[j], state[j]));
+                row.push((h.cube[j], sum * sum * sum));
+            }
+            if round == 0 {
+                row.push((h.begin, BabyBear::ONE));
+                row.extend_from_slice(first);
+            }
+            *state = sponge_round(state, round);
+            if round == SPONGE_ROUNDS - 1 {
+                row.push((h.last, BabyBear::ONE));
+                row.extend(h.output.iter().copied().zip(*state));
+                row.extend_from_slice(last);
+            }
+            rows.push(row);
+        }
+        rows
+    }
+
+    /// The memory log rows of `transfer` from `timestamp` on, one per word
+    /// of the step's accesses it copies, once they hold the bytes of
+    /// `stream` it claims.
+    fn transfer_rows(
+        transfer: &Transfer,
+        stream: &[u8],
+        step: &TraceStep,
+        timestamp: u32,
+    ) -> Result<Vec<(MemoryAccess, Option<TransferRow>)>, &'static str> {
+        let accesses = step
+            .memory_accesses
+            .iter()
+            .filter(|access| access.is_write == transfer.stream.writes());
+        let mut words = Vec::new();
+        let mut done = 0;
+        for (i, access) in accesses.enumerate() {
+            let start = if i == 0 { transfer.addr % 4 } else { 0 };
+            if done == transfer.length || access.addr != (transfer.addr & !3) + 4 * i as u32 {
+                return Err(UNCONSTRAINED_SYSCALL);
+            }
+            let count = (4 - start).min(transfer.length - done);
+            let value = match transfer.stream.writes() {
+                true => access.value_after,
+                false => access.value_before,
+            };
+            let bytes = &value.to_le_bytes()[start as usize..(start + count) as usize];
+            let offset = (stream.len() as u32 - transfer.position + done) as usize;
+            if bytes != &stream[offset..offset + count as usize] {
+                return Err(UNCONSTRAINED_SYSCALL);
+            }
+            let row = TransferRow {
+                first: i == 0,
+                stream: transfer.stream,
+                start,
+                count,
+                position: transfer.stream.key() + transfer.position - done,
+                remaining: transfer.length - done,
+                end: 0,
+            };
+            let access = MemoryAccess {
+                timestamp: timestamp + i as u32,
+                ..access.clone()
+            };
+            words.push((access, Some(row)));
+            done += count;
+        }
+        if done != transfer.length {
+            return Err(UNCONSTRAINED_SYSCALL);
+        }
+        let end = timestamp + words.len() as u32;
+        for (_, row) in &mut words {
+            if let Some(row) = row {
+                row.end = end;
+            }
+        }
+        Ok(words)
+    }
+
+    /// Fills the operand limbs and signs, and the carries of the sum or
+    /// difference and the product of `multiplicand` and `factor`, which are
+    /// kept on every row.
+    fn assign_arithmetic(
+        l: &TraceLayout,
+        row: &mut [BabyBear],
+        opcode: Opcode,
+        a: u64,
+        b: u64,
+        factor: u64,
+        multiplicand: u64,
+    ) {
+        let (a_limbs, b_limbs) = (l.limbs(a), l.limbs(b));
+        set_limbs(row, &l.rs1_value, &a_limbs);
+        set_limbs(row, &l.rs2_value, &b_limbs);
+        let half = LIMB / 2;
+        let (a_top, b_top) = (a_limbs[a_limbs.len() - 1], b_limbs[b_limbs.len() - 1]);
+        let (a_negative, b_negative) = (a_top >= half, b_top >= half);
+        row[l.rs1_sign] = BabyBear::from(a_negative as u32);
+        row[l.rs2_sign] = BabyBear::from(b_negative as u32);
+        row[l.rs1_top] = BabyBear::from_u64(a_top % half);
+        row[l.rs2_top] = BabyBear::from_u64(b_top % half);
+
+        let mut carry = 0;
+        for (j, &column) in l.carries.iter().enumerate() {
+            carry = match opcode {
+                Opcode::Sub => (a_limbs[j] < b_limbs[j] + carry) as u64,
+                _ => (a_limbs[j] + b_limbs[j] + carry) / LIMB,
+            };
+            row[column] = BabyBear::from_u64(carry);
+        }
+
+        let m_limbs = l.limbs(multiplicand);
+        set_limbs(row, &l.multiplicand, &m_limbs);
+        let m_top = m_limbs[m_limbs.len() - 1];
+        let m_negative = m_top >= half;
+        row[l.multiplicand_sign] = BabyBear::from(m_negative as u32);
+        row[l.multiplicand_top] = BabyBear::from_u64(m_top % half);
+
+        use Opcode::{Div, Mulh, Mulhsu, Rem, Sra, Srai};
+        let m_extended = matches!(opcode, Mulh | Mulhsu | Div | Rem | Sra | Srai) && m_negative;
+        let b_extended = matches!(opcode, Mulh | Div | Rem) && b_negative;
+        row[l.multiplicand_extended] = BabyBear::from(m_extended as u32);
+        row[l.rs2_extended] = BabyBear::from(b_extended as u32);
+        let widen = |limbs: Vec<u64>, extended: bool| -> Vec<u64> {
+            let fill = if extended { LIMB - 1 } else { 0 };
+            let width = 2 * limbs.len();
+            limbs
+                .into_iter()
+                .chain(std::iter::repeat(fill))
+                .take(width)
+                .collect()
+        };
+        let f_limbs = l.limbs(factor);
+        set_limbs(row, &l.factor, &f_limbs);
+        let (m_wide, b_wide) = (widen(m_limbs, m_extended), widen(f_limbs, b_extended));
+        let mut carry = 0;
+        for k in 0..l.product.len() {
+            let sum = (0..=k).map(|i| m_wide[i] * b_wide[k - i]).sum::<u64>() + carry;
+            carry = sum / LIMB;
+            row[l.product[k]] = BabyBear::from_u64(sum % LIMB);
+            row[l.product_carries[k]] = BabyBear::from_u64(carry);
+        }
+    }
+
+    /// Fills the shift columns for a shift by `b`, and returns the factor
+    /// of the product: `2^exponent` on shifts, `b` otherwise.
+    fn assign_shift(l: &TraceLayout, row: &mut [BabyBear], opcode: Opcode, b: u64) -> u64 {
+        use Opcode::{Sll, Slli, Slliw, Sllw, Sra, Srai, Sraiw, Sraw, Srl, Srli, Srliw, Srlw};
+        let sh = &l.shift;
+        let xlen = l.profile.xlen() as u64;
+        // Word shifts shift by the low five bits
+        let amount = match opcode {
+            Sllw | Slliw | Srlw | Srliw | Sraw | Sraiw => b & 0x1f,
+            _ => b & (xlen - 1),
+        };
+        row[sh.amount] = BabyBear::from_u64(b & 0x1f);
+        row[sh.bit5] = BabyBear::from_u64(b >> 5 & 1);
+        row[sh.high] = BabyBear::from_u64((b & (LIMB - 1)) >> 6);
+        let amount_field = BabyBear::from_u64(amount);
+        row[sh.inverse] = amount_field.inverse().unwrap_or(BabyBear::ZERO);
+        row[sh.zero] = BabyBear::from((amount == 0) as u32);
+        row[sh.power] = BabyBear::ONE;
+
+        let exponent = match opcode {
+            Sll | Slli | Sllw | Slliw => amount,
+            Srl | Srli | Sra | Srai | Srlw | Srliw | Sraw | Sraiw => (xlen - amount) % xlen,
+            _ => return b,
+        };
+        let bits = exponent % LIMB_BITS as u64;
+        row[sh.limb_is[(exponent / LIMB_BITS as u64) as usize]] = BabyBear::ONE;
+        for (i, &column) in sh.bits.iter().enumerate() {
+            row[column] = BabyBear::from_u64(bits >> i & 1);
+        }
+        row[sh.power] = BabyBear::from_u64(1 << bits);
+        1 << exponent
+    }
+
+    /// Fills the division columns for `a / b` with the given remainder, once
+    /// the product holds the quotient times `b`.
+    fn assign_division(
+        l: &TraceLayout,
+        row: &mut [BabyBear],
+        opcode: Opcode,
+        a: u64,
+        b: u64,
+        remainder: u64,
+    ) {
+        let d = &l.division;
+        let xlen = l.profile.xlen();
+        let sign_bit = 1u64 << (xlen - 1);
+        let mask = u64::MAX >> (64 - xlen);
+        let division = matches!(
+            opcode,
+            Opcode::Div | Opcode::Divu | Opcode::Rem | Opcode::Remu
+        );
+        let signed = matches!(opcode, Opcode::Div | Opcode::Rem);
+        let negative = |value: u64| signed && value & sign_bit != 0;
+
+        let r_limbs = l.limbs(remainder);
+        set_limbs(row, &d.remainder, &r_limbs);
+        let r_top = r_limbs[r_limbs.len() - 1];
+        row[d.remainder_sign] = BabyBear::from((r_top >= LIMB / 2) as u32);
+        row[d.remainder_top] = BabyBear::from_u64(r_top % (LIMB / 2));
+        row[d.negative_dividend] = BabyBear::from(negative(a) as u32);
+        row[d.negative_divisor] = BabyBear::from(negative(b) as u32);
+        row[d.negative_remainder] = BabyBear::from(negative(remainder) as u32);
+
+        // quotient * divisor + remainder, which is the dividend
+        let mut carry = 0;
+        for (k, &column) in d.carries.iter().enumerate() {
+            let r_limb = match r_limbs.get(k) {
+                Some(&limb) => limb,
+                None => (LIMB - 1) * negative(remainder) as u64,
+            };
+            carry = (row[l.product[k]].as_u32() as u64 + r_limb + carry) / LIMB;
+            row[column] = BabyBear::from_u64(carry);
+        }
+
+        // Magnitudes, and the carries of adding them to the negative values
+        let mut magnitude = |value: u64, negated: bool, columns: &[usize], carries: &[usize]| {
+            let abs = if negated {
+                value.wrapping_neg() & mask
+            } else {
+                value
+            };
+            let (limbs, abs_limbs) = (l.limbs(value), l.limbs(abs));
+            set_limbs(row, columns, &abs_limbs);
+            let mut carry = 0;
+            for (j, &column) in carries.iter().enumerate() {
+                if negated {
+                    carry = (limbs[j] + abs_limbs[j] + carry) / LIMB;
+                    row[column] = BabyBear::from_u64(carry);
+                }
+            }
+            abs
+        };
+        let remainder_abs = magnitude(
+            remainder,
+            negative(a),
+            &d.remainder_abs,
+            &d.remainder_abs_carries,
+        );
+        let divisor_abs = magnitude(b, negative(b), &d.divisor_abs, &d.divisor_abs_carries);
+
+        // |divisor| - |remainder| - 1, subtracted limb by limb
+        let (b_limbs, r_limbs) = (l.limbs(divisor_abs), l.limbs(remainder_abs));
+        let mut borrow = 1;
+        for j in 0..d.gap.len() {
+            let difference = b_limbs[j] as i64 - r_limbs[j] as i64 - borrow;
+            borrow = (difference < 0) as i64;
+            row[d.gap[j]] = BabyBear::from_u64(difference.rem_euclid(LIMB as i64) as u64);
+            row[d.gap_borrows[j]] = BabyBear::from(borrow as u32);
+        }
+
+        let sum = BabyBear::from_u64(l.limbs(b).iter().sum());
+        row[d.divisor_inverse] = sum.inverse().unwrap_or(BabyBear::ZERO);
+        row[d.divisor_zero] = BabyBear::from(sum.is_zero() as u32);
+        row[d.by_zero] = BabyBear::from((division && b == 0) as u32);
+        row[d.overflow] = BabyBear::from((signed && a == sign_bit && b == mask) as u32);
+    }
+
+    /// Fills the comparison columns and returns whether `a < b` and
+    /// `a == b`.
+    fn assign_comparison(
+        l: &TraceLayout,
+        row: &mut [BabyBear],
+        a: u64,
+        b: u64,
+        signed_comparison: bool,
+    ) -> (bool, bool) {
+        let c = &l.comparison;
+        let xlen = l.profile.xlen();
+        let sign_bit = 1u64 << (xlen - 1);
+        let mask = u64::MAX >> (64 - xlen);
+        let (sign_a, sign_b) = ((a & sign_bit != 0) as i64, (b & sign_bit != 0) as i64);
+        let flip = if signed_comparison { sign_bit } else { 0 };
+        let (biased_a, biased_b) = (a ^ flip, b ^ flip);
+        let less = biased_a < biased_b;
+        let difference = biased_a.wrapping_sub(biased_b) & mask;
+
+        row[c.less] = BabyBear::from(less as u32);
+        if signed_comparison {
+            row[c.bias] = signed(sign_b - sign_a);
+        }
+        for (limb, &column) in c.borrows.iter().enumerate() {
+            let low = u64::MAX >> (64 - LIMB_BITS as usize * (limb + 1));
+            row[column] = BabyBear::from(((a & low) < (b & low)) as u32);
+        }
+        let limbs = l.limbs(difference);
+        set_limbs(row, &c.difference, &limbs);
+        let sum = BabyBear::from_u64(limbs.iter().sum());
+        row[c.inverse] = sum.inverse().unwrap_or(BabyBear::ZERO);
+        row[c.equal] = BabyBear::from(sum.is_zero() as u32);
+        (less, sum.is_zero())
+    }
+
+    /// A row that executes nothing in the given state.
+    fn state_row(l: &TraceLayout, pc: u32, registers: &[u64; 32]) -> Vec<BabyBear> {
+        let mut row = vec![BabyBear::ZERO; l.width()];
+        row[l.pc] = pc.into();
+        row[l.next_pc] = pc.into();
+        row[l.opcode] = (Opcode::Nop as u32).into();
+        row[l.comparison.equal] = BabyBear::ONE;
+        row[l.division.divisor_zero] = BabyBear::ONE;
+        row[l.sorted_memory.first] = BabyBear::ONE;
+        row[l.is_padding] = BabyBear::ONE;
+        row[l.shift.zero] = BabyBear::ONE;
+        row[l.shift.power] = BabyBear::ONE;
+        for (columns, &value) in l.registers.iter().zip(registers) {
+            set_limbs(&mut row, columns, &l.limbs(value));
+        }
+        for selectors in [&l.rs1_is, &l.rs2_is, &l.rd_is] {
+            row[selectors[0]] = BabyBear::ONE;
+        }
+        // Zero exactly when a0 has a descriptor slot
+        let a0 = BabyBear::from(registers[10] as u32);
+        let fd_tested: BabyBear = (0..FILE_SLOTS)
+            .map(|k| a0 - BabyBear::from(FIRST_FILE_FD + k as u32))
+            .product();
+        row[l.files.fd_tested] = fd_tested;
+        row[l.files.fd_inverse] = fd_tested.inverse().unwrap_or(BabyBear::ZERO);
+        row
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn width(&self) -> usize {
+        self.layout.width()
+    }
+
+    pub fn get(&self, row: usize, column: usize) -> BabyBear {
+        self.rows[row][column]
+    }
+
+    pub fn set(&mut self, row: usize, column: usize, value: BabyBear) {
+        self.rows[row][column] = value;
+    }
+
+    /// The values of one column, top to bottom.
+    pub fn column(&self, column: usize) -> Vec<BabyBear> {
+        self.rows.iter().map(|row| row[column]).collect()
+    }
+}
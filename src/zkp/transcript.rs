@@ -0,0 +1,59 @@
+//! Fiat–Shamir transcript.
+//!
+//! Prover and verifier absorb the same messages in the same order, so the
+//! challenges squeezed from it are ones the prover could not choose.
+
+use super::{BabyBear, BabyBearExt4, ExtensionField, Field};
+use crate::crypto::{hash_bytes, HashValue};
+
+#[derive(Debug, Clone)]
+pub struct Transcript {
+    state: HashValue,
+}
+
+impl Transcript {
+    /// A transcript separated from others by `label`.
+    pub fn new(label: &[u8]) -> Self {
+        Self {
+            state: hash_bytes(label),
+        }
+    }
+
+    pub fn absorb_bytes(&mut self, bytes: &[u8]) {
+        let mut input = self.state.to_vec();
+        input.extend_from_slice(bytes);
+        self.state = hash_bytes(&input);
+    }
+
+    pub fn absorb_u64(&mut self, value: u64) {
+        self.absorb_bytes(&value.to_le_bytes());
+    }
+
+    pub fn absorb_field<F: ExtensionField<BabyBear>>(&mut self, values: &[F]) {
+        let bytes: Vec<u8> = values
+            .iter()
+            .flat_map(|value| value.as_base_slice())
+            .flat_map(|value| value.as_u32().to_le_bytes())
+            .collect();
+        self.absorb_bytes(&bytes);
+    }
+
+    fn squeeze(&mut self) -> u64 {
+        self.absorb_bytes(b"challenge");
+        u64::from_le_bytes(self.state[..8].try_into().unwrap())
+    }
+
+    /// A challenge from the extension field, so that guessing it in
+    /// advance has negligible probability.
+    pub fn challenge(&mut self) -> BabyBearExt4 {
+        let coordinates: Vec<BabyBear> = (0..BabyBearExt4::DEGREE)
+            .map(|_| BabyBear::from_u64(self.squeeze()))
+            .collect();
+        BabyBearExt4::from_base_slice(&coordinates)
+    }
+
+    /// A challenge index in `0..bound`.
+    pub fn challenge_index(&mut self, bound: usize) -> usize {
+        (self.squeeze() % bound as u64) as usize
+    }
+}
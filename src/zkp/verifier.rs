@@ -1,9 +1,20 @@
-use super::{Proof, SerializableStarkProof};
+use super::{
+    constraint_quotient, digest_of, domain_shift, quotient_chunks, verify_opening, Air, BabyBear,
+    BabyBearExt4, ConstraintInputs, ConstraintSystem, ExtensionField, Field, FriConfig,
+    LookupTable, Proof, PublicValues, RowPair, StarkProof, TwoAdicField, VanishingFactors,
+    BABY_BEAR_MODULUS,
+};
+use crate::vm::{stack::read_argv, IsaProfile, MAX_KV_DEPTH};
 
 pub trait VerificationSystem {
     type Error: std::fmt::Debug;
 
-    fn verify_proof(&self, proof: &Proof, public_inputs: &[u8]) -> Result<bool, Self::Error>;
+    /// Checks `proof` and that it was made for a run with `public_values`.
+    fn verify_proof(
+        &self,
+        proof: &Proof,
+        public_values: &PublicValues,
+    ) -> Result<bool, Self::Error>;
 }
 
 pub struct Verifier<V: VerificationSystem> {
@@ -17,8 +28,8 @@ impl<V: VerificationSystem> Verifier<V> {
         }
     }
 
-    pub fn verify(&self, proof: &Proof, public_inputs: &[u8]) -> Result<bool, V::Error> {
-        self.verification_system.verify_proof(proof, public_inputs)
+    pub fn verify(&self, proof: &Proof, public_values: &PublicValues) -> Result<bool, V::Error> {
+        self.verification_system.verify_proof(proof, public_values)
     }
 }
 
@@ -37,53 +48,305 @@ impl StarkVerifier {
         Self { security_level }
     }
 
-    fn verify_fri_proof(&self, stark_proof: &SerializableStarkProof) -> bool {
-        // Simplified FRI verification
-        // In a real implementation, this would:
-        // 1. Verify all Merkle commitments
-        // 2. Check folding consistency
-        // 3. Verify query proofs
+    /// Checks that the proof has the shape the AIR and the security level
+    /// require.
+    fn verify_shape(&self, stark_proof: &StarkProof, air: &Air) -> Result<(), &'static str> {
+        let blowup = stark_proof.expansion_factor;
+        if !blowup.is_power_of_two() || blowup < 2 || air.max_degree() > blowup as usize {
+            return Err("Invalid expansion factor");
+        }
+        let num_queries = stark_proof.fri_proof.query_proofs.len();
+        if (num_queries as u32) * blowup.trailing_zeros() < self.security_level {
+            return Err("Too few FRI queries for the security level");
+        }
+        let trace_length = stark_proof.trace_length;
+        if !trace_length.is_power_of_two()
+            || trace_length < 2
+            || trace_length.trailing_zeros() + blowup.trailing_zeros() > BabyBear::TWO_ADICITY
+        {
+            return Err("Invalid trace length");
+        }
+        if stark_proof.trace_openings.len() != num_queries
+            || stark_proof.aux_openings.len() != num_queries
+            || stark_proof.quotient_openings.len() != num_queries
+        {
+            return Err("Wrong number of openings");
+        }
+        let quotient_width = quotient_chunks(air) * BabyBearExt4::DEGREE;
+        let aux_width = air.aux_width() * BabyBearExt4::DEGREE;
+        if stark_proof.public_inputs.len() != air.num_public_inputs
+            || stark_proof.trace_ood_values.len() != air.width()
+            || stark_proof.trace_ood_next_values.len() != air.width()
+            || stark_proof.aux_ood_values.len() != aux_width
+            || stark_proof.aux_ood_next_values.len() != aux_width
+            || stark_proof.quotient_ood_values.len() != quotient_width
+        {
+            return Err("Wrong number of out-of-domain values");
+        }
 
-        // For now, just check that we have commitments and a final polynomial
-        !stark_proof.fri_proof.commitments.is_empty()
-            && !stark_proof.fri_proof.final_polynomial.is_empty()
+        // One entry per word, so each is claimed once
+        let boundary = &stark_proof.memory_boundary;
+        if boundary.iter().any(|word| !word.addr.is_multiple_of(4))
+            || boundary.windows(2).any(|pair| pair[0].addr >= pair[1].addr)
+        {
+            return Err("Invalid memory boundary");
+        }
+        Ok(())
     }
 
-    fn verify_constraint_evaluations(&self, stark_proof: &SerializableStarkProof) -> bool {
-        // Check that constraint evaluations are all zero (satisfied)
-        stark_proof
-            .constraint_evaluations
+    /// Checks that the proof's public values are `public_values`: the first
+    /// and last pc, the gas used, the initial registers, the key-value store
+    /// roots, and the memory its first accesses read. The program
+    /// arguments are read from the initial stack.
+    fn verify_public_values(
+        &self,
+        stark_proof: &StarkProof,
+        public_values: &PublicValues,
+        constraint_system: &ConstraintSystem,
+    ) -> Result<(), &'static str> {
+        let gas_used = u32::try_from(stark_proof.gas_used)
+            .ok()
+            .filter(|&gas| gas < BABY_BEAR_MODULUS)
+            .ok_or("Gas used does not fit the field")?;
+        let keys = public_values.kv_keys;
+        match &public_values.state_transition {
+            Some(transition) => {
+                if digest_of(&transition.old_root).is_none()
+                    || digest_of(&transition.new_root).is_none()
+                {
+                    return Err("State root is not a digest");
+                }
+                if !keys.is_power_of_two() || !(2..=1 << MAX_KV_DEPTH).contains(&keys) {
+                    return Err("Key-value store size out of range");
+                }
+            }
+            None if keys != 0 => return Err("Key-value store size out of range"),
+            None => {}
+        }
+        if stark_proof.public_inputs
+            != constraint_system.expected_public_inputs(public_values, gas_used)
+        {
+            return Err("Public inputs do not match");
+        }
+        let initial_memory = &public_values.initial_memory;
+        // x2 is sp
+        let sp = public_values.initial_registers[2] as u32;
+        let xlen = constraint_system.layout.profile.xlen();
+        if read_argv(initial_memory, sp, xlen).unwrap_or_default() != public_values.argv {
+            return Err("Program arguments do not match the initial stack");
+        }
+        if stark_proof
+            .memory_boundary
             .iter()
-            .all(|&eval| eval == 0)
+            .any(|word| initial_memory.get(&word.addr).copied().unwrap_or(0) != word.initial_value)
+        {
+            return Err("Memory boundary does not match the initial memory");
+        }
+        Ok(())
     }
 
-    fn verify_trace_commitment(&self, stark_proof: &SerializableStarkProof) -> bool {
-        // In a real implementation, this would verify the Merkle tree commitment
-        // For now, just check that the commitment is not all zeros
-        stark_proof.trace_commitment != [0u8; 32]
+    /// Checks that the quotient opened at `z` is the constraints at `z`
+    /// divided by their vanishing polynomials.
+    fn verify_ood_quotient(
+        &self,
+        stark_proof: &StarkProof,
+        air: &Air,
+        inputs: &ConstraintInputs,
+        z: BabyBearExt4,
+    ) -> Result<(), &'static str> {
+        let n = stark_proof.trace_length as u64;
+        let last = BabyBear::two_adic_generator(n.trailing_zeros())
+            .inverse()
+            .unwrap();
+        let inverse =
+            |value: BabyBearExt4| value.inverse().ok_or("Out-of-domain point in the domain");
+        let vanishing_inv = inverse(z.pow(n) - BabyBearExt4::ONE)?;
+        let from_coordinates = |values: &[BabyBearExt4]| -> Vec<BabyBearExt4> {
+            values
+                .chunks(BabyBearExt4::DEGREE)
+                .map(BabyBearExt4::from_coordinates)
+                .collect()
+        };
+        let rows = RowPair {
+            current: &stark_proof.trace_ood_values,
+            next: &stark_proof.trace_ood_next_values,
+            aux_current: &from_coordinates(&stark_proof.aux_ood_values),
+            aux_next: &from_coordinates(&stark_proof.aux_ood_next_values),
+        };
+        let factors = VanishingFactors {
+            every_row: vanishing_inv,
+            transition: (z - last.into()) * vanishing_inv,
+            first_row: inverse(z - BabyBearExt4::ONE)?,
+            last_row: inverse(z - last.into())?,
+        };
+        let expected = constraint_quotient(air, &rows, inputs, &factors);
+
+        // Chunk i holds the coefficients of X^(i n) and up
+        let z_n = z.pow(n);
+        let quotient: BabyBearExt4 = from_coordinates(&stark_proof.quotient_ood_values)
+            .into_iter()
+            .zip(z_n.powers())
+            .map(|(chunk, power)| chunk * power)
+            .sum();
+
+        if quotient != expected {
+            return Err("Constraints not satisfied at the out-of-domain point");
+        }
+        Ok(())
+    }
+
+    /// Replays the transcript, checks the out-of-domain quotient and the
+    /// FRI proof. Every queried first-layer value must be the DEEP quotient
+    /// of authenticated trace, auxiliary and quotient rows.
+    fn verify_stark(
+        &self,
+        stark_proof: &StarkProof,
+        public_values: &PublicValues,
+        constraint_system: &ConstraintSystem,
+    ) -> Result<(), &'static str> {
+        let air = &constraint_system.air;
+        self.verify_shape(stark_proof, air)?;
+
+        // The verifier sums the table side of the lookups itself, decoding
+        // the program it fetches from the initial memory and taking the
+        // input, journal and syscall prices from the public values
+        let public =
+            constraint_system.public_entries(public_values, &stark_proof.lookup_multiplicities);
+        // The gas adds up over the integers only while no trace of this
+        // length can cost the modulus, paying the most for an instruction
+        // and a syscall on every row
+        let max_price = |table: LookupTable| {
+            public
+                .range((table, 0)..=(table, u32::MAX))
+                .filter_map(|(_, entry)| entry.last())
+                .map(|price| price.as_u32() as u64)
+                .max()
+                .unwrap_or(0)
+        };
+        let max_price = max_price(LookupTable::Program) + max_price(LookupTable::Syscall);
+        if max_price * stark_proof.trace_length as u64 >= BABY_BEAR_MODULUS as u64 {
+            return Err("Gas price too large for the trace");
+        }
+        let mut transcript = stark_proof.transcript();
+        let challenges = StarkProof::lookup_challenges(&mut transcript);
+        let lookup_sum = stark_proof
+            .lookup_sum(&challenges, &public)
+            .ok_or("Invalid lookup multiplicities")?;
+        transcript.absorb_bytes(&stark_proof.aux_commitment);
+        let inputs = ConstraintInputs {
+            public_inputs: &stark_proof.public_inputs,
+            challenges,
+            lookup_sum,
+            beta: transcript.challenge(),
+        };
+        transcript.absorb_bytes(&stark_proof.quotient_commitment);
+        let z = transcript.challenge();
+        stark_proof.absorb_ood_values(&mut transcript);
+        let alpha = transcript.challenge();
+        self.verify_ood_quotient(stark_proof, air, &inputs, z)?;
+
+        let blowup = stark_proof.expansion_factor as usize;
+        let config = FriConfig {
+            blowup,
+            num_queries: stark_proof.fri_proof.query_proofs.len(),
+        };
+        let log_size = (stark_proof.trace_length * blowup).trailing_zeros();
+        let half = 1usize << (log_size - 1);
+        let omega = BabyBear::two_adic_generator(log_size);
+        let zg = z * BabyBear::two_adic_generator(stark_proof.trace_length.trailing_zeros());
+        let quotient_width = stark_proof.quotient_ood_values.len();
+        let aux_width = stark_proof.aux_ood_values.len();
+
+        config.verify(
+            &stark_proof.fri_proof,
+            log_size,
+            domain_shift(),
+            &mut transcript,
+            |query, index| {
+                let x = domain_shift() * omega.pow(index as u64);
+                let mut pair = [BabyBearExt4::ZERO; 2];
+                for side in 0..2 {
+                    let trace = &stark_proof.trace_openings[query][side];
+                    let aux = &stark_proof.aux_openings[query][side];
+                    let quotient = &stark_proof.quotient_openings[query][side];
+                    let position = index + side * half;
+                    let valid = trace.values.len() == air.width()
+                        && aux.values.len() == aux_width
+                        && quotient.values.len() == quotient_width
+                        && verify_opening(
+                            &stark_proof.trace_commitment,
+                            position,
+                            &trace.values,
+                            &trace.merkle_path,
+                        )
+                        && verify_opening(
+                            &stark_proof.aux_commitment,
+                            position,
+                            &aux.values,
+                            &aux.merkle_path,
+                        )
+                        && verify_opening(
+                            &stark_proof.quotient_commitment,
+                            position,
+                            &quotient.values,
+                            &quotient.merkle_path,
+                        );
+                    if !valid {
+                        return Err("Invalid trace opening");
+                    }
+
+                    // The second opening is at -x
+                    let x = BabyBearExt4::from(if side == 0 { x } else { -x });
+                    let inverse = |value: BabyBearExt4| {
+                        value.inverse().ok_or("Out-of-domain point in the domain")
+                    };
+                    pair[side] = stark_proof.deep_quotient(
+                        &trace.values,
+                        &aux.values,
+                        &quotient.values,
+                        alpha,
+                        inverse(x - z)?,
+                        inverse(x - zg)?,
+                    );
+                }
+                Ok(pair)
+            },
+        )
     }
 }
 
 impl VerificationSystem for StarkVerifier {
     type Error = &'static str;
 
-    fn verify_proof(&self, proof: &Proof, _public_inputs: &[u8]) -> Result<bool, Self::Error> {
+    fn verify_proof(
+        &self,
+        proof: &Proof,
+        public_values: &PublicValues,
+    ) -> Result<bool, Self::Error> {
         // Deserialize the STARK proof
-        let stark_proof: SerializableStarkProof = bincode::deserialize(&proof.witness)
+        let stark_proof: StarkProof = bincode::deserialize(&proof.witness)
             .map_err(|_| "Failed to deserialize STARK proof")?;
 
-        // Verify trace commitment
-        if !self.verify_trace_commitment(&stark_proof) {
+        // The reported gas and ISA must be the ones bound into the proof
+        if stark_proof.gas_used != proof.gas_used
+            || IsaProfile::from_id(stark_proof.isa_profile) != Some(proof.isa_profile)
+        {
             return Ok(false);
         }
 
-        // Verify constraint evaluations
-        if !self.verify_constraint_evaluations(&stark_proof) {
+        let constraint_system = ConstraintSystem::new().with_profile(proof.isa_profile);
+        if self
+            .verify_public_values(&stark_proof, public_values, &constraint_system)
+            .is_err()
+        {
             return Ok(false);
         }
 
-        // Verify FRI proof
-        if !self.verify_fri_proof(&stark_proof) {
+        // Verify the AIR through the quotient and FRI
+        if self
+            .verify_stark(&stark_proof, public_values, &constraint_system)
+            .is_err()
+        {
             return Ok(false);
         }
 
@@ -97,7 +360,11 @@ pub struct MockVerificationSystem;
 impl VerificationSystem for MockVerificationSystem {
     type Error = &'static str;
 
-    fn verify_proof(&self, _proof: &Proof, _public_inputs: &[u8]) -> Result<bool, Self::Error> {
+    fn verify_proof(
+        &self,
+        _proof: &Proof,
+        _public_values: &PublicValues,
+    ) -> Result<bool, Self::Error> {
         Ok(true)
     }
 }
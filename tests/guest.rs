@@ -0,0 +1,342 @@
+use zkvm_from_scratch::{
+    vm::{stack::read_argv, PanicLocation, Program, ReplayLog},
+    ExecutorEnv, ExitStatus, Prover, StarkProver, StarkVerifier, Verifier, VmState,
+};
+use zkvm_guest::memory::MEMORY_SIZE;
+
+/// Built from `examples/guests/sum` with `just build-guests`.
+const SUM_ELF: &[u8] = include_bytes!("../examples/guests/sum.elf");
+
+fn sum_guest(values: &[u32]) -> VmState {
+    let mut vm = VmState::new(MEMORY_SIZE);
+    vm.write_input(values).unwrap();
+    vm.load_elf(SUM_ELF).unwrap();
+    vm
+}
+
+#[test]
+fn test_guest_commits_sum() {
+    let mut vm = sum_guest(&[1, 2, 3, u32::MAX]);
+
+    assert_eq!(vm.run(100_000), Ok(ExitStatus::Halted(0)));
+    assert_eq!(vm.decode_journal::<u32>(), Ok(5));
+    assert_eq!(vm.input_cursor, vm.input.len());
+    assert_eq!(vm.stdout, b"summing 4 values\n");
+}
+
+#[test]
+fn test_guest_panic_is_reported() {
+    let mut vm = sum_guest(&[]);
+
+    assert_eq!(vm.run(100_000), Ok(ExitStatus::Panicked));
+    let panic = vm.panic.unwrap();
+    assert_eq!(panic.message, "no values to sum");
+    assert_eq!(
+        panic.location,
+        Some(PanicLocation {
+            file: "src/main.rs".to_string(),
+            line: 17,
+            column: 9,
+        })
+    );
+    assert_eq!(
+        panic.to_string(),
+        "guest panicked at src/main.rs:17:9:\nno values to sum"
+    );
+    assert!(vm.journal.is_empty());
+    assert!(vm.stdout.is_empty());
+}
+
+#[test]
+fn test_guest_execution_proves() {
+    let mut vm = sum_guest(&[7, 8]);
+
+    let prover = Prover::new(StarkProver::default());
+    let trace = prover.generate_execution_trace(&mut vm, 100_000).unwrap();
+    assert_eq!(trace.exit_status, ExitStatus::Halted(0));
+    let proof = prover.prove_execution(&trace).unwrap();
+    let verifier = Verifier::new(StarkVerifier::default());
+    let public_values = trace.public_values();
+    assert_eq!(public_values.journal, 15u32.to_le_bytes());
+    assert_eq!(public_values.input, vm.input);
+    assert!(verifier.verify(&proof, &public_values).unwrap());
+
+    // The proof binds the journal, the input and the exit status
+    let mut forged = public_values.clone();
+    forged.journal = 16u32.to_le_bytes().to_vec();
+    assert!(!verifier.verify(&proof, &forged).unwrap());
+    let mut forged = public_values.clone();
+    forged.input[4] ^= 1;
+    assert!(!verifier.verify(&proof, &forged).unwrap());
+    let mut forged = public_values.clone();
+    forged.exit_status = ExitStatus::Halted(1);
+    assert!(!verifier.verify(&proof, &forged).unwrap());
+    let mut forged = public_values;
+    forged.exit_status = ExitStatus::StepLimit;
+    assert!(!verifier.verify(&proof, &forged).unwrap());
+
+    // A panicking guest proves as well
+    let mut vm = sum_guest(&[]);
+    let trace = prover.generate_execution_trace(&mut vm, 100_000).unwrap();
+    assert_eq!(trace.exit_status, ExitStatus::Panicked);
+    let proof = prover.prove_execution(&trace).unwrap();
+    assert!(verifier.verify(&proof, &trace.public_values()).unwrap());
+}
+
+#[test]
+fn test_executor_env_session_proves() {
+    let session = ExecutorEnv::builder()
+        .with_elf(SUM_ELF)
+        .with_input(&[10u32, 20, 30][..])
+        .with_trace()
+        .build()
+        .unwrap()
+        .execute()
+        .unwrap();
+
+    assert_eq!(session.exit_status, ExitStatus::Halted(0));
+    assert_eq!(session.decode_journal::<u32>(), Ok(60));
+    assert_eq!(session.stdout, b"summing 3 values\n");
+    assert!(session.stderr.is_empty() && session.panic.is_none());
+    assert!(session.cycles > 0);
+
+    let trace = session.trace.unwrap();
+    assert_eq!(trace.steps.len() as u64, session.cycles);
+    let proof = Prover::new(StarkProver::default())
+        .prove_execution(&trace)
+        .unwrap();
+    assert_eq!(proof.gas_used, session.gas_used);
+    let verifier = Verifier::new(StarkVerifier::default());
+    assert!(verifier.verify(&proof, &trace.public_values()).unwrap());
+}
+
+#[test]
+fn test_guest_heap_and_memory_stats() {
+    let env = || {
+        ExecutorEnv::builder()
+            .with_elf(SUM_ELF)
+            .with_input(&vec![1u32; 100])
+    };
+
+    let session = env().build().unwrap().execute().unwrap();
+    assert_eq!(session.decode_journal::<u32>(), Ok(100));
+    let state = &session.final_state;
+    assert!(state.heap_start >= 0x10000 && state.heap_start % 16 == 0);
+    // The allocator grows the break to page boundaries
+    assert_eq!(state.brk % 4096, 0);
+    assert_eq!(session.memory.peak_heap, state.brk - state.heap_start);
+    assert!(session.memory.peak_stack > 0 && session.memory.peak_stack < 4096);
+    // The program and its heap share two pages; the stack needs one more
+    assert!(session.memory.touched_pages >= 3);
+
+    // Without room for a full page the allocator asks for exactly what it needs
+    let session = env()
+        .with_max_heap(1024)
+        .build()
+        .unwrap()
+        .execute()
+        .unwrap();
+    assert_eq!(session.exit_status, ExitStatus::Halted(0));
+    assert!((400..1024).contains(&session.memory.peak_heap));
+
+    let session = env().with_max_heap(64).build().unwrap().execute().unwrap();
+    assert_eq!(session.exit_status, ExitStatus::Panicked);
+    assert!(session
+        .panic
+        .unwrap()
+        .message
+        .contains("memory allocation of 400 bytes failed"));
+}
+
+/// Hand-written from `examples/guests/linux-hello/hello.s` with `just build-guests`.
+const LINUX_HELLO_ELF: &[u8] = include_bytes!("../examples/guests/linux-hello.elf");
+
+#[test]
+fn test_linux_syscall_emulation() {
+    let env = || {
+        ExecutorEnv::builder()
+            .with_elf(LINUX_HELLO_ELF)
+            .with_input_bytes(b"zkvm\n")
+    };
+
+    let session = env()
+        .with_linux_syscalls()
+        .build()
+        .unwrap()
+        .execute()
+        .unwrap();
+    assert_eq!(session.exit_status, ExitStatus::Halted(0));
+    assert_eq!(session.stdout, b"Hello, zkvm\n");
+    assert!(session.stderr.is_empty());
+    assert_eq!(session.memory.peak_heap, 64);
+
+    // Without the emulation layer the first Linux syscall is rejected
+    assert_eq!(
+        env().build().unwrap().execute().err(),
+        Some("Unsupported environment call")
+    );
+}
+
+#[test]
+fn test_syscall_replay() {
+    let recorded = ExecutorEnv::builder()
+        .with_elf(LINUX_HELLO_ELF)
+        .with_input_bytes(b"zkvm\n")
+        .with_linux_syscalls()
+        .with_trace()
+        .build()
+        .unwrap()
+        .execute()
+        .unwrap();
+    let log = recorded.replay_log.clone();
+    assert!(log.records.iter().any(|record| record.syscall == 63));
+    assert_eq!(
+        ReplayLog::from_bytes(&log.to_bytes().unwrap()),
+        Ok(log.clone())
+    );
+
+    // No handlers and no input: everything comes from the log
+    let replay = |log: ReplayLog| {
+        ExecutorEnv::builder()
+            .with_elf(LINUX_HELLO_ELF)
+            .with_replay(log)
+            .build()
+            .unwrap()
+            .execute()
+    };
+    let replayed = replay(log.clone()).unwrap();
+    assert_eq!(replayed.exit_status, ExitStatus::Halted(0));
+    assert_eq!(replayed.stdout, b"Hello, zkvm\n");
+    assert_eq!(replayed.replay_log, log);
+
+    let trace = recorded.trace.unwrap();
+    let prover = Prover::new(StarkProver::default());
+    let replayed_trace = prover
+        .replay_execution_trace(&trace.initial_state, log.clone(), 100_000)
+        .unwrap();
+    assert_eq!(replayed_trace.steps.len(), trace.steps.len());
+    assert_eq!(
+        replayed_trace.final_state.registers,
+        trace.final_state.registers
+    );
+    // The replay takes the input the guest read from the log, and proves
+    let public_values = replayed_trace.public_values();
+    assert_eq!(public_values.input, b"zkvm\n");
+    assert_eq!(public_values.exit_status, ExitStatus::Halted(0));
+    let proof = prover.prove_execution(&replayed_trace).unwrap();
+    let verifier = Verifier::new(StarkVerifier::default());
+    assert!(verifier.verify(&proof, &public_values).unwrap());
+    let mut forged = public_values;
+    forged.input = b"zkvm?".to_vec();
+    assert!(!verifier.verify(&proof, &forged).unwrap());
+
+    // The name read from stdin no longer matches what the program echoes
+    let mut tampered = log.clone();
+    let read = tampered
+        .records
+        .iter_mut()
+        .find(|record| record.syscall == 63)
+        .unwrap();
+    read.memory_writes[0].1 ^= 1;
+    assert_eq!(
+        replay(tampered).err(),
+        Some("Replay diverged: syscall read different memory")
+    );
+
+    let mut truncated = log.clone();
+    truncated.records.pop();
+    assert_eq!(
+        replay(truncated).err(),
+        Some("Replay diverged: more syscalls than were recorded")
+    );
+
+    let mut extended = log;
+    extended.records.push(extended.records[0].clone());
+    assert_eq!(
+        replay(extended).err(),
+        Some("Replay diverged: guest stopped before the end of the log")
+    );
+}
+
+#[test]
+fn test_guest_args_and_initial_stack() {
+    let env = ExecutorEnv::builder()
+        .with_elf(SUM_ELF)
+        .with_input(&[10u32, 20, 30][..])
+        .with_args(["sum", "total"])
+        .with_env("MODE", "fast");
+
+    let vm = env.build().unwrap().into_vm().unwrap();
+    let program = Program::from_elf(SUM_ELF).unwrap();
+    assert!(program.global_pointer.is_some());
+    assert_eq!(vm.registers[3], program.global_pointer.unwrap() as u64);
+
+    // argc, argv[0..2], null, envp[0], null, AT_NULL
+    let sp = vm.registers[2] as u32;
+    assert_eq!(sp % 16, 0);
+    let word = |index: u32| vm.memory.read_word(sp + 4 * index).unwrap();
+    let string = |addr: u32| {
+        let bytes: Vec<u8> = (addr..)
+            .map(|addr| vm.memory.read_byte(addr).unwrap())
+            .take_while(|&byte| byte != 0)
+            .collect();
+        String::from_utf8(bytes).unwrap()
+    };
+    assert_eq!(word(0), 2);
+    assert_eq!(string(word(1)), "sum");
+    assert_eq!(string(word(2)), "total");
+    assert_eq!(word(3), 0);
+    assert_eq!(string(word(4)), "MODE=fast");
+    assert_eq!((word(5), word(6), word(7)), (0, 0, 0));
+    assert!(word(4) + 10 <= MEMORY_SIZE as u32);
+
+    let env = || {
+        ExecutorEnv::builder()
+            .with_elf(SUM_ELF)
+            .with_input(&[10u32, 20, 30][..])
+            .with_args(["sum", "total"])
+    };
+    let session = env().build().unwrap().execute().unwrap();
+    assert_eq!(session.exit_status, ExitStatus::Halted(0));
+    assert_eq!(session.stdout, b"summing 3 values\ntotal: 60\n");
+    assert_eq!(session.argv, ["sum", "total"]);
+
+    // argv is read from the initial stack at the sp the proof starts from:
+    // a verifier expecting other arguments rejects the proof, and so does
+    // one told sp pointed elsewhere
+    let session = env().with_trace().build().unwrap().execute().unwrap();
+    let trace = session.trace.unwrap();
+    let proof = Prover::new(StarkProver::default())
+        .prove_execution(&trace)
+        .unwrap();
+    let verifier = Verifier::new(StarkVerifier::default());
+    let public_values = trace.public_values();
+    assert_eq!(public_values.argv, session.argv);
+    assert!(verifier.verify(&proof, &public_values).unwrap());
+    let mut forged = public_values.clone();
+    forged.argv = vec!["sum".to_string()];
+    assert!(!verifier.verify(&proof, &forged).unwrap());
+    let mut forged = public_values.clone();
+    forged.initial_registers[2] += 4;
+    forged.argv = Vec::new();
+    assert_eq!(
+        read_argv(
+            &forged.initial_memory,
+            forged.initial_registers[2] as u32,
+            32
+        ),
+        None
+    );
+    assert!(!verifier.verify(&proof, &forged).unwrap());
+
+    assert_eq!(
+        ExecutorEnv::builder()
+            .with_elf(SUM_ELF)
+            .with_args(["a\0b"])
+            .build()
+            .unwrap()
+            .into_vm()
+            .err(),
+        Some("Argument contains a NUL byte")
+    );
+}